@@ -0,0 +1,255 @@
+//! Synthetic inputs for the benchmarks.
+
+#![allow(dead_code)]
+
+use binspan::decode::{Meta, Val};
+use bytes::Bytes;
+
+/// Entry of a ZIP archive built by [`zip`].
+pub struct Entry {
+    pub name: String,
+    /// Compression method, 0 for stored and 8 for deflated.
+    pub method: u16,
+    pub crc: u32,
+    pub uncompressed_size: u32,
+    pub data: Vec<u8>,
+}
+
+impl Entry {
+    /// Entry that stores `data` without compression.
+    pub fn stored(name: String, data: Vec<u8>) -> Self {
+        Self {
+            name,
+            method: 0,
+            crc: crc32fast::hash(&data),
+            uncompressed_size: data.len() as u32,
+            data,
+        }
+    }
+}
+
+/// ZIP archive with the given entries.
+///
+/// Archives with more than 65535 entries get a ZIP64 end of central directory record.
+pub fn zip(entries: impl IntoIterator<Item = Entry>) -> Bytes {
+    zip_at(0, entries)
+}
+
+/// ZIP archive whose offsets assume that it is preceded by `base` bytes.
+pub fn zip_at(base: u32, entries: impl IntoIterator<Item = Entry>) -> Bytes {
+    let (mut out, mut cd, mut n) = (Vec::new(), Vec::new(), 0u64);
+    for e in entries {
+        let offset = base + out.len() as u32;
+        let common = |b: &mut Vec<u8>| {
+            // version needed, flags, method, time, date
+            for x in [20u16, 0, e.method, 0, 0x21] {
+                b.extend(x.to_le_bytes());
+            }
+            b.extend(e.crc.to_le_bytes());
+            b.extend((e.data.len() as u32).to_le_bytes());
+            b.extend(e.uncompressed_size.to_le_bytes());
+            b.extend((e.name.len() as u16).to_le_bytes());
+            b.extend(0u16.to_le_bytes());
+        };
+        out.extend(0x04034b50u32.to_le_bytes());
+        common(&mut out);
+        out.extend(e.name.as_bytes());
+        out.extend(&e.data);
+
+        cd.extend(0x02014b50u32.to_le_bytes());
+        cd.extend(0x031eu16.to_le_bytes());
+        common(&mut cd);
+        // comment length, disk, internal and external attributes
+        cd.extend([0; 10]);
+        cd.extend(offset.to_le_bytes());
+        cd.extend(e.name.as_bytes());
+        n += 1;
+    }
+    let cd_offset = u64::from(base) + out.len() as u64;
+    out.extend(&cd);
+    if n > 0xffff {
+        let eocd64 = u64::from(base) + out.len() as u64;
+        out.extend(0x06064b50u32.to_le_bytes());
+        out.extend(44u64.to_le_bytes());
+        out.extend(0x2du16.to_le_bytes());
+        out.extend(0x2du16.to_le_bytes());
+        out.extend([0; 8]);
+        out.extend(n.to_le_bytes());
+        out.extend(n.to_le_bytes());
+        out.extend((cd.len() as u64).to_le_bytes());
+        out.extend(cd_offset.to_le_bytes());
+
+        out.extend(0x07064b50u32.to_le_bytes());
+        out.extend(0u32.to_le_bytes());
+        out.extend(eocd64.to_le_bytes());
+        out.extend(1u32.to_le_bytes());
+    }
+    let count = u16::try_from(n).unwrap_or(0xffff);
+    out.extend(0x06054b50u32.to_le_bytes());
+    out.extend([0; 4]);
+    out.extend(count.to_le_bytes());
+    out.extend(count.to_le_bytes());
+    out.extend((cd.len() as u32).to_le_bytes());
+    out.extend((cd_offset as u32).to_le_bytes());
+    out.extend(0u16.to_le_bytes());
+    out.into()
+}
+
+/// ZIP archive with `n` small stored entries.
+pub fn zip_n(n: usize) -> Bytes {
+    zip((0..n).map(|i| {
+        Entry::stored(
+            format!("dir/file{i}.txt"),
+            format!("content {i}\n").into_bytes(),
+        )
+    }))
+}
+
+/// Deflated entry that uncompresses to `chunk` repeated `n` times.
+///
+/// The uncompressed data is never held in memory.
+pub fn deflated(name: String, chunk: &[u8], n: usize) -> Entry {
+    use miniz_oxide::deflate::core::{compress, create_comp_flags_from_zip_params};
+    use miniz_oxide::deflate::core::{CompressorOxide, TDEFLFlush, TDEFLStatus};
+    // negative window bits select raw deflate without zlib header
+    let mut d = CompressorOxide::new(create_comp_flags_from_zip_params(6, -15, 0));
+    let (mut data, mut buf) = (Vec::new(), vec![0; 1 << 16]);
+    let mut hasher = crc32fast::Hasher::new();
+    for i in 0..n {
+        hasher.update(chunk);
+        let last = i + 1 == n;
+        let flush = if last {
+            TDEFLFlush::Finish
+        } else {
+            TDEFLFlush::None
+        };
+        let mut input = chunk;
+        loop {
+            let (status, read, written) = compress(&mut d, input, &mut buf, flush);
+            input = &input[read..];
+            data.extend(&buf[..written]);
+            match status {
+                TDEFLStatus::Done => break,
+                TDEFLStatus::Okay if !last && input.is_empty() => break,
+                TDEFLStatus::Okay => (),
+                s => panic!("deflate failed: {s:?}"),
+            }
+        }
+    }
+    Entry {
+        name,
+        method: 8,
+        crc: hasher.finalize(),
+        uncompressed_size: (chunk.len() * n) as u32,
+        data,
+    }
+}
+
+/// Tar archive with the given entries of name, type flag, and data.
+pub fn tar(entries: impl IntoIterator<Item = (String, u8, Vec<u8>)>) -> Bytes {
+    let mut out = Vec::new();
+    for (name, typeflag, data) in entries {
+        let mut h = [0u8; 512];
+        h[..name.len()].copy_from_slice(name.as_bytes());
+        h[100..108].copy_from_slice(b"0000644\0");
+        h[108..116].copy_from_slice(b"0000000\0");
+        h[116..124].copy_from_slice(b"0000000\0");
+        h[124..136].copy_from_slice(format!("{:011o}\0", data.len()).as_bytes());
+        h[136..148].copy_from_slice(b"00000000000\0");
+        h[148..156].fill(b' ');
+        h[156] = typeflag;
+        h[257..265].copy_from_slice(b"ustar\x0000");
+        let sum: u32 = h.iter().map(|c| u32::from(*c)).sum();
+        h[148..156].copy_from_slice(format!("{sum:06o}\0 ").as_bytes());
+        out.extend(h);
+        out.extend(&data);
+        out.resize(out.len().next_multiple_of(512), 0);
+    }
+    out.extend([0; 1024]);
+    out.into()
+}
+
+/// Metadata of `v` and its descendants, without forcing lazy values.
+pub fn metas<'a>(m: &'a Meta, v: &'a Val, out: &mut Vec<&'a Meta>) {
+    out.push(m);
+    match v.forced() {
+        Some(Val::Obj(o)) => o.iter().for_each(|(_, m, v)| metas(m, v, out)),
+        Some(Val::Arr(a)) => a.iter().for_each(|(m, v)| metas(m, v, out)),
+        _ => (),
+    }
+}
+
+/// PAX extended header record, whose length counts its own digits.
+pub fn pax_record(key: &str, value: &str) -> Vec<u8> {
+    let body = format!(" {key}={value}\n");
+    let digits = (1..)
+        .find(|d| (body.len() + d).to_string().len() == *d)
+        .unwrap();
+    format!("{}{body}", body.len() + digits).into_bytes()
+}
+
+/// Allocator that counts allocations and keeps track of the allocated bytes.
+///
+/// Benchmarks that report memory install it with `#[global_allocator]`.
+pub mod counting {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+
+    static CURRENT: AtomicUsize = AtomicUsize::new(0);
+    static PEAK: AtomicUsize = AtomicUsize::new(0);
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    pub struct Counting;
+
+    fn grow(n: usize) {
+        COUNT.fetch_add(1, Relaxed);
+        let current = CURRENT.fetch_add(n, Relaxed) + n;
+        PEAK.fetch_max(current, Relaxed);
+    }
+
+    unsafe impl GlobalAlloc for Counting {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let p = System.alloc(layout);
+            if !p.is_null() {
+                grow(layout.size())
+            }
+            p
+        }
+
+        unsafe fn dealloc(&self, p: *mut u8, layout: Layout) {
+            System.dealloc(p, layout);
+            CURRENT.fetch_sub(layout.size(), Relaxed);
+        }
+
+        unsafe fn realloc(&self, p: *mut u8, layout: Layout, size: usize) -> *mut u8 {
+            let q = System.realloc(p, layout, size);
+            if !q.is_null() {
+                CURRENT.fetch_sub(layout.size(), Relaxed);
+                grow(size)
+            }
+            q
+        }
+    }
+
+    /// Allocated bytes, peak allocated bytes, and number of allocations while running `f`.
+    pub fn measure<T>(f: impl FnOnce() -> T) -> (T, Usage) {
+        let start = CURRENT.load(Relaxed);
+        PEAK.store(start, Relaxed);
+        let count = COUNT.load(Relaxed);
+        let y = f();
+        let usage = Usage {
+            retained: CURRENT.load(Relaxed).saturating_sub(start),
+            peak: PEAK.load(Relaxed) - start,
+            allocations: COUNT.load(Relaxed) - count,
+        };
+        (y, usage)
+    }
+
+    #[derive(Debug)]
+    pub struct Usage {
+        /// Bytes that are still allocated afterwards, such as those of a returned tree.
+        pub retained: usize,
+        pub peak: usize,
+        pub allocations: usize,
+    }
+}
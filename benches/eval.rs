@@ -0,0 +1,48 @@
+//! Decoding a large archive, and evaluating and printing the decoded tree.
+
+mod common;
+
+use binspan::decode::{DisplayOpts, Obj, Val};
+use binspan::zip::{decode_zip, Opts};
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::hint::black_box;
+
+fn decode(c: &mut Criterion) {
+    let b = common::zip_n(100_000);
+    let opts = Opts::default();
+    let mut g = c.benchmark_group("zip 100k entries");
+    g.sample_size(10);
+    g.bench_function("decode", |x| {
+        x.iter(|| {
+            let mut o = Obj::default();
+            decode_zip(&mut o, b.clone(), &opts).unwrap();
+            o
+        })
+    });
+
+    let mut o = Obj::default();
+    decode_zip(&mut o, b.clone(), &opts).unwrap();
+    let v = Val::Obj(Box::new(o));
+    g.bench_function("eval", |x| x.iter(|| black_box(&v).eval()));
+    g.bench_function("eval_in_place", |x| {
+        x.iter_batched(
+            || v.clone(),
+            |mut v| {
+                v.eval_in_place();
+                v
+            },
+            criterion::BatchSize::LargeInput,
+        )
+    });
+    let opts = DisplayOpts {
+        root: Some(b.clone()),
+        ..DisplayOpts::default()
+    };
+    g.bench_function("display", |x| x.iter(|| v.display(&opts).to_string()));
+    #[cfg(feature = "serde")]
+    g.bench_function("cbor", |x| x.iter(|| binspan::ser::to_cbor(&v, &b)));
+    g.finish();
+}
+
+criterion_group!(benches, decode);
+criterion_main!(benches);
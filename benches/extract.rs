@@ -0,0 +1,70 @@
+//! Listing the entries of archives, with and without building the decoded tree.
+//!
+//! Besides timing, this prints the allocations of both paths.
+//! Listing decodes into `Discard`, so its allocations are those of the entries only.
+
+mod common;
+
+use binspan::decode::Obj;
+use binspan::{tar, zip};
+use bytes::Bytes;
+use common::counting::{measure, Counting};
+use criterion::{criterion_group, criterion_main, Criterion};
+
+#[global_allocator]
+static ALLOC: Counting = Counting;
+
+const N: usize = 100_000;
+
+fn zip_tree(b: &Bytes) -> Obj {
+    let mut o = Obj::default();
+    zip::decode_zip(&mut o, b.clone(), &zip::Opts::default()).unwrap();
+    o
+}
+
+fn zip_entries(b: &Bytes) -> Vec<zip::Entry> {
+    zip::entries(b.clone(), &zip::Opts::default()).unwrap()
+}
+
+fn tar_tree(b: &Bytes) -> Obj {
+    let mut o = Obj::default();
+    tar::decode_tar(&mut o, b.clone(), &tar::Opts::default()).unwrap();
+    o
+}
+
+fn tar_entries(b: &Bytes) -> Vec<tar::Entry> {
+    tar::entries(b.clone(), &tar::Opts::default()).unwrap()
+}
+
+fn report<T, U>(format: &str, tree: impl FnOnce() -> T, entries: impl FnOnce() -> U) {
+    let (t, tree) = measure(tree);
+    drop(t);
+    let (e, entries) = measure(entries);
+    drop(e);
+    eprintln!(
+        "{format}: allocations per entry: {:.1} building the tree, {:.1} listing",
+        tree.allocations as f64 / N as f64,
+        entries.allocations as f64 / N as f64,
+    );
+}
+
+fn bench(c: &mut Criterion) {
+    let z = common::zip_n(N);
+    let t = common::tar((0..N).map(|i| {
+        let data = format!("content {i}\n").into_bytes();
+        (format!("dir/file{i}.txt"), b'0', data)
+    }));
+    report("zip", || zip_tree(&z), || zip_entries(&z));
+    report("tar", || tar_tree(&t), || tar_entries(&t));
+
+    let mut g = c.benchmark_group("100k entries");
+    g.sample_size(10);
+    g.bench_function("zip tree", |x| x.iter(|| zip_tree(&z)));
+    g.bench_function("zip entries", |x| x.iter(|| zip_entries(&z)));
+    g.bench_function("tar tree", |x| x.iter(|| tar_tree(&t)));
+    g.bench_function("tar entries", |x| x.iter(|| tar_entries(&t)));
+    g.finish();
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
@@ -0,0 +1,69 @@
+//! Decoding flags fields, and forcing the objects of their bits.
+//!
+//! Besides timing, this prints the number of allocations per field,
+//! which building the objects only when they are forced keeps low.
+
+mod common;
+
+use binspan::decode::{flags, le, Decoded, Obj};
+use bitflags::bitflags;
+use bytes::Bytes;
+use common::counting::{measure, Counting};
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+
+#[global_allocator]
+static ALLOC: Counting = Counting;
+
+bitflags! {
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    struct Flags: u16 {
+        const encrypted = 1 << 0;
+        const compression_option1 = 1 << 1;
+        const compression_option2 = 1 << 2;
+        const data_descriptor = 1 << 3;
+        const enhanced_deflation = 1 << 4;
+        const compressed_patched_data = 1 << 5;
+        const strong_encryption = 1 << 6;
+        const language_encoding = 1 << 11;
+    }
+}
+
+const N: usize = 100_000;
+
+fn decode(b: &Bytes) -> Vec<Decoded<Flags>> {
+    let mut b = b.clone();
+    (0..N)
+        .map(|_| flags::<Obj, Flags>(le::u16(&mut b)).unwrap())
+        .collect()
+}
+
+fn force(fields: &[Decoded<Flags>]) {
+    fields.iter().for_each(|d| {
+        d.val.force();
+    })
+}
+
+fn bench(c: &mut Criterion) {
+    // include unknown bits, which are listed separately
+    let b: Bytes = (0..N)
+        .flat_map(|i| (i as u16 | 0x8000).to_le_bytes())
+        .collect();
+    let (fields, decoding) = measure(|| decode(&b));
+    let ((), forcing) = measure(|| force(&fields));
+    eprintln!(
+        "allocations per field: {:.1} decoding, {:.1} forcing",
+        decoding.allocations as f64 / N as f64,
+        forcing.allocations as f64 / N as f64,
+    );
+    drop(fields);
+
+    let mut g = c.benchmark_group("100k flags");
+    g.bench_function("decode", |x| x.iter(|| decode(&b)));
+    g.bench_function("force", |x| {
+        x.iter_batched(|| decode(&b), |f| force(&f), BatchSize::LargeInput)
+    });
+    g.finish();
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
@@ -0,0 +1,47 @@
+//! Locating the central directory at the end of a large sparse file.
+//!
+//! The end of central directory record is searched only in the tail of the input,
+//! so this takes as long for a file of gigabytes as for a small one,
+//! and the pages of the memory-mapped file before the tail are never read.
+
+mod common;
+
+use binspan::zip::{Directory, Opts};
+use bytes::Bytes;
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::io::{Seek, SeekFrom, Write};
+
+/// Memory-map a sparse file of `hole` zero bytes followed by a small archive.
+fn sparse(hole: u32) -> Bytes {
+    let path = std::env::temp_dir().join(format!("binspan-bench-{}.zip", std::process::id()));
+    let mut f = std::fs::File::options()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&path)
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    f.set_len(hole.into()).unwrap();
+    f.seek(SeekFrom::End(0)).unwrap();
+    f.write_all(&common::zip_at(
+        hole,
+        [common::Entry::stored("a".into(), b"a".to_vec())],
+    ))
+    .unwrap();
+    Bytes::from_owner(unsafe { memmap2::Mmap::map(&f) }.unwrap())
+}
+
+fn locate(c: &mut Criterion) {
+    let mut g = c.benchmark_group("locate central directory");
+    for (name, hole) in [("small", 0), ("3 GiB", 3 << 30)] {
+        let b = sparse(hole);
+        g.bench_function(name, |x| {
+            x.iter(|| Directory::new(b.clone(), &Opts::default()).unwrap())
+        });
+    }
+    g.finish();
+}
+
+criterion_group!(benches, locate);
+criterion_main!(benches);
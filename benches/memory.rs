@@ -0,0 +1,55 @@
+//! Memory used by a decoded tree of about a million nodes.
+//!
+//! Besides timing the decoding, this prints the bytes that the tree occupies,
+//! which is what shrinking [`Val`] and [`Meta`] aims at.
+
+mod common;
+
+use binspan::decode::{Meta, Obj, Val};
+use binspan::zip::{decode_zip, Opts};
+use common::counting::{measure, Counting};
+use criterion::{criterion_group, criterion_main, Criterion};
+
+#[global_allocator]
+static ALLOC: Counting = Counting;
+
+/// Number of values in `v`, including `v` itself, without forcing lazy values.
+fn nodes(v: &Val) -> usize {
+    1 + match v.forced() {
+        Some(Val::Obj(o)) => o.iter().map(|(_, _, v)| nodes(v)).sum(),
+        Some(Val::Arr(a)) => a.iter().map(|(_, v)| nodes(v)).sum(),
+        _ => 0,
+    }
+}
+
+fn decode(b: &bytes::Bytes) -> Val {
+    let mut o = Obj::default();
+    decode_zip(&mut o, b.clone(), &Opts::default()).unwrap();
+    Val::Obj(Box::new(o))
+}
+
+fn tree(c: &mut Criterion) {
+    // every entry yields 34 nodes in its local file and central directory record
+    let b = common::zip_n(30_000);
+    let (v, usage) = measure(|| decode(&b));
+    let n = nodes(&v);
+    eprintln!(
+        "{n} nodes: {} bytes retained ({} per node), {} bytes peak, {} allocations; \
+         size_of::<Val>() = {}, size_of::<Meta>() = {}",
+        usage.retained,
+        usage.retained / n,
+        usage.peak,
+        usage.allocations,
+        size_of::<Val>(),
+        size_of::<Meta>(),
+    );
+    drop(v);
+
+    let mut g = c.benchmark_group("1M nodes");
+    g.sample_size(10);
+    g.bench_function("decode", |x| x.iter(|| decode(&b)));
+    g.finish();
+}
+
+criterion_group!(benches, tree);
+criterion_main!(benches);
@@ -0,0 +1,75 @@
+//! Offsets of the spans of a tar archive decoded from memory and from a stream.
+//!
+//! Decoding from memory, such as from a mapped file, slices a single buffer,
+//! while decoding from a stream reads every entry into a buffer of its own.
+//! In both cases, spans know their offsets from the cursor of their buffer;
+//! this compares that with subtracting the address of the input from that of the bytes,
+//! which only works for a single buffer.
+
+mod common;
+
+use binspan::decode::{Meta, Obj, Val};
+use binspan::tar::{decode_tar, decode_tar_read, Opts};
+use bytes::Bytes;
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::ops::Range;
+
+const ENTRIES: usize = 20_000;
+
+fn archive() -> Bytes {
+    common::tar((0..ENTRIES).map(|i| {
+        let data = format!("content {i}\n").into_bytes();
+        (format!("dir/file{i}.txt"), b'0', data)
+    }))
+}
+
+fn decode(b: &Bytes) -> Val {
+    let mut o = Obj::default();
+    decode_tar(&mut o, b.clone(), &Opts::default()).unwrap();
+    Val::Obj(Box::new(o))
+}
+
+/// Range of the bytes of `m` relative to `root`, computed from their addresses.
+fn by_address(m: &Meta, root: &Bytes) -> Option<Range<usize>> {
+    let start = (m.bytes().as_ptr() as usize).checked_sub(root.as_ptr() as usize)?;
+    let end = start + m.bytes().len();
+    (end <= root.len()).then_some(start..end)
+}
+
+fn bench(c: &mut Criterion) {
+    let b = archive();
+    let v = decode(&b);
+    let (root, mut all) = (Meta::from(&b), Vec::new());
+    common::metas(&root, &v, &mut all);
+    let streamed: Vec<_> = decode_tar_read(&b[..], &Opts::default())
+        .map(Result::unwrap)
+        .collect();
+    let mut all_streamed = Vec::new();
+    for s in &streamed {
+        let fields = s.file.iter();
+        fields.for_each(|(_, m, v)| common::metas(m, v, &mut all_streamed));
+    }
+
+    let mut g = c.benchmark_group("20k tar entries");
+    g.sample_size(10);
+    g.bench_function("decode from memory", |x| x.iter(|| decode(&b)));
+    g.bench_function("decode from a stream", |x| {
+        x.iter(|| decode_tar_read(&b[..], &Opts::default()).count())
+    });
+    g.bench_function("offset_in from memory", |x| {
+        x.iter(|| all.iter().filter_map(|m| m.offset_in(&b)).count())
+    });
+    g.bench_function("offset from memory", |x| {
+        x.iter(|| all.iter().filter_map(|m| m.offset()).count())
+    });
+    g.bench_function("offset from a stream", |x| {
+        x.iter(|| all_streamed.iter().filter_map(|m| m.offset()).count())
+    });
+    g.bench_function("address arithmetic from memory", |x| {
+        x.iter(|| all.iter().filter_map(|m| by_address(m, &b)).count())
+    });
+    g.finish();
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
@@ -0,0 +1,31 @@
+//! Decoding the central directory of a large archive serially and in parallel.
+
+mod common;
+
+use binspan::decode::Obj;
+use binspan::zip::{decode_zip, Opts};
+use criterion::{criterion_group, criterion_main, Criterion};
+
+fn central_directory(c: &mut Criterion) {
+    let b = common::zip_n(200_000);
+    let mut g = c.benchmark_group("zip 200k entries");
+    g.sample_size(10);
+    for parallel in [false, true] {
+        let opts = Opts {
+            parallel,
+            ..Opts::default()
+        };
+        let name = if parallel { "parallel" } else { "serial" };
+        g.bench_function(name, |x| {
+            x.iter(|| {
+                let mut o = Obj::default();
+                decode_zip(&mut o, b.clone(), &opts).unwrap();
+                o
+            })
+        });
+    }
+    g.finish();
+}
+
+criterion_group!(benches, central_directory);
+criterion_main!(benches);
@@ -0,0 +1,59 @@
+//! Decoding a tar archive whose PAX extended headers hold a million xattr records.
+//!
+//! Besides timing, this prints the allocations and retained bytes per record.
+//! Keywords repeat across headers, so interning them keeps both low.
+
+mod common;
+
+use binspan::decode::Obj;
+use binspan::tar::{decode_tar, Opts};
+use bytes::Bytes;
+use common::counting::{measure, Counting};
+use criterion::{criterion_group, criterion_main, Criterion};
+
+#[global_allocator]
+static ALLOC: Counting = Counting;
+
+const HEADERS: usize = 10_000;
+const RECORDS: usize = 100;
+
+fn archive() -> Bytes {
+    common::tar((0..HEADERS).flat_map(|i| {
+        let records = (0..RECORDS)
+            .flat_map(|j| common::pax_record(&format!("SCHILY.xattr.user.attr{j}"), "value"))
+            .collect();
+        let header = (format!("PaxHeaders/file{i}"), b'x', records);
+        let file = (
+            format!("file{i}"),
+            b'0',
+            format!("content {i}\n").into_bytes(),
+        );
+        [header, file]
+    }))
+}
+
+fn decode(b: &Bytes) -> Obj {
+    let mut o = Obj::default();
+    decode_tar(&mut o, b.clone(), &Opts::default()).unwrap();
+    o
+}
+
+fn bench(c: &mut Criterion) {
+    let b = archive();
+    let (o, usage) = measure(|| decode(&b));
+    let records = (HEADERS * RECORDS) as f64;
+    eprintln!(
+        "per record: {:.2} allocations, {:.0} bytes retained",
+        usage.allocations as f64 / records,
+        usage.retained as f64 / records,
+    );
+    drop(o);
+
+    let mut g = c.benchmark_group("1M PAX records");
+    g.sample_size(10);
+    g.bench_function("decode", |x| x.iter(|| decode(&b)));
+    g.finish();
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
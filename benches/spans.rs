@@ -0,0 +1,55 @@
+//! Memory that the spans of a decoded tree of about a million nodes take.
+//!
+//! Besides timing access to the spans, this prints how much the compact spans in [`Meta`],
+//! a shared cursor with an offset and a length, save over a [`Bytes`] handle per span,
+//! and how many spans are duplicates of others.
+
+mod common;
+
+use binspan::decode::{Meta, Obj, Val};
+use binspan::zip::{decode_zip, Opts};
+use bytes::Bytes;
+use criterion::{criterion_group, criterion_main, Criterion};
+use std::collections::HashSet;
+use std::hint::black_box;
+
+fn bench(c: &mut Criterion) {
+    // every entry yields 34 nodes in its local file and central directory record
+    let b = common::zip_n(30_000);
+    let mut o = Obj::default();
+    decode_zip(&mut o, b.clone(), &Opts::default()).unwrap();
+    let (root, v) = (Meta::from(&b), Val::Obj(Box::new(o)));
+    let mut all = Vec::new();
+    common::metas(&root, &v, &mut all);
+
+    let n = all.len();
+    let in_input = all.iter().filter(|m| m.offset_in(&b).is_some()).count();
+    let spans: HashSet<_> = all.iter().filter_map(|m| m.offset_in(&b)).collect();
+    // what `Meta` took when it held a `Bytes` handle besides the boxed extra metadata
+    let with_bytes = size_of::<Bytes>() + size_of::<Option<Box<()>>>();
+    let saved = n * (with_bytes - size_of::<Meta>());
+    eprintln!(
+        "{n} nodes, {in_input} spans in the input, {} distinct; \
+         size_of::<Meta>() = {}, {with_bytes} with a Bytes handle; \
+         compact spans save {saved} bytes ({} per node)",
+        spans.len(),
+        size_of::<Meta>(),
+        saved / n,
+    );
+
+    let mut g = c.benchmark_group("1M spans");
+    g.bench_function("bytes", |x| {
+        x.iter(|| {
+            all.iter()
+                .map(|m| black_box(m.bytes()).len())
+                .sum::<usize>()
+        })
+    });
+    g.bench_function("offset_in", |x| {
+        x.iter(|| all.iter().filter_map(|m| m.offset_in(&b)).count())
+    });
+    g.finish();
+}
+
+criterion_group!(benches, bench);
+criterion_main!(benches);
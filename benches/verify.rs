@@ -0,0 +1,43 @@
+//! Verifying the CRC-32 of a 2 GiB entry.
+//!
+//! Verification streams the uncompressed data through the hasher,
+//! so the peak memory that this prints stays flat regardless of the size of the entry.
+
+mod common;
+
+use binspan::decode::{Obj, Val};
+use binspan::zip::{decode_zip, Opts};
+use bytes::Bytes;
+use common::counting::{measure, Counting};
+use criterion::{criterion_group, criterion_main, Criterion};
+
+#[global_allocator]
+static ALLOC: Counting = Counting;
+
+/// Decode `b` and verify the CRC-32 of its first entry.
+fn verify(b: &Bytes) -> bool {
+    let mut o = Obj::default();
+    decode_zip(&mut o, b.clone(), &Opts::default()).unwrap();
+    let v = Val::Obj(Box::new(o));
+    let valid = v.query("local_files[0].compressed.crc_32.valid").unwrap();
+    matches!(valid, Val::Bool(true))
+}
+
+fn crc(c: &mut Criterion) {
+    let entry = common::deflated("zeros".into(), &[0; 1 << 20], 2048);
+    let b = common::zip([entry]);
+    let (valid, usage) = measure(|| verify(&b));
+    assert!(valid);
+    eprintln!(
+        "verifying 2 GiB: {} bytes peak, {} allocations",
+        usage.peak, usage.allocations
+    );
+
+    let mut g = c.benchmark_group("2 GiB entry");
+    g.sample_size(10);
+    g.bench_function("verify", |x| x.iter(|| verify(&b)));
+    g.finish();
+}
+
+criterion_group!(benches, crc);
+criterion_main!(benches);
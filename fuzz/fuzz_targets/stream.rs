@@ -0,0 +1,25 @@
+//! Decode arbitrary input as tar archive read from a stream, entry by entry.
+
+#![no_main]
+
+use binspan::decode::Val;
+use binspan::tar::{decode_tar_read, Opts};
+use binspan::Limits;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // the stream reads an entry as declared by its header, so the field limit bounds memory
+    let limits = Limits {
+        max_field_bytes: Some(1 << 20),
+        max_decompressed_bytes: Some(1 << 20),
+        max_entries: Some(1 << 10),
+    };
+    let opts = Opts {
+        limits,
+        ..Opts::default()
+    };
+    for entry in decode_tar_read(data, &opts) {
+        let Ok(entry) = entry else { break };
+        Val::Obj(Box::new(entry.file)).eval();
+    }
+});
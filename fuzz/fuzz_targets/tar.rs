@@ -0,0 +1,24 @@
+//! Decode arbitrary input as tar archive, and force all lazy values.
+
+#![no_main]
+
+use binspan::decode::{Obj, Val};
+use binspan::tar::{decode_tar, Opts};
+use binspan::Limits;
+use bytes::Bytes;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let limits = Limits {
+        max_field_bytes: Some(1 << 20),
+        max_decompressed_bytes: Some(1 << 20),
+        max_entries: Some(1 << 10),
+    };
+    let opts = Opts {
+        limits,
+        ..Opts::default()
+    };
+    let mut o = Obj::default();
+    let _ = decode_tar(&mut o, Bytes::copy_from_slice(data), &opts);
+    Val::Obj(Box::new(o)).eval();
+});
@@ -0,0 +1,25 @@
+//! Decode arbitrary input as ZIP archive, and force all lazy values.
+
+#![no_main]
+
+use binspan::decode::{Obj, Val};
+use binspan::zip::{decode_zip, Opts};
+use binspan::Limits;
+use bytes::Bytes;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // small limits keep crafted sizes from exhausting memory instead of failing early
+    let limits = Limits {
+        max_field_bytes: Some(1 << 20),
+        max_decompressed_bytes: Some(1 << 20),
+        max_entries: Some(1 << 10),
+    };
+    let opts = Opts {
+        limits,
+        ..Opts::default()
+    };
+    let mut o = Obj::default();
+    let _ = decode_zip(&mut o, Bytes::copy_from_slice(data), &opts);
+    Val::Obj(Box::new(o)).eval();
+});
@@ -0,0 +1,512 @@
+pub mod cat;
+pub mod config;
+pub mod coverage;
+pub mod decode;
+pub mod diff;
+pub mod entry;
+pub mod error;
+pub mod extract;
+pub mod filter;
+pub mod hex;
+pub mod html;
+pub mod input;
+pub mod jq;
+pub mod json;
+pub mod list;
+pub mod progress;
+pub mod query;
+pub mod scan;
+pub mod serial;
+pub mod stats;
+pub mod tree;
+#[cfg(feature = "tui")]
+pub mod tui;
+pub mod verify;
+
+use binspan::{formats, tar, zip, Format as _};
+use bytes::Bytes;
+use clap::builder::{PossibleValue, PossibleValuesParser, TypedValueParser};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use error::Error;
+use std::path::PathBuf;
+
+/// Decode binary files, keeping track of where every value comes from.
+#[derive(Parser)]
+#[command(version, args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    #[command(flatten)]
+    pub decode: DecodeArgs,
+    /// Print the configuration merged from all configuration files and exit
+    ///
+    /// Configuration is read from `binspan.toml` in the current directory and
+    /// from `binspan/binspan.toml` in the user's configuration directory,
+    /// where the former takes precedence.
+    /// Command-line options take precedence over both.
+    #[arg(long, exclusive = true)]
+    pub dump_config: bool,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Decode a file and print the decoded value (default)
+    Decode(DecodeArgs),
+    /// Write the contents of archive entries to a directory
+    Extract(ExtractArgs),
+    /// List the entries of an archive
+    List(ListArgs),
+    /// Write the bytes of a decoded value
+    Cat(CatArgs),
+    /// Print a hexdump annotated with the decoded fields
+    Hex(HexArgs),
+    /// Compare the decoded values of two files
+    Diff(DiffArgs),
+    /// Report which parts of a file are covered by decoded values
+    Coverage(CoverageArgs),
+    /// Decode all files in a directory and summarise the results
+    Scan(ScanArgs),
+    /// Browse the decoded value interactively
+    #[cfg(feature = "tui")]
+    Tui(TuiArgs),
+    /// Run all integrity checks on a file and report their results
+    Verify(VerifyArgs),
+}
+
+impl Cli {
+    /// Parse the command line, using the configuration files for defaults.
+    pub fn load() -> Result<Self, Error> {
+        use clap::{CommandFactory, FromArgMatches};
+        let cmd = config::load()?.apply(<Self as CommandFactory>::command());
+        Ok(Self::from_arg_matches(&cmd.get_matches()).unwrap_or_else(|e| e.exit()))
+    }
+
+    pub fn command(self) -> Command {
+        self.command.unwrap_or(Command::Decode(self.decode))
+    }
+}
+
+#[derive(Args)]
+pub struct DecodeArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+    #[command(flatten)]
+    pub output: OutputArgs,
+    /// Do not fail on decoding errors, but report them and output the partial result
+    #[arg(long)]
+    pub lenient: bool,
+    /// Output decoding errors as JSON to standard output, together with the value if successful
+    #[arg(long)]
+    pub errors_json: bool,
+    /// Report timings and statistics about the decoded value on standard error
+    #[arg(long)]
+    pub stats: bool,
+    /// Write counters of the format features found in the input as JSON to this file
+    ///
+    /// Features are, for example, compression methods or tar variants.
+    /// When decoding several files, the counters are summed over all files.
+    #[arg(long, value_name = "FILE")]
+    pub stats_json: Option<PathBuf>,
+    /// Write a self-contained HTML report of the decoded value and its checks to this file
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["errors_json", "files"])]
+    pub html: Option<PathBuf>,
+    /// Further files to decode
+    ///
+    /// The output for every file is labelled with its name:
+    /// JSON values are wrapped as `{"file": ..., "value": ...}`, one per line,
+    /// and trees are preceded by a header.
+    #[arg(value_name = "FILE")]
+    pub files: Vec<PathBuf>,
+    /// Stop at the first file that fails, instead of continuing with the other files
+    #[arg(long)]
+    pub fail_fast: bool,
+}
+
+#[derive(Args, Clone)]
+pub struct InputArgs {
+    /// File to decode, or `-` for standard input
+    #[arg(required = true)]
+    pub file: Option<PathBuf>,
+    /// Fail if the input is larger than this
+    #[arg(long, value_name = "BYTES")]
+    pub max_input_size: Option<usize>,
+    /// Format of the input file
+    #[arg(long, default_value = "auto", value_parser = Format::parser())]
+    pub format: Format,
+    /// Accept structures even if their signature does not match
+    #[arg(long)]
+    pub force: bool,
+    /// Continue after ZIP central directory records or tar entries that fail to decode
+    #[arg(long)]
+    pub recover: bool,
+    /// Maximal number of bytes that a single entry may decompress to
+    #[arg(long, value_name = "BYTES")]
+    pub max_uncompressed: Option<usize>,
+    /// Keep up to this many bytes of uncompressed data to reuse it when it is needed again
+    #[arg(long, value_name = "BYTES")]
+    pub cache_size: Option<usize>,
+    /// Only decode archive entries whose name matches this glob (can be given multiple times)
+    #[arg(long, value_name = "PATTERN")]
+    pub include: Vec<String>,
+    /// Do not decode archive entries whose name matches this glob (can be given multiple times)
+    #[arg(long, value_name = "PATTERN")]
+    pub exclude: Vec<String>,
+    /// Interpret `--include` and `--exclude` patterns as regular expressions
+    #[arg(long)]
+    pub regex: bool,
+    /// Do not show progress or notes on standard error
+    #[arg(long, short)]
+    pub quiet: bool,
+    /// Decode ZIP central directory records in parallel
+    #[cfg(feature = "rayon")]
+    #[arg(long)]
+    pub parallel: bool,
+    /// Print every field to standard error as it is decoded, with its offset and length
+    #[arg(long)]
+    pub trace: bool,
+}
+
+impl InputArgs {
+    fn filter(&self) -> Result<Option<binspan::Filter>, Error> {
+        filter::filter(&self.include, &self.exclude, self.regex)
+    }
+
+    pub fn zip_opts(&self) -> Result<zip::Opts, Error> {
+        Ok(zip::Opts {
+            force: self.force || config::get().zip.force == Some(true),
+            limits: self.limits(),
+            filter: self.filter()?,
+            progress: progress::progress("decoding", self.quiet),
+            metrics: None,
+            cache: None,
+            stats: None,
+            #[cfg(feature = "rayon")]
+            parallel: self.parallel,
+            #[cfg(not(feature = "rayon"))]
+            parallel: false,
+            recover: self.recover,
+        })
+    }
+
+    pub fn tar_opts(&self) -> Result<tar::Opts, Error> {
+        Ok(tar::Opts {
+            filter: self.filter()?,
+            progress: progress::progress("decoding", self.quiet),
+            metrics: None,
+            stats: None,
+            recover: self.recover,
+            limits: self.limits(),
+        })
+    }
+
+    fn limits(&self) -> binspan::Limits {
+        let default = binspan::Limits::default();
+        let max = self.max_uncompressed.or(config::get().zip.max_uncompressed);
+        binspan::Limits {
+            max_decompressed_bytes: max.or(default.max_decompressed_bytes),
+            ..default
+        }
+    }
+
+    /// Load the input file and determine its format.
+    pub fn open(&self) -> Result<(Bytes, Format), Error> {
+        let path = self.file.as_ref().expect("file is required");
+        let b = input::load(path, self.max_input_size, self.quiet)?;
+        let format = match self.format {
+            Format::Auto => binspan::sniff(&b)
+                .and_then(Format::from_name)
+                .ok_or_else(|| {
+                    Error::Decode(format!("{}: unknown format, use --format", path.display()))
+                })?,
+            f => f,
+        };
+        Ok((b, format))
+    }
+}
+
+#[derive(Args)]
+pub struct ExtractArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+    /// Directory to extract to
+    #[arg(long, short, value_name = "DIR")]
+    pub out: PathBuf,
+    /// Only extract the entry with this name (can be given multiple times)
+    #[arg(long, value_name = "NAME")]
+    pub entry: Vec<String>,
+    /// Allow entry names that are absolute or contain `..`
+    #[arg(long)]
+    pub unsafe_paths: bool,
+    /// Create symbolic and hard links stored in tar archives
+    #[arg(long)]
+    pub links: bool,
+}
+
+#[derive(Args)]
+pub struct ListArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+    /// Sort entries instead of listing them in archive order
+    #[arg(long, value_enum)]
+    pub sort: Option<Sort>,
+}
+
+#[derive(Args)]
+pub struct CatArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+    /// Path of the value, such as `local_files[0].compressed`
+    #[arg(long)]
+    pub path: String,
+    /// Write to this file instead of standard output
+    #[arg(long, value_name = "FILE")]
+    pub raw_out: Option<PathBuf>,
+    /// Write to standard output even if it is a terminal
+    #[arg(long)]
+    pub force_tty: bool,
+}
+
+#[derive(Args)]
+pub struct HexArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+    /// Only dump the bytes of the value at this path
+    #[arg(long)]
+    pub path: Option<String>,
+    /// Only dump the bytes in this range, such as `0x10..0x40` or `16..`
+    #[arg(long, value_name = "START..END")]
+    pub range: Option<String>,
+    /// Mark field boundaries with `|` instead of colors
+    #[arg(long)]
+    pub no_color: bool,
+    /// Decode lazy values, such as compressed data, to label their contents
+    #[arg(long)]
+    pub eager: bool,
+}
+
+#[derive(Args)]
+pub struct VerifyArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+    /// Skip checks that require uncompressing data
+    #[arg(long)]
+    pub fast: bool,
+}
+
+#[derive(Args)]
+pub struct CoverageArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+    /// Number of largest uncovered regions to show
+    #[arg(long, value_name = "N", default_value_t = 10)]
+    pub top: usize,
+    /// Output the report as JSON
+    #[arg(long)]
+    pub json: bool,
+    /// Indent JSON output
+    #[arg(long, requires = "json")]
+    pub pretty: bool,
+}
+
+#[derive(Args)]
+pub struct DiffArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+    /// File to compare with
+    pub other: PathBuf,
+    /// Match archive entries by name instead of by position
+    #[arg(long)]
+    pub by_name: bool,
+    /// Also show values that are equal but lie at different offsets
+    #[arg(long)]
+    pub show_offsets: bool,
+    /// Output the differences as JSON
+    #[arg(long)]
+    pub json: bool,
+}
+
+#[derive(Args)]
+pub struct ScanArgs {
+    /// Directory to scan recursively
+    pub dir: PathBuf,
+    /// Only decode files whose name matches this glob (can be given multiple times)
+    #[arg(long, value_name = "PATTERN")]
+    pub glob: Vec<String>,
+    /// Output one JSON object per file
+    #[arg(long)]
+    pub jsonl: bool,
+    /// Number of files to decode in parallel (default: number of CPUs)
+    #[arg(long, short)]
+    pub jobs: Option<usize>,
+    /// Fail on files that are larger than this
+    #[arg(long, value_name = "BYTES")]
+    pub max_input_size: Option<usize>,
+    /// Maximal number of bytes that a single entry may decompress to
+    #[arg(long, value_name = "BYTES")]
+    pub max_uncompressed: Option<usize>,
+}
+
+#[cfg(feature = "tui")]
+#[derive(Args)]
+pub struct TuiArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Sort {
+    Name,
+    Size,
+    Mtime,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Encoding {
+    /// Indented tree
+    Tree,
+    /// JSON, like `--json`
+    Json,
+    /// CBOR, with raw and string values as byte strings
+    Cbor,
+    /// MessagePack, with raw and string values as byte strings
+    Msgpack,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Detect the format from the input
+    Auto,
+    Zip,
+    Tar,
+    /// Format registered with [`binspan::formats::register`], by its name
+    Registered(&'static str),
+}
+
+impl Format {
+    /// Format with the given name, where registered formats take precedence over built-in ones.
+    pub fn from_name(name: &str) -> Option<Self> {
+        if let Some(f) = formats::get(name) {
+            return Some(Self::Registered(f.name()));
+        }
+        [Self::Zip, Self::Tar]
+            .into_iter()
+            .find(|f| f.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::Zip => zip::Zip::NAME,
+            Self::Tar => tar::Tar::NAME,
+            Self::Registered(name) => name,
+        }
+    }
+
+    /// Parser of `--format`, which accepts the formats registered when it is created.
+    fn parser() -> impl TypedValueParser<Value = Self> {
+        let auto = PossibleValue::new("auto").help("Detect the format from the input");
+        let names = [Self::Zip.name(), Self::Tar.name()].into_iter();
+        let names = names.chain(formats::names().into_iter().filter(|n| *n != "auto"));
+        let mut values = vec![auto];
+        for name in names {
+            if !values.iter().any(|v| v.matches(name, false)) {
+                values.push(PossibleValue::new(name));
+            }
+        }
+        // only `auto` is not the name of a format
+        PossibleValuesParser::new(values).map(|name| Self::from_name(&name).unwrap_or(Self::Auto))
+    }
+}
+
+#[derive(Args)]
+pub struct OutputArgs {
+    /// Output the decoded value as JSON
+    ///
+    /// Integers above 2^53, such as large ZIP64 sizes, are output exactly,
+    /// but many JSON consumers lose precision when reading them.
+    /// Use `--output cbor` or `--output msgpack` to preserve them.
+    #[arg(long)]
+    pub json: bool,
+    /// Output the decoded value as JSON, wrapping every value with its offset and length
+    ///
+    /// Together with `--output`, wrap values in the given format.
+    #[arg(long, visible_alias = "spans", conflicts_with = "json")]
+    pub json_spans: bool,
+    /// Output the decoded value in this format
+    #[arg(long, value_enum, value_name = "FORMAT", conflicts_with_all = ["json", "filter"])]
+    pub output: Option<Encoding>,
+    /// Indent JSON output
+    #[arg(long, overrides_with = "compact")]
+    pub pretty: bool,
+    /// Do not indent JSON output (default)
+    #[arg(long, overrides_with = "pretty")]
+    pub compact: bool,
+    /// Raw spans longer than this are truncated in JSON output
+    #[arg(long, value_name = "BYTES", default_value_t = 64)]
+    pub max_raw_bytes: usize,
+    /// Include the SHA-256 of truncated raw spans in JSON output
+    #[arg(long)]
+    pub sha256: bool,
+    /// Do not output values nested deeper than this
+    #[arg(long, visible_alias = "max-depth")]
+    pub depth: Option<usize>,
+    /// Show at most this many elements of each array in tree output
+    #[arg(long, value_name = "N", default_value_t = 20)]
+    pub max_items: usize,
+    /// Evaluate lazy values, such as uncompressed data, instead of only showing their length
+    #[arg(long)]
+    pub eager: bool,
+    /// Only output the value at this path, such as `local_files[3].file_name`
+    ///
+    /// If given multiple times, output an array of values.
+    #[arg(long)]
+    pub path: Vec<String>,
+    /// Run this jq filter on the decoded value and output its results as JSON
+    ///
+    /// The filter `@span` yields the offset and length of a value from the input.
+    #[arg(long, conflicts_with_all = ["path", "json_spans"])]
+    pub filter: Option<String>,
+    /// Show the values that cover the byte at this offset, such as `0x1f3a`
+    ///
+    /// Values are listed from the outermost to the innermost,
+    /// including data that was derived from the bytes at the offset, such as uncompressed data.
+    #[arg(long, value_name = "OFFSET", value_parser = query::parse_offset,
+          conflicts_with_all = ["path", "filter", "output"])]
+    pub at: Option<usize>,
+}
+
+impl OutputArgs {
+    pub fn tree_opts(&self) -> tree::Opts {
+        tree::Opts {
+            color: tree::color(),
+            max_depth: self.depth,
+            max_items: self.max_items,
+            eager: self.eager,
+        }
+    }
+
+    /// Return true if JSON output should be indented.
+    ///
+    /// `--compact` wins over `--pretty` given as default in the configuration.
+    pub fn pretty(&self) -> bool {
+        self.pretty && !self.compact
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        match self.output {
+            Some(e) => e,
+            None if self.json || self.json_spans => Encoding::Json,
+            None => Encoding::Tree,
+        }
+    }
+
+    pub fn json_opts(&self) -> json::Opts {
+        json::Opts {
+            spans: self.json_spans,
+            max_raw_bytes: self.max_raw_bytes,
+            sha256: self.sha256,
+            depth: self.depth,
+            eager: self.eager,
+        }
+    }
+}
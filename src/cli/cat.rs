@@ -0,0 +1,30 @@
+use super::error::Error;
+use super::{decode, query, CatArgs};
+use binspan::decode::Val;
+use std::io::{IsTerminal, Write};
+use std::process::ExitCode;
+
+pub fn run(args: CatArgs) -> Result<ExitCode, Error> {
+    let (b, o, _) = decode::decode(&args.input)?;
+    let root = binspan::decode::Meta::from(&b);
+    let found = query::query(&o, &root, core::slice::from_ref(&args.path))?;
+    let (_, m, v) = found[0];
+    // values such as uncompressed data hold other bytes than those they were decoded from
+    let bytes = match v.force() {
+        Val::Bytes(b) => (**b).clone(),
+        _ => m.bytes(),
+    };
+    if let Some(path) = &args.raw_out {
+        std::fs::write(path, bytes).map_err(|e| Error::Io(format!("{}: {e}", path.display())))?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    let mut stdout = std::io::stdout().lock();
+    if stdout.is_terminal() && !args.force_tty {
+        let msg = "refusing to write binary data to a terminal, use --force-tty or --raw-out";
+        return Err(Error::Usage(msg.into()));
+    }
+    stdout
+        .write_all(&bytes)
+        .map_err(|e| Error::Io(e.to_string()))?;
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,159 @@
+use super::error::Error;
+use clap::Command;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::sync::OnceLock;
+
+/// Name of the configuration file.
+const FILE: &str = "binspan.toml";
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Defaults for command-line options, read from configuration files.
+#[derive(Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Config {
+    /// Color output, detected from the terminal if not given.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lenient: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quiet: Option<bool>,
+    /// Format of input files, such as `zip`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<String>,
+    /// Output format of `decode`, such as `json`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pretty: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_input_size: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_uncompressed: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_raw_bytes: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_items: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub depth: Option<usize>,
+    /// Options that apply only to ZIP archives.
+    pub zip: Zip,
+}
+
+#[derive(Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Zip {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub force: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_uncompressed: Option<usize>,
+}
+
+/// Paths of the configuration files, from highest to lowest priority.
+fn paths() -> Vec<PathBuf> {
+    let xdg = std::env::var_os("XDG_CONFIG_HOME").map(PathBuf::from);
+    let home = std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".config"));
+    let dir = xdg.filter(|p| p.is_absolute()).or(home);
+    let user = dir.map(|d| d.join("binspan").join(FILE));
+    [Some(PathBuf::from(FILE)), user]
+        .into_iter()
+        .flatten()
+        .collect()
+}
+
+/// Add the entries of `low` to `high` that are not in `high`, merging tables.
+fn merge(high: &mut toml::Table, low: toml::Table) {
+    for (k, v) in low {
+        match (high.get_mut(&k), v) {
+            (Some(toml::Value::Table(h)), toml::Value::Table(l)) => merge(h, l),
+            (Some(_), _) => (),
+            (None, v) => {
+                high.insert(k, v);
+            }
+        }
+    }
+}
+
+fn read() -> Result<Config, Error> {
+    let mut table = toml::Table::new();
+    for path in paths() {
+        let s = match std::fs::read_to_string(&path) {
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            r => r.map_err(|e| Error::Io(format!("{}: {e}", path.display())))?,
+        };
+        let t = s
+            .parse()
+            .map_err(|e| Error::Usage(format!("{}: {e}", path.display())))?;
+        merge(&mut table, t);
+    }
+    let warn = |key: serde_ignored::Path| eprintln!("warning: unknown configuration key `{key}`");
+    serde_ignored::deserialize(toml::Value::Table(table), warn)
+        .map_err(|e| Error::Usage(format!("invalid configuration: {e}")))
+}
+
+/// Read the configuration files and make the configuration available via [`get`].
+pub fn load() -> Result<&'static Config, Error> {
+    let config = read()?;
+    Ok(CONFIG.get_or_init(|| config))
+}
+
+/// Return the configuration, which is empty if [`load`] was not called.
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
+
+impl Config {
+    /// Default values of command-line arguments, by argument ID.
+    fn defaults(&self) -> Vec<(&'static str, String)> {
+        let string = |s: &Option<String>| s.clone();
+        let show = |x: Option<usize>| x.map(|x| x.to_string());
+        let flag = |b: Option<bool>| b.map(|b| b.to_string());
+        let defaults = [
+            ("lenient", flag(self.lenient)),
+            ("quiet", flag(self.quiet)),
+            ("format", string(&self.format)),
+            ("output", string(&self.output)),
+            ("pretty", flag(self.pretty)),
+            ("sha256", flag(self.sha256)),
+            ("max_input_size", show(self.max_input_size)),
+            ("max_uncompressed", show(self.max_uncompressed)),
+            ("max_raw_bytes", show(self.max_raw_bytes)),
+            ("max_items", show(self.max_items)),
+            ("depth", show(self.depth)),
+        ];
+        let defaults = defaults.into_iter();
+        defaults.filter_map(|(id, v)| Some((id, v?))).collect()
+    }
+
+    /// Use the configured values as defaults for the arguments of `cmd` and its subcommands.
+    pub fn apply(&self, cmd: Command) -> Command {
+        fn go(cmd: Command, defaults: &[(&str, &'static str)]) -> Command {
+            let cmd = cmd.mut_args(
+                |a| match defaults.iter().find(|(id, _)| a.get_id() == *id) {
+                    Some((_, v)) => a.default_value(*v),
+                    None => a,
+                },
+            );
+            cmd.mut_subcommands(|s| go(s, defaults))
+        }
+        // the values live until the end of the program, so we can leak them
+        let defaults: Vec<_> = self
+            .defaults()
+            .into_iter()
+            .map(|(id, v)| (id, &*v.leak()))
+            .collect();
+        go(cmd, &defaults)
+    }
+}
+
+/// Print the configuration, merged from all configuration files.
+pub fn dump() -> Result<ExitCode, Error> {
+    let s = toml::to_string(get()).map_err(|e| Error::Usage(e.to_string()))?;
+    print!("{s}");
+    Ok(ExitCode::SUCCESS)
+}
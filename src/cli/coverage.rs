@@ -0,0 +1,74 @@
+use super::error::Error;
+use super::{decode, json, CoverageArgs};
+use binspan::decode::{coverage, Coverage, Path};
+use core::ops::Range;
+use serde_json::json;
+use std::process::ExitCode;
+
+/// Number of bytes previewed of uncovered regions.
+const PREVIEW: usize = 16;
+
+fn hex(b: &[u8]) -> String {
+    let hex: Vec<_> = b.iter().map(|c| format!("{c:02x}")).collect();
+    hex.join(" ")
+}
+
+pub fn run(args: CoverageArgs) -> Result<ExitCode, Error> {
+    let (b, o, _) = decode::decode(&args.input)?;
+    let Coverage {
+        covered,
+        mut uncovered,
+        overlaps,
+    } = coverage(&o, &b);
+    let percent = (covered as f64 * 100.0) / (b.len().max(1) as f64);
+    uncovered.sort_by_key(|r| (core::cmp::Reverse(r.len()), r.start));
+    let largest = &uncovered[..uncovered.len().min(args.top)];
+    let preview = |r: &Range<usize>| hex(&b[r.start..r.end.min(r.start + PREVIEW)]);
+
+    if args.json {
+        let regions: Vec<_> = largest
+            .iter()
+            .map(|r| json!({"offset": r.start, "len": r.len(), "preview": preview(r)}))
+            .collect();
+        let overlaps: Vec<_> = overlaps
+            .iter()
+            .map(|o| {
+                let paths = o.paths.each_ref().map(|p| Path(p).to_string());
+                json!({"offset": o.range.start, "len": o.range.len(), "paths": paths})
+            })
+            .collect();
+        let report = json!({
+            "size": b.len(),
+            "covered": covered,
+            "uncovered_regions": uncovered.len(),
+            "largest_uncovered": regions,
+            "overlaps": overlaps,
+        });
+        json::print(&report, args.pretty)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    println!("covered: {covered} of {} bytes ({percent:.2}%)", b.len());
+    if !largest.is_empty() {
+        println!("largest uncovered regions ({} in total):", uncovered.len());
+    }
+    for r in largest {
+        let more = if r.len() > PREVIEW { " …" } else { "" };
+        println!(
+            "  {:#010x}..{:#010x}  {:>8} bytes  {}{more}",
+            r.start,
+            r.end,
+            r.len(),
+            preview(r)
+        );
+    }
+    if !overlaps.is_empty() {
+        println!("overlapping spans:");
+    }
+    for o in &overlaps {
+        let [a, b] = o.paths.each_ref().map(|p| Path(p).to_string());
+        let (start, end) = (o.range.start, o.range.end);
+        println!("  {start:#010x}..{end:#010x}  {a} and {b}");
+    }
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,316 @@
+use super::error::{self, Error};
+use super::{
+    html, jq, json, progress, query, serial, stats, tree, verify, DecodeArgs, Encoding, Format,
+    InputArgs,
+};
+use binspan::decode::{self, Decoded, Meta, Val};
+use binspan::trace::{self, StderrTracer};
+use binspan::{formats, tar, zip, Cache, Metrics, Stats};
+use bytes::Bytes;
+use serde_json::json;
+use std::cell::RefCell;
+use std::process::ExitCode;
+use std::rc::Rc;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Decode the input, returning the input, the decoded value, and whether decoding succeeded.
+///
+/// The decoded value contains everything that was decoded before an eventual error.
+pub fn decode(input: &InputArgs) -> Result<(Bytes, Val, decode::Result), Error> {
+    decode_with(input, None, None, None)
+}
+
+/// Like [`decode`], but take measurements with `metrics`, cache uncompressed data in `cache`,
+/// and count the features of the input in `stats`.
+pub fn decode_with(
+    input: &InputArgs,
+    metrics: Option<Arc<Metrics>>,
+    cache: Option<Arc<Cache>>,
+    stats: Option<Arc<Stats>>,
+) -> Result<(Bytes, Val, decode::Result), Error> {
+    let (b, format) = input.open()?;
+    let mut o = decode::Obj::default();
+
+    let name = Bytes::from_static(format.name().as_bytes());
+    let m = decode::with_input(&b, || Meta::from(b.slice(..0)));
+    let name = Decoded::new(m, Val::Str(Box::new(name)), ());
+    o.add("format", Ok(name))
+        .expect("adding a decoded value succeeds");
+    let decode_format = || -> Result<decode::Result, Error> {
+        match format {
+            Format::Tar => {
+                let opts = tar::Opts {
+                    metrics,
+                    stats,
+                    ..input.tar_opts()?
+                };
+                Ok(tar::decode_tar(&mut o, b.clone(), &opts))
+            }
+            Format::Zip | Format::Auto => {
+                let opts = zip::Opts {
+                    metrics,
+                    cache,
+                    stats,
+                    ..input.zip_opts()?
+                };
+                Ok(zip::decode_zip(&mut o, b.clone(), &opts))
+            }
+            Format::Registered(name) => {
+                let format = formats::get(name)
+                    .ok_or_else(|| Error::Usage(format!("format {name} is not registered")))?;
+                Ok(format.decode(&mut o, b.clone()))
+            }
+        }
+    };
+    // formats that implement `DynFormat` themselves need not call `with_input`
+    let decode_format = || decode::with_input(&b, decode_format);
+    let r = match input.trace {
+        true => {
+            let tracer = Rc::new(RefCell::new(StderrTracer { root: b.clone() }));
+            trace::observe(tracer, decode_format)
+        }
+        false => decode_format(),
+    }?;
+    Ok((b, Val::Obj(Box::new(o)), r))
+}
+
+pub fn run(args: DecodeArgs) -> Result<ExitCode, Error> {
+    let stats = args.stats_json.as_ref().map(|_| Arc::new(Stats::default()));
+    let code = run_files(&args, stats.clone());
+    if let (Some(path), Some(stats)) = (&args.stats_json, stats) {
+        let json = serde_json::to_string(&stats.counts()).map_err(|e| e.to_string())?;
+        std::fs::write(path, json).map_err(|e| Error::Io(format!("{}: {e}", path.display())))?;
+    }
+    code
+}
+
+fn run_files(args: &DecodeArgs, stats: Option<Arc<Stats>>) -> Result<ExitCode, Error> {
+    if args.files.is_empty() {
+        return run_file(args, &args.input, None, stats);
+    }
+    let files = args.input.file.iter().chain(&args.files);
+    let mut code = ExitCode::SUCCESS;
+    for file in files {
+        let name = file.display().to_string();
+        let mut input = args.input.clone();
+        input.file = Some(file.clone());
+        let result = run_file(args, &input, Some(&name), stats.clone()).unwrap_or_else(|e| {
+            match e {
+                // I/O errors already mention the file
+                Error::Io(_) => eprintln!("error: {e}"),
+                _ => eprintln!("error: {name}: {e}"),
+            }
+            e.exit_code()
+        });
+        if result != ExitCode::SUCCESS {
+            if args.fail_fast {
+                return Ok(result);
+            } else if code == ExitCode::SUCCESS {
+                code = result
+            }
+        }
+    }
+    Ok(code)
+}
+
+/// Decode the file given by `input` and print its output, labelled with `label` if given.
+fn run_file(
+    args: &DecodeArgs,
+    input: &InputArgs,
+    label: Option<&str>,
+    stats: Option<Arc<Stats>>,
+) -> Result<ExitCode, Error> {
+    let start = Instant::now();
+    let alloc = args.stats.then(stats::alloc_start).flatten();
+    let metrics = args.stats.then(|| Arc::new(Metrics::default()));
+    let cache = input.cache_size.map(|max| Arc::new(Cache::new(max)));
+    let (b, o, r) = decode_with(input, metrics.clone(), cache.clone(), stats)?;
+    let code = output(args, label, &b, &o, r);
+    if let Some(metrics) = metrics {
+        let report = stats::report(&b, &o, &metrics, cache.as_deref(), start.elapsed(), alloc);
+        eprint!("{report}");
+    }
+    code
+}
+
+fn output(
+    args: &DecodeArgs,
+    label: Option<&str>,
+    b: &Bytes,
+    o: &Val,
+    r: decode::Result,
+) -> Result<ExitCode, Error> {
+    let output = &args.output;
+    let root = Meta::from(b.clone());
+    let opts = output.json_opts();
+    let encoding = output.encoding();
+    let print_json = |y| match label {
+        Some(file) => json::print(&json!({"file": file, "value": y}), output.pretty()),
+        None => json::print(&y, output.pretty()),
+    };
+    let header = || {
+        if let Some(file) = label {
+            println!("==> {file} <==")
+        }
+    };
+    if let Some(path) = &args.html {
+        let name = args.input.file.as_ref().expect("file is required");
+        let progress = progress::progress("verifying", args.input.quiet);
+        let limit = args.input.limits().decompressed();
+        let checks = verify::checks(b, o, &r, false, limit, progress);
+        let report = html::render(
+            &name.display().to_string(),
+            b,
+            o,
+            &r,
+            &checks,
+            output.max_items,
+        );
+        std::fs::write(path, report).map_err(|e| Error::Io(format!("{}: {e}", path.display())))?;
+        if !args.lenient {
+            r.map_err(|e| Error::decode(b, &e))?;
+        }
+        return Ok(ExitCode::SUCCESS);
+    }
+    if let Err(e) = &r {
+        if !args.lenient && !args.errors_json {
+            return Err(Error::decode(b, e));
+        }
+    }
+    if args.errors_json {
+        let errors: Vec<_> = r
+            .as_ref()
+            .err()
+            .into_iter()
+            .map(|e| {
+                let offset = e.offset();
+                let context: Vec<_> = e.context().iter().rev().collect();
+                json!({"path": error::path(e), "context": context, "offset": offset, "message": e.msg()})
+            })
+            .collect();
+        let mut report = json!({ "errors": errors });
+        if r.is_ok() || args.lenient {
+            report["value"] = json::val(b, &root, o, &opts);
+        }
+        print_json(report)?;
+    } else if let Some(filter) = &output.filter {
+        for y in jq::run(filter, b, o.clone(), opts)? {
+            print_json(y)?;
+        }
+    } else if let Some(offset) = output.at {
+        let covering = query::at(b, &root, o, offset)?;
+        if encoding == Encoding::Json {
+            print_json(query::json_at(b, &covering, &opts))?;
+        } else {
+            header();
+            print!("{}", query::render_at(b, &covering));
+        }
+    } else if output.path.is_empty() {
+        match encoding {
+            Encoding::Tree => {
+                header();
+                print!("{}", tree::render(b, o, &output.tree_opts()));
+                for w in o.warnings(b) {
+                    eprintln!("warning: {w}");
+                }
+            }
+            Encoding::Json => print_json(json::val(b, &root, o, &opts))?,
+            e => serial::print(&serial::Ser::new(b, &root, o, &opts), label, e)?,
+        }
+    } else {
+        let found = query::query(o, &root, &output.path)?;
+        if encoding == Encoding::Json {
+            let f = |(path, m, v): (_, &Meta, _)| {
+                let offset = m.offset_in(b).map(|r| r.start);
+                let value = json::val(b, m, v, &opts);
+                json!({"path": path, "offset": offset, "len": m.bytes().len(), "value": value})
+            };
+            let mut results: Vec<_> = found.into_iter().map(f).collect();
+            let results = match results.len() {
+                1 => results.remove(0),
+                _ => results.into(),
+            };
+            print_json(results)?;
+        } else if encoding != Encoding::Tree {
+            let f = |(path, m, v)| serial::Found {
+                path,
+                ser: serial::Ser::new(b, m, v, &opts),
+            };
+            let mut results: Vec<_> = found.into_iter().map(f).collect();
+            match results.len() {
+                1 => serial::print(&results.remove(0), label, encoding)?,
+                _ => serial::print(&results, label, encoding)?,
+            }
+        } else {
+            header();
+            let opts = output.tree_opts();
+            for (path, m, v) in found {
+                print!("{}", tree::render_node(b, path, m, v, &opts));
+            }
+        }
+    }
+    Ok(match r {
+        Ok(()) => ExitCode::SUCCESS,
+        // only reached with `--errors-json`, which already reported the error
+        Err(e) if !args.lenient => Error::decode(b, &e).exit_code(),
+        Err(e) => {
+            if !args.errors_json {
+                let file = label.map_or(String::new(), |f| format!("{f}: "));
+                eprintln!("error: {file}{}", Error::decode(b, &e));
+            }
+            ExitCode::SUCCESS
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::Cli;
+    use binspan::decode::{le, raw, Obj};
+    use binspan::formats::DynFormat;
+    use clap::Parser;
+
+    /// Format that starts with `TOY` and a version byte.
+    struct Toy;
+
+    impl DynFormat for Toy {
+        fn name(&self) -> &'static str {
+            "toy"
+        }
+
+        fn sniff(&self, b: &[u8]) -> bool {
+            b.starts_with(b"TOY")
+        }
+
+        fn decode(&self, o: &mut Obj, mut b: Bytes) -> decode::Result {
+            o.add("magic", raw(&mut b, 3))?;
+            o.add("version", le::u8(&mut b))?;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn registered_format() {
+        let path = std::env::temp_dir().join(format!("binspan-toy-{}", std::process::id()));
+        std::fs::write(&path, b"TOY\x02").unwrap();
+        let parse = |args: &[&str]| {
+            let file = path.to_str().unwrap();
+            Cli::try_parse_from(["binspan"].iter().chain(args).chain([&file]))
+        };
+        assert!(parse(&["--format", "toy"]).is_err());
+
+        formats::register(Box::new(Toy));
+        for args in [&["--format", "toy"][..], &[]] {
+            let cli = parse(args).unwrap();
+            let Ok((_, v, Ok(()))) = decode(&cli.decode.input) else {
+                panic!("decoding failed");
+            };
+            assert!(matches!(v.query("format"), Ok(Val::Str(s)) if **s == b"toy"[..]));
+            assert!(matches!(v.query("version"), Ok(Val::U8(2))));
+        }
+        formats::unregister("toy");
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,149 @@
+use super::error::Error;
+use super::{decode, list, DiffArgs, Format};
+use binspan::decode::{self as d, Diff, DiffKind, Index, Meta, Path, Val};
+use bytes::Bytes;
+use std::process::ExitCode;
+
+/// Arrays of archive entries, together with the key that holds the name of an entry.
+const ENTRIES: &[(&str, &str)] = &[
+    ("central_directories", "file_name"),
+    ("local_files", "file_name"),
+    ("files", "name"),
+];
+
+/// Entries of an array, keyed by name.
+fn named<'a>(a: &'a Val, name: &'static str) -> Vec<(String, &'a Meta, &'a Val)> {
+    let Val::Arr(a) = a.force() else {
+        return Vec::new();
+    };
+    let name = |v: &Val| {
+        let (m, _) = v.get(&Index::Str(name.into()))?;
+        Some(list::escape(&m.bytes()))
+    };
+    let f = |(m, v): (&'a Meta, &'a Val)| Some((name(v)?, m, v));
+    a.iter().filter_map(f).collect()
+}
+
+/// Compare the entries of two arrays by name instead of by position.
+fn diff_named(
+    key: &'static str,
+    name: &'static str,
+    a: (&Bytes, &Val),
+    b: (&Bytes, &Val),
+) -> Vec<Diff> {
+    let (na, nb) = (named(a.1, name), named(b.1, name));
+    let mut diffs = Vec::new();
+    let path = |n: &str| vec![Index::Str(key.into()), Index::Str(n.to_string().into())];
+    for (n, _, va) in &na {
+        match nb.iter().find(|(n_, ..)| n_ == n) {
+            Some((_, _, vb)) => {
+                for mut diff in d::diff_moved((a.0, va), (b.0, vb)) {
+                    diff.path.splice(..0, path(n));
+                    diffs.push(diff);
+                }
+            }
+            None => diffs.push(Diff {
+                path: path(n),
+                kind: DiffKind::Removed,
+                old: Some("entry".into()),
+                new: None,
+            }),
+        }
+    }
+    for (n, ..) in nb
+        .iter()
+        .filter(|(n, ..)| !na.iter().any(|(n_, ..)| n_ == n))
+    {
+        diffs.push(Diff {
+            path: path(n),
+            kind: DiffKind::Added,
+            old: None,
+            new: Some("entry".into()),
+        });
+    }
+    diffs
+}
+
+pub fn run(args: DiffArgs) -> Result<ExitCode, Error> {
+    let mut other = args.input.clone();
+    other.file = Some(args.other.clone());
+    let (ba, a, ra) = decode::decode(&args.input)?;
+    let (bb, b, rb) = decode::decode(&other)?;
+    ra.map_err(|e| Error::decode(&ba, &e))?;
+    rb.map_err(|e| Error::decode(&bb, &e))?;
+
+    if matches!(args.input.format, Format::Auto) {
+        let name = |v: &Val| match v.get(&Index::Str("format".into())) {
+            Some((_, Val::Str(s))) => String::from_utf8_lossy(s).into_owned(),
+            _ => String::new(),
+        };
+        let (fa, fb) = (name(&a), name(&b));
+        if fa != fb {
+            return Err(Error::Usage(format!(
+                "inputs have different formats ({fa} and {fb}), use --format"
+            )));
+        }
+    }
+
+    let mut diffs = d::diff_moved((&ba, &a), (&bb, &b));
+    if args.by_name {
+        let by_name = |d: &Diff| {
+            ENTRIES
+                .iter()
+                .any(|(k, _)| matches!(d.path.first(), Some(Index::Str(k_)) if k_ == k))
+        };
+        diffs.retain(|d| !by_name(d));
+        for (k, name) in ENTRIES {
+            let key = Index::Str((*k).into());
+            if let (Some((_, va)), Some((_, vb))) = (a.get(&key), b.get(&key)) {
+                diffs.extend(diff_named(k, name, (&ba, va), (&bb, vb)));
+            }
+        }
+    }
+    if !args.show_offsets {
+        diffs.retain(|d| d.kind != DiffKind::Moved);
+    }
+
+    let count = |kind| diffs.iter().filter(|d| d.kind == kind).count();
+    let kinds = [
+        ("changed", DiffKind::Changed),
+        ("added", DiffKind::Added),
+        ("removed", DiffKind::Removed),
+        ("moved", DiffKind::Moved),
+    ];
+    if args.json {
+        let f = |d: &Diff| {
+            let kind = format!("{:?}", d.kind).to_lowercase();
+            let path = Path(&d.path).to_string();
+            serde_json::json!({"path": path, "kind": kind, "old": d.old, "new": d.new})
+        };
+        let mut summary = serde_json::Map::new();
+        for (name, kind) in kinds {
+            summary.insert(name.into(), count(kind).into());
+        }
+        let diffs: Vec<_> = diffs.iter().map(f).collect();
+        let json = serde_json::json!({"diffs": diffs, "summary": summary});
+        super::json::print(&json, false)?;
+    } else {
+        for d in &diffs {
+            let path = Path(&d.path);
+            let (old, new) = (
+                d.old.as_deref().unwrap_or(""),
+                d.new.as_deref().unwrap_or(""),
+            );
+            match d.kind {
+                DiffKind::Changed => println!("{path}: {old} -> {new}"),
+                DiffKind::Added => println!("+ {path}: {new}"),
+                DiffKind::Removed => println!("- {path}: {old}"),
+                DiffKind::Moved => println!("{path}: moved from {old} to {new}"),
+            }
+        }
+        let summary: Vec<_> = kinds
+            .into_iter()
+            .filter(|(_, kind)| args.show_offsets || *kind != DiffKind::Moved)
+            .map(|(name, kind)| format!("{} {name}", count(kind)))
+            .collect();
+        println!("{}", summary.join(", "));
+    }
+    Ok(ExitCode::SUCCESS)
+}
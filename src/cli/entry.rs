@@ -0,0 +1,140 @@
+use super::error::Error;
+use super::{Format, InputArgs};
+use binspan::{tar, zip};
+use bytes::Bytes;
+
+const S_IFMT: u32 = 0o170000;
+const S_IFDIR: u32 = 0o040000;
+const S_IFLNK: u32 = 0o120000;
+
+pub enum Kind {
+    File,
+    Dir,
+    Symlink,
+    Hardlink,
+    Other(u8),
+}
+
+enum Source {
+    Zip { entry: zip::Entry, limit: usize },
+    Tar { data: Bytes, linkname: Bytes },
+}
+
+/// Archive entry, independent of the archive format.
+pub struct Entry {
+    pub name: Bytes,
+    pub kind: Kind,
+    /// Unix file mode, including the file type bits.
+    pub mode: Option<u32>,
+    /// Modification time in seconds since the Unix epoch.
+    pub mtime: i64,
+    /// Size of the uncompressed data.
+    pub size: u64,
+    pub compressed_size: Option<u64>,
+    /// Expected CRC-32 of the data.
+    pub crc_32: Option<u32>,
+    source: Source,
+}
+
+impl Entry {
+    fn from_zip(e: zip::Entry, limit: usize) -> Self {
+        let mode = e.unix_mode();
+        let kind = match mode {
+            _ if e.is_dir() => Kind::Dir,
+            Some(m) if m & S_IFMT == S_IFLNK => Kind::Symlink,
+            _ => Kind::File,
+        };
+        Self {
+            name: e.name.clone(),
+            kind,
+            mode,
+            mtime: e.mtime(),
+            size: e.uncompressed_size,
+            compressed_size: Some(e.compressed_size),
+            crc_32: Some(e.crc_32),
+            source: Source::Zip { entry: e, limit },
+        }
+    }
+
+    fn from_tar(e: tar::Entry) -> Self {
+        let kind = match e.typeflag {
+            b'0' | b'\0' | b'7' => Kind::File,
+            b'1' => Kind::Hardlink,
+            b'2' => Kind::Symlink,
+            b'5' => Kind::Dir,
+            c => Kind::Other(c),
+        };
+        Self {
+            name: e.name,
+            kind,
+            mode: Some(e.mode),
+            mtime: e.mtime.try_into().unwrap_or(i64::MAX),
+            size: e.data.len() as u64,
+            compressed_size: None,
+            crc_32: None,
+            source: Source::Tar {
+                data: e.data,
+                linkname: e.linkname,
+            },
+        }
+    }
+
+    /// Uncompressed data.
+    pub fn data(&self) -> Result<Bytes, String> {
+        match &self.source {
+            Source::Zip { entry, limit } => entry.uncompress(*limit).ok_or_else(|| {
+                let method = entry.compression_method;
+                format!("cannot uncompress (compression method {method}, limit {limit} bytes)")
+            }),
+            Source::Tar { data, .. } => Ok(data.clone()),
+        }
+    }
+
+    /// Target of a symbolic or hard link.
+    pub fn link_target(&self) -> Result<Bytes, String> {
+        match &self.source {
+            Source::Zip { .. } => self.data(),
+            Source::Tar { linkname, .. } => Ok(linkname.clone()),
+        }
+    }
+
+    /// Unix file mode, guessed from the entry kind if the archive does not store one.
+    pub fn unix_mode(&self) -> u32 {
+        let typ = match self.kind {
+            Kind::Dir => S_IFDIR,
+            Kind::Symlink => S_IFLNK,
+            _ => 0o100000,
+        };
+        let perm = if matches!(self.kind, Kind::Dir) {
+            0o755
+        } else {
+            0o644
+        };
+        match self.mode {
+            Some(m) if m & S_IFMT == 0 => typ | m,
+            Some(m) => m,
+            None => typ | perm,
+        }
+    }
+}
+
+/// Load the entries of an archive, without uncompressing them.
+pub fn load(input: &InputArgs) -> Result<Vec<Entry>, Error> {
+    let (b, format) = input.open()?;
+    let err = |e| Error::decode(&b, &e);
+    Ok(match format {
+        Format::Tar => {
+            let entries = tar::entries(b.clone(), &input.tar_opts()?).map_err(err)?;
+            entries.into_iter().map(Entry::from_tar).collect()
+        }
+        Format::Zip | Format::Auto => {
+            let entries = zip::entries(b.clone(), &input.zip_opts()?).map_err(err)?;
+            let limit = input.max_uncompressed.unwrap_or(usize::MAX);
+            let f = |e| Entry::from_zip(e, limit);
+            entries.into_iter().map(f).collect()
+        }
+        Format::Registered(name) => {
+            return Err(Error::Usage(format!("format {name} has no entries")));
+        }
+    })
+}
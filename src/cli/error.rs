@@ -0,0 +1,72 @@
+use binspan::decode::{self, Path};
+use bytes::Bytes;
+use core::fmt::{self, Display, Formatter};
+use std::process::ExitCode;
+
+/// Error that aborts a command.
+pub enum Error {
+    /// The input could not be decoded.
+    Decode(String),
+    /// The command line is invalid.
+    Usage(String),
+    /// Reading or writing a file failed.
+    Io(String),
+}
+
+impl Error {
+    /// Render a decoding error together with the input bytes around it.
+    pub fn decode(root: &Bytes, e: &decode::Error) -> Self {
+        let mut s = e.to_string();
+        if let Some(offset) = e.offset().filter(|o| *o <= root.len()) {
+            s += &format!("\n{}", context(root, offset));
+        }
+        Self::Decode(s)
+    }
+
+    pub fn exit_code(&self) -> ExitCode {
+        ExitCode::from(match self {
+            Self::Decode(_) => 1,
+            Self::Usage(_) => 2,
+            Self::Io(_) => 3,
+        })
+    }
+}
+
+impl From<String> for Error {
+    fn from(s: String) -> Self {
+        Self::Usage(s)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Self::Decode(s) | Self::Usage(s) | Self::Io(s) => s.fmt(f),
+        }
+    }
+}
+
+/// Path of the error, starting from the root.
+pub fn path(e: &decode::Error) -> String {
+    let path: Vec<_> = e.path().iter().rev().cloned().collect();
+    Path(&path).to_string()
+}
+
+/// Hexdump of the lines around `offset`, with the byte at `offset` marked.
+fn context(root: &[u8], offset: usize) -> String {
+    const WIDTH: usize = 16;
+    let line = offset - offset % WIDTH;
+    let start = line.saturating_sub(WIDTH);
+    let end = (line + 2 * WIDTH).min(root.len()).max(line + 1);
+    let mut s = String::new();
+    for l in (start..end).step_by(WIDTH) {
+        let bytes = root.get(l..root.len().min(l + WIDTH)).unwrap_or_default();
+        let hex: Vec<_> = bytes.iter().map(|c| format!("{c:02x}")).collect();
+        s += &format!("  {l:08x}  {}\n", hex.join(" "));
+        if l == line {
+            s += &format!("  {}^^\n", " ".repeat(10 + 3 * (offset - line)));
+        }
+    }
+    s.pop();
+    s
+}
@@ -0,0 +1,209 @@
+use super::entry::{self, Entry, Kind};
+use super::error::Error;
+use super::ExtractArgs;
+use std::collections::HashSet;
+use std::path::{Component, Path, PathBuf};
+use std::process::ExitCode;
+use std::time::{Duration, SystemTime};
+
+/// Convert an entry name to a relative path.
+///
+/// Unless `unsafe_paths` is set, refuse names that could escape the output directory.
+fn sanitise(name: &[u8], unsafe_paths: bool) -> Result<PathBuf, String> {
+    let path = PathBuf::from(String::from_utf8_lossy(name).into_owned());
+    let safe = |c| matches!(c, Component::Normal(_) | Component::CurDir);
+    if unsafe_paths || path.components().all(safe) {
+        Ok(path)
+    } else {
+        Err("unsafe path, use --unsafe-paths to extract anyway".into())
+    }
+}
+
+/// Path of `rel` without `.` components, to compare it with other such paths.
+fn normal(rel: &Path) -> PathBuf {
+    rel.components()
+        .filter(|c| !matches!(c, Component::CurDir))
+        .collect()
+}
+
+/// Refuse `rel` if it or one of its parents is a symbolic link made by this extraction,
+/// so that entries are not written through links to places outside the output directory.
+fn check_links(out: &Path, rel: &Path, made: &HashSet<PathBuf>) -> Result<(), String> {
+    let mut prefix = PathBuf::new();
+    for c in normal(rel).components() {
+        prefix.push(c);
+        let meta = || std::fs::symlink_metadata(out.join(&prefix));
+        if made.contains(&prefix) && meta().is_ok_and(|m| m.file_type().is_symlink()) {
+            let prefix = prefix.display();
+            return Err(format!("path leads through the extracted link {prefix}"));
+        }
+    }
+    Ok(())
+}
+
+fn set_metadata(path: &Path, mode: Option<u32>, mtime: i64) -> std::io::Result<()> {
+    let secs = Duration::from_secs(mtime.unsigned_abs());
+    let time = if mtime >= 0 {
+        SystemTime::UNIX_EPOCH.checked_add(secs)
+    } else {
+        SystemTime::UNIX_EPOCH.checked_sub(secs)
+    };
+    // before the permissions, which may forbid opening the file
+    if let Some(time) = time {
+        std::fs::File::open(path)?.set_modified(time)?
+    }
+    #[cfg(unix)]
+    if let Some(mode) = mode {
+        use std::os::unix::fs::PermissionsExt;
+        let perms = std::fs::Permissions::from_mode(mode & 0o7777);
+        std::fs::set_permissions(path, perms)?;
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+    Ok(())
+}
+
+/// Extract a single entry and return a note to print after its name.
+///
+/// `made` holds the symbolic links made so far, relative to the output directory.
+fn extract(e: &Entry, args: &ExtractArgs, made: &mut HashSet<PathBuf>) -> Result<String, String> {
+    let rel = sanitise(&e.name, args.unsafe_paths)?;
+    if !args.unsafe_paths {
+        check_links(&args.out, &rel, made)?
+    }
+    let path = args.out.join(&rel);
+    let io = |e: std::io::Error| e.to_string();
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(io)?;
+    }
+    let link = || {
+        if args.links {
+            Ok(String::from_utf8_lossy(&e.link_target()?).into_owned())
+        } else {
+            Err("link, use --links to create it".to_string())
+        }
+    };
+    match e.kind {
+        Kind::Dir => std::fs::create_dir_all(&path).map_err(io)?,
+        Kind::File => {
+            let data = e.data()?;
+            std::fs::write(&path, &data).map_err(io)?;
+            set_metadata(&path, e.mode, e.mtime).map_err(io)?;
+            return Ok(match e.crc_32 {
+                Some(crc) if crc == crc32fast::hash(&data) => " (CRC ok)".into(),
+                Some(_) => return Err("CRC mismatch".into()),
+                None => String::new(),
+            });
+        }
+        #[cfg(unix)]
+        Kind::Symlink => {
+            let target = link()?;
+            // links are resolved relative to their directory, so `..` could leave the output
+            if sanitise(target.as_bytes(), args.unsafe_paths).is_err() {
+                return Err("unsafe link target, use --unsafe-paths to create it anyway".into());
+            }
+            std::os::unix::fs::symlink(&target, &path).map_err(io)?;
+            made.insert(normal(&rel));
+            return Ok(format!(" -> {target}"));
+        }
+        #[cfg(not(unix))]
+        Kind::Symlink => return Err("symbolic links are not supported".into()),
+        Kind::Hardlink => {
+            let target = link()?;
+            let target_rel = sanitise(target.as_bytes(), args.unsafe_paths)?;
+            if !args.unsafe_paths {
+                check_links(&args.out, &target_rel, made)?
+            }
+            let target_path = args.out.join(target_rel);
+            std::fs::hard_link(target_path, &path).map_err(io)?;
+            return Ok(format!(" => {target}"));
+        }
+        Kind::Other(c) => return Err(format!("unsupported entry type {:?}", char::from(c))),
+    }
+    Ok(String::new())
+}
+
+pub fn run(args: ExtractArgs) -> Result<ExitCode, Error> {
+    let entries = entry::load(&args.input)?;
+    let selected = |e: &&Entry| {
+        let name = String::from_utf8_lossy(&e.name);
+        args.entry.is_empty() || args.entry.iter().any(|n| *n == name)
+    };
+
+    let (mut failed, mut made) = (false, HashSet::new());
+    for e in entries.iter().filter(selected) {
+        let name = String::from_utf8_lossy(&e.name);
+        match extract(e, &args, &mut made) {
+            Ok(note) => println!("extracted {name}{note}"),
+            Err(err) => {
+                failed = true;
+                println!("failed    {name}: {err}")
+            }
+        }
+    }
+    Ok(ExitCode::from(u8::from(failed)))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use crate::cli::{Cli, Command};
+    use clap::Parser;
+
+    /// Tar archive of entries with a name, a type flag, a link name, and data.
+    fn tar(entries: &[(&str, u8, &str, &[u8])]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (name, typeflag, linkname, data) in entries {
+            let mut h = [0u8; 512];
+            h[..name.len()].copy_from_slice(name.as_bytes());
+            h[100..108].copy_from_slice(b"0000644\0");
+            h[124..136].copy_from_slice(format!("{:011o}\0", data.len()).as_bytes());
+            h[136..148].copy_from_slice(b"00000000000\0");
+            h[148..156].fill(b' ');
+            h[156] = *typeflag;
+            h[157..157 + linkname.len()].copy_from_slice(linkname.as_bytes());
+            let sum: u32 = h.iter().map(|c| u32::from(*c)).sum();
+            h[148..156].copy_from_slice(format!("{sum:06o}\0 ").as_bytes());
+            out.extend(h);
+            out.extend(*data);
+            out.resize(out.len().next_multiple_of(512), 0);
+        }
+        out.extend([0; 1024]);
+        out
+    }
+
+    #[test]
+    fn links_do_not_lead_outside() {
+        let dir = std::env::temp_dir().join(format!("binspan-slip-{}", std::process::id()));
+        let out = dir.join("out");
+        std::fs::create_dir_all(&out).unwrap();
+        let archive = dir.join("slip.tar");
+        let entries: [(&str, u8, &str, &[u8]); 5] = [
+            ("a", b'2', "../outside", b""),
+            ("a/evil", b'0', "", b"evil"),
+            ("b", b'2', "d", b""),
+            ("d", b'5', "", b""),
+            ("b/evil", b'0', "", b"evil"),
+        ];
+        std::fs::write(&archive, tar(&entries)).unwrap();
+        let args = ["binspan", "extract", "--links", "--out"];
+        let cli = Cli::try_parse_from(
+            args.into_iter()
+                .chain([out.to_str().unwrap(), archive.to_str().unwrap()]),
+        )
+        .unwrap();
+        let Some(Command::Extract(args)) = cli.command else {
+            unreachable!()
+        };
+        // entries that are refused are reported as failed
+        let code = super::run(args).ok();
+        assert_eq!(code, Some(std::process::ExitCode::from(1)));
+
+        // the link to outside is refused, so `a` becomes a directory
+        assert!(!dir.join("outside").exists());
+        assert!(out.join("a/evil").is_file());
+        // the link inside is made, but nothing is written through it
+        assert!(out.join("b").symlink_metadata().unwrap().is_symlink());
+        assert!(!out.join("d/evil").exists());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,57 @@
+use super::error::Error;
+use binspan::Filter;
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+use regex::bytes::RegexSet;
+use std::rc::Rc;
+
+/// Patterns that entry names are matched against.
+enum Patterns {
+    Glob(GlobSet),
+    Regex(RegexSet),
+}
+
+impl Patterns {
+    fn new(patterns: &[String], regex: bool) -> Result<Self, Error> {
+        let err = |p: &str, e: &dyn core::fmt::Display| Error::Usage(format!("{p}: {e}"));
+        if regex {
+            return Ok(Self::Regex(
+                RegexSet::new(patterns).map_err(|e| err("invalid regex", &e))?,
+            ));
+        }
+        let mut set = GlobSetBuilder::new();
+        for p in patterns {
+            // `*` does not match `/`, but `**` does
+            let glob = GlobBuilder::new(p).literal_separator(true).build();
+            set.add(glob.map_err(|e| err(p, &e))?);
+        }
+        Ok(Self::Glob(
+            set.build().map_err(|e| err("invalid glob", &e))?,
+        ))
+    }
+
+    fn is_match(&self, name: &[u8]) -> bool {
+        match self {
+            Self::Glob(g) => g.is_match(&*String::from_utf8_lossy(name)),
+            Self::Regex(r) => r.is_match(name),
+        }
+    }
+}
+
+/// Select names that match any pattern in `include` (if given), but none in `exclude`.
+pub fn filter(
+    include: &[String],
+    exclude: &[String],
+    regex: bool,
+) -> Result<Option<Filter>, Error> {
+    if include.is_empty() && exclude.is_empty() {
+        return Ok(None);
+    }
+    let (inc, exc) = (
+        Patterns::new(include, regex)?,
+        Patterns::new(exclude, regex)?,
+    );
+    let all = include.is_empty();
+    Ok(Some(Rc::new(move |name: &[u8]| {
+        (all || inc.is_match(name)) && !exc.is_match(name)
+    })))
+}
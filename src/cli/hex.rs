@@ -0,0 +1,107 @@
+use super::error::Error;
+use super::tree::{self, paint};
+use super::{decode, query, HexArgs};
+use binspan::decode::{Meta, Path};
+use core::ops::Range;
+use std::process::ExitCode;
+
+const WIDTH: usize = 16;
+const COLORS: [&str; 6] = ["31", "32", "33", "34", "35", "36"];
+const GAP: &str = "41";
+
+/// Parse a range such as `0x10..0x40`, where both ends are optional.
+fn parse_range(s: &str) -> Result<(Option<usize>, Option<usize>), String> {
+    let (start, end) = s
+        .split_once("..")
+        .ok_or_else(|| format!("invalid range `{s}`, expected `START..END`"))?;
+    let num = |n: &str| (!n.is_empty()).then(|| query::parse_offset(n)).transpose();
+    let err = |_| format!("invalid range `{s}`");
+    Ok((num(start).map_err(err)?, num(end).map_err(err)?))
+}
+
+pub fn run(args: HexArgs) -> Result<ExitCode, Error> {
+    let (b, o, _) = decode::decode(&args.input)?;
+    let root = Meta::from(&b);
+    let mut view = 0..b.len();
+    if let Some(path) = &args.path {
+        let (path, m, _) = query::query(&o, &root, core::slice::from_ref(path))?[0];
+        view = m
+            .offset_in(&b)
+            .ok_or_else(|| format!("`{path}` is not part of the input"))?;
+    }
+    if let Some(range) = &args.range {
+        let (start, end) = parse_range(range)?;
+        let start = start.unwrap_or(view.start).clamp(view.start, view.end);
+        view = start..end.unwrap_or(view.end).clamp(start, view.end);
+    }
+
+    // paint spans in depth-first order, so that the innermost span wins
+    let spans = o.spans(&b, args.eager);
+    let mut labels: Vec<Option<usize>> = vec![None; view.len()];
+    for (i, s) in spans.iter().enumerate() {
+        let Range { start, end } = s.range.clone();
+        let (start, end) = (start.max(view.start), end.min(view.end));
+        if start < end {
+            labels[start - view.start..end - view.start].fill(Some(i));
+        }
+    }
+
+    let color = !args.no_color && tree::color();
+    // number the runs of equally labelled bytes, so that adjacent runs get different colors
+    let mut runs = Vec::with_capacity(labels.len());
+    for (i, l) in labels.iter().enumerate() {
+        let prev = runs.last().copied().unwrap_or(0);
+        runs.push(if i > 0 && labels[i - 1] == *l {
+            prev
+        } else {
+            prev + 1
+        });
+    }
+    let color_of = |i: usize| match labels[i] {
+        _ if !color => None,
+        None => Some(GAP),
+        Some(_) => Some(COLORS[runs[i] % COLORS.len()]),
+    };
+    let name = |i: usize| match labels[i] {
+        None => "<gap>".to_string(),
+        Some(l) => Path(&spans[l].path).to_string(),
+    };
+
+    for line in (0..labels.len()).step_by(WIDTH) {
+        let bytes = line..(line + WIDTH).min(labels.len());
+        let starts = |i: &usize| *i == 0 || runs[i - 1] != runs[*i];
+        let mut hex = String::new();
+        let mut ascii = String::new();
+        for i in bytes.clone() {
+            let sep = if !color && i > line && starts(&i) {
+                '|'
+            } else {
+                ' '
+            };
+            let c = b[view.start + i];
+            hex.push(sep);
+            hex += &paint(&format!("{c:02x}"), color_of(i));
+            let a = if c.is_ascii_graphic() || c == b' ' {
+                c as char
+            } else {
+                '.'
+            };
+            ascii += &paint(&a.to_string(), color_of(i));
+        }
+        let missing = WIDTH - bytes.len();
+        let names: Vec<_> = bytes
+            .filter(starts)
+            .map(|i| match color {
+                true => paint(&name(i), color_of(i)),
+                false => format!("[{}]", name(i)),
+            })
+            .collect();
+        let offset = view.start + line;
+        let (pad, apad) = (" ".repeat(3 * missing), " ".repeat(missing));
+        println!(
+            "{offset:08x} {hex}{pad}  {ascii}{apad}  {}",
+            names.join(" ")
+        );
+    }
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,228 @@
+use super::tree::scalar;
+use super::verify::Check;
+use binspan::decode::{self, Index, Meta, Val};
+use bytes::Bytes;
+use core::fmt::Write;
+
+/// Raw values up to this length are shown as hexdump.
+const HEX_BYTES: usize = 256;
+
+const STYLE: &str = "
+body { font-family: sans-serif; margin: 2em; }
+code, pre, .tree { font-family: monospace; }
+header table, table.checks { border-collapse: collapse; margin-bottom: 1em; }
+th, td { text-align: left; padding: 0.1em 0.8em 0.1em 0; }
+details > :not(summary) { margin-left: 1.5em; }
+summary { cursor: pointer; }
+.key { color: #1f4e9c; }
+.val { color: #1b7a2c; }
+.span, .more, .desc { color: #777; }
+.error, .fail { color: #b00020; font-weight: bold; }
+.has-error { background: #fde7ea; }
+pre.hex { margin: 0.2em 0 0.4em 1.5em; color: #444; }
+";
+
+fn escape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '<' => out += "&lt;",
+            '>' => out += "&gt;",
+            '&' => out += "&amp;",
+            '"' => out += "&quot;",
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Hexdump of `b`, with offsets starting at `offset`.
+fn hexdump(b: &[u8], offset: usize) -> String {
+    let mut out = String::new();
+    for (i, line) in b.chunks(16).enumerate() {
+        let hex: Vec<_> = line.iter().map(|c| format!("{c:02x}")).collect();
+        let ascii: String = line
+            .iter()
+            .map(|c| match c {
+                0x20..=0x7e => *c as char,
+                _ => '.',
+            })
+            .collect();
+        let line = format!("{:08x}  {:47}  {ascii}\n", offset + i * 16, hex.join(" "));
+        out += &escape(&line);
+    }
+    out
+}
+
+struct Report<'a> {
+    root: &'a Bytes,
+    max_items: usize,
+    /// Path to the value that failed to decode, root-first.
+    error: Vec<Index>,
+    path: Vec<Index>,
+    out: String,
+}
+
+impl Report<'_> {
+    fn children(&mut self, v: &Val) {
+        match v.force() {
+            Val::Obj(o) => {
+                for (k, m, v) in &**o {
+                    self.path.push(Index::Str(k.to_string().into()));
+                    self.node(k, m, v);
+                    self.path.pop();
+                }
+            }
+            Val::Arr(a) => {
+                let shown = a.len().min(self.max_items);
+                for (i, (m, v)) in a.iter().enumerate().take(shown) {
+                    self.path.push(Index::Int(i));
+                    self.node(&format!("[{i}]"), m, v);
+                    self.path.pop();
+                }
+                if a.len() > shown {
+                    let more = a.len() - shown;
+                    let _ = write!(self.out, "<div class=\"more\">… and {more} more</div>");
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn node(&mut self, label: &str, m: &Meta, v: &Val) {
+        let v = v.force();
+        let mut head = format!("<span class=\"key\">{}</span>", escape(label));
+        let value = match v {
+            Val::Arr(a) => format!(" [{}]", a.len()),
+            Val::Obj(_) => String::new(),
+            _ => format!(": <span class=\"val\">{}</span>", escape(&scalar(m, v))),
+        };
+        head += &value;
+        if let Some(d) = m.description() {
+            head += &format!(" <span class=\"desc\">({})</span>", escape(d));
+        }
+        let range = m.offset_in(self.root);
+        head += &match &range {
+            Some(r) => format!(
+                " <span class=\"span\">@{:#x}..{:#x} ({} bytes)</span>",
+                r.start,
+                r.end,
+                r.len()
+            ),
+            None => " <span class=\"span\">(derived)</span>".into(),
+        };
+        if let Some(e) = m.error() {
+            head += &format!(" <span class=\"error\">error: {}</span>", escape(e.msg()));
+        }
+        let class = if m.error().is_some() {
+            " class=\"has-error\""
+        } else {
+            ""
+        };
+
+        if matches!(v, Val::Arr(_) | Val::Obj(_)) {
+            // open the top level and the way to the error
+            let open = self.path.len() <= 1 || self.error.starts_with(&self.path);
+            let open = if open { " open" } else { "" };
+            let _ = write!(self.out, "<details{open}><summary{class}>{head}</summary>");
+            self.children(v);
+            self.out += "</details>";
+        } else {
+            let _ = write!(self.out, "<div class=\"leaf\"><span{class}>{head}</span>");
+            let raw = matches!(v, Val::Raw { .. } | Val::Bytes(_));
+            if raw && (1..=HEX_BYTES).contains(&m.bytes().len()) {
+                let offset = range.map_or(0, |r| r.start);
+                let _ = write!(
+                    self.out,
+                    "<pre class=\"hex\">{}</pre>",
+                    hexdump(&m.bytes(), offset)
+                );
+            }
+            self.out += "</div>";
+        }
+    }
+}
+
+fn checks_table(checks: &[Check]) -> String {
+    let mut out = String::from("<table class=\"checks\">");
+    for c in checks {
+        let (status, msg) = match &c.result {
+            Ok(()) => ("<td>ok</td>", String::new()),
+            Err(e) => ("<td class=\"fail\">FAIL</td>", escape(e)),
+        };
+        let offset = c.offset.map_or("-".into(), |o| format!("{o:#x}"));
+        let path = if c.path.is_empty() {
+            "&lt;root&gt;".into()
+        } else {
+            escape(&c.path)
+        };
+        let _ = write!(
+            out,
+            "<tr>{status}<td>{offset}</td><td><code>{path}</code></td><td>{}</td><td>{msg}</td></tr>",
+            c.what
+        );
+    }
+    out + "</table>"
+}
+
+/// Render a self-contained HTML report of the decoded value `v` of the input `root`.
+pub fn render(
+    name: &str,
+    root: &Bytes,
+    v: &Val,
+    r: &decode::Result,
+    checks: &[Check],
+    max_items: usize,
+) -> String {
+    let name = escape(name);
+    let format = match v.get(&Index::Str("format".into())) {
+        Some((_, Val::Str(s))) => escape(&String::from_utf8_lossy(s)),
+        _ => "unknown".into(),
+    };
+    let failed = checks.iter().filter(|c| c.result.is_err()).count();
+    let mut report = Report {
+        root,
+        max_items,
+        error: r
+            .as_ref()
+            .err()
+            .map_or(Vec::new(), |e| e.path().iter().rev().cloned().collect()),
+        path: Vec::new(),
+        out: String::new(),
+    };
+    report.children(v);
+
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{name}</title><style>{STYLE}</style></head><body>"
+    );
+    let _ = write!(
+        out,
+        "<header><h1>{name}</h1><table>\
+         <tr><th>Format</th><td>{format}</td></tr>\
+         <tr><th>Size</th><td>{} bytes</td></tr>\
+         <tr><th>Checks</th><td>{} checks, <span{}>{failed} failed</span></td></tr>\
+         </table>",
+        root.len(),
+        checks.len(),
+        if failed > 0 { " class=\"fail\"" } else { "" },
+    );
+    if let Err(e) = r {
+        let _ = write!(
+            out,
+            "<p class=\"error\">error: {}</p>",
+            escape(&e.to_string())
+        );
+    }
+    let open = if failed > 0 { " open" } else { "" };
+    let _ = write!(
+        out,
+        "<details{open}><summary>Checks</summary>{}</details></header>",
+        checks_table(checks)
+    );
+    let _ = writeln!(
+        out,
+        "<div class=\"tree\">{}</div></body></html>",
+        report.out
+    );
+    out
+}
@@ -0,0 +1,145 @@
+use super::error::Error;
+use bytes::{Bytes, BytesMut};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Number of bytes to read at once from a stream.
+const CHUNK: usize = 64 * 1024;
+
+/// Read the whole stream, failing if it is longer than `max` bytes.
+///
+/// If `hint` is the size of the stream, the buffer is allocated only once.
+fn read_stream(r: impl Read, max: Option<usize>, hint: Option<usize>) -> io::Result<Bytes> {
+    // read one byte more than the maximum to detect longer streams
+    let limit = max.map_or(usize::MAX, |max| max.saturating_add(1));
+    let mut r = r.take(limit as u64);
+    // one more byte to find the end of the stream without growing the buffer
+    let capacity = hint.map_or(CHUNK, |h| h.saturating_add(1));
+    let mut b = BytesMut::with_capacity(capacity.min(limit));
+    let mut filled = 0;
+    loop {
+        if filled == b.len() {
+            if filled == limit {
+                break;
+            }
+            // zero only the bytes to read next, so that reserved memory stays untouched
+            b.resize(filled + CHUNK.min(limit - filled), 0);
+        }
+        match r.read(&mut b[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(e),
+        }
+    }
+    check_size(filled, max)?;
+    b.truncate(filled);
+    Ok(b.freeze())
+}
+
+/// Size of standard input, if it is a regular file.
+#[cfg(unix)]
+fn stdin_size() -> Option<usize> {
+    use std::os::fd::AsFd;
+    let file = File::from(io::stdin().as_fd().try_clone_to_owned().ok()?);
+    let meta = file.metadata().ok()?;
+    meta.is_file().then(|| usize::try_from(meta.len()).ok())?
+}
+
+#[cfg(not(unix))]
+fn stdin_size() -> Option<usize> {
+    None
+}
+
+fn check_size(len: usize, max: Option<usize>) -> io::Result<()> {
+    match max {
+        Some(max) if len > max => Err(io::Error::other(format!(
+            "input is larger than the maximal input size of {max} bytes"
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Read a file whose size is known via positioned reads.
+#[cfg(unix)]
+fn read_file(file: &File, max: Option<usize>) -> io::Result<Bytes> {
+    use std::os::unix::fs::FileExt;
+    let len = file.metadata()?.len();
+    let len = usize::try_from(len).map_err(io::Error::other)?;
+    if len == 0 {
+        // special files such as pipes or `/proc` entries report a size of 0
+        return read_stream(file, max, None);
+    }
+    check_size(len, max)?;
+    let mut v = vec![0; len];
+    file.read_exact_at(&mut v, 0)?;
+    Ok(Bytes::from(v))
+}
+
+#[cfg(not(unix))]
+fn read_file(file: &File, max: Option<usize>) -> io::Result<Bytes> {
+    read_stream(file, max, None)
+}
+
+/// Load the contents of a file, or of standard input if `path` is `-`.
+///
+/// Files are memory-mapped if possible, and read otherwise,
+/// which is noted on standard error unless `quiet` is set.
+pub fn load(path: &Path, max: Option<usize>, quiet: bool) -> Result<Bytes, Error> {
+    if path == Path::new("-") {
+        let err = |e| Error::Io(format!("<stdin>: {e}"));
+        return read_stream(io::stdin().lock(), max, stdin_size()).map_err(err);
+    }
+    let err = |e| Error::Io(format!("{}: {e}", path.display()));
+    let file = File::open(path).map_err(err)?;
+    match unsafe { memmap2::Mmap::map(&file) } {
+        Ok(mmap) => {
+            check_size(mmap.len(), max).map_err(err)?;
+            Ok(Bytes::from_owner(mmap))
+        }
+        Err(e) => {
+            if !quiet {
+                let path = path.display();
+                eprintln!("note: {path}: cannot map file ({e}), falling back to reading it");
+            }
+            read_file(&file, max).map_err(err)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Reader that returns at most 100 bytes at a time, like a pipe.
+    struct Pipe(io::Cursor<Vec<u8>>);
+
+    impl Read for Pipe {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = buf.len().min(100);
+            self.0.read(&mut buf[..n])
+        }
+    }
+
+    fn pipe(len: usize) -> Pipe {
+        Pipe(io::Cursor::new((0..len).map(|i| i as u8).collect()))
+    }
+
+    #[test]
+    fn stream_at_cap() {
+        let max = 3 * CHUNK;
+        let b = read_stream(pipe(max), Some(max), None).unwrap();
+        assert_eq!(b.len(), max);
+        assert!(b.iter().enumerate().all(|(i, x)| *x == i as u8));
+    }
+
+    #[test]
+    fn stream_larger_than_cap() {
+        let max = 3 * CHUNK;
+        for hint in [None, Some(max + 1)] {
+            let e = read_stream(pipe(max + 1), Some(max), hint).unwrap_err();
+            assert!(e.to_string().contains("larger than the maximal input size"));
+        }
+    }
+}
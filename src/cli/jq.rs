@@ -0,0 +1,610 @@
+//! jq filters over decoded values.
+//!
+//! Decoded values are converted to JSON only when a filter inspects them,
+//! so lazy values are forced only if a filter actually touches them.
+use super::error::Error;
+use super::json;
+use binspan::decode::{self as d, Meta};
+use bytes::Bytes;
+use core::cell::OnceCell;
+use core::cmp::Ordering;
+use core::fmt::{self, Display, Formatter};
+use jaq_core::box_iter::{box_once, BoxIter};
+use jaq_core::compile::Undefined;
+use jaq_core::{load, path::Opt, Compiler, Ctx, Exn, Native, RcIter, RunPtr, ValT as _};
+use jaq_json::Val as Json;
+use jaq_std::{unary, v};
+use std::rc::Rc;
+
+type ValR = jaq_core::ValR<Val>;
+type ValX<'a> = jaq_core::ValX<'a, Val>;
+
+/// Decoded value that all nodes refer to.
+struct Root {
+    bytes: Bytes,
+    meta: Meta,
+    val: d::Val,
+    opts: json::Opts,
+}
+
+/// Value inside the decoded value, given by the positions of its ancestors.
+struct Node {
+    root: Rc<Root>,
+    path: Vec<usize>,
+    json: OnceCell<Json>,
+}
+
+/// Value processed by filters.
+///
+/// Values obtained from the input by indexing and iterating are nodes;
+/// everything else, such as values constructed by filters, is JSON.
+#[derive(Clone)]
+enum Val {
+    Node(Rc<Node>),
+    Json(Json),
+}
+
+impl Node {
+    /// Return the node without forcing it.
+    fn get(&self) -> (&Meta, &d::Val) {
+        let root = (&self.root.meta, &self.root.val);
+        self.path.iter().fold(root, |(_, v), i| match v.force() {
+            d::Val::Obj(o) => o.iter().nth(*i).map(|(_, m, v)| (m, v)).unwrap(),
+            d::Val::Arr(a) => a.iter().nth(*i).unwrap(),
+            _ => unreachable!(),
+        })
+    }
+
+    fn val(&self) -> &d::Val {
+        self.get().1.force()
+    }
+
+    fn child(&self, i: usize) -> Val {
+        let mut path = self.path.clone();
+        path.push(i);
+        Val::Node(Rc::new(Node {
+            root: self.root.clone(),
+            path,
+            json: OnceCell::new(),
+        }))
+    }
+
+    /// Number of children, if the node is an array or an object.
+    fn len(&self) -> Option<usize> {
+        match self.val() {
+            d::Val::Obj(o) => Some(o.len()),
+            d::Val::Arr(a) => Some(a.len()),
+            _ => None,
+        }
+    }
+
+    /// Keys of the children, if the node is an array or an object.
+    fn keys(&self) -> Option<Vec<Json>> {
+        match self.val() {
+            d::Val::Obj(o) => Some(o.iter().map(|(k, ..)| k.to_string().into()).collect()),
+            d::Val::Arr(a) => Some((0..a.len() as isize).map(Json::Int).collect()),
+            _ => None,
+        }
+    }
+
+    /// Position of the child at `index`, if the node is an array or an object.
+    fn position(&self, index: &Json) -> Option<Option<usize>> {
+        Some(match (self.val(), index) {
+            (d::Val::Obj(o), Json::Str(k)) => o.iter().rposition(|(k_, ..)| k_ == k.as_str()),
+            (d::Val::Arr(a), Json::Int(i)) => wrap(*i, a.len()).filter(|i| *i < a.len()),
+            _ => return None,
+        })
+    }
+
+    fn span(&self) -> Json {
+        let m = self.get().0;
+        let offset = m.offset_in(&self.root.bytes).map(|r| r.start);
+        let span = serde_json::json!({"offset": offset, "length": m.bytes().len()});
+        Json::from(span)
+    }
+}
+
+fn err(e: jaq_core::Error<Json>) -> jaq_core::Error<Val> {
+    jaq_core::Error::str(e)
+}
+
+fn wrap(i: isize, len: usize) -> Option<usize> {
+    if i >= 0 {
+        Some(i as usize)
+    } else {
+        len.checked_sub(i.unsigned_abs())
+    }
+}
+
+/// Order of types in comparisons.
+fn rank(j: &Json) -> u8 {
+    match j {
+        Json::Null => 0,
+        Json::Bool(_) => 1,
+        Json::Int(_) | Json::Float(_) | Json::Num(_) => 2,
+        Json::Str(_) => 3,
+        Json::Arr(_) => 4,
+        Json::Obj(_) => 5,
+    }
+}
+
+impl Val {
+    /// Convert the value to JSON, forcing all lazy values inside it.
+    fn json(&self) -> &Json {
+        match self {
+            Self::Node(n) => n.json.get_or_init(|| {
+                let (m, v) = n.get();
+                Json::from(json::val(&n.root.bytes, m, v, &n.root.opts))
+            }),
+            Self::Json(j) => j,
+        }
+    }
+
+    fn into_json(self) -> Json {
+        match self {
+            Self::Node(_) => self.json().clone(),
+            Self::Json(j) => j,
+        }
+    }
+
+    /// Like `rank`, but without converting arrays and objects to JSON.
+    fn rank(&self) -> u8 {
+        match self {
+            Self::Node(n) => match n.val() {
+                d::Val::Arr(_) => 4,
+                d::Val::Obj(_) => 5,
+                _ => rank(self.json()),
+            },
+            Self::Json(j) => rank(j),
+        }
+    }
+
+    fn keys(&self) -> Option<Vec<Json>> {
+        match self {
+            Self::Node(n) => n.keys(),
+            Self::Json(Json::Arr(a)) => Some((0..a.len() as isize).map(Json::Int).collect()),
+            Self::Json(Json::Obj(o)) => Some(o.keys().map(|k| Json::Str(k.clone())).collect()),
+            Self::Json(_) => None,
+        }
+    }
+
+    fn key_values(&self) -> Option<Vec<(Json, Val)>> {
+        let keys = self.keys()?;
+        let vals: Vec<_> = match self {
+            Self::Node(n) => (0..keys.len()).map(|i| n.child(i)).collect(),
+            Self::Json(Json::Arr(a)) => a.iter().cloned().map(Val::Json).collect(),
+            Self::Json(Json::Obj(o)) => o.values().cloned().map(Val::Json).collect(),
+            Self::Json(_) => return None,
+        };
+        Some(keys.into_iter().zip(vals).collect())
+    }
+
+    fn path_values(self, path: Vec<Json>) -> BoxIter<'static, (Val, Val)> {
+        let head = (Val::Json(path.iter().cloned().collect()), self.clone());
+        let kvs = self.key_values().into_iter().flatten();
+        let tail = kvs.flat_map(move |(k, v)| {
+            let path = path.iter().cloned().chain([k]).collect();
+            v.path_values(path)
+        });
+        Box::new(core::iter::once(head).chain(tail))
+    }
+
+    fn length(&self) -> ValR {
+        if let Self::Node(n) = self {
+            if let Some(len) = n.len() {
+                return Ok(Self::from(len as isize));
+            }
+        }
+        Ok(match self.json() {
+            Json::Null => Json::Int(0),
+            Json::Bool(_) => return Err(jaq_core::Error::str(format!("{self} has no length"))),
+            Json::Int(i) => Json::Int(i.abs()),
+            Json::Str(s) => Json::Int(s.chars().count() as isize),
+            Json::Arr(a) => Json::Int(a.len() as isize),
+            Json::Obj(o) => Json::Int(o.len() as isize),
+            j => Json::Float(jaq_std::ValT::as_f64(j).map_err(err)?.abs()),
+        }
+        .into())
+    }
+
+    fn has(&self, k: &Self) -> ValR {
+        match self.keys() {
+            Some(_) if matches!(k.json(), Json::Int(i) if *i < 0) => Ok(false.into()),
+            Some(keys) => Ok(keys.contains(k.json()).into()),
+            None => Err(jaq_core::Error::index(self.clone(), k.clone())),
+        }
+    }
+
+    fn indices(&self, y: &Self) -> ValR {
+        let ints = |i: Vec<usize>| {
+            Ok(Self::Json(
+                i.into_iter().map(|i| Json::Int(i as isize)).collect(),
+            ))
+        };
+        let (x, y) = (self.json(), y.json());
+        match (x, y) {
+            (Json::Str(_), Json::Str(y)) if y.is_empty() => ints(Vec::new()),
+            (Json::Arr(_), Json::Arr(y)) if y.is_empty() => ints(Vec::new()),
+            (Json::Str(x), Json::Str(y)) => {
+                let x: Vec<_> = x.chars().collect();
+                let y: Vec<_> = y.chars().collect();
+                ints((0..x.len()).filter(|i| x[*i..].starts_with(&y)).collect())
+            }
+            (Json::Arr(x), Json::Arr(y)) => {
+                ints((0..x.len()).filter(|i| x[*i..].starts_with(y)).collect())
+            }
+            (Json::Arr(x), y) => ints((0..x.len()).filter(|i| &x[*i] == y).collect()),
+            _ => Err(jaq_core::Error::index(self.clone(), Val::Json(y.clone()))),
+        }
+    }
+
+    fn span(&self) -> ValR {
+        match self {
+            Self::Node(n) => Ok(Self::Json(n.span())),
+            Self::Json(_) => Err(jaq_core::Error::str(format!("{self} has no span"))),
+        }
+    }
+}
+
+fn contains(x: &Json, y: &Json) -> bool {
+    match (x, y) {
+        (Json::Str(x), Json::Str(y)) => x.contains(&**y),
+        (Json::Arr(x), Json::Arr(y)) => y.iter().all(|y| x.iter().any(|x| contains(x, y))),
+        (Json::Obj(x), Json::Obj(y)) => y
+            .iter()
+            .all(|(k, y)| x.get(k).is_some_and(|x| contains(x, y))),
+        _ => x == y,
+    }
+}
+
+fn fromjson(s: &str) -> ValR {
+    let v: serde_json::Value = serde_json::from_str(s)
+        .map_err(|e| jaq_core::Error::str(format!("cannot parse {s} as JSON: {e}")))?;
+    Ok(Val::Json(v.into()))
+}
+
+impl jaq_core::ValT for Val {
+    fn from_num(n: &str) -> ValR {
+        Json::from_num(n).map(Self::Json).map_err(err)
+    }
+
+    fn from_map<I: IntoIterator<Item = (Self, Self)>>(iter: I) -> ValR {
+        let iter = iter
+            .into_iter()
+            .map(|(k, v)| (k.into_json(), v.into_json()));
+        Json::from_map(iter).map(Self::Json).map_err(err)
+    }
+
+    fn values(self) -> Box<dyn Iterator<Item = ValR>> {
+        match self.key_values() {
+            Some(kvs) => Box::new(kvs.into_iter().map(|(_, v)| Ok(v))),
+            None => Box::new(
+                self.into_json()
+                    .values()
+                    .map(|v| v.map(Self::Json).map_err(err)),
+            ),
+        }
+    }
+
+    fn index(self, index: &Self) -> ValR {
+        if let Self::Node(n) = &self {
+            if let Some(i) = n.position(index.json()) {
+                return Ok(i.map_or(Self::Json(Json::Null), |i| n.child(i)));
+            }
+        }
+        let index = index.json();
+        self.into_json().index(index).map(Self::Json).map_err(err)
+    }
+
+    fn range(self, range: jaq_core::val::Range<&Self>) -> ValR {
+        let range = range.start.map(Self::json)..range.end.map(Self::json);
+        self.into_json().range(range).map(Self::Json).map_err(err)
+    }
+
+    fn map_values<'a, I: Iterator<Item = ValX<'a>>>(
+        self,
+        opt: Opt,
+        f: impl Fn(Self) -> I,
+    ) -> ValX<'a> {
+        match self.into_json() {
+            Json::Arr(a) => {
+                let iter = a.iter().cloned().map(Self::Json).flat_map(f);
+                Ok(iter.collect::<Result<_, _>>()?)
+            }
+            Json::Obj(o) => {
+                let iter = o.iter().filter_map(|(k, v)| {
+                    let k = Self::Json(Json::Str(k.clone()));
+                    f(Self::Json(v.clone())).next().map(|v| Ok((k, v?)))
+                });
+                Ok(Self::from_map(iter.collect::<Result<Vec<_>, Exn<_>>>()?)?)
+            }
+            j => opt.fail(Self::Json(j), |v| {
+                Exn::from(jaq_core::Error::typ(v, "iterable (array or object)"))
+            }),
+        }
+    }
+
+    fn map_index<'a, I: Iterator<Item = ValX<'a>>>(
+        self,
+        index: &Self,
+        opt: Opt,
+        f: impl Fn(Self) -> I,
+    ) -> ValX<'a> {
+        let mut kvs = match self.key_values() {
+            Some(kvs) => kvs,
+            None => {
+                return opt.fail(self, |v| {
+                    Exn::from(jaq_core::Error::typ(v, "iterable (array or object)"))
+                })
+            }
+        };
+        let arr = matches!(self.rank(), 4);
+        let i = match (arr, index.json()) {
+            (true, Json::Int(i)) => wrap(*i, kvs.len()).filter(|i| *i < kvs.len()),
+            (false, Json::Str(_)) => kvs.iter().position(|(k, _)| k == index.json()),
+            _ => None,
+        };
+        let i = match i {
+            Some(i) => i,
+            None if !arr && matches!(index.json(), Json::Str(_)) => {
+                kvs.push((index.json().clone(), Self::Json(Json::Null)));
+                kvs.len() - 1
+            }
+            None => {
+                return opt.fail(self, |v| {
+                    Exn::from(jaq_core::Error::index(v, index.clone()))
+                })
+            }
+        };
+        match f(kvs[i].1.clone()).next().transpose()? {
+            Some(y) => kvs[i].1 = y,
+            None => drop(kvs.remove(i)),
+        }
+        Ok(match arr {
+            true => kvs.into_iter().map(|(_, v)| v).collect(),
+            false => Self::from_map(kvs.into_iter().map(|(k, v)| (Self::Json(k), v)))?,
+        })
+    }
+
+    fn map_range<'a, I: Iterator<Item = ValX<'a>>>(
+        self,
+        range: jaq_core::val::Range<&Self>,
+        opt: Opt,
+        f: impl Fn(Self) -> I,
+    ) -> ValX<'a> {
+        let Json::Arr(a) = self.json() else {
+            return opt.fail(self, |v| Exn::from(jaq_core::Error::typ(v, "array")));
+        };
+        let len = a.len();
+        let bound = |b: Option<&Self>, default| match b.map(Self::json) {
+            None => Ok(default),
+            Some(Json::Int(i)) => Ok(wrap(*i, len).unwrap_or(0).min(len)),
+            Some(j) => Err(jaq_core::Error::typ(Self::Json(j.clone()), "integer")),
+        };
+        let (from, upto) = match (bound(range.start, 0), bound(range.end, len)) {
+            (Ok(from), Ok(upto)) => (from, upto.max(from)),
+            (Err(e), _) | (_, Err(e)) => return opt.fail(self, |_| Exn::from(e)),
+        };
+        let mut a = (**a).clone();
+        let slice = a[from..upto].iter().cloned().map(Self::Json).collect();
+        let y = match f(slice).next().transpose()?.map(Self::into_json) {
+            Some(Json::Arr(y)) => (*y).clone(),
+            Some(y) => return Err(Exn::from(jaq_core::Error::typ(Self::Json(y), "array"))),
+            None => Vec::new(),
+        };
+        a.splice(from..upto, y);
+        Ok(Self::Json(Json::Arr(a.into())))
+    }
+
+    fn as_bool(&self) -> bool {
+        match self {
+            Self::Node(n) => !matches!(n.val(), d::Val::Null | d::Val::Bool(false)),
+            Self::Json(j) => j.as_bool(),
+        }
+    }
+
+    fn as_str(&self) -> Option<&str> {
+        self.json().as_str()
+    }
+}
+
+impl jaq_std::ValT for Val {
+    fn into_seq<S: FromIterator<Self>>(self) -> Result<S, Self> {
+        match (self.rank(), self.key_values()) {
+            (4, Some(kvs)) => Ok(kvs.into_iter().map(|(_, v)| v).collect()),
+            _ => Err(self),
+        }
+    }
+
+    fn as_isize(&self) -> Option<isize> {
+        jaq_std::ValT::as_isize(self.json())
+    }
+
+    fn as_f64(&self) -> Result<f64, jaq_core::Error<Self>> {
+        jaq_std::ValT::as_f64(self.json()).map_err(err)
+    }
+}
+
+impl Display for Val {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.json().fmt(f)
+    }
+}
+
+impl PartialEq for Val {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Val {}
+
+impl PartialOrd for Val {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Val {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let by_rank = self.rank().cmp(&other.rank());
+        by_rank.then_with(|| self.json().cmp(other.json()))
+    }
+}
+
+macro_rules! from {
+    ($($t:ty),*) => {$(
+        impl From<$t> for Val {
+            fn from(x: $t) -> Self {
+                Self::Json(x.into())
+            }
+        }
+    )*};
+}
+
+from!(bool, isize, f64, String, Json);
+
+impl FromIterator<Self> for Val {
+    fn from_iter<T: IntoIterator<Item = Self>>(iter: T) -> Self {
+        Self::Json(iter.into_iter().map(Self::into_json).collect())
+    }
+}
+
+macro_rules! binop {
+    ($trait:ident, $f:ident) => {
+        impl core::ops::$trait for Val {
+            type Output = ValR;
+            fn $f(self, rhs: Self) -> ValR {
+                let (l, r) = (self.into_json(), rhs.into_json());
+                l.$f(r).map(Self::Json).map_err(err)
+            }
+        }
+    };
+}
+
+binop!(Add, add);
+binop!(Sub, sub);
+binop!(Mul, mul);
+binop!(Div, div);
+binop!(Rem, rem);
+
+impl core::ops::Neg for Val {
+    type Output = ValR;
+    fn neg(self) -> ValR {
+        (-self.into_json()).map(Self::Json).map_err(err)
+    }
+}
+
+fn bome<'a>(r: ValR) -> BoxIter<'a, ValX<'a>> {
+    box_once(r.map_err(Exn::from))
+}
+
+/// Filters that `jaq_json` defines for its own values, and `@span`.
+fn funs() -> impl Iterator<Item = jaq_std::Filter<Native<Val>>> {
+    let funs: [jaq_std::Filter<RunPtr<Val>>; 11] = [
+        ("@span", v(0), |_, cv| bome(cv.1.span())),
+        ("tojson", v(0), |_, cv| bome(Ok(cv.1.to_string().into()))),
+        ("fromjson", v(0), |_, cv| {
+            let s =
+                cv.1.as_str()
+                    .ok_or_else(|| jaq_core::Error::typ(cv.1.clone(), "string"));
+            bome(s.and_then(fromjson))
+        }),
+        ("length", v(0), |_, cv| bome(cv.1.length())),
+        ("keys_unsorted", v(0), |_, cv| {
+            let keys = cv.1.keys().map(|k| Val::Json(k.into_iter().collect()));
+            bome(keys.ok_or_else(|| jaq_core::Error::typ(cv.1, "iterable (array or object)")))
+        }),
+        ("has", v(1), |_, cv| unary(cv, |v, k| v.has(&k))),
+        ("contains", v(1), |_, cv| {
+            unary(cv, |x, y| Ok(contains(x.json(), y.json()).into()))
+        }),
+        ("indices", v(1), |_, cv| unary(cv, |x, y| x.indices(&y))),
+        ("bsearch", v(1), |_, cv| {
+            unary(cv, |a, x| match jaq_std::ValT::into_seq::<Vec<_>>(a) {
+                Ok(a) => Ok(a
+                    .binary_search(&x)
+                    .map_or_else(|i| -1 - i as isize, |i| i as isize)
+                    .into()),
+                Err(a) => Err(jaq_core::Error::typ(a, "array")),
+            })
+        }),
+        ("path_values", v(0), |_, cv| {
+            let pair = |(p, v)| Ok([p, v].into_iter().collect());
+            Box::new(cv.1.path_values(Vec::new()).skip(1).map(pair))
+        }),
+        ("paths", v(0), |_, cv| {
+            Box::new(cv.1.path_values(Vec::new()).skip(1).map(|(p, _)| Ok(p)))
+        }),
+    ];
+    jaq_std::funs().chain(funs.into_iter().map(jaq_std::run))
+}
+
+fn expected(what: &str, found: &str) -> String {
+    match found {
+        "" => format!("expected {what} at end of filter"),
+        _ => format!("expected {what} before {found:?}"),
+    }
+}
+
+fn load_errors(e: load::Error<&str>) -> Vec<String> {
+    match e {
+        load::Error::Io(e) => e.into_iter().map(|(_, e)| e).collect(),
+        load::Error::Lex(e) => e.iter().map(|(x, s)| expected(x.as_str(), s)).collect(),
+        load::Error::Parse(e) => e.iter().map(|(x, s)| expected(x.as_str(), s)).collect(),
+    }
+}
+
+/// Run `filter` on the decoded value `v` of the input `root`, returning its outputs as JSON.
+pub fn run(
+    filter: &str,
+    root: &Bytes,
+    v: d::Val,
+    opts: json::Opts,
+) -> Result<Vec<serde_json::Value>, Error> {
+    let invalid = |e: Vec<String>| Error::Usage(format!("invalid filter: {}", e.join(", ")));
+    let program = load::File {
+        code: filter,
+        path: (),
+    };
+    let loader = load::Loader::new(jaq_std::defs().chain(jaq_json::defs()));
+    let arena = load::Arena::default();
+    let modules = loader
+        .load(&arena, program)
+        .map_err(|errs| invalid(errs.into_iter().flat_map(|(_, e)| load_errors(e)).collect()))?;
+    let undefined = |(s, u): (&str, Undefined)| format!("undefined {} {s}", u.as_str());
+    let filter = Compiler::default()
+        .with_funs(funs())
+        .compile(modules)
+        .map_err(|errs| {
+            invalid(
+                errs.into_iter()
+                    .flat_map(|(_, e)| e)
+                    .map(undefined)
+                    .collect(),
+            )
+        })?;
+
+    let root = Rc::new(Root {
+        bytes: root.clone(),
+        meta: Meta::from(root),
+        val: v,
+        opts,
+    });
+    let input = Val::Node(Rc::new(Node {
+        root,
+        path: Vec::new(),
+        json: OnceCell::new(),
+    }));
+    let inputs = RcIter::new(core::iter::empty());
+    let out = filter.run((Ctx::new([], &inputs), input));
+    out.map(|y| match y {
+        Ok(y) => Ok(y.into_json().into()),
+        Err(e) => {
+            let e = e.into_val();
+            let msg = e.as_str().map_or_else(|| e.to_string(), String::from);
+            Err(Error::Usage(format!("filter failed: {msg}")))
+        }
+    })
+    .collect()
+}
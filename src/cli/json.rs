@@ -0,0 +1,118 @@
+use binspan::decode::{Meta, Path, Val};
+use bytes::Bytes;
+use core::fmt::Write;
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+
+pub struct Opts {
+    /// Wrap every value with its offset and length.
+    pub spans: bool,
+    /// Raw spans longer than this are truncated.
+    pub max_raw_bytes: usize,
+    /// Include the SHA-256 of truncated raw spans.
+    pub sha256: bool,
+    /// Values nested deeper than this are output as `null`.
+    pub depth: Option<usize>,
+    /// Force lazy values nested in the output value.
+    ///
+    /// Otherwise, lazy values that were not forced yet are output via [`lazy`].
+    pub eager: bool,
+}
+
+/// Placeholder for a lazy value that was not forced, with the length of its span.
+pub fn lazy(m: &Meta) -> Value {
+    json!({"lazy": true, "len": m.bytes().len()})
+}
+
+fn hex(b: &[u8]) -> String {
+    b.iter().fold(String::new(), |mut s, b| {
+        write!(s, "{b:02x}").unwrap();
+        s
+    })
+}
+
+fn raw(b: &Bytes, opts: &Opts) -> Value {
+    if b.len() <= opts.max_raw_bytes {
+        return hex(b).into();
+    }
+    let mut o = Map::new();
+    o.insert("len".into(), b.len().into());
+    o.insert("hex".into(), hex(&b[..opts.max_raw_bytes]).into());
+    if opts.sha256 {
+        o.insert("sha256".into(), hex(&Sha256::digest(b)).into());
+    }
+    o.into()
+}
+
+/// Convert a value to JSON, forcing it if it is lazy.
+pub fn val(root: &Bytes, m: &Meta, v: &Val, opts: &Opts) -> Value {
+    val_at(root, m, v.force(), opts, 0)
+}
+
+fn val_at(root: &Bytes, m: &Meta, v: &Val, opts: &Opts, depth: usize) -> Value {
+    let val = |m: &Meta, v: &Val| val_at(root, m, v, opts, depth + 1);
+    let value = match v {
+        _ if opts.depth.is_some_and(|max| depth > max) => Value::Null,
+        Val::Null => Value::Null,
+        Val::Bool(b) => (*b).into(),
+        Val::U8(u) => (*u).into(),
+        Val::U16(u) => (*u).into(),
+        Val::U32(u) => (*u).into(),
+        Val::U64(u) => (*u).into(),
+        // JSON numbers in `serde_json` cannot exceed 64 bits, so larger integers become strings
+        Val::U128(u) => u64::try_from(**u).map_or_else(|_| u.to_string().into(), Value::from),
+        Val::I8(i) => (*i).into(),
+        Val::I16(i) => (*i).into(),
+        Val::I32(i) => (*i).into(),
+        Val::I64(i) => (*i).into(),
+        // JSON has no NaN or infinity, so these become null
+        Val::F32(x) => (*x).into(),
+        Val::F64(x) => (*x).into(),
+        Val::Raw { .. } => raw(&m.bytes(), opts),
+        Val::Bytes(b) => raw(b, opts),
+        Val::Str(s) => String::from_utf8_lossy(s).into(),
+        Val::Arr(a) => a.iter().map(|(m, v)| val(m, v)).collect(),
+        Val::Obj(o) => Value::Object(
+            o.iter()
+                .map(|(k, m, v)| (k.to_string(), val(m, v)))
+                .collect(),
+        ),
+        Val::Lazy(_) => match v.forced_if(opts.eager) {
+            Some(v) => return val_at(root, m, v, opts, depth),
+            None => lazy(m),
+        },
+    };
+    if opts.spans {
+        let offset = m.offset_in(root).map(|r| r.start);
+        let mut span = json!({"offset": offset, "len": m.bytes().len(), "value": value});
+        if let Some((from, via)) = m.derived_from() {
+            span["derived_from"] = derived_from(root, from);
+            span["via"] = via.into();
+        }
+        if let Some(path) = m.points_to() {
+            span["points_to"] = Path(path).to_string().into();
+        }
+        span
+    } else {
+        value
+    }
+}
+
+/// Span of the bytes that a value was derived from.
+///
+/// If these bytes were derived themselves, their offset is that of their origin in `root`.
+pub fn derived_from(root: &Bytes, from: &Meta) -> Value {
+    let offset = from.origin_in(root).map(|r| r.start);
+    json!({"offset": offset, "len": from.bytes().len()})
+}
+
+/// Print JSON to standard output.
+pub fn print(json: &Value, pretty: bool) -> Result<(), String> {
+    let s = if pretty {
+        serde_json::to_string_pretty(json)
+    } else {
+        serde_json::to_string(json)
+    };
+    println!("{}", s.map_err(|e| e.to_string())?);
+    Ok(())
+}
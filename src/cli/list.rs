@@ -0,0 +1,103 @@
+use super::entry::{self, Entry};
+use super::error::Error;
+use super::{ListArgs, Sort};
+use binspan::decode::civil_from_days;
+use std::process::ExitCode;
+
+/// Escape control characters and invalid UTF-8 in an entry name.
+pub fn escape(name: &[u8]) -> String {
+    let mut s = String::new();
+    for chunk in name.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            if c.is_control() {
+                s.extend(c.escape_default());
+            } else {
+                s.push(c);
+            }
+        }
+        for b in chunk.invalid() {
+            s += &format!("\\x{b:02x}");
+        }
+    }
+    s
+}
+
+/// Render a Unix file mode like `ls -l`, such as `drwxr-xr-x`.
+fn permissions(mode: u32) -> String {
+    let typ = match mode & 0o170000 {
+        0o040000 => 'd',
+        0o120000 => 'l',
+        0o020000 => 'c',
+        0o060000 => 'b',
+        0o010000 => 'p',
+        0o140000 => 's',
+        _ => '-',
+    };
+    let rwx = (0..9).rev().map(|i| match mode >> i & 1 {
+        0 => '-',
+        _ => ['x', 'w', 'r'][i % 3],
+    });
+    core::iter::once(typ).chain(rwx).collect()
+}
+
+/// Render seconds since the Unix epoch as `YYYY-MM-DD HH:MM`.
+pub fn time(secs: i64) -> String {
+    let (y, m, d) = civil_from_days(secs.div_euclid(86400));
+    let secs = secs.rem_euclid(86400);
+    format!(
+        "{y:04}-{m:02}-{d:02} {:02}:{:02}",
+        secs / 3600,
+        secs / 60 % 60
+    )
+}
+
+fn ratio(size: u64, compressed: u64) -> String {
+    match size {
+        0 => "0%".into(),
+        _ => format!("{:.0}%", 100.0 - 100.0 * compressed as f64 / size as f64),
+    }
+}
+
+pub fn run(args: ListArgs) -> Result<ExitCode, Error> {
+    let mut entries = entry::load(&args.input)?;
+    match args.sort {
+        Some(Sort::Name) => entries.sort_by(|a, b| a.name.cmp(&b.name)),
+        Some(Sort::Size) => entries.sort_by_key(|e| e.size),
+        Some(Sort::Mtime) => entries.sort_by_key(|e| e.mtime),
+        None => (),
+    }
+    let zip = entries.iter().any(|e| e.compressed_size.is_some());
+    let row = |e: &Entry| {
+        let mut cols = vec![permissions(e.unix_mode()), e.size.to_string()];
+        if let Some(c) = e.compressed_size {
+            cols.extend([c.to_string(), ratio(e.size, c)]);
+        }
+        cols.extend([time(e.mtime), escape(&e.name)]);
+        cols
+    };
+    let rows: Vec<Vec<String>> = entries.iter().map(row).collect();
+    let width = |i: usize| rows.iter().map(|r| r[i].len()).max().unwrap_or(0);
+    let widths: Vec<_> = (0..rows.first().map_or(0, Vec::len)).map(width).collect();
+    for r in &rows {
+        let (name, cols) = r.split_last().expect("rows have names");
+        // left-align permissions, right-align numbers and times
+        let cols = cols
+            .iter()
+            .zip(&widths)
+            .enumerate()
+            .map(|(i, (c, w))| match i {
+                0 => format!("{c:w$}"),
+                _ => format!("{c:>w$}"),
+            });
+        println!("{}  {name}", cols.collect::<Vec<_>>().join("  "));
+    }
+
+    let size: u64 = entries.iter().map(|e| e.size).sum();
+    let mut total = format!("{} entries, {size} bytes", entries.len());
+    if zip {
+        let compressed = entries.iter().filter_map(|e| e.compressed_size).sum();
+        total += &format!(", {compressed} compressed ({})", ratio(size, compressed));
+    }
+    println!("{total}");
+    Ok(ExitCode::SUCCESS)
+}
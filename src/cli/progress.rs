@@ -0,0 +1,52 @@
+use binspan::Progress;
+use std::cell::Cell;
+use std::io::{IsTerminal, Write};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// Minimal time between two updates of the indicator.
+const INTERVAL: Duration = Duration::from_millis(100);
+
+/// Progress indicator on standard error, which is cleared when dropped.
+struct Indicator {
+    label: &'static str,
+    last: Cell<Option<Instant>>,
+}
+
+impl Indicator {
+    fn update(&self, done: u64, total: u64) {
+        let now = Instant::now();
+        if self.last.get().is_some_and(|t| now - t < INTERVAL) && done < total {
+            return;
+        }
+        self.last.set(Some(now));
+        let percent = (done * 100).checked_div(total).unwrap_or(100);
+        let mut stderr = std::io::stderr();
+        let _ = write!(
+            stderr,
+            "\r\x1b[K{}: {percent}% ({done}/{total})",
+            self.label
+        );
+        let _ = stderr.flush();
+    }
+}
+
+impl Drop for Indicator {
+    fn drop(&mut self) {
+        if self.last.get().is_some() {
+            eprint!("\r\x1b[K");
+        }
+    }
+}
+
+/// Show progress labelled with `label`, unless `quiet` is set or standard error is no terminal.
+pub fn progress(label: &'static str, quiet: bool) -> Option<Progress> {
+    if quiet || !std::io::stderr().is_terminal() {
+        return None;
+    }
+    let indicator = Indicator {
+        label,
+        last: Cell::new(None),
+    };
+    Some(Rc::new(move |done, total| indicator.update(done, total)))
+}
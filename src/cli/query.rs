@@ -0,0 +1,148 @@
+use super::json::{self, Opts};
+use super::tree;
+use binspan::decode::{Index, Meta, Path, Val};
+use bytes::Bytes;
+use serde_json::{json, Value};
+
+/// Describe why `path` could not be resolved, given that its first `n` elements could.
+fn missing(v: &Val, m: &Meta, path: &[Index], n: usize) -> String {
+    let prefix = &path[..n];
+    let (_, parent) = v.get_path(m, prefix).expect("prefix exists");
+    let at = if prefix.is_empty() {
+        "the root".to_string()
+    } else {
+        format!("`{}`", Path(prefix))
+    };
+    let found = match parent.force() {
+        Val::Obj(o) => {
+            let keys: Vec<_> = o.iter().map(|(k, ..)| k).collect();
+            format!("{at} has the keys {}", keys.join(", "))
+        }
+        Val::Arr(a) => format!("{at} is an array of {} elements", a.len()),
+        _ => format!("{at} has no children"),
+    };
+    format!("no value at `{}`: {found}", Path(&path[..=n]))
+}
+
+/// Resolve all paths, forcing only the lazy values along the paths.
+pub fn query<'a, S: AsRef<str>>(
+    v: &'a Val,
+    m: &'a Meta,
+    paths: &'a [S],
+) -> Result<Vec<(&'a str, &'a Meta, &'a Val)>, String> {
+    let f = |p: &'a S| {
+        let p = p.as_ref();
+        let path = Index::parse_path(p).map_err(|e| format!("{p}: {e}"))?;
+        let (m, v) = v.get_path(m, &path).map_err(|n| missing(v, m, &path, n))?;
+        Ok((p, m, v))
+    };
+    paths.iter().map(f).collect()
+}
+
+/// Parse an offset given in decimal or, prefixed with `0x`, in hexadecimal.
+pub fn parse_offset(s: &str) -> Result<usize, String> {
+    let n = match s.strip_prefix("0x") {
+        Some(h) => usize::from_str_radix(h, 16),
+        None => s.parse(),
+    };
+    n.map_err(|_| format!("invalid offset `{s}`"))
+}
+
+/// Values that cover a byte, from the outermost to the innermost.
+pub struct Covering<'a> {
+    pub offset: usize,
+    pub chain: Vec<(Vec<Index>, &'a Meta, &'a Val)>,
+}
+
+impl Covering<'_> {
+    /// The innermost value, if it is a gap between fields.
+    fn gap(&self) -> bool {
+        let inner = self.chain.last().map(|(_, _, v)| v.force());
+        matches!(inner, Some(Val::Raw { gap: true }))
+    }
+
+    /// True if the innermost value has children, none of which covers the byte.
+    fn uncovered(&self) -> bool {
+        match self.chain.last().map(|(_, _, v)| v.force()) {
+            None => true,
+            Some(Val::Obj(o)) => !o.is_empty(),
+            Some(Val::Arr(a)) => !a.is_empty(),
+            Some(_) => false,
+        }
+    }
+}
+
+/// Find the values that cover the byte at `offset` in `root`.
+///
+/// `m` is the metadata of `v`, which is the value decoded from `root`.
+pub fn at<'a>(
+    root: &Bytes,
+    m: &'a Meta,
+    v: &'a Val,
+    offset: usize,
+) -> Result<Covering<'a>, String> {
+    if offset >= root.len() {
+        let len = root.len();
+        return Err(format!(
+            "offset {offset:#x} lies beyond the input of {len} bytes"
+        ));
+    }
+    let f = |(path, _): (Vec<Index>, &Meta)| {
+        let (m, v) = v.get_path(m, &path).expect("path was found");
+        (path, m, v)
+    };
+    let chain = v.find_at(root, offset).into_iter().map(f).collect();
+    Ok(Covering { offset, chain })
+}
+
+/// Span of a value, or the span of the bytes it was derived from.
+fn span(root: &Bytes, m: &Meta) -> String {
+    let len = m.bytes().len();
+    match (m.offset_in(root), m.derived_from()) {
+        (Some(r), _) => format!("{:#x}..{:#x}", r.start, r.end),
+        (None, Some((from, via))) => {
+            let from = from
+                .origin_in(root)
+                .map_or("?".into(), |r| format!("{r:#x?}"));
+            format!("{len} bytes derived via {via} from {from}")
+        }
+        (None, None) => format!("{len} bytes outside the input"),
+    }
+}
+
+/// Render the values that cover a byte, one per line.
+pub fn render_at(root: &Bytes, c: &Covering) -> String {
+    let mut s = format!("offset {:#x}:\n", c.offset);
+    for (path, m, v) in &c.chain {
+        let value = match v.force() {
+            Val::Obj(_) | Val::Arr(_) => String::new(),
+            v => format!("  {}", tree::scalar(m, v)),
+        };
+        s += &format!("  {}  {}{value}\n", Path(path), span(root, m));
+    }
+    if c.gap() {
+        s += "  the offset lies in a gap between fields\n";
+    } else if c.uncovered() {
+        s += "  the offset is not covered by any field\n";
+    }
+    s
+}
+
+/// Convert the values that cover a byte to JSON.
+pub fn json_at(root: &Bytes, c: &Covering, opts: &Opts) -> Value {
+    let f = |(path, m, v): &(Vec<Index>, &Meta, &Val)| {
+        let offset = m.offset_in(root).map(|r| r.start);
+        let value = match v.force() {
+            Val::Obj(_) | Val::Arr(_) => Value::Null,
+            _ => json::val(root, m, v, opts),
+        };
+        let mut y = json!({"path": Path(path).to_string(), "offset": offset, "len": m.bytes().len(), "value": value});
+        if let Some((from, via)) = m.derived_from() {
+            y["derived_from"] = json::derived_from(root, from);
+            y["via"] = via.into();
+        }
+        y
+    };
+    let values: Vec<_> = c.chain.iter().map(f).collect();
+    json!({"offset": c.offset, "values": values, "gap": c.gap(), "covered": !c.uncovered()})
+}
@@ -0,0 +1,159 @@
+use super::error::Error;
+use super::{decode, input, Format, InputArgs, ScanArgs};
+use binspan::decode::{Index, Val};
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// Summary of decoding a single file.
+struct Report {
+    path: PathBuf,
+    format: Option<String>,
+    entries: Option<usize>,
+    error: Option<String>,
+}
+
+/// Collect all files below `dir` whose names match `glob`, in sorted order.
+fn walk(dir: &Path, glob: &GlobSet, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    let mut entries = std::fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|e| e.file_name());
+    for e in entries {
+        let typ = e.file_type()?;
+        if typ.is_dir() {
+            walk(&e.path(), glob, files)?;
+        } else if typ.is_file() && (glob.is_empty() || glob.is_match(e.file_name())) {
+            files.push(e.path());
+        }
+    }
+    Ok(())
+}
+
+fn scan(path: PathBuf, args: &ScanArgs) -> Report {
+    let report = |error: Option<String>| Report {
+        path: path.clone(),
+        format: None,
+        entries: None,
+        error,
+    };
+    // files in unknown formats are skipped, not failed
+    let format = match input::load(&path, args.max_input_size, true) {
+        Ok(b) => binspan::sniff(&b).and_then(Format::from_name),
+        Err(e) => return report(Some(e.to_string())),
+    };
+    let Some(format) = format else {
+        return report(None);
+    };
+    let input = InputArgs {
+        file: Some(path.clone()),
+        max_input_size: args.max_input_size,
+        format,
+        force: false,
+        recover: false,
+        max_uncompressed: args.max_uncompressed,
+        cache_size: None,
+        include: Vec::new(),
+        exclude: Vec::new(),
+        regex: false,
+        quiet: true,
+        // files are already decoded in parallel
+        #[cfg(feature = "rayon")]
+        parallel: false,
+        trace: false,
+    };
+    let (_, o, r) = match decode::decode(&input) {
+        Ok(d) => d,
+        Err(e) => return report(Some(e.to_string())),
+    };
+    let key = |k: &'static str| Index::Str(k.into());
+    let format = match o.get(&key("format")) {
+        Some((_, Val::Str(s))) => Some(String::from_utf8_lossy(s).into_owned()),
+        _ => None,
+    };
+    let entries = ["central_directories", "files"]
+        .into_iter()
+        .find_map(|k| match o.get(&key(k)) {
+            Some((_, Val::Arr(a))) => Some(a.len()),
+            _ => None,
+        });
+    let error = r.err().map(|e| e.to_string());
+    Report {
+        path,
+        format,
+        entries,
+        error,
+    }
+}
+
+pub fn run(args: ScanArgs) -> Result<ExitCode, Error> {
+    let mut glob = GlobSetBuilder::new();
+    for g in &args.glob {
+        glob.add(Glob::new(g).map_err(|e| Error::Usage(format!("{g}: {e}")))?);
+    }
+    let glob = glob.build().map_err(|e| Error::Usage(e.to_string()))?;
+    let mut files = Vec::new();
+    walk(&args.dir, &glob, &mut files)
+        .map_err(|e| Error::Io(format!("{}: {e}", args.dir.display())))?;
+
+    // decode files in parallel, but report them in the order of `files`
+    let jobs = args
+        .jobs
+        .unwrap_or_else(|| std::thread::available_parallelism().map_or(1, |n| n.get()));
+    let next = AtomicUsize::new(0);
+    let reports: Mutex<Vec<Option<Report>>> = Mutex::new(files.iter().map(|_| None).collect());
+    std::thread::scope(|s| {
+        for _ in 0..jobs.clamp(1, files.len().max(1)) {
+            s.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(path) = files.get(i) else { break };
+                let report = scan(path.clone(), &args);
+                reports.lock().unwrap()[i] = Some(report);
+            });
+        }
+    });
+    let reports: Vec<_> = reports
+        .into_inner()
+        .unwrap()
+        .into_iter()
+        .flatten()
+        .collect();
+
+    let failed = reports.iter().filter(|r| r.error.is_some()).count();
+    for r in &reports {
+        let path = r.path.display();
+        if args.jsonl {
+            let json = serde_json::json!({
+                "path": path.to_string(),
+                "format": r.format,
+                "entries": r.entries,
+                "error": r.error,
+            });
+            println!("{json}");
+        } else {
+            let status = match (&r.format, &r.error) {
+                (_, Some(_)) => "FAIL",
+                (None, None) => "skip",
+                (Some(_), None) => "ok",
+            };
+            let format = r.format.as_deref().unwrap_or("-");
+            let entries = r.entries.map_or("-".into(), |n| n.to_string());
+            let error = r.error.as_ref().map_or(String::new(), |e| format!(": {e}"));
+            println!("{status:4}  {format:6}  {entries:>7}  {path}{error}");
+        }
+    }
+    if !args.jsonl {
+        let skipped = reports
+            .iter()
+            .filter(|r| r.format.is_none() && r.error.is_none());
+        let skipped = skipped.count();
+        println!(
+            "{} files, {failed} failed, {skipped} skipped",
+            reports.len()
+        );
+    }
+    Ok(match failed {
+        0 => ExitCode::SUCCESS,
+        _ => ExitCode::FAILURE,
+    })
+}
@@ -0,0 +1,238 @@
+use super::json::Opts;
+use super::Encoding;
+use binspan::decode::{Meta, Path, Val};
+use bytes::Bytes;
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use sha2::{Digest, Sha256};
+
+/// Decoded value that can be serialized with serde.
+///
+/// In contrast to JSON output, raw and string values are serialized as byte strings.
+pub struct Ser<'a> {
+    root: &'a Bytes,
+    m: &'a Meta,
+    v: &'a Val,
+    opts: &'a Opts,
+    depth: usize,
+}
+
+impl<'a> Ser<'a> {
+    pub fn new(root: &'a Bytes, m: &'a Meta, v: &'a Val, opts: &'a Opts) -> Self {
+        let depth = 0;
+        Self {
+            root,
+            m,
+            v: v.force(),
+            opts,
+            depth,
+        }
+    }
+
+    fn child(&self, m: &'a Meta, v: &'a Val) -> Self {
+        let depth = self.depth + 1;
+        Self {
+            m,
+            v,
+            depth,
+            ..*self
+        }
+    }
+
+    /// Serialize the value without its span.
+    fn value<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        match self.v {
+            _ if self.opts.depth.is_some_and(|max| self.depth > max) => s.serialize_unit(),
+            Val::Null => s.serialize_unit(),
+            Val::Bool(b) => s.serialize_bool(*b),
+            Val::U8(u) => s.serialize_u8(*u),
+            Val::U16(u) => s.serialize_u16(*u),
+            Val::U32(u) => s.serialize_u32(*u),
+            Val::U64(u) => s.serialize_u64(*u),
+            Val::U128(u) => s.serialize_u128(**u),
+            Val::I8(i) => s.serialize_i8(*i),
+            Val::I16(i) => s.serialize_i16(*i),
+            Val::I32(i) => s.serialize_i32(*i),
+            Val::I64(i) => s.serialize_i64(*i),
+            Val::F32(x) => s.serialize_f32(*x),
+            Val::F64(x) => s.serialize_f64(*x),
+            Val::Raw { .. } => self.raw(&self.m.bytes(), s),
+            Val::Bytes(b) => self.raw(b, s),
+            Val::Str(b) => s.serialize_bytes(b),
+            Val::Arr(a) => s.collect_seq(a.iter().map(|(m, v)| self.child(m, v))),
+            Val::Obj(o) => s.collect_map(o.iter().map(|(k, m, v)| (k, self.child(m, v)))),
+            Val::Lazy(_) => match self.v.forced_if(self.opts.eager) {
+                Some(v) => Self { v, ..*self }.value(s),
+                None => self.lazy(s),
+            },
+        }
+    }
+
+    fn lazy<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        let mut map = s.serialize_map(Some(2))?;
+        map.serialize_entry("lazy", &true)?;
+        map.serialize_entry("len", &self.m.bytes().len())?;
+        map.end()
+    }
+
+    fn raw<S: Serializer>(&self, b: &[u8], s: S) -> Result<S::Ok, S::Error> {
+        if b.len() <= self.opts.max_raw_bytes {
+            return s.serialize_bytes(b);
+        }
+        let mut map = s.serialize_map(None)?;
+        map.serialize_entry("len", &b.len())?;
+        let bytes = &b[..self.opts.max_raw_bytes];
+        map.serialize_entry("bytes", &Bytestring(bytes))?;
+        if self.opts.sha256 {
+            map.serialize_entry("sha256", &Bytestring(&Sha256::digest(b)))?;
+        }
+        map.end()
+    }
+}
+
+impl Serialize for Ser<'_> {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        if !self.opts.spans {
+            return self.value(s);
+        }
+        let value = Value(self);
+        let derived = self.m.derived_from();
+        let points_to = self.m.points_to();
+        let len = 3 + 2 * usize::from(derived.is_some()) + usize::from(points_to.is_some());
+        let mut map = s.serialize_map(Some(len))?;
+        map.serialize_entry("offset", &self.m.offset_in(self.root).map(|r| r.start))?;
+        map.serialize_entry("len", &self.m.bytes().len())?;
+        map.serialize_entry("value", &value)?;
+        if let Some((from, via)) = derived {
+            let derived_from = DerivedFrom {
+                offset: from.origin_in(self.root).map(|r| r.start),
+                len: from.bytes().len(),
+            };
+            map.serialize_entry("derived_from", &derived_from)?;
+            map.serialize_entry("via", via)?;
+        }
+        if let Some(path) = points_to {
+            map.serialize_entry("points_to", &Path(path).to_string())?;
+        }
+        map.end()
+    }
+}
+
+/// Span of the bytes that a value was derived from, like [`super::json::derived_from`].
+#[derive(serde::Serialize)]
+struct DerivedFrom {
+    offset: Option<usize>,
+    len: usize,
+}
+
+/// Serialize only the value of a [`Ser`], without its span.
+struct Value<'a>(&'a Ser<'a>);
+
+impl Serialize for Value<'_> {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        self.0.value(s)
+    }
+}
+
+/// Serialize a byte slice as byte string.
+struct Bytestring<'a>(&'a [u8]);
+
+impl Serialize for Bytestring<'_> {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_bytes(self.0)
+    }
+}
+
+/// Value found at a path, together with its span.
+pub struct Found<'a> {
+    pub path: &'a str,
+    pub ser: Ser<'a>,
+}
+
+impl Serialize for Found<'_> {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        let m = self.ser.m;
+        let mut map = s.serialize_map(Some(4))?;
+        map.serialize_entry("path", self.path)?;
+        map.serialize_entry("offset", &m.offset_in(self.ser.root).map(|r| r.start))?;
+        map.serialize_entry("len", &m.bytes().len())?;
+        map.serialize_entry("value", &self.ser)?;
+        map.end()
+    }
+}
+
+/// Serialized value, labelled with the name of the file it was decoded from.
+struct Labelled<'a, T> {
+    file: &'a str,
+    value: &'a T,
+}
+
+impl<T: Serialize> Serialize for Labelled<'_, T> {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        let mut map = s.serialize_map(Some(2))?;
+        map.serialize_entry("file", self.file)?;
+        map.serialize_entry("value", self.value)?;
+        map.end()
+    }
+}
+
+/// Write `x` to standard output in a binary encoding, labelled with `file` if given.
+pub fn print(x: &impl Serialize, file: Option<&str>, encoding: Encoding) -> Result<(), String> {
+    match file {
+        Some(file) => write(&Labelled { file, value: x }, encoding),
+        None => write(x, encoding),
+    }
+}
+
+fn write(x: &impl Serialize, encoding: Encoding) -> Result<(), String> {
+    let mut stdout = std::io::stdout().lock();
+    match encoding {
+        Encoding::Cbor => ciborium::into_writer(x, &mut stdout).map_err(|e| e.to_string()),
+        Encoding::Msgpack => rmp_serde::encode::write(&mut stdout, x).map_err(|e| e.to_string()),
+        Encoding::Tree | Encoding::Json => unreachable!("not a binary encoding"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cli::json;
+    use std::collections::BTreeMap;
+
+    /// Larger than 2^53, so it cannot be represented exactly as `f64`.
+    const SIZE: u64 = (1 << 53) + 1;
+
+    fn zip64_size() -> (Bytes, Val) {
+        let root = Bytes::from_static(&[0xff; 8]);
+        let entries = [("size".into(), Meta::from(&root), Val::U64(SIZE))];
+        (root, Val::Obj(Box::new(entries.into_iter().collect())))
+    }
+
+    fn opts() -> Opts {
+        Opts {
+            spans: false,
+            max_raw_bytes: usize::MAX,
+            sha256: false,
+            depth: None,
+            eager: true,
+        }
+    }
+
+    #[test]
+    fn large_integers_round_trip() {
+        let (root, v) = zip64_size();
+        let (m, opts) = (Meta::from(&root), opts());
+        let ser = Ser::new(&root, &m, &v, &opts);
+
+        let mut cbor = Vec::new();
+        ciborium::into_writer(&ser, &mut cbor).unwrap();
+        let cbor: BTreeMap<String, u64> = ciborium::from_reader(&cbor[..]).unwrap();
+        assert_eq!(cbor["size"], SIZE);
+
+        let msgpack = rmp_serde::to_vec(&ser).unwrap();
+        let msgpack: BTreeMap<String, u64> = rmp_serde::from_slice(&msgpack).unwrap();
+        assert_eq!(msgpack["size"], SIZE);
+
+        let json = serde_json::to_string(&json::val(&root, &m, &v, &opts)).unwrap();
+        assert_eq!(json, format!("{{\"size\":{SIZE}}}"));
+    }
+}
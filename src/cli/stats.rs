@@ -0,0 +1,176 @@
+use binspan::decode::{Meta, Val};
+use binspan::{Cache, Metrics, Phase};
+use bytes::Bytes;
+use core::ops::Range;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+#[cfg(feature = "alloc-stats")]
+mod counting {
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+
+    static CURRENT: AtomicUsize = AtomicUsize::new(0);
+    static PEAK: AtomicUsize = AtomicUsize::new(0);
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+
+    /// Allocator that keeps track of the peak number of allocated bytes
+    /// and of the number of allocations.
+    pub struct Counting;
+
+    fn grow(n: usize) {
+        COUNT.fetch_add(1, Relaxed);
+        let current = CURRENT.fetch_add(n, Relaxed) + n;
+        PEAK.fetch_max(current, Relaxed);
+    }
+
+    unsafe impl GlobalAlloc for Counting {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let p = System.alloc(layout);
+            if !p.is_null() {
+                grow(layout.size())
+            }
+            p
+        }
+
+        unsafe fn dealloc(&self, p: *mut u8, layout: Layout) {
+            System.dealloc(p, layout);
+            CURRENT.fetch_sub(layout.size(), Relaxed);
+        }
+
+        unsafe fn realloc(&self, p: *mut u8, layout: Layout, size: usize) -> *mut u8 {
+            let q = System.realloc(p, layout, size);
+            if !q.is_null() {
+                CURRENT.fetch_sub(layout.size(), Relaxed);
+                grow(size)
+            }
+            q
+        }
+    }
+
+    /// Reset the peak to the currently allocated bytes and return them.
+    pub fn reset() -> usize {
+        let current = CURRENT.load(Relaxed);
+        PEAK.store(current, Relaxed);
+        COUNT.store(0, Relaxed);
+        current
+    }
+
+    /// Peak number of allocated bytes and number of allocations since [`reset`].
+    pub fn peak() -> (usize, usize) {
+        (PEAK.load(Relaxed), COUNT.load(Relaxed))
+    }
+}
+
+#[cfg(feature = "alloc-stats")]
+pub use counting::Counting;
+
+/// Start measuring allocations, returning the currently allocated bytes if measurable.
+pub fn alloc_start() -> Option<usize> {
+    #[cfg(feature = "alloc-stats")]
+    return Some(counting::reset());
+    #[cfg(not(feature = "alloc-stats"))]
+    None
+}
+
+fn alloc_peak() -> Option<(usize, usize)> {
+    #[cfg(feature = "alloc-stats")]
+    return Some(counting::peak());
+    #[cfg(not(feature = "alloc-stats"))]
+    None
+}
+
+#[derive(Default)]
+struct Walk {
+    nodes: BTreeMap<&'static str, usize>,
+    lazies: usize,
+    forced: usize,
+    /// Byte ranges of leaves that were read.
+    read: Vec<Range<usize>>,
+}
+
+impl Walk {
+    /// Count `v` and its descendants, without forcing lazy values.
+    fn node(&mut self, root: &Bytes, m: &Meta, v: &Val) {
+        if matches!(v, Val::Lazy(_)) {
+            self.lazies += 1;
+            self.forced += usize::from(v.forced().is_some());
+        }
+        let Some(v) = v.forced() else {
+            *self.nodes.entry("Lazy").or_default() += 1;
+            return;
+        };
+        *self.nodes.entry(v.variant()).or_default() += 1;
+        match v {
+            Val::Arr(_) | Val::Obj(_) => self.children(root, v),
+            _ => self.read.extend(m.offset_in(root)),
+        }
+    }
+
+    fn children(&mut self, root: &Bytes, v: &Val) {
+        match v {
+            Val::Arr(a) => a.iter().for_each(|(m, v)| self.node(root, m, v)),
+            Val::Obj(o) => o.iter().for_each(|(_, m, v)| self.node(root, m, v)),
+            _ => (),
+        }
+    }
+
+    /// Number of bytes covered by the ranges that were read.
+    fn bytes_read(&mut self) -> usize {
+        self.read.sort_by_key(|r| (r.start, r.end));
+        let (mut total, mut end) = (0, 0);
+        for r in &self.read {
+            let start = r.start.max(end);
+            if r.end > start {
+                total += r.end - start;
+                end = r.end;
+            }
+        }
+        total
+    }
+}
+
+/// Report statistics about decoding the input `root` to `v`.
+///
+/// `elapsed` is the total time and `alloc` is the result of [`alloc_start`].
+pub fn report(
+    root: &Bytes,
+    v: &Val,
+    metrics: &Metrics,
+    cache: Option<&Cache>,
+    elapsed: Duration,
+    alloc: Option<usize>,
+) -> String {
+    let mut walk = Walk::default();
+    walk.children(root, v);
+    let time = |phase| metrics.elapsed(phase);
+    let read = walk.bytes_read();
+    let percent = (read * 100).checked_div(root.len()).unwrap_or(100);
+    let total: usize = walk.nodes.values().sum();
+    let nodes: Vec<_> = walk.nodes.iter().map(|(k, n)| format!("{k} {n}")).collect();
+    let peak = match alloc.zip(alloc_peak()) {
+        Some((start, (peak, count))) => {
+            format!("{} bytes, {count} allocations", peak.saturating_sub(start))
+        }
+        None => "not measured".into(),
+    };
+    let cache = match cache {
+        Some(c) => format!("{} hits, {} misses", c.hits(), c.misses()),
+        None => "disabled".into(),
+    };
+    format!(
+        "time: {elapsed:?} (locate {:?}, headers {:?}, decompress {:?})\n\
+         bytes read: {read} of {} ({percent}%)\n\
+         nodes: {total} ({})\n\
+         lazy values forced: {} of {}\n\
+         uncompressed data cache: {cache}\n\
+         peak allocation: {peak}\n",
+        time(Phase::Locate),
+        time(Phase::Headers),
+        time(Phase::Decompress),
+        root.len(),
+        nodes.join(", "),
+        walk.forced,
+        walk.lazies,
+    )
+}
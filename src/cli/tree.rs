@@ -0,0 +1,206 @@
+use binspan::decode::{Meta, Path, Val};
+use bytes::Bytes;
+use std::io::IsTerminal;
+
+const NAME: &str = "34";
+const VALUE: &str = "32";
+const DIM: &str = "2";
+const ERROR: &str = "31";
+const WARNING: &str = "33";
+
+/// Number of bytes shown of raw values.
+const PREVIEW: usize = 16;
+
+/// Options for tree output.
+pub struct Opts {
+    pub color: bool,
+    /// Do not show values nested deeper than this.
+    pub max_depth: Option<usize>,
+    /// Show at most this many elements of each array.
+    pub max_items: usize,
+    /// Force lazy values instead of showing only their length.
+    pub eager: bool,
+}
+
+/// Return true if standard output should be colored.
+pub fn color() -> bool {
+    let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    let color = super::config::get().color;
+    !no_color && color.unwrap_or_else(|| std::io::stdout().is_terminal())
+}
+
+/// Wrap `s` in the ANSI escape sequence for `color`, if given.
+pub fn paint(s: &str, color: Option<&str>) -> String {
+    match color {
+        Some(c) => format!("\x1b[{c}m{s}\x1b[0m"),
+        None => s.into(),
+    }
+}
+
+/// Render a value that is neither an array nor an object.
+pub fn scalar(m: &Meta, v: &Val) -> String {
+    if let Some(s) = m.formatted(v) {
+        return s;
+    }
+    match v {
+        Val::Null => "null".into(),
+        Val::Bool(b) => b.to_string(),
+        Val::U8(u) => u.to_string(),
+        Val::U16(u) => u.to_string(),
+        Val::U32(u) => u.to_string(),
+        Val::U64(u) => u.to_string(),
+        Val::U128(u) => u.to_string(),
+        Val::I8(i) => i.to_string(),
+        Val::I16(i) => i.to_string(),
+        Val::I32(i) => i.to_string(),
+        Val::I64(i) => i.to_string(),
+        Val::F32(x) => x.to_string(),
+        Val::F64(x) => x.to_string(),
+        Val::Str(s) => format!("{:?}", String::from_utf8_lossy(s)),
+        _ => {
+            let b = match v {
+                Val::Bytes(b) => (**b).clone(),
+                _ => m.bytes(),
+            };
+            let hex: Vec<_> = b.iter().take(PREVIEW).map(|c| format!("{c:02x}")).collect();
+            match b.len() {
+                0 => "(empty)".into(),
+                n if n > PREVIEW => format!("{} … ({n} bytes)", hex.join(" ")),
+                _ => hex.join(" "),
+            }
+        }
+    }
+}
+
+struct Tree<'a> {
+    root: &'a Bytes,
+    /// Value that paths of references start from, if it is rendered.
+    top: Option<&'a Val>,
+    opts: &'a Opts,
+    out: String,
+}
+
+impl Tree<'_> {
+    fn paint(&self, s: &str, color: &str) -> String {
+        paint(s, self.opts.color.then_some(color))
+    }
+
+    fn children(&mut self, depth: usize, v: &Val) {
+        match v.force() {
+            Val::Obj(o) => o.iter().for_each(|(k, m, v)| self.node(depth, k, m, v)),
+            Val::Arr(a) => {
+                let shown = a.len().min(self.opts.max_items);
+                for (i, (m, v)) in a.iter().enumerate().take(shown) {
+                    self.node(depth, &format!("[{i}]"), m, v)
+                }
+                if a.len() > shown {
+                    let more = format!("… and {} more", a.len() - shown);
+                    let line = format!("{}{}\n", "  ".repeat(depth), self.paint(&more, DIM));
+                    self.out += &line;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn node(&mut self, depth: usize, label: &str, m: &Meta, v: &Val) {
+        let v = v.forced_if(self.opts.eager);
+        let mut line = "  ".repeat(depth) + &self.paint(label, NAME);
+        let deeper = self.opts.max_depth.is_none_or(|max| depth < max);
+        match v {
+            None => {
+                let lazy = format!("(lazy, {} bytes)", m.bytes().len());
+                line += &format!(": {}", self.paint(&lazy, DIM))
+            }
+            Some(Val::Arr(a)) => line += &format!(" [{}]", a.len()),
+            Some(Val::Obj(o)) if !deeper && !o.is_empty() => line += " {…}",
+            Some(Val::Obj(_)) => (),
+            Some(v) => line += &format!(": {}", self.paint(&scalar(m, v), VALUE)),
+        }
+        if let Some(d) = m.description() {
+            line += &format!(" ({d})");
+        }
+        if let Some(path) = m.points_to() {
+            line += &format!(" -> {}", Path(path));
+            let resolve = |t| path.iter().try_fold(t, |v: &Val, i| Some(v.get(i)?.1));
+            if self.top.is_some_and(|t| resolve(t).is_none()) {
+                line += &format!(" {}", self.paint("(broken)", ERROR));
+            }
+        }
+        let offset = match m.offset_in(self.root) {
+            Some(r) => format!("@{:#x}..{:#x}", r.start, r.end),
+            None => "(derived)".into(),
+        };
+        line += &format!("  {}", self.paint(&offset, DIM));
+        if let Some(e) = m.error() {
+            line += &format!("  {}", self.paint(&format!("error: {}", e.msg()), ERROR));
+        }
+        for w in m.warnings() {
+            line += &format!("  {}", self.paint(&format!("warning: {w}"), WARNING));
+        }
+        self.out += &line;
+        self.out += "\n";
+        if let Some(v) = v.filter(|_| deeper) {
+            self.children(depth + 1, v);
+        }
+    }
+}
+
+/// Render the children of `v` as an indented tree.
+pub fn render(root: &Bytes, v: &Val, opts: &Opts) -> String {
+    let mut tree = Tree {
+        root,
+        top: Some(v),
+        opts,
+        out: String::new(),
+    };
+    tree.children(0, v);
+    tree.out
+}
+
+/// Render `v`, labelled with `label`, and its descendants as an indented tree.
+pub fn render_node(root: &Bytes, label: &str, m: &Meta, v: &Val, opts: &Opts) -> String {
+    let mut tree = Tree {
+        root,
+        top: None,
+        opts,
+        out: String::new(),
+    };
+    tree.node(0, label, m, v.force());
+    tree.out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use binspan::decode::Index;
+
+    #[test]
+    fn broken_links_are_marked() {
+        let root = Bytes::from_static(&[0; 4]);
+        let link = |to: usize| {
+            let mut m = Meta::from(root.slice(0..4));
+            m.point_to(vec![Index::Str("files".into()), Index::Int(to)]);
+            (m, Val::U32(0))
+        };
+        let (ok, broken) = (link(0), link(1));
+        let files = [(Meta::from(root.slice(0..4)), Val::Raw { gap: false })];
+        let files = Val::Arr(Box::new(files.into_iter().collect()));
+        let entries = [
+            ("files".into(), Meta::from(root.slice(0..4)), files),
+            ("ok".into(), ok.0, ok.1),
+            ("broken".into(), broken.0, broken.1),
+        ];
+        let v = Val::Obj(Box::new(entries.into_iter().collect()));
+        let opts = Opts {
+            color: false,
+            max_depth: None,
+            max_items: usize::MAX,
+            eager: true,
+        };
+        let out = render(&root, &v, &opts);
+        let line = |key: &str| out.lines().find(|l| l.starts_with(key)).unwrap();
+        assert!(line("ok").ends_with("-> files[0]  @0x0..0x4"), "{out}");
+        assert!(line("broken").contains("-> files[1] (broken)"), "{out}");
+    }
+}
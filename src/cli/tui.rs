@@ -0,0 +1,402 @@
+use super::error::Error;
+use super::{decode, tree, TuiArgs};
+use binspan::decode::{Index, Meta, Path, Val};
+use bytes::Bytes;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Paragraph};
+use ratatui::DefaultTerminal;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+use std::process::ExitCode;
+
+/// Number of array elements that are shown at once.
+const PAGE: usize = 100;
+/// Number of bytes per hexdump line.
+const WIDTH: usize = 16;
+
+enum Kind<'a> {
+    Node(&'a Meta, &'a Val),
+    /// Placeholder for the given number of array elements that are not shown yet.
+    More(usize),
+}
+
+/// Line of the tree view.
+struct Row<'a> {
+    depth: usize,
+    /// Path of the node, or of the array for placeholders.
+    path: Vec<Index>,
+    kind: Kind<'a>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Paths of expanded nodes.
+    expanded: HashSet<String>,
+    /// Number of shown elements of arrays, if more than one page.
+    shown: HashMap<String, usize>,
+    selected: usize,
+    /// First visible row of the tree view.
+    top: usize,
+    /// Number of lines that the hex view is scrolled below the start of the selected span.
+    hex_scroll: usize,
+    /// Query that is being typed.
+    typing: Option<String>,
+    query: String,
+    status: String,
+}
+
+fn key(path: &[Index]) -> String {
+    Path(path).to_string()
+}
+
+fn label(path: &[Index]) -> String {
+    match path.last() {
+        Some(Index::Str(k)) => k.to_string(),
+        Some(Index::Int(i)) => format!("[{i}]"),
+        None => String::new(),
+    }
+}
+
+fn is_container(v: &Val) -> bool {
+    matches!(v, Val::Obj(_) | Val::Arr(_) | Val::Lazy(_))
+}
+
+impl State {
+    /// Append the rows of the children of `v`, which is expanded.
+    fn rows<'a>(&self, v: &'a Val, depth: usize, path: &mut Vec<Index>, out: &mut Vec<Row<'a>>) {
+        let shown = self.shown.get(&key(path)).copied().unwrap_or(PAGE);
+        let mut child = |i: Index, m: &'a Meta, v: &'a Val, out: &mut Vec<Row<'a>>| {
+            path.push(i);
+            out.push(Row {
+                depth,
+                path: path.clone(),
+                kind: Kind::Node(m, v),
+            });
+            if self.expanded.contains(&key(path)) {
+                self.rows(v, depth + 1, path, out);
+            }
+            path.pop();
+        };
+        match v.force() {
+            Val::Obj(o) => {
+                for (k, m, v) in &**o {
+                    child(Index::Str(k.to_string().into()), m, v, out)
+                }
+            }
+            Val::Arr(a) => {
+                for (i, (m, v)) in a.iter().enumerate().take(shown) {
+                    child(Index::Int(i), m, v, out)
+                }
+                if a.len() > shown {
+                    out.push(Row {
+                        depth,
+                        path: path.clone(),
+                        kind: Kind::More(a.len() - shown),
+                    });
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Expand all ancestors of `path`, so that it becomes visible.
+    fn reveal(&mut self, path: &[Index]) {
+        for n in 0..path.len() {
+            if let Index::Int(i) = path[n] {
+                let shown = self.shown.entry(key(&path[..n])).or_insert(PAGE);
+                *shown = (*shown).max((i / PAGE + 1) * PAGE);
+            }
+            if n > 0 {
+                self.expanded.insert(key(&path[..n]));
+            }
+        }
+    }
+}
+
+/// Paths of all nodes in depth-first order, without forcing lazy values.
+fn all_paths<'a>(v: &'a Val, path: &mut Vec<Index>, out: &mut Vec<(Vec<Index>, &'a Val)>) {
+    let mut child = |i, v: &'a Val, out: &mut Vec<_>| {
+        path.push(i);
+        out.push((path.clone(), v));
+        all_paths(v, path, out);
+        path.pop();
+    };
+    match v {
+        Val::Obj(o) => o
+            .iter()
+            .for_each(|(k, _, v)| child(Index::Str(k.to_string().into()), v, out)),
+        Val::Arr(a) => a
+            .iter()
+            .enumerate()
+            .for_each(|(i, (_, v))| child(Index::Int(i), v, out)),
+        _ => (),
+    }
+}
+
+/// Find the first node after `from` whose name or string value contains `query`.
+fn search(v: &Val, from: &[Index], query: &str) -> Option<Vec<Index>> {
+    let mut paths = Vec::new();
+    all_paths(v, &mut Vec::new(), &mut paths);
+    let start = paths
+        .iter()
+        .position(|(p, _)| p == from)
+        .map_or(0, |i| i + 1);
+    let matches = |(p, v): &&(Vec<Index>, &Val)| {
+        let s = matches!(v, Val::Str(s) if String::from_utf8_lossy(s).contains(query));
+        s || label(p).contains(query)
+    };
+    let (after, before) = paths.split_at(start.min(paths.len()));
+    let found = after.iter().chain(before).find(matches);
+    found.map(|(p, _)| p.clone())
+}
+
+/// Copy `s` to the clipboard of the terminal via OSC 52.
+fn copy(s: &str) -> io::Result<()> {
+    const B64: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut enc = String::new();
+    for chunk in s.as_bytes().chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0, |n, (i, c)| n | u32::from(*c) << (16 - 8 * i));
+        for i in 0..4 {
+            let c = if i <= chunk.len() {
+                B64[(n >> (18 - 6 * i) & 63) as usize]
+            } else {
+                b'='
+            };
+            enc.push(c.into());
+        }
+    }
+    let mut out = io::stdout();
+    write!(out, "\x1b]52;c;{enc}\x07")?;
+    out.flush()
+}
+
+fn tree_line(row: &Row, expanded: &HashSet<String>, selected: bool) -> Line<'static> {
+    let indent = "  ".repeat(row.depth);
+    let style = if selected {
+        Style::new().add_modifier(Modifier::REVERSED)
+    } else {
+        Style::new()
+    };
+    let (m, v) = match row.kind {
+        Kind::More(n) => {
+            let more = format!("{indent}… {n} more");
+            return Line::styled(more, style.fg(Color::DarkGray));
+        }
+        Kind::Node(m, v) => (m, v),
+    };
+    let mut spans = vec![Span::raw(indent)];
+    if is_container(v) {
+        let open = expanded.contains(&key(&row.path));
+        spans.push(Span::raw(if open { "▾ " } else { "▸ " }));
+    } else {
+        spans.push(Span::raw("  "));
+    }
+    spans.push(Span::styled(label(&row.path), Style::new().fg(Color::Blue)));
+    match v {
+        Val::Arr(a) => spans.push(Span::raw(format!(" [{}]", a.len()))),
+        Val::Obj(_) => (),
+        Val::Lazy(_) => spans.push(Span::styled(" …", Style::new().fg(Color::DarkGray))),
+        _ => {
+            let value = format!(": {}", tree::scalar(m, v));
+            spans.push(Span::styled(value, Style::new().fg(Color::Green)))
+        }
+    }
+    if let Some(e) = m.error() {
+        spans.push(Span::styled(
+            format!("  ! {}", e.msg()),
+            Style::new().fg(Color::Red),
+        ));
+    }
+    Line::from(spans).style(style)
+}
+
+/// Hexdump of the span of `m` and the bytes following it.
+fn hex_lines(root: &Bytes, m: &Meta, scroll: usize, height: usize) -> Vec<Line<'static>> {
+    let (base, range) = match m.offset_in(root) {
+        Some(r) => (root.clone(), r),
+        None => (m.bytes(), 0..m.bytes().len()),
+    };
+    let first = range.start - range.start % WIDTH + scroll * WIDTH;
+    let highlight = Style::new().add_modifier(Modifier::REVERSED);
+    let mut lines = Vec::new();
+    for line in (first..base.len()).step_by(WIDTH).take(height) {
+        let mut spans = vec![Span::raw(format!("{line:08x} "))];
+        let bytes = line..base.len().min(line + WIDTH);
+        for i in bytes.clone() {
+            let style = if range.contains(&i) {
+                highlight
+            } else {
+                Style::new()
+            };
+            spans.push(Span::raw(" "));
+            spans.push(Span::styled(format!("{:02x}", base[i]), style));
+        }
+        spans.push(Span::raw(" ".repeat(3 * (WIDTH - bytes.len()) + 2)));
+        for i in bytes {
+            let c = base[i];
+            let c = if c.is_ascii_graphic() || c == b' ' {
+                c as char
+            } else {
+                '.'
+            };
+            let style = if range.contains(&i) {
+                highlight
+            } else {
+                Style::new()
+            };
+            spans.push(Span::styled(c.to_string(), style));
+        }
+        lines.push(Line::from(spans));
+    }
+    lines
+}
+
+fn browse(terminal: &mut DefaultTerminal, root: &Bytes, o: &Val) -> io::Result<()> {
+    let mut state = State::default();
+    loop {
+        let mut rows = Vec::new();
+        state.rows(o, 0, &mut Vec::new(), &mut rows);
+        state.selected = state.selected.min(rows.len().saturating_sub(1));
+        let row = rows.get(state.selected);
+
+        terminal.draw(|f| {
+            let [main, status] =
+                Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(f.area());
+            let [left, right] =
+                Layout::horizontal([Constraint::Fill(1), Constraint::Length(78)]).areas(main);
+
+            let height = usize::from(left.height.saturating_sub(2));
+            if state.selected < state.top {
+                state.top = state.selected;
+            } else if state.selected >= state.top + height {
+                state.top = state.selected + 1 - height;
+            }
+            let lines = rows.iter().enumerate().skip(state.top).take(height);
+            let lines = lines.map(|(i, r)| tree_line(r, &state.expanded, i == state.selected));
+            let title = row.map_or(String::new(), |r| key(&r.path));
+            let tree = Paragraph::new(lines.collect::<Vec<_>>());
+            f.render_widget(tree.block(Block::bordered().title(title)), left);
+
+            let height = usize::from(right.height.saturating_sub(2));
+            let (hex, title) = match row.map(|r| &r.kind) {
+                Some(Kind::Node(m, _)) => {
+                    let title = match m.offset_in(root) {
+                        Some(r) => format!("{:#x}..{:#x} ({} bytes)", r.start, r.end, r.len()),
+                        None => format!("derived ({} bytes)", m.bytes().len()),
+                    };
+                    (hex_lines(root, m, state.hex_scroll, height), title)
+                }
+                _ => (Vec::new(), String::new()),
+            };
+            f.render_widget(
+                Paragraph::new(hex).block(Block::bordered().title(title)),
+                right,
+            );
+
+            let status_line = match &state.typing {
+                Some(q) => format!("/{q}"),
+                None => state.status.clone(),
+            };
+            f.render_widget(Paragraph::new(status_line), status);
+        })?;
+
+        let Event::Key(k) = event::read()? else {
+            continue;
+        };
+        if k.kind != KeyEventKind::Press {
+            continue;
+        }
+        if let Some(q) = &mut state.typing {
+            match k.code {
+                KeyCode::Char(c) => q.push(c),
+                KeyCode::Backspace => drop(q.pop()),
+                KeyCode::Esc => state.typing = None,
+                KeyCode::Enter => {
+                    state.query = state.typing.take().unwrap_or_default();
+                    find_next(&mut state, o, &rows);
+                }
+                _ => (),
+            }
+            continue;
+        }
+        state.status.clear();
+        let path = row.map(|r| r.path.clone()).unwrap_or_default();
+        let page = usize::from(terminal.size()?.height.saturating_sub(3));
+        match k.code {
+            KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+            KeyCode::Down | KeyCode::Char('j') => state.selected += 1,
+            KeyCode::Up | KeyCode::Char('k') => state.selected = state.selected.saturating_sub(1),
+            KeyCode::PageDown => state.selected += page,
+            KeyCode::PageUp => state.selected = state.selected.saturating_sub(page),
+            KeyCode::Home | KeyCode::Char('g') => state.selected = 0,
+            KeyCode::End | KeyCode::Char('G') => state.selected = rows.len(),
+            KeyCode::Char('J') => state.hex_scroll += 1,
+            KeyCode::Char('K') => state.hex_scroll = state.hex_scroll.saturating_sub(1),
+            KeyCode::Right | KeyCode::Char('l') | KeyCode::Enter => match row.map(|r| &r.kind) {
+                Some(Kind::More(_)) => *state.shown.entry(key(&path)).or_insert(PAGE) += PAGE,
+                Some(Kind::Node(_, v)) if is_container(v) => {
+                    state.expanded.insert(key(&path));
+                }
+                _ => (),
+            },
+            KeyCode::Left | KeyCode::Char('h') => {
+                // collapse the node, or go to its parent if it is collapsed
+                let collapsed = state.expanded.remove(&key(&path));
+                let parent = &path[..path.len().saturating_sub(1)];
+                let parent = rows.iter().position(|r| r.path == parent);
+                if let (false, Some(i)) = (collapsed, parent) {
+                    state.selected = i;
+                }
+            }
+            KeyCode::Char('/') => state.typing = Some(String::new()),
+            KeyCode::Char('n') => find_next(&mut state, o, &rows),
+            KeyCode::Char('y') => {
+                copy(&key(&path))?;
+                state.status = format!("copied path {}", key(&path));
+            }
+            KeyCode::Char('Y') => {
+                if let Some(Kind::Node(m, _)) = row.map(|r| &r.kind) {
+                    let offset = m
+                        .offset_in(root)
+                        .map_or("derived".into(), |r| format!("{:#x}", r.start));
+                    copy(&offset)?;
+                    state.status = format!("copied offset {offset}");
+                }
+            }
+            _ => (),
+        }
+        let moved = rows.get(state.selected).map(|r| &r.path) != Some(&path);
+        if moved {
+            state.hex_scroll = 0;
+        }
+    }
+}
+
+fn find_next(state: &mut State, o: &Val, rows: &[Row]) {
+    let from = rows.get(state.selected).map_or(&[][..], |r| &r.path);
+    match search(o, from, &state.query) {
+        Some(path) => {
+            state.reveal(&path);
+            let mut rows = Vec::new();
+            state.rows(o, 0, &mut Vec::new(), &mut rows);
+            state.selected = rows.iter().position(|r| r.path == path).unwrap_or(0);
+            state.hex_scroll = 0;
+        }
+        None => state.status = format!("not found: {}", state.query),
+    }
+}
+
+pub fn run(args: TuiArgs) -> Result<ExitCode, Error> {
+    let (b, o, _) = decode::decode(&args.input)?;
+    let mut terminal = ratatui::try_init().map_err(|e| Error::Io(e.to_string()))?;
+    let r = browse(&mut terminal, &b, &o);
+    ratatui::restore();
+    r.map_err(|e| Error::Io(e.to_string()))?;
+    Ok(ExitCode::SUCCESS)
+}
@@ -0,0 +1,287 @@
+use super::error::Error;
+use super::{decode, progress, VerifyArgs};
+use binspan::decode::{self as d, Index, Meta, Path, Span, Val};
+use binspan::{tar, zip, Format as _, Progress};
+use bytes::Bytes;
+use std::process::ExitCode;
+
+/// Result of an integrity check.
+pub struct Check {
+    pub path: String,
+    pub offset: Option<usize>,
+    pub what: &'static str,
+    pub result: Result<(), String>,
+}
+
+struct Checks<'a> {
+    root: &'a Bytes,
+    checks: Vec<Check>,
+}
+
+impl Checks<'_> {
+    fn push(&mut self, path: &[Index], m: &Meta, what: &'static str, result: Result<(), String>) {
+        let offset = m.offset_in(self.root).map(|r| r.start);
+        self.push_at(path, offset, what, result)
+    }
+
+    fn push_at(
+        &mut self,
+        path: &[Index],
+        offset: Option<usize>,
+        what: &'static str,
+        result: Result<(), String>,
+    ) {
+        self.checks.push(Check {
+            path: Path(path).to_string(),
+            offset,
+            what,
+            result,
+        });
+    }
+}
+
+fn key(k: &'static str) -> Index {
+    Index::Str(k.into())
+}
+
+fn int(v: &Val) -> Option<u64> {
+    match v.force() {
+        Val::U8(u) => Some((*u).into()),
+        Val::U16(u) => Some((*u).into()),
+        Val::U32(u) => Some((*u).into()),
+        Val::U64(u) => Some(*u),
+        Val::U128(u) => (**u).try_into().ok(),
+        Val::I8(i) => (*i).try_into().ok(),
+        Val::I16(i) => (*i).try_into().ok(),
+        Val::I32(i) => (*i).try_into().ok(),
+        Val::I64(i) => (*i).try_into().ok(),
+        _ => None,
+    }
+}
+
+fn expect<T: PartialEq + core::fmt::Debug>(expected: T, found: T) -> Result<(), String> {
+    match expected == found {
+        true => Ok(()),
+        false => Err(format!("expected {expected:?}, found {found:?}")),
+    }
+}
+
+/// Checks that apply to all formats, found by the names of the values.
+fn generic(c: &mut Checks, spans: &[Span]) {
+    for s in spans {
+        let Some(Index::Str(k)) = s.path.last() else {
+            continue;
+        };
+        let offset = Some(s.range.start);
+        if k == "signature" {
+            // the signature belongs to the innermost record that has a known signature
+            let record = s.path.iter().rev().find_map(|i| match i {
+                Index::Str(k) => zip::SIGNATURES.iter().find(|(r, _)| r == k),
+                Index::Int(_) => None,
+            });
+            if let Some((_, sig)) = record {
+                let found = &c.root[s.range.clone()];
+                c.push_at(&s.path, offset, "signature", expect(&sig[..], found));
+            }
+        } else if k.ends_with("_padding") {
+            let zero = c.root[s.range.clone()].iter().all(|b| *b == 0);
+            let result = zero.then_some(()).ok_or_else(|| "non-zero padding".into());
+            c.push_at(&s.path, offset, "padding is zero", result);
+        }
+    }
+}
+
+fn zip(c: &mut Checks, o: &Val, fast: bool, limit: usize, progress: Option<Progress>) {
+    let (root, root_m) = (c.root, Meta::from(c.root));
+    let get = |path: &[Index]| o.get_path(&root_m, path).ok();
+    let cds: Vec<_> = match get(&[key("central_directories")]) {
+        Some((_, Val::Arr(a))) => a.iter().collect(),
+        _ => Vec::new(),
+    };
+
+    let eocd = [
+        "end_of_central_directory_record_zip64",
+        "end_of_central_directory_record",
+    ]
+    .into_iter()
+    .find_map(|k| Some((k, get(&[key(k)])?)));
+    if let Some((k, (eocd_m, eocd))) = eocd {
+        for n in [
+            "nr_of_central_dir_records",
+            "nr_of_central_dir_records_on_disk",
+        ] {
+            if let Some((m, Some(stored))) = eocd.get(&key(n)).map(|(m, v)| (m, int(v))) {
+                let result = expect(cds.len() as u64, stored);
+                c.push(&[key(k), key(n)], m, "number of records", result);
+            }
+        }
+        // the central directory should end where the end of central directory record starts
+        let cd = get(&[key("central_directories")]).and_then(|(m, _)| m.offset_in(root));
+        if let (Some(cd), Some(eocd_r)) = (cd, eocd_m.offset_in(root)) {
+            let path = [key(k), key("size_of_central_dir")];
+            let m = eocd
+                .get(&key("size_of_central_dir"))
+                .map_or(eocd_m, |(m, _)| m);
+            c.push(
+                &path,
+                m,
+                "central directory size",
+                expect(eocd_r.start, cd.end),
+            );
+        }
+    }
+
+    if fast {
+        return;
+    }
+    for (i, (_, cd)) in cds.iter().enumerate() {
+        let Some(crc) = cd.get(&key("crc_32")).and_then(|(_, v)| int(v)) else {
+            continue;
+        };
+        // records of other disks and gaps left by recovery have no local file at their index
+        let link = cd.get(&key("relative_offset_of_local_file_header"));
+        let Some(lf_path) = link.and_then(|(m, _)| m.points_to()) else {
+            continue;
+        };
+        let Some((lf_m, lf)) = get(lf_path) else {
+            continue;
+        };
+        let path = [lf_path, &[key("compressed")]].concat();
+        let method = lf.get(&key("compression_method"));
+        let method = method
+            .and_then(|(_, v)| v.get(&key("value")))
+            .and_then(|(_, v)| int(v));
+        // stream the data through the hasher instead of materialising it
+        let (m, actual) = match lf.get(&key("compressed")) {
+            None => (lf_m, Some(crc32fast::hash(&[]))),
+            Some((m, _)) => (
+                m,
+                method.and_then(|method| zip::crc32(&m.bytes(), method.try_into().ok()?, limit)),
+            ),
+        };
+        let result = match actual.map(u64::from) {
+            Some(actual) if actual == crc => Ok(()),
+            Some(actual) => Err(format!("stored {crc:#010x}, actual {actual:#010x}")),
+            None => Err("cannot uncompress".into()),
+        };
+        c.push(&path, m, "CRC-32", result);
+        if let Some(progress) = &progress {
+            progress(i as u64 + 1, cds.len() as u64);
+        }
+    }
+}
+
+fn tar(c: &mut Checks, o: &Val) {
+    let root = Meta::from(c.root);
+    let Ok((files_m, Val::Arr(files))) = o.get_path(&root, &[key("files")]) else {
+        return;
+    };
+    for (i, (m, file)) in files.iter().enumerate() {
+        let header = &m.bytes()[..m.bytes().len().min(tar::BLOCK_BYTES)];
+        let m = file.get(&key("chksum")).map_or(m, |(m, _)| m);
+        let result = tar::valid_checksum(header)
+            .then_some(())
+            .ok_or_else(|| "checksum mismatch".into());
+        let path = [key("files"), Index::Int(i), key("chksum")];
+        c.push(&path, m, "header checksum", result);
+    }
+    if let Some(r) = files_m.offset_in(c.root) {
+        let rest = c.root.slice(r.end..);
+        let end = &rest[..rest.len().min(tar::END_MARKER.len())];
+        let result = (end == tar::END_MARKER)
+            .then_some(())
+            .ok_or_else(|| "missing or incomplete end-of-archive marker".into());
+        c.push_at(&[], Some(r.end), "end-of-archive marker", result);
+    }
+}
+
+/// Run all integrity checks on a decoded value.
+///
+/// If `fast` is set, skip checks that need to decompress data.
+/// Data that decompresses to more than `limit` bytes fails its check.
+pub fn checks(
+    b: &Bytes,
+    o: &Val,
+    r: &d::Result,
+    fast: bool,
+    limit: usize,
+    progress: Option<Progress>,
+) -> Vec<Check> {
+    let mut c = Checks {
+        root: b,
+        checks: Vec::new(),
+    };
+    if let Err(e) = r {
+        let path: Vec<_> = e.path().iter().rev().cloned().collect();
+        c.push_at(&path, e.offset(), "decoding", Err(e.msg().into()));
+    }
+    generic(&mut c, &o.spans(b, false));
+    match o.get(&key("format")).map(|(_, v)| v) {
+        Some(Val::Str(f)) if **f == zip::Zip::NAME => zip(&mut c, o, fast, limit, progress),
+        Some(Val::Str(f)) if **f == tar::Tar::NAME => tar(&mut c, o),
+        _ => (),
+    }
+    c.checks
+}
+
+pub fn run(args: VerifyArgs) -> Result<ExitCode, Error> {
+    let (b, o, r) = decode::decode(&args.input)?;
+    let progress = progress::progress("verifying", args.input.quiet);
+    let limit = args.input.limits().decompressed();
+    let checks = checks(&b, &o, &r, args.fast, limit, progress);
+
+    let failed = checks.iter().filter(|c| c.result.is_err()).count();
+    for check in &checks {
+        let status = if check.result.is_ok() { "ok" } else { "FAIL" };
+        let offset = check.offset.map_or("-".into(), |o| format!("{o:#x}"));
+        let path = if check.path.is_empty() {
+            "<root>"
+        } else {
+            &check.path
+        };
+        let msg = check
+            .result
+            .as_ref()
+            .err()
+            .map_or(String::new(), |e| format!(": {e}"));
+        println!("{status:4}  {offset:>8}  {path}: {}{msg}", check.what);
+    }
+    println!("{} checks, {failed} failed", checks.len());
+    Ok(match failed {
+        0 => ExitCode::SUCCESS,
+        _ => ExitCode::FAILURE,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use binspan::decode::Obj;
+
+    /// Paths and results of the CRC-32 checks of `b` with `limit`.
+    fn crcs(b: &Bytes, limit: usize) -> Vec<(String, bool)> {
+        let mut o = Obj::default();
+        zip::decode_zip(&mut o, b.clone(), &Default::default()).unwrap();
+        let mut c = Checks {
+            root: b,
+            checks: Vec::new(),
+        };
+        zip(&mut c, &Val::Obj(Box::new(o)), false, limit, None);
+        let crcs = c.checks.into_iter().filter(|c| c.what == "CRC-32");
+        crcs.map(|c| (c.path, c.result.is_ok())).collect()
+    }
+
+    #[test]
+    fn crcs_follow_the_links_to_local_files() {
+        let mut b = include_bytes!("../../test.zip").to_vec();
+        let eocd = b.len() - 22;
+        let cd = u32::from_le_bytes(b[eocd + 16..eocd + 20].try_into().unwrap()) as usize;
+        // the first entry lies on another disk, so it has no local file
+        b[cd + 34] = 1;
+        let b = Bytes::from(b);
+        let path = |i| format!("local_files[{i}].compressed");
+        assert_eq!(crcs(&b, usize::MAX), [(path(0), true), (path(1), true)]);
+        // the second entry decompresses to 6 bytes, the third is empty
+        assert_eq!(crcs(&b, 5), [(path(0), false), (path(1), true)]);
+    }
+}
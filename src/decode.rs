@@ -1,39 +1,172 @@
+use crate::trace;
+use alloc::borrow::Cow;
 use bytes::Bytes;
-use core::cell::LazyCell;
+use core::cell::RefCell;
 use core::fmt::{self, Debug, Formatter};
 use core::ops::{Range, RangeBounds};
-use std::rc::Rc;
+use std::collections::HashSet;
+use std::sync::{Arc, LazyLock};
 
+/// Result of a decoding operation.
 pub type Result<T = (), E = Error> = core::result::Result<T, E>;
 
+/// Decoding error.
 #[derive(Clone, Debug)]
 pub struct Error {
-    position: Bytes,
+    /// Number of bytes from the position to the end of the buffer that it lies in.
+    remaining: usize,
+    /// Offset of the position in the input, if it is known.
+    offset: Option<usize>,
     path: Vec<Index>,
+    context: Vec<Cow<'static, str>>,
     msg: String,
 }
 
-#[derive(Clone, Debug)]
+/// Position of a value inside an object or array.
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum Index {
-    Str(&'static str),
+    Str(Key),
     Int(usize),
 }
 
+impl Index {
+    /// Parse a path such as `local_files[3].file_name`.
+    ///
+    /// Keys are separated by `.` and array indices are written in brackets.
+    /// A leading `.` is optional.
+    pub fn parse_path(s: &str) -> Result<Vec<Self>, String> {
+        let mut path = Vec::new();
+        let mut rest = s.strip_prefix('.').unwrap_or(s);
+        while !rest.is_empty() {
+            if let Some(r) = rest.strip_prefix('[') {
+                let (i, r) = r.split_once(']').ok_or("expected `]`")?;
+                let i = i
+                    .parse()
+                    .map_err(|_| format!("invalid array index `{i}`"))?;
+                path.push(Self::Int(i));
+                rest = r.strip_prefix('.').unwrap_or(r);
+            } else {
+                let end = rest.find(['.', '[']).unwrap_or(rest.len());
+                let (key, r) = rest.split_at(end);
+                if key.is_empty() {
+                    return Err(format!("empty key in path `{s}`"));
+                }
+                path.push(Self::Str(key.to_string().into()));
+                rest = r.strip_prefix('.').unwrap_or(r);
+            }
+        }
+        Ok(path)
+    }
+}
+
+/// Path from the root to a value, displayed like `local_files[3].file_name`.
+pub struct Path<'a>(pub &'a [Index]);
+
+impl fmt::Display for Path<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        for (n, i) in self.0.iter().enumerate() {
+            match i {
+                Index::Str(k) if n == 0 => write!(f, "{k}")?,
+                Index::Str(k) => write!(f, ".{k}")?,
+                Index::Int(i) => write!(f, "[{i}]")?,
+            }
+        }
+        Ok(())
+    }
+}
+
 impl Error {
+    /// Create an error that occurred at the start of `position`.
+    ///
+    /// If `position` lies in the input being decoded, see [`with_input`],
+    /// the error records its offset in the input.
     pub fn new(position: &Bytes, msg: impl ToString) -> Self {
+        let offset = INPUT.with_borrow(|c| {
+            let c = c.as_ref()?;
+            Some(c.base? + c.start_of(position)?)
+        });
         Self {
-            position: position.clone(),
+            remaining: position.len(),
+            offset,
             path: Vec::new(),
+            context: Vec::new(),
             msg: msg.to_string(),
         }
     }
 
-    fn with_index(mut self, i: Index) -> Self {
+    /// Number of bytes from the position where the error occurred
+    /// to the end of the buffer that it occurred in.
+    pub fn remaining(&self) -> usize {
+        self.remaining
+    }
+
+    /// Path from the value where the error occurred up to the root, leaf first.
+    pub fn path(&self) -> &[Index] {
+        &self.path
+    }
+
+    /// Descriptions of the structures that were being decoded, innermost first.
+    pub fn context(&self) -> &[Cow<'static, str>] {
+        &self.context
+    }
+
+    /// Error message.
+    pub fn msg(&self) -> &str {
+        &self.msg
+    }
+
+    /// Offset in the input where the error occurred.
+    ///
+    /// This is known for errors that occurred in the input being decoded,
+    /// such as by [`crate::zip::decode_zip`], but not for errors in data derived from it,
+    /// such as uncompressed data.
+    pub fn offset(&self) -> Option<usize> {
+        self.offset
+    }
+
+    /// Set the offset of the error in the input.
+    pub(crate) fn with_offset(mut self, offset: usize) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+
+    pub(crate) fn with_index(mut self, i: Index) -> Self {
         self.path.push(i);
         self
     }
+
+    /// Describe the structure that was being decoded when the error occurred.
+    ///
+    /// Decoders call this on errors that they propagate,
+    /// so that structures added later enclose those added before.
+    pub fn with_context(mut self, c: impl Into<Cow<'static, str>>) -> Self {
+        self.context.push(c.into());
+        self
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        // the path is stored leaf first
+        let path: Vec<_> = self.path.iter().rev().cloned().collect();
+        if !path.is_empty() {
+            write!(f, "{}: ", Path(&path))?;
+        }
+        if !self.context.is_empty() {
+            let context: Vec<_> = self.context.iter().rev().map(|c| &**c).collect();
+            write!(f, "in {}: ", context.join(", "))?;
+        }
+        f.write_str(&self.msg)?;
+        match self.offset {
+            Some(o) => write!(f, " (at offset {o:#x})"),
+            None => Ok(()),
+        }
+    }
 }
 
+impl std::error::Error for Error {}
+
+/// Result of a successful decoder: metadata, value, and output for the decoder.
 pub struct Decoded<T> {
     pub meta: Meta,
     pub val: Val,
@@ -63,265 +196,3543 @@ impl<T> Decoded<T> {
             val: self.val,
         }
     }
+
+    /// Set the human-readable description of the value.
+    ///
+    /// ```
+    /// use binspan::decode::{le, Obj};
+    /// use bytes::Bytes;
+    ///
+    /// let mut b = Bytes::from_static(&[0x14, 0x00]);
+    /// let mut o = Obj::default();
+    /// let d = le::u16(&mut b).map(|d| d.describe("version needed to extract"));
+    /// assert_eq!(o.add("version", d)?, 20);
+    /// let (m, _) = o.get("version").unwrap();
+    /// assert_eq!(m.description(), Some("version needed to extract"));
+    /// # Ok::<(), binspan::decode::Error>(())
+    /// ```
+    pub fn describe(self, description: impl Into<String>) -> Self {
+        self.map_meta(|m| m.describe(Some(description.into())))
+    }
 }
 
+/// Function that renders a value in a custom way, such as a number in hexadecimal.
+pub type FormatFn = fn(&Val, &mut Formatter) -> fmt::Result;
+
 /// Metadata of a value.
 ///
 /// This is precisely the information that gets lost when changing a value.
-#[derive(Clone, Debug)]
+///
+/// Instead of a [`Bytes`] handle, the metadata stores the range of its bytes
+/// in a [`Cursor`] that all values decoded from the same buffer share,
+/// which keeps it small and lets it know its offset in the input.
+#[derive(Clone)]
 pub struct Meta {
-    pub bytes: Bytes,
+    src: Arc<Cursor>,
+    /// Range of the bytes in the buffer of `src`.
+    start: usize,
+    len: usize,
+    /// Metadata that most values do not have, boxed to keep `Meta` small.
+    extra: Option<Box<MetaExtra>>,
+}
+
+#[derive(Clone, Debug, Default)]
+struct MetaExtra {
     error: Option<Error>,
-    format: Option<fn(&Val) -> fmt::Result>,
+    /// Conditions that did not prevent decoding, but that are suspicious.
+    warnings: Vec<String>,
+    format: Option<FormatFn>,
     description: Option<String>,
+    /// Metadata of the bytes that the bytes were derived from, and the transformation.
+    derived: Option<(Meta, &'static str)>,
+    /// Path from the root to the value that the value refers to.
+    points_to: Option<Vec<Index>>,
 }
 
 impl Meta {
-    pub fn describe(self, description: Option<String>) -> Self {
+    /// Bytes that the value was decoded from.
+    ///
+    /// This slices the buffer that the bytes lie in.
+    pub fn bytes(&self) -> Bytes {
+        self.src.data.slice(self.start..self.start + self.len)
+    }
+
+    /// Error that occurred while decoding the value.
+    pub fn error(&self) -> Option<&Error> {
+        self.extra.as_ref()?.error.as_ref()
+    }
+
+    /// Set the error that occurred while decoding the value.
+    pub fn with_error(mut self, e: Error) -> Self {
+        self.extra_mut().error = Some(e);
+        self
+    }
+
+    /// Range of the bytes in the input, if they were decoded from the input.
+    ///
+    /// This is not known for bytes derived from the input, such as uncompressed data,
+    /// nor for bytes decoded outside of [`with_input`] and [`Cursor::run`].
+    pub fn offset(&self) -> Option<Range<usize>> {
+        let start = self.src.base? + self.start;
+        Some(start..start + self.len)
+    }
+
+    /// Range of the bytes relative to `root`,
+    /// if `root` is the buffer that they were decoded from.
+    ///
+    /// This works for all values decoded from `root`,
+    /// such as the input given to [`crate::zip::decode_zip`],
+    /// including lazily built values, which share the buffer of the value they were built from.
+    pub fn offset_in(&self, root: &Bytes) -> Option<Range<usize>> {
+        let data = &self.src.data;
+        let same = data.as_ptr() == root.as_ptr() && data.len() == root.len();
+        same.then(|| self.start..self.start + self.len)
+    }
+
+    /// Metadata of the same bytes, without the rest of the metadata.
+    pub fn span(&self) -> Self {
         Self {
-            description,
-            ..self
+            src: self.src.clone(),
+            start: self.start,
+            len: self.len,
+            extra: None,
         }
     }
-}
 
-impl From<Bytes> for Meta {
-    fn from(bytes: Bytes) -> Self {
-        Self {
-            bytes,
-            error: None,
-            format: None,
-            description: None,
+    /// Make the metadata refer to `b`, keeping the rest of the metadata.
+    fn respan(&mut self, b: &Bytes) {
+        let Self {
+            src, start, len, ..
+        } = Self::from(b);
+        (self.src, self.start, self.len) = (src, start, len);
+    }
+
+    /// Metadata of bytes derived from the bytes of `parent` by the transformation `via`,
+    /// such as `"deflate"` for uncompressed data.
+    pub fn derived(bytes: Bytes, parent: &Meta, via: &'static str) -> Self {
+        let mut m = Self::from(bytes);
+        m.extra_mut().derived = Some((parent.clone(), via));
+        m
+    }
+
+    /// Metadata of the bytes that the bytes were derived from, and the transformation.
+    pub fn derived_from(&self) -> Option<(&Meta, &'static str)> {
+        let (m, via) = self.extra.as_ref()?.derived.as_ref()?;
+        Some((m, via))
+    }
+
+    /// Range relative to `root` of the bytes, or of the bytes that they were derived from.
+    ///
+    /// This follows derivations through multiple levels,
+    /// such as data uncompressed from data uncompressed from `root`.
+    pub fn origin_in(&self, root: &Bytes) -> Option<Range<usize>> {
+        (self.offset_in(root)).or_else(|| self.derived_from()?.0.origin_in(root))
+    }
+
+    /// Path from the root to the value that the value refers to,
+    /// such as the local file that an offset points to.
+    ///
+    /// The path is not guaranteed to exist, for example if decoding stopped before reaching it.
+    pub fn points_to(&self) -> Option<&[Index]> {
+        self.extra.as_ref()?.points_to.as_deref()
+    }
+
+    /// Record that the value refers to the value at `path`, see [`Self::points_to`].
+    pub fn point_to(&mut self, path: Vec<Index>) {
+        self.extra_mut().points_to = Some(path)
+    }
+
+    /// Conditions that did not prevent decoding the value, but that are suspicious.
+    pub fn warnings(&self) -> &[String] {
+        self.extra.as_ref().map_or(&[], |e| &e.warnings)
+    }
+
+    /// Add a warning, see [`Self::warnings`].
+    pub fn warn(&mut self, msg: impl Into<String>) {
+        self.extra_mut().warnings.push(msg.into())
+    }
+
+    /// Function to format the value.
+    pub fn format(&self) -> Option<FormatFn> {
+        self.extra.as_ref()?.format
+    }
+
+    /// Format the value with [`Self::format`] if it is set.
+    pub fn formatted(&self, v: &Val) -> Option<String> {
+        struct Display<'a>(FormatFn, &'a Val);
+        impl fmt::Display for Display<'_> {
+            fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+                (self.0)(self.1, f)
+            }
+        }
+        Some(Display(self.format()?, v).to_string())
+    }
+
+    pub fn with_format(mut self, format: FormatFn) -> Self {
+        self.extra_mut().format = Some(format);
+        self
+    }
+
+    /// Human-readable description of the value.
+    pub fn description(&self) -> Option<&str> {
+        self.extra.as_ref()?.description.as_deref()
+    }
+
+    pub fn describe(mut self, description: Option<String>) -> Self {
+        if description.is_some() || self.extra.is_some() {
+            self.extra_mut().description = description;
         }
+        self
+    }
+
+    fn extra_mut(&mut self) -> &mut MetaExtra {
+        self.extra.get_or_insert_with(Default::default)
+    }
+}
+
+impl Debug for Meta {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("Meta")
+            .field("bytes", &self.bytes())
+            .field("extra", &self.extra)
+            .finish()
     }
 }
 
+/// Metadata of bytes in the input being decoded refer to its cursor,
+/// other bytes get a cursor of their own.
 impl From<&Bytes> for Meta {
     fn from(bytes: &Bytes) -> Self {
-        Self::from(bytes.clone())
+        let in_input = INPUT.with_borrow(|c| {
+            let c = c.as_ref()?;
+            Some((c.clone(), c.start_of(bytes)?))
+        });
+        let (src, start) = in_input.unwrap_or_else(|| match bytes.is_empty() {
+            true => (EMPTY.clone(), 0),
+            false => (Arc::new(Cursor::derived(bytes.clone())), 0),
+        });
+        Self {
+            src,
+            start,
+            len: bytes.len(),
+            extra: None,
+        }
+    }
+}
+
+impl From<Bytes> for Meta {
+    fn from(bytes: Bytes) -> Self {
+        Self::from(&bytes)
+    }
+}
+
+/// Buffer that holds the input or a part of it, together with its offset in the input.
+///
+/// The values decoded from a buffer share its cursor,
+/// which is why their metadata is small and knows their offsets in the input,
+/// also if the input is read into several buffers, such as when decoding from a stream.
+#[derive(Debug)]
+pub struct Cursor {
+    data: Bytes,
+    /// Offset of `data` in the input, unknown for data derived from the input.
+    base: Option<usize>,
+}
+
+/// Cursor of metadata of empty bytes outside of the input.
+static EMPTY: LazyLock<Arc<Cursor>> = LazyLock::new(|| Arc::new(Cursor::derived(Bytes::new())));
+
+std::thread_local! {
+    /// Cursor that the current thread decodes from, see [`Cursor::run`].
+    static INPUT: RefCell<Option<Arc<Cursor>>> = const { RefCell::new(None) };
+}
+
+impl Cursor {
+    /// Cursor of `data`, which starts at offset `base` of the input.
+    pub fn new(data: Bytes, base: usize) -> Arc<Self> {
+        let base = Some(base);
+        Arc::new(Self { data, base })
+    }
+
+    fn derived(data: Bytes) -> Self {
+        Self { data, base: None }
+    }
+
+    pub fn data(&self) -> &Bytes {
+        &self.data
+    }
+
+    /// Offset of the data in the input, if the data is part of the input.
+    pub fn base(&self) -> Option<usize> {
+        self.base
+    }
+
+    /// Start of `b` in the data, if `b` was sliced from the data.
+    fn start_of(&self, b: &Bytes) -> Option<usize> {
+        // like `Bytes::slice_ref`, this compares addresses only within the same buffer
+        let start = (b.as_ptr() as usize).wrapping_sub(self.data.as_ptr() as usize);
+        (start <= self.data.len() && b.len() <= self.data.len() - start).then_some(start)
+    }
+
+    /// Run `f`, decoding from this cursor.
+    ///
+    /// The values and errors that `f` creates from bytes of the data refer to this cursor,
+    /// see [`Meta::offset`] and [`Error::offset`].
+    /// Calls can be nested, in which case the innermost cursor is used.
+    pub fn run<T>(self: &Arc<Self>, f: impl FnOnce() -> T) -> T {
+        within(Some(self.clone()), f)
+    }
+}
+
+/// Cursor that the current thread decodes from,
+/// to continue decoding from it on other threads or when forcing lazy values.
+pub(crate) fn current_input() -> Option<Arc<Cursor>> {
+    INPUT.with_borrow(|c| c.clone())
+}
+
+/// Reinstalls the outer cursor when dropped, also if decoding panics.
+struct Restore(Option<Arc<Cursor>>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        INPUT.set(self.0.take())
+    }
+}
+
+/// Run `f`, decoding from `input`.
+pub(crate) fn within<T>(input: Option<Arc<Cursor>>, f: impl FnOnce() -> T) -> T {
+    let _restore = Restore(INPUT.replace(input));
+    f()
+}
+
+/// Run `f` to decode `b`.
+///
+/// Unless `b` lies in the data of the cursor that is already decoded from,
+/// this runs `f` with a cursor of `b`, which starts the input if no cursor is decoded from,
+/// and is derived from the input otherwise, such as uncompressed data.
+/// Decoders of whole inputs, such as [`crate::zip::decode_zip`], call this,
+/// so that the offsets of their values and errors are relative to the input that they are given.
+pub fn with_input<T>(b: &Bytes, f: impl FnOnce() -> T) -> T {
+    let cursor = INPUT.with_borrow(|c| match c {
+        Some(c) if c.start_of(b).is_some() => None,
+        Some(_) => Some(Arc::new(Cursor::derived(b.clone()))),
+        None => Some(Cursor::new(b.clone(), 0)),
+    });
+    match cursor {
+        Some(c) => c.run(f),
+        None => f(),
+    }
+}
+
+/// Options for [`Val::eval_with`].
+#[derive(Clone, Debug)]
+pub struct EvalOpts {
+    /// Maximal depth of evaluated values, where the children of the evaluated value have depth 1.
+    pub max_depth: Option<usize>,
+    /// Maximal number of evaluated values, not counting the evaluated value itself.
+    pub max_nodes: Option<usize>,
+    /// Force lazy values.
+    pub force_lazy: bool,
+}
+
+/// The default options evaluate like [`Val::eval`].
+impl Default for EvalOpts {
+    fn default() -> Self {
+        Self {
+            max_depth: None,
+            max_nodes: None,
+            force_lazy: true,
+        }
     }
 }
 
+/// Decoded value.
+///
+/// Strings, arrays, and objects are boxed to keep values small.
 #[derive(Clone)]
 pub enum Val {
+    /// Value of a field that exists, but has no value,
+    /// such as data that could not be uncompressed.
+    ///
+    /// In contrast to [`Val::Raw`], the bytes of the field are not its value.
+    Null,
     Bool(bool),
     U8(u8),
     U16(u16),
     U32(u32),
     U64(u64),
-    Raw { gap: bool },
-    Str(Bytes),
-    Arr(Arr),
-    Obj(Obj),
-    Lazy(Rc<LazyCell<Val, Box<dyn FnOnce() -> Val>>>),
+    /// Boxed, because it would double the size of values otherwise.
+    U128(Box<u128>),
+    I8(i8),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+    /// Value that consists of the bytes it was decoded from, such as padding.
+    ///
+    /// If `gap` is true, no decoder accounted for the bytes.
+    Raw {
+        gap: bool,
+    },
+    /// Bytes that are not text, such as file contents or names of unknown encoding.
+    Bytes(Box<Bytes>),
+    /// Bytes that the decoder asserts to be text.
+    Str(Box<Bytes>),
+    Arr(Box<Arr>),
+    Obj(Box<Obj>),
+    Lazy(Arc<LazyLock<Val, Box<dyn FnOnce() -> Val + Send>>>),
 }
 
+const _: () = assert!(core::mem::size_of::<Val>() == 16);
+
+// decoded values can be processed on other threads, even if they contain lazy values
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Val>();
+    assert_send_sync::<Meta>();
+};
+
 impl Debug for Val {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        let map = |&(ref k, ref m, ref v)| (k, (m, v));
         match self {
+            Val::Null => "Null".fmt(f),
             Val::Bool(b) => b.fmt(f),
             Val::U8(u) => u.fmt(f),
             Val::U16(u) => u.fmt(f),
             Val::U32(u) => u.fmt(f),
             Val::U64(u) => u.fmt(f),
+            Val::U128(u) => u.fmt(f),
+            Val::I8(i) => i.fmt(f),
+            Val::I16(i) => i.fmt(f),
+            Val::I32(i) => i.fmt(f),
+            Val::I64(i) => i.fmt(f),
+            Val::F32(x) => x.fmt(f),
+            Val::F64(x) => x.fmt(f),
             Val::Raw { .. } => "Raw".fmt(f),
+            Val::Bytes(b) => b.fmt(f),
             Val::Str(s) => s.fmt(f),
             Val::Arr(a) => a.0.fmt(f),
-            Val::Obj(o) => f.debug_map().entries(o.0.iter().map(map)).finish(),
-            Val::Lazy(l) => l.fmt(f),
+            Val::Obj(o) => f
+                .debug_map()
+                .entries(o.0.iter().map(|(k, m, v)| (k, (m, v))))
+                .finish(),
+            Val::Lazy(l) => match LazyLock::get(l) {
+                Some(v) => v.fmt(f),
+                None => "Lazy".fmt(f),
+            },
         }
     }
 }
 
-#[derive(Clone, Debug, Default)]
-pub struct Obj(pub Vec<(&'static str, Meta, Val)>);
+/// Warning found in a decoded value, see [`Val::warnings`].
+#[derive(Clone, Debug)]
+pub struct Warning {
+    /// Path from the root to the value that the warning is about.
+    pub path: Vec<Index>,
+    /// Offset of the value in the input, if it lies in the input.
+    pub offset: Option<usize>,
+    pub msg: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}: {}", Path(&self.path), self.msg)?;
+        match self.offset {
+            Some(o) => write!(f, " (at offset {o:#x})"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Location of a value in the input.
+#[derive(Clone, Debug)]
+pub struct Span {
+    /// Path from the root to the value.
+    pub path: Vec<Index>,
+    /// Range of the bytes of the value in the input.
+    pub range: Range<usize>,
+    /// True if the value has no children.
+    pub leaf: bool,
+    /// Rendering of the value in a single line, if it is neither an array nor an object.
+    ///
+    /// This is `None` for lazy values that were not forced.
+    pub value: Option<String>,
+}
+
+/// Sequence of named values, in the order in which they were added.
+///
+/// Keys are usually static, but can also be derived from the input,
+/// such as the names of files.
+/// What happens when a key is added twice is determined by [`DuplicateKeys`].
+#[derive(Clone, Default)]
+pub struct Obj(
+    Vec<(Key, Meta, Val)>,
+    DuplicateKeys,
+    // boxed, because most objects are small and have no index
+    #[allow(clippy::box_collection)] Option<Box<HashSet<Key>>>,
+);
 
+/// Number of fields from which an object that renames duplicate keys indexes its keys,
+/// so that adding a key does not compare it with every other key.
+const INDEXED_LEN: usize = 16;
+
+/// What an object does when a key is added that it already contains.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum DuplicateKeys {
+    /// Append `_2`, `_3`, … to the key, so that all keys stay unique.
+    #[default]
+    Suffix,
+    /// Keep the key as is, so that the object may contain it several times.
+    Keep,
+}
+
+/// Sequence of values.
 #[derive(Clone, Debug, Default)]
-pub struct Arr(pub Vec<(Meta, Val)>);
+pub struct Arr(Vec<(Meta, Val)>);
 
-impl Val {
-    pub fn eval(&self) -> Self {
-        let fo = |(k, m, v): &(_, Meta, Val)| (*k, m.clone(), v.eval());
-        let fa = |(m, v): &(Meta, Val)| (m.clone(), v.eval());
-        match self {
-            Self::Lazy(l) => LazyCell::force(l).eval(),
-            Self::Arr(Arr(a)) => Self::Arr(Arr(a.iter().map(fa).collect())),
-            Self::Obj(Obj(o)) => Self::Obj(Obj(o.iter().map(fo).collect())),
-            Self::Raw { .. } | Self::Str(_) | Self::Bool(_) => self.clone(),
-            Self::U8(_) | Self::U16(_) | Self::U32(_) | Self::U64(_) => self.clone(),
-        }
+type ObjEntry = (Key, Meta, Val);
+type ObjRef<'a> = (&'a str, &'a Meta, &'a Val);
+type ObjIter<'a> = core::iter::Map<core::slice::Iter<'a, ObjEntry>, fn(&ObjEntry) -> ObjRef>;
+type ArrRef<'a> = (&'a Meta, &'a Val);
+type ArrIter<'a> = core::iter::Map<core::slice::Iter<'a, (Meta, Val)>, fn(&(Meta, Val)) -> ArrRef>;
+
+impl Obj {
+    /// Create an empty object with the given policy for duplicate keys.
+    pub fn with_duplicate_keys(dup: DuplicateKeys) -> Self {
+        Self(Vec::new(), dup, None)
     }
 
-    pub fn make_arr(&mut self) -> &mut Arr {
-        *self = Val::Arr(Arr::default());
-        match self {
-            Val::Arr(a) => a,
-            _ => unreachable!(),
-        }
+    /// Number of fields.
+    pub fn len(&self) -> usize {
+        self.0.len()
     }
 
-    pub fn make_obj(&mut self) -> &mut Obj {
-        *self = Val::Obj(Obj::default());
-        match self {
-            Val::Obj(o) => o,
-            _ => unreachable!(),
-        }
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Fields in the order in which they were added.
+    pub fn iter(&self) -> ObjIter<'_> {
+        self.0.iter().map(|(k, m, v)| (k, m, v))
     }
 
-    pub fn lazy(f: impl FnOnce() -> Self + 'static) -> Self {
-        Self::Lazy(Rc::new(LazyCell::new(Box::new(f))))
+    /// Last added field.
+    pub fn last(&self) -> Option<ObjRef<'_>> {
+        self.0.last().map(|(k, m, v)| (&**k, m, v))
     }
 }
 
-impl Default for Val {
-    fn default() -> Self {
-        Self::Raw { gap: false }
+/// The index of keys is left out, because the order of its keys is arbitrary.
+impl Debug for Obj {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_tuple("Obj").field(&self.0).field(&self.1).finish()
     }
 }
 
-impl Obj {
-    pub fn add_mut<T, F>(&mut self, field: &'static str, m: Meta, f: F) -> Result<T>
-    where
-        F: FnOnce(&mut Meta, &mut Val) -> Result<T>,
-    {
-        self.0.push((field, m, Val::default()));
-        match self.0.last_mut() {
-            Some((_, m, v)) => f(m, v).map_err(|e| {
-                m.error = Some(e.clone());
-                e.with_index(Index::Str(field))
-            }),
-            _ => unreachable!(),
-        }
+impl<'a> IntoIterator for &'a Obj {
+    type Item = ObjRef<'a>;
+    type IntoIter = ObjIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
     }
+}
 
-    pub fn add_consumed<T, F>(&mut self, field: &'static str, b: &mut Bytes, f: F) -> Result<T>
-    where
-        F: FnOnce(&mut Bytes, &mut Val) -> Result<T>,
-    {
-        self.add_mut(field, Meta::from(&*b), |m, v| consume(b, m, |b| f(b, v)))
+impl IntoIterator for Obj {
+    type Item = ObjEntry;
+    type IntoIter = alloc::vec::IntoIter<ObjEntry>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
     }
+}
 
-    pub fn add<T>(&mut self, field: &'static str, r: Result<Decoded<T>>) -> Result<T> {
-        let d = r.map_err(|e| e.with_index(Index::Str(field)))?;
-        self.0.push((field, d.meta, d.val));
-        Ok(d.out)
+/// Keys are handled like [`DuplicateKeys::Suffix`].
+impl FromIterator<ObjEntry> for Obj {
+    fn from_iter<I: IntoIterator<Item = ObjEntry>>(iter: I) -> Self {
+        let mut o = Self::default();
+        iter.into_iter().for_each(|(k, m, v)| o.push(k, m, v));
+        o
     }
 }
 
 impl Arr {
-    pub fn add_mut<T, F>(&mut self, m: Meta, f: F) -> Result<T>
-    where
-        F: FnOnce(&mut Meta, &mut Val) -> Result<T>,
-    {
-        let i = self.0.len();
-        self.0.push((m, Val::default()));
-        match self.0.last_mut() {
-            Some((m, v)) => f(m, v).map_err(|e| {
-                m.error = Some(e.clone());
-                e.with_index(Index::Int(i))
-            }),
-            _ => unreachable!(),
-        }
+    /// Number of elements.
+    pub fn len(&self) -> usize {
+        self.0.len()
     }
 
-    pub fn add_consumed<T, F>(&mut self, b: &mut Bytes, f: F) -> Result<T>
-    where
-        F: FnOnce(&mut Bytes, &mut Val) -> Result<T>,
-    {
-        self.add_mut(Meta::from(&*b), |m, v| consume(b, m, |b| f(b, v)))
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
     }
-}
 
-pub fn take(left: &mut Bytes, n: usize) -> Result<Bytes> {
-    let right = try_split_off(left, n)?;
-    Ok(core::mem::replace(left, right))
-}
+    /// Elements in the order in which they were added.
+    pub fn iter(&self) -> ArrIter<'_> {
+        self.0.iter().map(|(m, v)| (m, v))
+    }
 
-pub fn try_split_off(b: &mut Bytes, at: usize) -> Result<Bytes> {
-    if at > b.len() {
-        Err(Error::new(b, format!("expected {at} bytes")))
-    } else {
-        Ok(b.split_off(at))
+    /// Last added element.
+    pub fn last(&self) -> Option<ArrRef<'_>> {
+        self.0.last().map(|(m, v)| (m, v))
     }
 }
 
-fn to_range(bounds: impl RangeBounds<usize>, len: usize) -> Result<Range<usize>, usize> {
-    use core::ops::Bound;
-    let begin = match bounds.start_bound() {
-        Bound::Included(&n) => n,
-        Bound::Excluded(&n) => n.checked_add(1).ok_or(n)?,
-        Bound::Unbounded => 0,
-    };
-    let end = match bounds.end_bound() {
-        Bound::Included(&n) => n.checked_add(1).ok_or(n)?,
-        Bound::Excluded(&n) => n,
-        Bound::Unbounded => len,
-    };
+impl<'a> IntoIterator for &'a Arr {
+    type Item = ArrRef<'a>;
+    type IntoIter = ArrIter<'a>;
 
-    let max = core::cmp::max(begin, end);
-    if max > len {
-        Err(max)
-    } else {
-        Ok(begin..end)
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
     }
 }
 
-// Panics if `range.start > range.end`!
-pub fn try_slice(b: &Bytes, range: impl RangeBounds<usize>) -> Result<Bytes> {
-    let err = |n| Error::new(b, format!("expected {n} bytes"));
-    Ok(b.slice(to_range(range, b.len()).map_err(err)?))
+impl IntoIterator for Arr {
+    type Item = (Meta, Val);
+    type IntoIter = alloc::vec::IntoIter<(Meta, Val)>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.0.into_iter()
+    }
 }
 
-fn consumed<T>(b: &mut Bytes, f: impl FnOnce(&mut Bytes) -> Result<T>) -> Result<(Bytes, T)> {
-    let mut start = b.clone();
-    let y = f(b)?;
-    start.truncate(start.len() - b.len());
-    Ok((start, y))
+impl FromIterator<(Meta, Val)> for Arr {
+    fn from_iter<I: IntoIterator<Item = (Meta, Val)>>(iter: I) -> Self {
+        Self(iter.into_iter().collect())
+    }
 }
 
-pub fn consume<T>(
-    b: &mut Bytes,
-    to: &mut Meta,
-    f: impl FnOnce(&mut Bytes) -> Result<T>,
-) -> Result<T> {
-    let (consumed, y) = consumed(b, f)?;
-    to.bytes = consumed;
-    Ok(y)
+/// Key of a field in an [`Obj`], which is cheap to clone.
+///
+/// Keys are usually static strings.
+/// Keys read from the input are shared through an [`Interner`],
+/// so that a key that occurs many times is stored only once.
+#[derive(Clone)]
+pub struct Key(KeyRepr);
+
+#[derive(Clone)]
+enum KeyRepr {
+    Static(&'static str),
+    Shared(Arc<str>),
 }
 
-macro_rules! decode_int {
-    ($width: expr, $f:ident, $ty:ident, $val:expr) => {
-        pub fn $ty(b: &mut Bytes) -> Result<Decoded<$ty>> {
-            let b = take(b, $width)?;
-            // SAFETY: if `take` returns `Ok(b)`, then `b.len() = $width`
-            let a: [u8; $width] = (*b).try_into().unwrap();
-            let u = $ty::$f(a);
-            Ok(Decoded::new(Meta::from(b), $val(u), u))
+impl Key {
+    pub fn as_str(&self) -> &str {
+        match &self.0 {
+            KeyRepr::Static(s) => s,
+            KeyRepr::Shared(s) => s,
         }
-    };
+    }
+}
+
+impl core::ops::Deref for Key {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for Key {
+    fn as_ref(&self) -> &str {
+        self
+    }
+}
+
+impl core::borrow::Borrow<str> for Key {
+    fn borrow(&self) -> &str {
+        self
+    }
+}
+
+impl From<&'static str> for Key {
+    fn from(s: &'static str) -> Self {
+        Self(KeyRepr::Static(s))
+    }
+}
+
+impl From<Arc<str>> for Key {
+    fn from(s: Arc<str>) -> Self {
+        Self(KeyRepr::Shared(s))
+    }
+}
+
+impl From<String> for Key {
+    fn from(s: String) -> Self {
+        Self(KeyRepr::Shared(s.into()))
+    }
+}
+
+impl From<Cow<'static, str>> for Key {
+    fn from(s: Cow<'static, str>) -> Self {
+        match s {
+            Cow::Borrowed(s) => s.into(),
+            Cow::Owned(s) => s.into(),
+        }
+    }
+}
+
+impl PartialEq for Key {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for Key {}
+
+impl PartialEq<str> for Key {
+    fn eq(&self, other: &str) -> bool {
+        &**self == other
+    }
+}
+
+impl PartialEq<&str> for Key {
+    fn eq(&self, other: &&str) -> bool {
+        &**self == *other
+    }
+}
+
+impl PartialEq<Key> for str {
+    fn eq(&self, other: &Key) -> bool {
+        self == &**other
+    }
+}
+
+impl PartialEq<Key> for &str {
+    fn eq(&self, other: &Key) -> bool {
+        *self == &**other
+    }
+}
+
+impl PartialOrd for Key {
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Key {
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        (**self).cmp(&**other)
+    }
+}
+
+impl core::hash::Hash for Key {
+    fn hash<H: core::hash::Hasher>(&self, h: &mut H) {
+        (**self).hash(h)
+    }
+}
+
+impl Debug for Key {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        Debug::fmt(&**self, f)
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        fmt::Display::fmt(&**self, f)
+    }
+}
+
+/// Set of keys read from an input, so that equal keys share their text.
+///
+/// A decoder owns its interner while it decodes an input, such as a tar archive.
+/// Text is freed once the interner and all keys referring to it are dropped.
+#[derive(Default)]
+pub struct Interner(HashSet<Arc<str>>);
+
+impl Interner {
+    /// Return a key equal to `s`, allocating it only if it was not interned before.
+    pub fn intern(&mut self, s: &str) -> Key {
+        if let Some(k) = self.0.get(s) {
+            return Key::from(k.clone());
+        }
+        let k: Arc<str> = s.into();
+        self.0.insert(k.clone());
+        k.into()
+    }
+}
+
+impl Val {
+    /// Force all lazy values.
+    pub fn eval(&self) -> Self {
+        let fo = |(k, m, v): &(Key, Meta, Val)| (k.clone(), m.clone(), v.eval());
+        let fa = |(m, v): &(Meta, Val)| (m.clone(), v.eval());
+        match self {
+            Self::Lazy(l) => LazyLock::force(l).eval(),
+            Self::Arr(a) => Self::Arr(Box::new(Arr(a.0.iter().map(fa).collect()))),
+            Self::Obj(o) => Self::Obj(Box::new(Obj(o.0.iter().map(fo).collect(), o.1, None))),
+            Self::Null | Self::Raw { .. } | Self::Bytes(_) | Self::Str(_) => self.clone(),
+            Self::Bool(_) => self.clone(),
+            Self::U8(_) | Self::U16(_) | Self::U32(_) | Self::U64(_) => self.clone(),
+            Self::I8(_) | Self::I16(_) | Self::I32(_) | Self::I64(_) => self.clone(),
+            Self::U128(_) | Self::F32(_) | Self::F64(_) => self.clone(),
+        }
+    }
+
+    /// Force all lazy values and replace them by their produced values.
+    ///
+    /// In contrast to [`Self::eval`], this does not copy values that are not lazy.
+    /// The produced values of lazy values are only copied if the lazy value is shared.
+    pub fn eval_in_place(&mut self) {
+        if let Self::Lazy(l) = self {
+            *self = match Arc::get_mut(l) {
+                Some(l) => core::mem::take(&mut **l),
+                None => LazyLock::force(l).clone(),
+            };
+            return self.eval_in_place();
+        }
+        match self {
+            Self::Arr(a) => a.0.iter_mut().for_each(|(_, v)| v.eval_in_place()),
+            Self::Obj(o) => o.0.iter_mut().for_each(|(_, _, v)| v.eval_in_place()),
+            _ => (),
+        }
+    }
+
+    /// Force lazy values if `opts.force_lazy` is true, within the limits of `opts`.
+    ///
+    /// Children beyond the limits are replaced by raw values
+    /// whose description says which limit was reached.
+    pub fn eval_with(&self, opts: &EvalOpts) -> Self {
+        self.eval_limited(opts, 0, &mut 0)
+    }
+
+    /// Evaluate a value at `depth`, where `nodes` values were already evaluated.
+    fn eval_limited(&self, opts: &EvalOpts, depth: usize, nodes: &mut usize) -> Self {
+        let mut child = |m: &Meta, v: &Val| {
+            // keep only the bytes, because a format for example would not fit the placeholder
+            let truncated = |limit| (m.span().describe(Some(limit)), Self::default());
+            if opts.max_depth.is_some_and(|max| depth >= max) {
+                truncated("truncated: depth limit".into())
+            } else if opts.max_nodes.is_some_and(|max| *nodes >= max) {
+                truncated("truncated: node limit".into())
+            } else {
+                *nodes += 1;
+                (m.clone(), v.eval_limited(opts, depth + 1, nodes))
+            }
+        };
+        match self.forced_if(opts.force_lazy) {
+            None => self.clone(),
+            Some(Self::Arr(a)) => Self::Arr(Box::new(a.iter().map(|(m, v)| child(m, v)).collect())),
+            Some(Self::Obj(o)) => {
+                let entries = o.0.iter().map(|(k, m, v)| {
+                    let (m, v) = child(m, v);
+                    (k.clone(), m, v)
+                });
+                Self::Obj(Box::new(Obj(entries.collect(), o.1, None)))
+            }
+            Some(v) => v.clone(),
+        }
+    }
+
+    /// Return the value itself, or the value produced by it if it is lazy.
+    pub fn force(&self) -> &Self {
+        match self {
+            Self::Lazy(l) => LazyLock::force(l).force(),
+            v => v,
+        }
+    }
+
+    /// Return the value produced by a lazy value if it was already forced, without forcing it.
+    ///
+    /// Values that are not lazy are returned as they are.
+    pub fn forced(&self) -> Option<&Self> {
+        match self {
+            Self::Lazy(l) => LazyLock::get(l)?.forced(),
+            v => Some(v),
+        }
+    }
+
+    /// Return [`Self::force`] if `force` is true, else [`Self::forced`].
+    pub fn forced_if(&self, force: bool) -> Option<&Self> {
+        if force {
+            Some(self.force())
+        } else {
+            self.forced()
+        }
+    }
+
+    /// Name of the variant of the value, such as `"U16"`.
+    pub fn variant(&self) -> &'static str {
+        match self {
+            Self::Null => "Null",
+            Self::Bool(_) => "Bool",
+            Self::U8(_) => "U8",
+            Self::U16(_) => "U16",
+            Self::U32(_) => "U32",
+            Self::U64(_) => "U64",
+            Self::U128(_) => "U128",
+            Self::I8(_) => "I8",
+            Self::I16(_) => "I16",
+            Self::I32(_) => "I32",
+            Self::I64(_) => "I64",
+            Self::F32(_) => "F32",
+            Self::F64(_) => "F64",
+            Self::Raw { .. } => "Raw",
+            Self::Bytes(_) => "Bytes",
+            Self::Str(_) => "Str",
+            Self::Arr(_) => "Arr",
+            Self::Obj(_) => "Obj",
+            Self::Lazy(_) => "Lazy",
+        }
+    }
+
+    /// Return the child at index `i`, forcing lazy values.
+    ///
+    /// If an object contains the same key several times, the first match is returned.
+    pub fn get(&self, i: &Index) -> Option<(&Meta, &Val)> {
+        match (self.force(), i) {
+            (Self::Obj(o), Index::Str(k)) => o.get(k),
+            (Self::Arr(a), Index::Int(i)) => a.0.get(*i).map(|(m, v)| (m, v)),
+            _ => None,
+        }
+    }
+
+    /// Return the descendant at `path`, forcing lazy values along the way.
+    ///
+    /// The metadata `m` belongs to `self`.
+    /// On failure, return the length of the longest prefix of `path` that exists.
+    pub fn get_path<'a>(
+        &'a self,
+        m: &'a Meta,
+        path: &[Index],
+    ) -> Result<(&'a Meta, &'a Val), usize> {
+        let mut mv = (m, self);
+        for (n, i) in path.iter().enumerate() {
+            mv = mv.1.get(i).ok_or(n)?;
+        }
+        Ok(mv)
+    }
+
+    /// Return the descendant at a path such as `files[0].name`, see [`Index::parse_path`].
+    ///
+    /// Lazy values along the path are forced.
+    /// On failure, name the shortest prefix of the path that does not exist.
+    pub fn query(&self, path: &str) -> Result<&Val, String> {
+        let path = Index::parse_path(path)?;
+        let mut v = self;
+        for (n, i) in path.iter().enumerate() {
+            let missing = || format!("no value at `{}`", Path(&path[..=n]));
+            v = v.get(i).ok_or_else(missing)?.1;
+        }
+        Ok(v)
+    }
+
+    /// Return the warnings of all descendants, sorted by their offsets in `root`.
+    ///
+    /// Warnings at the same offset, and warnings about values that do not lie in `root`,
+    /// which come last, are in depth-first order, parents before children.
+    /// Lazy values are not forced, but the warnings of lazy values that were forced are included.
+    pub fn warnings(&self, root: &Bytes) -> Vec<Warning> {
+        let mut warnings = Vec::new();
+        self.warnings_into(root, &mut Vec::new(), &mut warnings);
+        warnings.sort_by_key(|w| (w.offset.is_none(), w.offset));
+        warnings
+    }
+
+    fn warnings_into(&self, root: &Bytes, path: &mut Vec<Index>, warnings: &mut Vec<Warning>) {
+        let mut visit = |i, m: &Meta, v: &Self| {
+            path.push(i);
+            let offset = || m.offset_in(root).map(|r| r.start);
+            warnings.extend(m.warnings().iter().map(|msg| Warning {
+                path: path.clone(),
+                offset: offset(),
+                msg: msg.clone(),
+            }));
+            v.warnings_into(root, path, warnings);
+            path.pop();
+        };
+        match self.forced() {
+            Some(Self::Obj(o)) => {
+                o.0.iter()
+                    .for_each(|(k, m, v)| visit(Index::Str(k.clone()), m, v))
+            }
+            Some(Self::Arr(a)) => {
+                a.0.iter()
+                    .enumerate()
+                    .for_each(|(i, (m, v))| visit(Index::Int(i), m, v))
+            }
+            _ => (),
+        }
+    }
+
+    /// Return the spans of all descendants in depth-first order, parents before children.
+    ///
+    /// Lazy values are forced only if `force` is true.
+    /// Values whose bytes do not lie in `root`, such as decompressed data,
+    /// are skipped together with their descendants.
+    pub fn spans(&self, root: &Bytes, force: bool) -> Vec<Span> {
+        let mut spans = Vec::new();
+        self.spans_into(root, force, &mut Vec::new(), &mut spans);
+        spans
+    }
+
+    fn spans_into(&self, root: &Bytes, force: bool, path: &mut Vec<Index>, spans: &mut Vec<Span>) {
+        fn inner(v: &Val, force: bool) -> &Val {
+            if force {
+                v.force()
+            } else {
+                v
+            }
+        }
+        let mut visit = |i, m: &Meta, v: &Self| {
+            let Some(range) = m.offset_in(root) else {
+                return;
+            };
+            let leaf = match inner(v, force) {
+                Self::Obj(o) => o.0.is_empty(),
+                Self::Arr(a) => a.0.is_empty(),
+                _ => true,
+            };
+            let value = match v.forced_if(force) {
+                Some(Self::Obj(_) | Self::Arr(_)) | None => None,
+                Some(v) => Some(render(m, v)),
+            };
+            path.push(i);
+            spans.push(Span {
+                path: path.clone(),
+                range,
+                leaf,
+                value,
+            });
+            v.spans_into(root, force, path, spans);
+            path.pop();
+        };
+        match inner(self, force) {
+            Self::Obj(o) => {
+                o.0.iter()
+                    .for_each(|(k, m, v)| visit(Index::Str(k.clone()), m, v))
+            }
+            Self::Arr(a) => {
+                a.0.iter()
+                    .enumerate()
+                    .for_each(|(i, (m, v))| visit(Index::Int(i), m, v))
+            }
+            _ => (),
+        }
+    }
+
+    /// Return the paths and metadata of the nested values that cover the byte at `offset` in
+    /// `root`, from the outermost to the innermost.
+    ///
+    /// Values derived from bytes that cover the offset, such as uncompressed data, are included.
+    /// Lazy values are forced only along the returned chain.
+    /// If several children cover the offset, the first one is followed.
+    pub fn find_at(&self, root: &Bytes, offset: usize) -> Vec<(Vec<Index>, &Meta)> {
+        let covers = |m: &Meta| m.origin_in(root).is_some_and(|r| r.contains(&offset));
+        let (mut found, mut path, mut v) = (Vec::new(), Vec::new(), self);
+        loop {
+            let child = match v.force() {
+                Self::Obj(o) => o.0.iter().find(|(_, m, _)| covers(m)).map(|(k, m, v)| {
+                    path.push(Index::Str(k.clone()));
+                    (m, v)
+                }),
+                Self::Arr(a) => a.0.iter().position(|(m, _)| covers(m)).map(|i| {
+                    path.push(Index::Int(i));
+                    (&a.0[i].0, &a.0[i].1)
+                }),
+                _ => None,
+            };
+            let Some((m, child)) = child else {
+                return found;
+            };
+            found.push((path.clone(), m));
+            v = child;
+        }
+    }
+
+    /// Return the path and range of the innermost value whose bytes in `root` contain `offset`.
+    ///
+    /// In contrast to [`Self::find_at`], values whose bytes are not part of `root`,
+    /// such as uncompressed data, are skipped, and all children that contain the offset are
+    /// searched, preferring leaves over objects and arrays, then deeper over shallower values.
+    /// Of equally good values, the first one is returned.
+    pub fn field_at(&self, root: &Bytes, offset: usize) -> Option<(Vec<Index>, Range<usize>)> {
+        let mut path = Vec::new();
+        self.field_at_in(root, offset, &mut path)
+            .map(|(_, path, range)| (path, range))
+    }
+
+    /// Like [`Self::field_at`], also returning whether the value is a leaf.
+    fn field_at_in(
+        &self,
+        root: &Bytes,
+        offset: usize,
+        path: &mut Vec<Index>,
+    ) -> Option<(bool, Vec<Index>, Range<usize>)> {
+        let mut best: Option<(bool, Vec<Index>, Range<usize>)> = None;
+        let mut visit = |i: Index, m: &Meta, v: &Val| {
+            let Some(range) = m.offset_in(root).filter(|r| r.contains(&offset)) else {
+                return;
+            };
+            path.push(i);
+            let found = match v.force() {
+                Self::Obj(_) | Self::Arr(_) => v
+                    .field_at_in(root, offset, path)
+                    .unwrap_or_else(|| (false, path.clone(), range)),
+                _ => (true, path.clone(), range),
+            };
+            path.pop();
+            let better = |(leaf, p, _): &(bool, Vec<Index>, _)| (*leaf, p.len());
+            if best.as_ref().is_none_or(|b| better(&found) > better(b)) {
+                best = Some(found)
+            }
+        };
+        match self.force() {
+            Self::Obj(o) => {
+                o.0.iter()
+                    .for_each(|(k, m, v)| visit(Index::Str(k.clone()), m, v))
+            }
+            Self::Arr(a) => {
+                a.0.iter()
+                    .enumerate()
+                    .for_each(|(i, (m, v))| visit(Index::Int(i), m, v))
+            }
+            _ => (),
+        }
+        best
+    }
+
+    pub fn make_arr(&mut self) -> &mut Arr {
+        *self = Val::Arr(Box::default());
+        match self {
+            Val::Arr(a) => a,
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn make_obj(&mut self) -> &mut Obj {
+        *self = Val::Obj(Box::default());
+        match self {
+            Val::Obj(o) => o,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Display the value as an indented tree.
+    ///
+    /// Objects and arrays are shown with one line per descendant, other values in a single line.
+    pub fn display<'a>(&'a self, opts: &'a DisplayOpts) -> DisplayVal<'a> {
+        DisplayVal { v: self, opts }
+    }
+
+    /// Display the value as an indented tree that only changes when the decoded data changes,
+    /// such as to compare it with a previously stored output.
+    ///
+    /// Lazy values are forced, and byte ranges are shown relative to `root`.
+    /// Integers are shown in decimal, strings quoted, and raw values and bytes in full as hex,
+    /// ignoring the formats of values.
+    pub fn to_canonical_string(&self, root: &Bytes) -> String {
+        let opts = DisplayOpts {
+            force: true,
+            root: Some(root.clone()),
+            canonical: true,
+        };
+        self.display(&opts).to_string()
+    }
+
+    /// Value that `f` builds when it is first forced.
+    ///
+    /// `f` decodes from the cursor that the current thread decodes from, if any.
+    pub fn lazy(f: impl FnOnce() -> Self + Send + 'static) -> Self {
+        let input = current_input();
+        Self::Lazy(Arc::new(LazyLock::new(Box::new(|| within(input, f)))))
+    }
+}
+
+impl Default for Val {
+    fn default() -> Self {
+        Self::Raw { gap: false }
+    }
+}
+
+impl Obj {
+    /// Number of occurrences of the key at index `i` before `i`.
+    fn occurrence(&self, i: usize) -> usize {
+        let k = &self.0[i].0;
+        self.0[..i].iter().filter(|(k_, ..)| k_ == k).count()
+    }
+
+    /// Return the `n`-th value with the key `k`.
+    fn nth_key(&self, k: &str, n: usize) -> Option<(&Meta, &Val)> {
+        let mut same = self.0.iter().filter(|(k_, ..)| *k_ == k);
+        same.nth(n).map(|(_, m, v)| (m, v))
+    }
+
+    /// Return true if the object contains the key `k`.
+    pub fn contains_key(&self, k: &str) -> bool {
+        match &self.2 {
+            Some(keys) => keys.contains(k),
+            None => self.0.iter().any(|(k_, ..)| k_ == k),
+        }
+    }
+
+    /// Return true if no key occurs more than once.
+    ///
+    /// This always holds unless the object keeps duplicate keys.
+    pub fn keys_are_unique(&self) -> bool {
+        let mut keys: Vec<&str> = self.0.iter().map(|(k, ..)| &**k).collect();
+        keys.sort_unstable();
+        keys.windows(2).all(|w| w[0] != w[1])
+    }
+
+    /// Rename a key if it is a duplicate and the policy demands it.
+    fn unique_key(&self, k: Key) -> Key {
+        match self.1 {
+            DuplicateKeys::Suffix if self.contains_key(&k) => (2..)
+                .map(|n| format!("{k}_{n}"))
+                .find(|k| !self.contains_key(k))
+                .unwrap()
+                .into(),
+            _ => k,
+        }
+    }
+
+    /// Record that `k` is about to be added, indexing the keys once there are enough of them.
+    ///
+    /// Only objects that rename duplicates are indexed, because their keys are unique.
+    fn index_key(&mut self, k: &Key) {
+        match &mut self.2 {
+            Some(keys) => {
+                keys.insert(k.clone());
+            }
+            None if self.1 == DuplicateKeys::Suffix && self.0.len() + 1 >= INDEXED_LEN => {
+                let keys = self.0.iter().map(|(k, ..)| k.clone());
+                self.2 = Some(Box::new(keys.chain([k.clone()]).collect()))
+            }
+            None => (),
+        }
+    }
+
+    /// Add a field, renaming its key like [`Self::unique_key`].
+    fn push(&mut self, k: Key, m: Meta, v: Val) {
+        let k = self.unique_key(k);
+        self.index_key(&k);
+        self.0.push((k, m, v))
+    }
+
+    /// Add the fields of `other` after the fields of `self`, keeping their order.
+    ///
+    /// Keys of `other` that are already in `self` are treated as if they were added one by one.
+    pub fn append(&mut self, other: Obj) {
+        other.0.into_iter().for_each(|(k, m, v)| self.push(k, m, v))
+    }
+
+    /// Insert a field at position `i`, shifting all fields after it.
+    ///
+    /// Panics if `i > self.len()`.
+    pub fn insert_at(&mut self, i: usize, k: impl Into<Key>, m: Meta, v: Val) {
+        let k = self.unique_key(k.into());
+        self.index_key(&k);
+        self.0.insert(i, (k, m, v))
+    }
+
+    /// Replace the field with the key `k`, keeping its position, and return the old field.
+    ///
+    /// If the object contains the key several times, the first match is replaced.
+    /// If it does not contain the key, nothing is replaced.
+    pub fn replace(&mut self, k: &str, m: Meta, v: Val) -> Option<(Meta, Val)> {
+        let (_, m_, v_) = self.0.iter_mut().find(|(k_, ..)| k_ == k)?;
+        Some((core::mem::replace(m_, m), core::mem::replace(v_, v)))
+    }
+
+    /// Return the value with the key `k`.
+    ///
+    /// If the object contains the key several times, the first match is returned.
+    pub fn get(&self, k: &str) -> Option<(&Meta, &Val)> {
+        self.nth_key(k, 0)
+    }
+
+    /// Return the value with the key `k` mutably, like [`Self::get`].
+    pub fn get_mut(&mut self, k: &str) -> Option<(&mut Meta, &mut Val)> {
+        let (_, m, v) = self.0.iter_mut().find(|(k_, ..)| k_ == k)?;
+        Some((m, v))
+    }
+
+    /// Return the descendant at `path`, forcing lazy values along the way.
+    ///
+    /// Like [`Self::get`], the first match is taken for duplicate keys.
+    pub fn get_path(&self, path: &[Index]) -> Option<(&Meta, &Val)> {
+        let (Index::Str(k), rest) = path.split_first()? else {
+            return None;
+        };
+        let (m, v) = self.get(k)?;
+        v.get_path(m, rest).ok()
+    }
+
+    pub fn add_mut<T, F>(&mut self, field: impl Into<Key>, m: Meta, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Meta, &mut Val) -> Result<T>,
+    {
+        self.push(field.into(), m, Val::default());
+        match self.0.last_mut() {
+            Some((k, m, v)) => fill(|| Index::Str(k.clone()), m, v, f),
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn add_consumed<T, F>(&mut self, field: impl Into<Key>, b: &mut Bytes, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Bytes, &mut Val) -> Result<T>,
+    {
+        self.add_mut(field, Meta::from(&*b), |m, v| consume(b, m, |b| f(b, v)))
+    }
+
+    pub fn add<T>(&mut self, field: impl Into<Key>, r: Result<Decoded<T>>) -> Result<T> {
+        let field = field.into();
+        match r {
+            Ok(d) => {
+                self.push(field, d.meta, d.val);
+                if let Some((k, m, _)) = self.0.last() {
+                    trace::field(|| Index::Str(k.clone()), m)
+                }
+                Ok(d.out)
+            }
+            Err(e) => {
+                trace::error(|| Index::Str(field.clone()), &e);
+                Err(e.with_index(Index::Str(field)))
+            }
+        }
+    }
+}
+
+/// Run `f` on a value that was just added at index `i`, recording an error in its metadata.
+fn fill<T, F>(i: impl Fn() -> Index, m: &mut Meta, v: &mut Val, f: F) -> Result<T>
+where
+    F: FnOnce(&mut Meta, &mut Val) -> Result<T>,
+{
+    match trace::enter(&i, || f(m, v)) {
+        Ok(y) => {
+            trace::field(&i, m);
+            Ok(y)
+        }
+        Err(e) => {
+            trace::error(&i, &e);
+            m.extra_mut().error = Some(e.clone());
+            Err(e.with_index(i()))
+        }
+    }
+}
+
+impl Arr {
+    pub fn add_mut<T, F>(&mut self, m: Meta, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Meta, &mut Val) -> Result<T>,
+    {
+        let i = self.0.len();
+        self.0.push((m, Val::default()));
+        match self.0.last_mut() {
+            Some((m, v)) => fill(|| Index::Int(i), m, v, f),
+            _ => unreachable!(),
+        }
+    }
+
+    pub fn add_consumed<T, F>(&mut self, b: &mut Bytes, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Bytes, &mut Val) -> Result<T>,
+    {
+        self.add_mut(Meta::from(&*b), |m, v| consume(b, m, |b| f(b, v)))
+    }
+
+    /// Add the elements of `other` after the elements of `self`.
+    pub fn append(&mut self, other: Arr) {
+        self.0.extend(other.0)
+    }
+}
+
+/// Destination of the fields decoded into an object.
+///
+/// Decoders that are generic over their sink can either build a tree of values
+/// by decoding into an [`Obj`], or only compute their outputs by decoding into [`Discard`].
+pub trait Sink: Default + Send {
+    /// Destination of the elements decoded into an array.
+    type Arr: ArrSink<Obj = Self>;
+
+    fn add<T>(&mut self, field: &'static str, r: Result<Decoded<T>>) -> Result<T>;
+
+    /// Add the value decoded by `f` if `cond` holds, else do not even run `f`.
+    fn add_opt<T, F>(&mut self, field: &'static str, cond: bool, f: F) -> Result<Option<T>>
+    where
+        F: FnOnce() -> Result<Decoded<T>>,
+    {
+        cond.then(|| self.add(field, f())).transpose()
+    }
+
+    /// Add a value together with a human-readable description of it.
+    fn add_described<T>(
+        &mut self,
+        field: &'static str,
+        description: &'static str,
+        r: Result<Decoded<T>>,
+    ) -> Result<T> {
+        self.add(field, r.map(|d| d.describe(description)))
+    }
+
+    /// Add a value whose key is not static, such as a key read from the input.
+    ///
+    /// Decoders intern such keys with an [`Interner`], so that repeated keys are cheap.
+    fn add_owned<T>(&mut self, field: Key, r: Result<Decoded<T>>) -> Result<T>;
+
+    /// Add an object, whose fields are decoded by `f`.
+    fn add_obj<T, F>(&mut self, field: &'static str, m: Meta, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Meta, &mut Self) -> Result<T>;
+
+    /// Add an array, whose elements are decoded by `f`.
+    fn add_arr<T, F>(&mut self, field: &'static str, m: Meta, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Meta, &mut Self::Arr) -> Result<T>;
+
+    /// Add an object whose fields are decoded by `f`, spanning the bytes that `f` consumes.
+    fn add_obj_consumed<T, F>(&mut self, field: &'static str, b: &mut Bytes, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Bytes, &mut Self) -> Result<T>,
+    {
+        self.add_obj(field, Meta::from(&*b), |m, o| consume(b, m, |b| f(b, o)))
+    }
+
+    /// Add an array whose elements are decoded by `f`, spanning the bytes that `f` consumes.
+    fn add_arr_consumed<T, F>(&mut self, field: &'static str, b: &mut Bytes, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Bytes, &mut Self::Arr) -> Result<T>,
+    {
+        self.add_arr(field, Meta::from(&*b), |m, a| consume(b, m, |b| f(b, a)))
+    }
+
+    /// Add bytes that were not decoded as a gap.
+    ///
+    /// Decoders use this for bytes that no structure accounts for, such as trailing garbage,
+    /// so that the values of an input still span all of it.
+    fn add_gap(&mut self, field: &'static str, b: Bytes) {
+        let gap = Decoded::new(Meta::from(b), Val::Raw { gap: true }, ());
+        // this cannot fail, because the value is already decoded
+        let _ = self.add(field, Ok(gap));
+    }
+
+    /// Replace the field `field` by what `f` returns for its metadata,
+    /// such as a placeholder by the actual value that is stored elsewhere.
+    ///
+    /// Does nothing if there is no such field.
+    fn replace(&mut self, field: &str, f: impl FnOnce(&Meta) -> (Meta, Val));
+
+    /// Return a lazy value, unless values are discarded anyway.
+    fn lazy(f: impl FnOnce() -> Val + Send + 'static) -> Val;
+}
+
+/// Destination of the elements decoded into an array.
+pub trait ArrSink: Default {
+    /// Destination of the fields of objects in the array.
+    type Obj: Sink<Arr = Self>;
+
+    /// Add an object, whose fields are decoded by `f`.
+    fn add_obj<T, F>(&mut self, m: Meta, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Meta, &mut Self::Obj) -> Result<T>;
+
+    /// Add an object whose fields are decoded by `f`, spanning the bytes that `f` consumes.
+    fn add_obj_consumed<T, F>(&mut self, b: &mut Bytes, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Bytes, &mut Self::Obj) -> Result<T>,
+    {
+        self.add_obj(Meta::from(&*b), |m, o| consume(b, m, |b| f(b, o)))
+    }
+
+    /// Add `count` objects, each decoded by `f` like by [`Self::add_obj_consumed`].
+    ///
+    /// The path of an error contains the index of the object that failed.
+    fn add_repeat<T, F>(&mut self, b: &mut Bytes, count: usize, mut f: F) -> Result<Vec<T>>
+    where
+        F: FnMut(&mut Bytes, &mut Self::Obj) -> Result<T>,
+    {
+        (0..count)
+            .map(|_| self.add_obj_consumed(b, &mut f))
+            .collect()
+    }
+
+    /// Add objects decoded by `f` until all of `b` is consumed.
+    ///
+    /// Fails if `f` consumes no bytes, because it would never end otherwise.
+    fn add_repeat_all<T, F>(&mut self, mut b: Bytes, mut f: F) -> Result<Vec<T>>
+    where
+        F: FnMut(&mut Bytes, &mut Self::Obj) -> Result<T>,
+    {
+        let mut ys = Vec::new();
+        while !b.is_empty() {
+            let len = b.len();
+            ys.push(self.add_obj_consumed(&mut b, &mut f)?);
+            if b.len() == len {
+                return Err(Error::new(&b, "element consumed no bytes"));
+            }
+        }
+        Ok(ys)
+    }
+
+    /// Call `f` until `b` is empty or starts with `sentinel`, leaving the sentinel in `b`.
+    ///
+    /// In contrast to [`Self::add_repeat`], `f` adds elements to the array itself,
+    /// so that it can also remove them again, such as when filtering.
+    /// Fails if `f` consumes no bytes, because it would never end otherwise.
+    fn add_until<F>(&mut self, b: &mut Bytes, sentinel: &[u8], mut f: F) -> Result
+    where
+        F: FnMut(&mut Bytes, &mut Self) -> Result,
+    {
+        while !b.is_empty() && !b.starts_with(sentinel) {
+            let len = b.len();
+            f(b, self)?;
+            if b.len() == len {
+                return Err(Error::new(b, "element consumed no bytes"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Add an object that was decoded before.
+    fn push(&mut self, m: Meta, o: Self::Obj);
+
+    /// Remove the last element.
+    fn pop(&mut self);
+
+    /// Replace the last element, such as one that failed to decode, by a gap over `b`.
+    ///
+    /// The metadata of the element keeps its error.
+    fn last_to_gap(&mut self, b: Bytes);
+
+    /// Record that `field` of the last element, an object, refers to the value at `path`.
+    fn link_last(&mut self, field: &str, path: Vec<Index>);
+
+    /// Reserve space for `n` more elements.
+    fn reserve(&mut self, n: usize);
+}
+
+impl Sink for Obj {
+    type Arr = Arr;
+
+    fn add<T>(&mut self, field: &'static str, r: Result<Decoded<T>>) -> Result<T> {
+        Obj::add(self, field, r)
+    }
+
+    fn add_owned<T>(&mut self, field: Key, r: Result<Decoded<T>>) -> Result<T> {
+        Obj::add(self, field, r)
+    }
+
+    fn add_obj<T, F>(&mut self, field: &'static str, m: Meta, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Meta, &mut Self) -> Result<T>,
+    {
+        self.add_mut(field, m, |m, v| f(m, v.make_obj()))
+    }
+
+    fn add_arr<T, F>(&mut self, field: &'static str, m: Meta, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Meta, &mut Arr) -> Result<T>,
+    {
+        self.add_mut(field, m, |m, v| f(m, v.make_arr()))
+    }
+
+    fn replace(&mut self, field: &str, f: impl FnOnce(&Meta) -> (Meta, Val)) {
+        if let Some((m, _)) = self.get(field) {
+            let (m, v) = f(m);
+            Obj::replace(self, field, m, v);
+        }
+    }
+
+    fn lazy(f: impl FnOnce() -> Val + Send + 'static) -> Val {
+        Val::lazy(f)
+    }
+}
+
+impl ArrSink for Arr {
+    type Obj = Obj;
+
+    fn add_obj<T, F>(&mut self, m: Meta, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Meta, &mut Obj) -> Result<T>,
+    {
+        self.add_mut(m, |m, v| f(m, v.make_obj()))
+    }
+
+    fn push(&mut self, m: Meta, o: Obj) {
+        self.0.push((m, Val::Obj(Box::new(o))))
+    }
+
+    fn pop(&mut self) {
+        self.0.pop();
+    }
+
+    fn last_to_gap(&mut self, b: Bytes) {
+        if let Some((m, v)) = self.0.last_mut() {
+            m.respan(&b);
+            *v = Val::Raw { gap: true };
+        }
+    }
+
+    fn link_last(&mut self, field: &str, path: Vec<Index>) {
+        if let Some((_, Val::Obj(o))) = self.0.last_mut() {
+            if let Some((m, _)) = o.get_mut(field) {
+                m.point_to(path)
+            }
+        }
+    }
+
+    fn reserve(&mut self, n: usize) {
+        self.0.reserve(n)
+    }
+}
+
+/// Sink that drops all values, keeping only the paths of errors.
+#[derive(Default)]
+pub struct Discard;
+
+impl Sink for Discard {
+    type Arr = DiscardArr;
+
+    fn add<T>(&mut self, field: &'static str, r: Result<Decoded<T>>) -> Result<T> {
+        r.map(|d| d.out)
+            .map_err(|e| e.with_index(Index::Str(field.into())))
+    }
+
+    fn add_owned<T>(&mut self, field: Key, r: Result<Decoded<T>>) -> Result<T> {
+        r.map(|d| d.out)
+            .map_err(|e| e.with_index(Index::Str(field)))
+    }
+
+    // descriptions are discarded anyway, so do not allocate them
+    fn add_described<T>(
+        &mut self,
+        field: &'static str,
+        _: &'static str,
+        r: Result<Decoded<T>>,
+    ) -> Result<T> {
+        self.add(field, r)
+    }
+
+    fn add_obj<T, F>(&mut self, field: &'static str, mut m: Meta, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Meta, &mut Self) -> Result<T>,
+    {
+        f(&mut m, self).map_err(|e| e.with_index(Index::Str(field.into())))
+    }
+
+    fn add_arr<T, F>(&mut self, field: &'static str, mut m: Meta, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Meta, &mut DiscardArr) -> Result<T>,
+    {
+        let r = f(&mut m, &mut DiscardArr::default());
+        r.map_err(|e| e.with_index(Index::Str(field.into())))
+    }
+
+    fn replace(&mut self, _: &str, _: impl FnOnce(&Meta) -> (Meta, Val)) {}
+
+    fn lazy(_: impl FnOnce() -> Val + Send + 'static) -> Val {
+        Val::default()
+    }
+}
+
+/// Array sink that drops all values, counting elements only to report paths of errors.
+#[derive(Default)]
+pub struct DiscardArr(usize);
+
+impl ArrSink for DiscardArr {
+    type Obj = Discard;
+
+    fn add_obj<T, F>(&mut self, mut m: Meta, f: F) -> Result<T>
+    where
+        F: FnOnce(&mut Meta, &mut Discard) -> Result<T>,
+    {
+        let i = self.0;
+        self.0 += 1;
+        f(&mut m, &mut Discard).map_err(|e| e.with_index(Index::Int(i)))
+    }
+
+    fn push(&mut self, _: Meta, _: Discard) {
+        self.0 += 1
+    }
+
+    fn pop(&mut self) {
+        self.0 -= 1
+    }
+
+    fn last_to_gap(&mut self, _: Bytes) {}
+
+    fn link_last(&mut self, _: &str, _: Vec<Index>) {}
+
+    fn reserve(&mut self, _: usize) {}
+}
+
+/// Kind of difference between two values.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DiffKind {
+    Added,
+    Removed,
+    Changed,
+    /// The value is the same, but it lies at a different offset.
+    Moved,
+}
+
+/// Difference between two values.
+#[derive(Clone, Debug)]
+pub struct Diff {
+    /// Path to the value, relative to the compared values.
+    pub path: Vec<Index>,
+    pub kind: DiffKind,
+    /// Rendering of the old value, or of its offset if it was moved.
+    pub old: Option<String>,
+    /// Rendering of the new value, or of its offset if it was moved.
+    pub new: Option<String>,
+}
+
+/// Compare two values: objects key by key, and arrays element by element.
+///
+/// Lazy values are forced before comparison.
+pub fn diff(a: &Val, b: &Val) -> Vec<Diff> {
+    let mut d = Differ::default();
+    let m = Meta::from(Bytes::new());
+    d.diff((&m, a), (&m, b));
+    d.diffs
+}
+
+/// Like [`diff`], but also report values that moved relative to their roots.
+pub fn diff_moved(a: (&Bytes, &Val), b: (&Bytes, &Val)) -> Vec<Diff> {
+    let mut d = Differ {
+        roots: Some((a.0, b.0)),
+        ..Differ::default()
+    };
+    d.diff((&Meta::from(a.0), a.1), (&Meta::from(b.0), b.1));
+    d.diffs
+}
+
+#[derive(Default)]
+struct Differ<'a> {
+    roots: Option<(&'a Bytes, &'a Bytes)>,
+    path: Vec<Index>,
+    diffs: Vec<Diff>,
+}
+
+/// Render a value in a single line.
+fn render(m: &Meta, v: &Val) -> String {
+    const MAX: usize = 16;
+    if let Some(s) = m.formatted(v) {
+        return s;
+    }
+    match v.force() {
+        Val::Null => "null".into(),
+        Val::Bool(b) => b.to_string(),
+        Val::U8(u) => u.to_string(),
+        Val::U16(u) => u.to_string(),
+        Val::U32(u) => u.to_string(),
+        Val::U64(u) => u.to_string(),
+        Val::U128(u) => u.to_string(),
+        Val::I8(i) => i.to_string(),
+        Val::I16(i) => i.to_string(),
+        Val::I32(i) => i.to_string(),
+        Val::I64(i) => i.to_string(),
+        Val::F32(x) => x.to_string(),
+        Val::F64(x) => x.to_string(),
+        Val::Str(s) => format!("{:?}", String::from_utf8_lossy(s)),
+        Val::Raw { .. } | Val::Bytes(_) => {
+            let b = match v.force() {
+                Val::Bytes(b) => (**b).clone(),
+                _ => m.bytes(),
+            };
+            let hex: String = b.iter().take(MAX).map(|c| format!("{c:02x}")).collect();
+            match b.len() {
+                n if n > MAX => format!("{hex}... ({n} bytes)"),
+                _ => hex,
+            }
+        }
+        Val::Arr(a) => format!("[{} elements]", a.0.len()),
+        Val::Obj(o) => format!("{{{} fields}}", o.0.len()),
+        Val::Lazy(_) => unreachable!(),
+    }
+}
+
+/// Return true if two values that are neither arrays nor objects are equal.
+fn leaf_eq((ma, a): (&Meta, &Val), (mb, b): (&Meta, &Val)) -> bool {
+    // compare signed and unsigned integers by their values
+    let int = |v: &Val| match v {
+        Val::U8(u) => Some(i128::from(*u)),
+        Val::U16(u) => Some(i128::from(*u)),
+        Val::U32(u) => Some(i128::from(*u)),
+        Val::U64(u) => Some(i128::from(*u)),
+        Val::U128(u) => i128::try_from(**u).ok(),
+        Val::I8(i) => Some(i128::from(*i)),
+        Val::I16(i) => Some(i128::from(*i)),
+        Val::I32(i) => Some(i128::from(*i)),
+        Val::I64(i) => Some(i128::from(*i)),
+        _ => None,
+    };
+    match (a, b) {
+        (Val::Null, Val::Null) => true,
+        (Val::Bool(a), Val::Bool(b)) => a == b,
+        (Val::Str(a), Val::Str(b)) => a == b,
+        (Val::Bytes(a), Val::Bytes(b)) => a == b,
+        // not every `u128` fits into `i128`
+        (Val::U128(a), Val::U128(b)) => a == b,
+        // compare bits such that equal NaNs are not reported as changed
+        (Val::F32(a), Val::F32(b)) => a.to_bits() == b.to_bits(),
+        (Val::F64(a), Val::F64(b)) => a.to_bits() == b.to_bits(),
+        (Val::Raw { .. }, Val::Raw { .. }) => ma.bytes() == mb.bytes(),
+        _ => int(a).is_some() && int(a) == int(b),
+    }
+}
+
+impl Differ<'_> {
+    fn push(&mut self, kind: DiffKind, old: Option<String>, new: Option<String>) {
+        let path = self.path.clone();
+        self.diffs.push(Diff {
+            path,
+            kind,
+            old,
+            new,
+        })
+    }
+
+    fn child(&mut self, i: Index, a: Option<(&Meta, &Val)>, b: Option<(&Meta, &Val)>) {
+        self.path.push(i);
+        match (a, b) {
+            (Some(a), Some(b)) => self.diff(a, b),
+            (Some((m, v)), None) => self.push(DiffKind::Removed, Some(render(m, v)), None),
+            (None, Some((m, v))) => self.push(DiffKind::Added, None, Some(render(m, v))),
+            (None, None) => (),
+        }
+        self.path.pop();
+    }
+
+    fn diff(&mut self, (ma, a): (&Meta, &Val), (mb, b): (&Meta, &Val)) {
+        match (a.force(), b.force()) {
+            (Val::Obj(oa), Val::Obj(ob)) => {
+                // match the n-th occurrence of a key in one object with the one in the other
+                for (i, (k, m, v)) in oa.0.iter().enumerate() {
+                    let key = Index::Str(k.clone());
+                    self.child(key, Some((m, v)), ob.nth_key(k, oa.occurrence(i)));
+                }
+                for (i, (k, m, v)) in ob.0.iter().enumerate() {
+                    if oa.nth_key(k, ob.occurrence(i)).is_none() {
+                        self.child(Index::Str(k.clone()), None, Some((m, v)));
+                    }
+                }
+            }
+            (Val::Arr(aa), Val::Arr(ab)) => {
+                for i in 0..aa.0.len().max(ab.0.len()) {
+                    let (a, b) = (aa.0.get(i), ab.0.get(i));
+                    self.child(
+                        Index::Int(i),
+                        a.map(|(m, v)| (m, v)),
+                        b.map(|(m, v)| (m, v)),
+                    );
+                }
+            }
+            (a, b) if !leaf_eq((ma, a), (mb, b)) => {
+                self.push(DiffKind::Changed, Some(render(ma, a)), Some(render(mb, b)))
+            }
+            _ => {
+                let Some((ra, rb)) = self.roots else { return };
+                let (oa, ob) = (ma.offset_in(ra), mb.offset_in(rb));
+                let (oa, ob) = (oa.map(|r| r.start), ob.map(|r| r.start));
+                if oa != ob {
+                    let offset = |o: Option<usize>| o.map(|o| format!("{o:#x}"));
+                    self.push(DiffKind::Moved, offset(oa), offset(ob))
+                }
+            }
+        }
+    }
+}
+
+/// Options for displaying values as indented trees.
+#[derive(Clone, Debug, Default)]
+pub struct DisplayOpts {
+    /// Force lazy values instead of showing them as `<lazy>`.
+    pub force: bool,
+    /// Show the byte ranges of values relative to these bytes.
+    pub root: Option<Bytes>,
+    /// Show values independently of their formats, see [`Val::to_canonical_string`].
+    pub canonical: bool,
+}
+
+/// Value displayed as an indented tree, see [`Val::display`].
+pub struct DisplayVal<'a> {
+    v: &'a Val,
+    opts: &'a DisplayOpts,
+}
+
+impl fmt::Display for DisplayVal<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self.v.forced_if(self.opts.force) {
+            None => f.write_str("<lazy>"),
+            Some(Val::Obj(o)) => fmt_obj(f, o, self.opts, 0),
+            Some(Val::Arr(a)) => fmt_arr(f, a, self.opts, 0),
+            Some(v) if self.opts.canonical => fmt_canonical(f, None, v),
+            Some(v) => fmt_scalar(f, None, v),
+        }
+    }
+}
+
+impl fmt::Display for Val {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.display(&DisplayOpts::default()).fmt(f)
+    }
+}
+
+impl fmt::Display for Obj {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        fmt_obj(f, self, &DisplayOpts::default(), 0)
+    }
+}
+
+impl fmt::Display for Arr {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        fmt_arr(f, self, &DisplayOpts::default(), 0)
+    }
+}
+
+fn fmt_obj(f: &mut Formatter, o: &Obj, opts: &DisplayOpts, depth: usize) -> fmt::Result {
+    o.iter()
+        .try_for_each(|(k, m, v)| fmt_entry(f, k, m, v, opts, depth))
+}
+
+fn fmt_arr(f: &mut Formatter, a: &Arr, opts: &DisplayOpts, depth: usize) -> fmt::Result {
+    a.iter()
+        .enumerate()
+        .try_for_each(|(i, (m, v))| fmt_entry(f, format_args!("[{i}]"), m, v, opts, depth))
+}
+
+/// Write a line with the key, range, and value, followed by lines with the children of the value.
+fn fmt_entry(
+    f: &mut Formatter,
+    key: impl fmt::Display,
+    m: &Meta,
+    v: &Val,
+    opts: &DisplayOpts,
+    depth: usize,
+) -> fmt::Result {
+    write!(f, "{:indent$}{key}", "", indent = 2 * depth)?;
+    if let Some(r) = opts.root.as_ref().and_then(|root| m.offset_in(root)) {
+        write!(f, " @{:#x}..{:#x}", r.start, r.end)?;
+    }
+    if let Some(path) = m.points_to() {
+        write!(f, " -> {}", Path(path))?;
+    }
+    match v.forced_if(opts.force) {
+        None => writeln!(f, ": <lazy>"),
+        Some(Val::Obj(o)) => {
+            writeln!(f, ":")?;
+            fmt_obj(f, o, opts, depth + 1)
+        }
+        Some(Val::Arr(a)) => {
+            writeln!(f, ":")?;
+            fmt_arr(f, a, opts, depth + 1)
+        }
+        Some(v) => {
+            f.write_str(": ")?;
+            match opts.canonical {
+                true => fmt_canonical(f, Some(m), v)?,
+                false => fmt_scalar(f, Some(m), v)?,
+            }
+            writeln!(f)
+        }
+    }
+}
+
+/// Write a value that is neither lazy, nor an array, nor an object, in a fixed way.
+fn fmt_canonical(f: &mut Formatter, m: Option<&Meta>, v: &Val) -> fmt::Result {
+    let hex = |f: &mut Formatter, b: &[u8]| match b {
+        // avoid trailing whitespace, which editors tend to remove from stored outputs
+        [] => f.write_str("(empty)"),
+        b => b.iter().try_for_each(|c| write!(f, "{c:02x}")),
+    };
+    match v {
+        Val::Null => f.write_str("null"),
+        Val::Bool(b) => write!(f, "{b}"),
+        Val::U8(u) => write!(f, "{u}"),
+        Val::U16(u) => write!(f, "{u}"),
+        Val::U32(u) => write!(f, "{u}"),
+        Val::U64(u) => write!(f, "{u}"),
+        Val::U128(u) => write!(f, "{u}"),
+        Val::I8(i) => write!(f, "{i}"),
+        Val::I16(i) => write!(f, "{i}"),
+        Val::I32(i) => write!(f, "{i}"),
+        Val::I64(i) => write!(f, "{i}"),
+        // `{:?}` shows floats such that they are read back exactly
+        Val::F32(x) => write!(f, "{x:?}"),
+        Val::F64(x) => write!(f, "{x:?}"),
+        Val::Str(s) => write!(f, "{:?}", String::from_utf8_lossy(s)),
+        Val::Bytes(b) => hex(f, b),
+        Val::Raw { .. } => match m {
+            Some(m) => hex(f, &m.bytes()),
+            None => f.write_str("<raw>"),
+        },
+        Val::Arr(_) | Val::Obj(_) | Val::Lazy(_) => unreachable!(),
+    }
+}
+
+/// Write a value that is neither lazy, nor an array, nor an object.
+fn fmt_scalar(f: &mut Formatter, m: Option<&Meta>, v: &Val) -> fmt::Result {
+    if let Some(format) = m.and_then(Meta::format) {
+        return format(v, f);
+    }
+    match v {
+        Val::Null => f.write_str("null"),
+        Val::Bool(b) => write!(f, "{b}"),
+        Val::U8(u) => write!(f, "{u} ({u:#x})"),
+        Val::U16(u) => write!(f, "{u} ({u:#x})"),
+        Val::U32(u) => write!(f, "{u} ({u:#x})"),
+        Val::U64(u) => write!(f, "{u} ({u:#x})"),
+        Val::U128(u) => write!(f, "{0} ({0:#x})", **u),
+        Val::I8(i) => write!(f, "{i} ({i:#x})"),
+        Val::I16(i) => write!(f, "{i} ({i:#x})"),
+        Val::I32(i) => write!(f, "{i} ({i:#x})"),
+        Val::I64(i) => write!(f, "{i} ({i:#x})"),
+        Val::F32(x) => write!(f, "{x}"),
+        Val::F64(x) => write!(f, "{x}"),
+        Val::Str(s) => write!(f, "{:?}", String::from_utf8_lossy(s)),
+        Val::Bytes(b) => write!(f, "<{} bytes>", b.len()),
+        Val::Raw { .. } => match m {
+            Some(m) => write!(f, "<{} bytes>", m.bytes().len()),
+            None => f.write_str("<raw>"),
+        },
+        Val::Arr(_) | Val::Obj(_) | Val::Lazy(_) => unreachable!(),
+    }
+}
+
+/// Coverage of an input by the leaves of a decoded value, see [`coverage`].
+#[derive(Clone, Debug)]
+pub struct Coverage {
+    /// Number of bytes covered by at least one leaf.
+    pub covered: usize,
+    /// Regions not covered by any leaf, sorted by start.
+    pub uncovered: Vec<Range<usize>>,
+    /// Regions covered by more than one leaf, sorted by start.
+    pub overlaps: Vec<Overlap>,
+}
+
+/// Region covered by two leaves.
+#[derive(Clone, Debug)]
+pub struct Overlap {
+    pub range: Range<usize>,
+    /// Paths to the two leaves, the one that starts first first.
+    pub paths: [Vec<Index>; 2],
+}
+
+/// Determine which bytes of `input` are covered by the leaves of `v`.
+///
+/// Lazy values are not forced, and leaves whose bytes do not lie in `input` are ignored.
+pub fn coverage(v: &Val, input: &Bytes) -> Coverage {
+    let spans = v.spans(input, false);
+    let mut leaves: Vec<_> = spans
+        .iter()
+        .filter(|s| s.leaf && !s.range.is_empty())
+        .collect();
+    leaves.sort_by_key(|s| s.range.start);
+
+    let mut uncovered = Vec::new();
+    let mut overlaps = Vec::new();
+    // the leaf that ends last among the leaves seen so far
+    let mut last: Option<&Span> = None;
+    for s in leaves {
+        let end = last.map_or(0, |l| l.range.end);
+        if s.range.start > end {
+            uncovered.push(end..s.range.start);
+        }
+        if let Some(l) = last.filter(|l| l.range.end > s.range.start) {
+            let range = s.range.start..s.range.end.min(l.range.end);
+            let paths = [l.path.clone(), s.path.clone()];
+            overlaps.push(Overlap { range, paths })
+        }
+        if s.range.end > end {
+            last = Some(s);
+        }
+    }
+    let end = last.map_or(0, |l| l.range.end);
+    if input.len() > end {
+        uncovered.push(end..input.len());
+    }
+    let covered = input.len() - uncovered.iter().map(|r| r.len()).sum::<usize>();
+    Coverage {
+        covered,
+        uncovered,
+        overlaps,
+    }
+}
+
+/// Split off the first `n` bytes.
+pub fn take(left: &mut Bytes, n: usize) -> Result<Bytes> {
+    let right = try_split_off(left, n)?;
+    Ok(core::mem::replace(left, right))
+}
+
+/// Fail if `b` has fewer than `n` bytes.
+///
+/// All functions that take or slice bytes check sizes read from the input with this
+/// before using them, so that absurd sizes fail early and with the same message.
+pub fn check_len(b: &Bytes, n: usize) -> Result<()> {
+    if n > b.len() {
+        Err(len_error(b, n))
+    } else {
+        Ok(())
+    }
+}
+
+fn len_error(b: &Bytes, n: usize) -> Error {
+    Error::new(
+        b,
+        format!("expected {n} bytes, but only {} remain", b.len()),
+    )
+}
+
+pub fn try_split_off(b: &mut Bytes, at: usize) -> Result<Bytes> {
+    check_len(b, at)?;
+    Ok(b.split_off(at))
+}
+
+fn to_range(b: &Bytes, bounds: impl RangeBounds<usize>) -> Result<Range<usize>> {
+    use core::ops::Bound;
+    let overflow = |n| len_error(b, n);
+    let begin = match bounds.start_bound() {
+        Bound::Included(&n) => n,
+        Bound::Excluded(&n) => n.checked_add(1).ok_or_else(|| overflow(n))?,
+        Bound::Unbounded => 0,
+    };
+    let end = match bounds.end_bound() {
+        Bound::Included(&n) => n.checked_add(1).ok_or_else(|| overflow(n))?,
+        Bound::Excluded(&n) => n,
+        Bound::Unbounded => b.len(),
+    };
+    if begin > end {
+        return Err(Error::new(b, format!("invalid range {begin}..{end}")));
+    }
+    check_len(b, end)?;
+    Ok(begin..end)
+}
+
+pub fn try_slice(b: &Bytes, range: impl RangeBounds<usize>) -> Result<Bytes> {
+    Ok(b.slice(to_range(b, range)?))
+}
+
+fn consumed<T>(b: &mut Bytes, f: impl FnOnce(&mut Bytes) -> Result<T>) -> Result<(Bytes, T)> {
+    let mut start = b.clone();
+    let y = f(b)?;
+    start.truncate(start.len() - b.len());
+    Ok((start, y))
+}
+
+pub fn consume<T>(
+    b: &mut Bytes,
+    to: &mut Meta,
+    f: impl FnOnce(&mut Bytes) -> Result<T>,
+) -> Result<T> {
+    let (consumed, y) = consumed(b, f)?;
+    to.respan(&consumed);
+    Ok(y)
+}
+
+/// Decode the fields of a structure into a sink in order, and collect their outputs.
+///
+/// `decode_struct!(o, b, Name { ... })` decodes the fields from the bytes `b`
+/// into the sink `o` and returns a `Name` whose fields are named like the decoded fields.
+/// Every field is given as one of:
+///
+/// - `field: decoder` or `field: decoder(args)`, which adds `field` with
+///   the result of `decoder(b)` or `decoder(b, args)`,
+/// - `skip field: decoder`, which adds `field`, but does not store its output, or
+/// - `field = expr`, which stores the output of `expr`, such as a nested object.
+///
+/// A single-line doc comment before a decoded field becomes the description of its value.
+///
+/// For example, `decode_struct!(o, b, X { a: le::u16, skip c: le::u8 })` is equivalent to
+/// `let a = o.add("a", le::u16(b))?; o.add("c", le::u8(b))?; X { a }`.
+macro_rules! decode_struct {
+    ($o:ident, $b:ident, $name:ident { $($fields:tt)* }) => {
+        decode_struct!(@ $o $b $name {} $($fields)*)
+    };
+    (@ $o:ident $b:ident $name:ident { $($done:ident)* }) => {
+        $name { $($done),* }
+    };
+    (@ $o:ident $b:ident $name:ident { $($done:ident)* } $(#[doc = $desc:literal])?
+     skip $field:ident: $($dec:ident)::+ $(($($arg:expr),*))? $(, $($rest:tt)*)?) => {{
+        decode_struct!(@add $o $field $($dec)::+($b $($(, $arg)*)?) $(, $desc)?)?;
+        decode_struct!(@ $o $b $name { $($done)* } $($($rest)*)?)
+    }};
+    (@ $o:ident $b:ident $name:ident { $($done:ident)* } $(#[doc = $desc:literal])?
+     $field:ident: $($dec:ident)::+ $(($($arg:expr),*))? $(, $($rest:tt)*)?) => {{
+        let $field = decode_struct!(@add $o $field $($dec)::+($b $($(, $arg)*)?) $(, $desc)?)?;
+        decode_struct!(@ $o $b $name { $($done)* $field } $($($rest)*)?)
+    }};
+    (@ $o:ident $b:ident $name:ident { $($done:ident)* }
+     $field:ident = $e:expr $(, $($rest:tt)*)?) => {{
+        let $field = $e;
+        decode_struct!(@ $o $b $name { $($done)* $field } $($($rest)*)?)
+    }};
+    (@add $o:ident $field:ident $r:expr) => {
+        $o.add(stringify!($field), $r)
+    };
+    (@add $o:ident $field:ident $r:expr, $desc:literal) => {
+        $o.add_described(stringify!($field), $desc.trim(), $r)
+    };
+}
+pub(crate) use decode_struct;
+
+macro_rules! decode_int {
+    ($width: expr, $f:ident, $ty:ident, $val:expr) => {
+        pub fn $ty(b: &mut Bytes) -> Result<Decoded<$ty>> {
+            let b = take(b, $width)?;
+            let a: [u8; $width] = (*b).try_into().map_err(|_| len_error(&b, $width))?;
+            let u = $ty::$f(a);
+            Ok(Decoded::new(Meta::from(b), $val(u), u))
+        }
+    };
+}
+
+/// Take an unsigned integer of `width` bytes, with the given byte order.
+fn uint(b: &mut Bytes, width: usize, le: bool) -> Result<Decoded<u64>> {
+    if !(1..=8).contains(&width) {
+        let msg = format!("expected integer width between 1 and 8 bytes, found {width}");
+        return Err(Error::new(b, msg));
+    }
+    let b = take(b, width)?;
+    let mut a = [0; 8];
+    let u = if le {
+        a[..width].copy_from_slice(&b);
+        u64::from_le_bytes(a)
+    } else {
+        a[8 - width..].copy_from_slice(&b);
+        u64::from_be_bytes(a)
+    };
+    Ok(Decoded::new(Meta::from(b), Val::U64(u), u))
 }
 
+/// Little-endian decoders.
 pub mod le {
     use super::*;
+
+    /// Take an unsigned integer of 1 to 8 bytes, such as a 24-bit integer.
+    pub fn uint(b: &mut Bytes, width: usize) -> Result<Decoded<u64>> {
+        super::uint(b, width, true)
+    }
+
     decode_int!(1, from_le_bytes, u8, Val::U8);
     decode_int!(2, from_le_bytes, u16, Val::U16);
     decode_int!(4, from_le_bytes, u32, Val::U32);
     decode_int!(8, from_le_bytes, u64, Val::U64);
+    decode_int!(16, from_le_bytes, u128, |u| Val::U128(Box::new(u)));
+    decode_int!(1, from_le_bytes, i8, Val::I8);
+    decode_int!(2, from_le_bytes, i16, Val::I16);
+    decode_int!(4, from_le_bytes, i32, Val::I32);
+    decode_int!(8, from_le_bytes, i64, Val::I64);
+    decode_int!(4, from_le_bytes, f32, Val::F32);
+    decode_int!(8, from_le_bytes, f64, Val::F64);
+}
+
+/// Big-endian decoders.
+pub mod be {
+    use super::*;
+
+    /// Take an unsigned integer of 1 to 8 bytes, such as a 24-bit integer.
+    pub fn uint(b: &mut Bytes, width: usize) -> Result<Decoded<u64>> {
+        super::uint(b, width, false)
+    }
+
+    decode_int!(1, from_be_bytes, u8, Val::U8);
+    decode_int!(2, from_be_bytes, u16, Val::U16);
+    decode_int!(4, from_be_bytes, u32, Val::U32);
+    decode_int!(8, from_be_bytes, u64, Val::U64);
+    decode_int!(16, from_be_bytes, u128, |u| Val::U128(Box::new(u)));
+    decode_int!(1, from_be_bytes, i8, Val::I8);
+    decode_int!(2, from_be_bytes, i16, Val::I16);
+    decode_int!(4, from_be_bytes, i32, Val::I32);
+    decode_int!(8, from_be_bytes, i64, Val::I64);
+    decode_int!(4, from_be_bytes, f32, Val::F32);
+    decode_int!(8, from_be_bytes, f64, Val::F64);
+}
+
+/// Decoders that look at bytes without consuming them.
+///
+/// These return `None` if fewer bytes remain than needed.
+/// Integers are little-endian.
+pub mod peek {
+    /// Return the first `n` bytes.
+    pub fn bytes(b: &[u8], n: usize) -> Option<&[u8]> {
+        b.get(..n)
+    }
+
+    pub fn u8(b: &[u8]) -> Option<u8> {
+        b.first().copied()
+    }
+
+    pub fn u16(b: &[u8]) -> Option<u16> {
+        Some(u16::from_le_bytes(*b.first_chunk()?))
+    }
+
+    pub fn u32(b: &[u8]) -> Option<u32> {
+        Some(u32::from_le_bytes(*b.first_chunk()?))
+    }
+}
+
+/// MS-DOS dates and times, as used by FAT file systems and ZIP archives.
+pub mod dos {
+    use super::*;
+
+    /// Time and date, each packed into a 16-bit integer.
+    ///
+    /// Times have a resolution of two seconds and no time zone.
+    #[derive(Clone, Copy, Debug, Default)]
+    pub struct DosDateTime {
+        pub time: u16,
+        pub date: u16,
+    }
+
+    // https://stackoverflow.com/a/8012148
+    fn mask(u: u16, offset: u8, width: u8) -> u8 {
+        let mask = ((1 << width as u16) - 1) << offset;
+        ((u & mask) >> offset) as u8
+    }
+
+    // https://learn.microsoft.com/en-gb/windows/win32/api/winbase/nf-winbase-dosdatetimetofiletime
+    impl DosDateTime {
+        pub fn second(&self) -> u8 {
+            mask(self.time, 0, 5) * 2
+        }
+
+        pub fn minute(&self) -> u8 {
+            mask(self.time, 5, 6)
+        }
+
+        pub fn hour(&self) -> u8 {
+            mask(self.time, 11, 5)
+        }
+
+        pub fn day(&self) -> u8 {
+            mask(self.date, 0, 5)
+        }
+
+        pub fn month(&self) -> u8 {
+            mask(self.date, 5, 4)
+        }
+
+        pub fn year(&self) -> u16 {
+            u16::from(mask(self.date, 9, 7)) + 1980
+        }
+
+        /// Names and values of the components that are out of range, such as month 13.
+        pub fn invalid(&self) -> impl Iterator<Item = (&'static str, u8)> {
+            let components = [
+                ("second", self.second(), 0..=59),
+                ("minute", self.minute(), 0..=59),
+                ("hour", self.hour(), 0..=23),
+                ("day", self.day(), 1..=31),
+                ("month", self.month(), 1..=12),
+            ];
+            let invalid = components.into_iter().filter(|(_, v, r)| !r.contains(v));
+            invalid.map(|(k, v, _)| (k, v))
+        }
+
+        /// Seconds since the Unix epoch, interpreting the time as UTC.
+        pub fn unix(&self) -> i64 {
+            let days = days_from_civil(self.year().into(), self.month(), self.day());
+            let (hr, min, sec) = (self.hour(), self.minute(), self.second());
+            days * 86400 + i64::from(hr) * 3600 + i64::from(min) * 60 + i64::from(sec)
+        }
+    }
+
+    /// ISO 8601 rendering, such as `2025-03-07T09:21:10`.
+    impl fmt::Display for DosDateTime {
+        fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+            let (y, mo, d) = (self.year(), self.month(), self.day());
+            let (h, mi, s) = (self.hour(), self.minute(), self.second());
+            write!(f, "{y:04}-{mo:02}-{d:02}T{h:02}:{mi:02}:{s:02}")
+        }
+    }
+
+    /// Take an MS-DOS time followed by an MS-DOS date.
+    ///
+    /// The value is a lazily built object with the components of the time as `fat_time`,
+    /// those of the date as `fat_date`, and their combination as `iso8601`.
+    /// Components that are out of range are not rejected, but warned about.
+    pub fn datetime<S: Sink>(b: &mut Bytes) -> Result<Decoded<DosDateTime>> {
+        let b = take(b, 4)?;
+        let (time, date) = (b.slice(..2), b.slice(2..));
+        let dt = DosDateTime {
+            time: u16::from_le_bytes([time[0], time[1]]),
+            date: u16::from_le_bytes([date[0], date[1]]),
+        };
+        let mut meta = Meta::from(&b);
+        for (k, v) in dt.invalid() {
+            meta.warn(format!("{k} {v} is out of range"));
+        }
+        let lazy = move || {
+            let obj = |m: &Meta, entries: [(&'static str, Val); 3]| {
+                let entries = entries.into_iter().map(|(k, v)| (k.into(), m.clone(), v));
+                Val::Obj(Box::new(entries.collect()))
+            };
+            let (tm, dm) = (Meta::from(time), Meta::from(date));
+            let time = obj(
+                &tm,
+                [
+                    ("second", Val::U8(dt.second())),
+                    ("minute", Val::U8(dt.minute())),
+                    ("hour", Val::U8(dt.hour())),
+                ],
+            );
+            let date = obj(
+                &dm,
+                [
+                    ("day", Val::U8(dt.day())),
+                    ("month", Val::U8(dt.month())),
+                    ("year", Val::U16(dt.year())),
+                ],
+            );
+            let iso = Val::Str(Box::new(dt.to_string().into()));
+            let entries = [
+                (
+                    "fat_time".into(),
+                    tm.describe(Some("MS-DOS packed time".into())),
+                    time,
+                ),
+                (
+                    "fat_date".into(),
+                    dm.describe(Some("MS-DOS packed date".into())),
+                    date,
+                ),
+                ("iso8601".into(), Meta::from(b), iso),
+            ];
+            Val::Obj(Box::new(entries.into_iter().collect()))
+        };
+        Ok(Decoded::new(meta, S::lazy(lazy), dt))
+    }
+}
+
+/// Checksums that decoders use to verify data.
+pub mod checksum {
+    use super::*;
+
+    /// CRC-32, as used by ZIP, gzip, and PNG.
+    pub fn crc32(b: &[u8]) -> u32 {
+        crc32fast::hash(b)
+    }
+
+    /// Adler-32, as used by zlib.
+    pub fn adler32(b: &[u8]) -> u32 {
+        const MOD: u32 = 65521;
+        // largest number of bytes that can be summed before the sums overflow
+        const NMAX: usize = 5552;
+        let (mut a, mut s) = (1u32, 0u32);
+        for chunk in b.chunks(NMAX) {
+            for x in chunk {
+                a += u32::from(*x);
+                s += a;
+            }
+            (a, s) = (a % MOD, s % MOD);
+        }
+        s << 16 | a
+    }
+
+    /// Compare a stored checksum with the one computed from the data, if that is known.
+    ///
+    /// The result is an object with the stored checksum as `value`, and
+    /// the computed checksum as `computed` and whether both agree as `valid`,
+    /// which are omitted if the checksum could not be computed.
+    /// All entries take the metadata of the stored checksum.
+    pub fn verify(stored: Decoded<u32>, computed: Option<u32>) -> Val {
+        let Decoded { meta, val, out } = stored;
+        let computed =
+            computed.map(|c| [("computed", Val::U32(c)), ("valid", Val::Bool(c == out))]);
+        let entries = [("value", val)]
+            .into_iter()
+            .chain(computed.into_iter().flatten());
+        Val::Obj(Box::new(
+            entries.map(|(k, v)| (k.into(), meta.clone(), v)).collect(),
+        ))
+    }
 }
 
+/// Take `n` bytes without interpreting them.
 pub fn raw(b: &mut Bytes, n: usize) -> Result<Decoded<Bytes>> {
     let b = take(b, n)?;
-    Ok(Decoded::new(Meta::from(&b), Val::default(), b))
+    let v = Val::Bytes(Box::new(b.clone()));
+    Ok(Decoded::new(Meta::from(&b), v, b))
+}
+
+/// Take a string terminated by NUL.
+///
+/// The value and output contain the string without the terminator,
+/// whereas the span also covers the terminator.
+pub fn cstr(b: &mut Bytes) -> Result<Decoded<Bytes>> {
+    let Some(n) = b.iter().position(|c| *c == b'\0') else {
+        let msg = format!("expected NUL in the remaining {} bytes", b.len());
+        return Err(Error::new(b, msg).with_context("NUL-terminated string"));
+    };
+    let s = take(b, n + 1)?;
+    let content = s.slice(..n);
+    Ok(Decoded::new(
+        Meta::from(s),
+        Val::Str(Box::new(content.clone())),
+        content,
+    ))
+}
+
+/// Take a length decoded by `len`, followed by that many bytes.
+///
+/// The value is an object with the fields `length` and `data`,
+/// and the output is the data.
+pub fn len_prefixed<F>(b: &mut Bytes, len: F) -> Result<Decoded<Bytes>>
+where
+    F: FnOnce(&mut Bytes) -> Result<Decoded<u64>>,
+{
+    let start = b.clone();
+    let mut o = Obj::default();
+    let n = o.add("length", len(b))?;
+    let data = o.add_consumed("data", b, |b, _| {
+        let n = usize::try_from(n).map_err(|_| {
+            let msg = format!("expected unsigned machine-sized integer, found {n}");
+            Error::new(b, msg)
+        })?;
+        take(b, n)
+    })?;
+    let m = Meta::from(start.slice(..start.len() - b.len()));
+    Ok(Decoded::new(m, Val::Obj(Box::new(o)), data))
+}
+
+/// Take a UTF-16LE string of `n_units` code units.
+///
+/// The value contains the string encoded as UTF-8, whereas the span covers the UTF-16 bytes.
+/// Invalid surrogates are replaced by U+FFFD.
+pub fn utf16le(b: &mut Bytes, n_units: usize) -> Result<Decoded<String>> {
+    let n = n_units
+        .checked_mul(2)
+        .ok_or_else(|| Error::new(b, format!("UTF-16 string of {n_units} units too large")))?;
+    let s = take(b, n)?;
+    let units = s.chunks_exact(2).map(|c| u16::from_le_bytes([c[0], c[1]]));
+    let out: String = char::decode_utf16(units)
+        .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect();
+    let val = Val::Str(Box::new(Bytes::from(out.clone())));
+    Ok(Decoded::new(Meta::from(s), val, out))
+}
+
+/// Take a UTF-16LE string terminated by a NUL code unit.
+///
+/// Like [`utf16le`], but the span also covers the terminator.
+pub fn utf16le_cstr(b: &mut Bytes) -> Result<Decoded<String>> {
+    let Some(n) = b.chunks_exact(2).position(|c| c == [0, 0]) else {
+        let msg = format!("expected NUL in the remaining {} bytes", b.len());
+        return Err(Error::new(b, msg).with_context("NUL-terminated UTF-16 string"));
+    };
+    let (s, d) = consumed(b, |b| {
+        let d = utf16le(b, n)?;
+        take(b, 2)?;
+        Ok(d)
+    })?;
+    Ok(d.map_meta(|_| Meta::from(s)))
+}
+
+/// Take the padding up to the next multiple of `alignment` bytes after the start of `origin`.
+///
+/// The value is a gap, and the output is the number of padding bytes.
+/// `b` must be what remains of `origin` after taking bytes from its start,
+/// so that the offset follows from their lengths.
+pub fn pad_to(b: &mut Bytes, alignment: usize, origin: &Bytes) -> Result<Decoded<usize>> {
+    let offset = origin
+        .len()
+        .checked_sub(b.len())
+        .ok_or_else(|| Error::new(b, "padding does not start inside its origin"))?;
+    let n = offset
+        .checked_rem(alignment)
+        .map_or(0, |r| (alignment - r) % alignment);
+    let pad = take(b, n).map_err(|e| Error {
+        msg: format!("expected {n} padding bytes, but only {} remain", b.len()),
+        ..e
+    })?;
+    Ok(Decoded::new(Meta::from(pad), Val::Raw { gap: true }, n))
+}
+
+/// Show a decoded integer as the variant of `T` that it stands for.
+///
+/// The value becomes an object with the integer as `value` and
+/// the name of the variant as `name`, which is omitted if no variant matches.
+/// The object is built lazily, and the output stays the integer.
+pub fn sym<S, T, U>(r: Result<Decoded<U>>) -> Result<Decoded<U>>
+where
+    S: Sink,
+    T: num_traits::FromPrimitive + Debug + 'static,
+    U: Copy + Into<u64> + Send + 'static,
+{
+    let Decoded { meta, val, out } = r?;
+    let m = meta.clone();
+    let lazy = move || {
+        let name = T::from_u64(out.into()).map(|t| format!("{t:?}"));
+        let name = name.map(|n| ("name".into(), m.clone(), Val::Str(Box::new(n.into()))));
+        let value = ("value".into(), m, val);
+        Val::Obj(Box::new([value].into_iter().chain(name).collect()))
+    };
+    Ok(Decoded::new(meta, S::lazy(lazy), out))
+}
+
+/// Show decoded bits as the flags of `F` that they contain.
+///
+/// The value becomes an object with a boolean for every named flag, followed by
+/// `unknown_bits`, the positions of set bits that belong to no named flag,
+/// which is omitted if there are none.
+/// The object is built lazily, and the output are the flags.
+pub fn flags<S, F>(r: Result<Decoded<F::Bits>>) -> Result<Decoded<F>>
+where
+    S: Sink,
+    F: bitflags::Flags,
+    F::Bits: Into<u64> + Send,
+{
+    let Decoded { meta, out, .. } = r?;
+    // capture only what is needed to build the object, to keep the closure small
+    let span = meta.span();
+    let lazy = move || {
+        let flags = F::from_bits_retain(out);
+        let named = F::FLAGS.iter().filter(|f| !f.name().is_empty());
+        let mut o = Vec::with_capacity(named.clone().count() + 1);
+        let mut known = F::empty();
+        for f in named {
+            let has = flags.contains(F::from_bits_retain(f.value().bits()));
+            known.insert(F::from_bits_retain(f.value().bits()));
+            o.push((f.name().into(), span.clone(), Val::Bool(has)));
+        }
+        let unknown: u64 = flags.difference(known).bits().into();
+        if unknown != 0 {
+            let bits = (0..64).filter(|i| unknown >> i & 1 == 1);
+            let bits = bits.map(|i| (span.clone(), Val::U8(i)));
+            let bits = Val::Arr(Box::new(bits.collect()));
+            o.push(("unknown_bits".into(), span, bits));
+        }
+        // names of flags are unique, so they need no renaming
+        Val::Obj(Box::new(Obj(o, DuplicateKeys::default(), None)))
+    };
+    Ok(Decoded::new(meta, S::lazy(lazy), F::from_bits_retain(out)))
+}
+
+/// Number of days between 1970-01-01 and the given date of the proleptic Gregorian calendar.
+///
+/// <https://howardhinnant.github.io/date_algorithms.html#days_from_civil>
+fn days_from_civil(y: i64, m: u8, d: u8) -> i64 {
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let (m, d) = (i64::from(m), i64::from(d));
+    let doy = (153 * (m + if m > 2 { -3 } else { 9 }) + 2) / 5 + d - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// Year, month, and day of the given number of days since 1970-01-01.
+///
+/// <https://howardhinnant.github.io/date_algorithms.html#civil_from_days>
+pub fn civil_from_days(z: i64) -> (i64, u32, u32) {
+    let z = z + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    (yoe + era * 400 + i64::from(m <= 2), m, d)
+}
+
+/// Render seconds since the Unix epoch as UTC date and time, such as `1970-01-01T00:00:00Z`.
+pub fn iso8601(secs: i64) -> String {
+    let (y, m, d) = civil_from_days(secs.div_euclid(86400));
+    let secs = secs.rem_euclid(86400);
+    let (hr, min, sec) = (secs / 3600, secs / 60 % 60, secs % 60);
+    format!("{y:04}-{m:02}-{d:02}T{hr:02}:{min:02}:{sec:02}Z")
 }
 
+/// Show decoded seconds since the Unix epoch also as date and time.
+///
+/// The value becomes an object with the seconds as `unix` and their rendering by
+/// [`iso8601`] as `iso8601`, which is omitted if the seconds do not fit into `i64`.
+/// The object is built lazily, and the output stays the seconds.
+pub fn unix_time<S, U>(r: Result<Decoded<U>>) -> Result<Decoded<U>>
+where
+    S: Sink,
+    U: Copy + TryInto<i64> + Send + 'static,
+{
+    let Decoded { meta, val, out } = r?;
+    let m = meta.clone();
+    let lazy = move || {
+        let iso = out
+            .try_into()
+            .ok()
+            .map(|s| Val::Str(Box::new(iso8601(s).into())));
+        let iso = iso.map(|iso| ("iso8601".into(), m.clone(), iso));
+        let unix = ("unix".into(), m, val);
+        Val::Obj(Box::new([unix].into_iter().chain(iso).collect()))
+    };
+    Ok(Decoded::new(meta, S::lazy(lazy), out))
+}
+
+/// Keep a decoded value, but warn if its output differs from `expected`.
+///
+/// This is for values that the specification constrains,
+/// but that do not prevent decoding the rest if they are violated.
+pub fn expect<T: PartialEq + fmt::Display>(
+    r: Result<Decoded<T>>,
+    expected: T,
+) -> Result<Decoded<T>> {
+    let mut d = r?;
+    if d.out != expected {
+        d.meta.warn(format!("expected {expected}, found {}", d.out));
+    }
+    Ok(d)
+}
+
+/// Take bytes that are equal to `s`, or any bytes if `force` is set.
+///
+/// The value is a string of the bytes taken.
+/// If `force` is set and the bytes differ from `s`, the mismatch is stored as warning of the value.
 pub fn precise(b: &mut Bytes, s: &[u8], force: bool) -> Result<Decoded<()>> {
     let byte_str = |b: &[u8]| b.iter().copied().map(char::from).collect::<String>();
     let err = || format!("expected byte sequence {:?}", byte_str(s));
     let b = take(b, s.len()).map_err(|e| Error { msg: err(), ..e })?;
-    if b == s || force {
-        Ok(Decoded::new(Meta::from(b), Val::default(), ()))
-    } else {
-        Err(Error::new(&b, err()))
+    let mut m = Meta::from(&b);
+    if b != s {
+        let e = Error::new(&b, format!("{}, found {:?}", err(), byte_str(&b)));
+        if !force {
+            return Err(e);
+        }
+        m.warn(e.msg);
+    }
+    Ok(Decoded::new(m, Val::Str(Box::new(b)), ()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn err<T>(r: Result<T>) -> Error {
+        match r {
+            Ok(_) => panic!("expected an error"),
+            Err(e) => e,
+        }
+    }
+
+    /// Decode from a cursor of `root` until the result is dropped.
+    fn input(root: &Bytes) -> Restore {
+        Restore(INPUT.replace(Some(Cursor::new(root.clone(), 0))))
+    }
+
+    #[test]
+    fn interned_keys_share_text() {
+        let mut keys = Interner::default();
+        let a = keys.intern("SCHILY.xattr.user.tag");
+        let b = keys.intern(&String::from("SCHILY.xattr.user.tag"));
+        assert_eq!(a.as_ptr(), b.as_ptr());
+        assert_ne!(a.as_ptr(), keys.intern("path").as_ptr());
+        assert_eq!(a, Key::from("SCHILY.xattr.user.tag"));
+        assert_eq!(
+            format!("{a} {a:?}"),
+            r#"SCHILY.xattr.user.tag "SCHILY.xattr.user.tag""#
+        );
+    }
+
+    #[test]
+    fn dynamic_keys_in_paths() {
+        let mut keys = Interner::default();
+        let mut o = Obj::default();
+        let e = o.add_owned::<()>(keys.intern("path"), Err(Error::new(&Bytes::new(), "bad")));
+        assert_eq!(e.unwrap_err().path(), [Index::Str("path".into())]);
+    }
+
+    #[test]
+    fn warnings_are_sorted_by_offset() {
+        let root = Bytes::from_static(&[0; 16]);
+        let _input = input(&root);
+        let warned = |b: Bytes, msg: &str| {
+            let mut m = Meta::from(b);
+            m.warn(msg);
+            Decoded::new(m, Val::Null, ())
+        };
+        let mut o = Obj::default();
+        o.add("derived", Ok(warned(Bytes::from(vec![0]), "derived")))
+            .unwrap();
+        o.add("late", Ok(warned(root.slice(10..12), "late")))
+            .unwrap();
+        o.add("early", Ok(warned(root.slice(2..4), "early")))
+            .unwrap();
+        o.add("also_early", Ok(warned(root.slice(2..3), "also early")))
+            .unwrap();
+        let warnings = Val::Obj(Box::new(o)).warnings(&root);
+        let msgs: Vec<_> = warnings.iter().map(|w| w.msg.as_str()).collect();
+        assert_eq!(msgs, ["early", "also early", "late", "derived"]);
+    }
+
+    #[test]
+    fn big_endian_integers() {
+        let root = Bytes::from_static(&[0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0xde, 0xf0, 0xff]);
+        let _input = input(&root);
+        let mut b = root.clone();
+        let d = be::u16(&mut b).unwrap();
+        assert_eq!(d.out, 0x1234);
+        assert_eq!(d.meta.offset_in(&root), Some(0..2));
+        let d = be::u32(&mut b).unwrap();
+        assert_eq!(d.out, 0x56789abc);
+        assert_eq!(d.meta.offset_in(&root), Some(2..6));
+        assert!(matches!(d.val, Val::U32(0x56789abc)));
+        let d = be::uint(&mut b, 3).unwrap();
+        assert_eq!(d.out, 0xdef0ff);
+        assert_eq!(d.meta.offset_in(&root), Some(6..9));
+        assert!(b.is_empty());
+        assert_eq!(be::u64(&mut root.clone()).unwrap().out, 0x123456789abcdef0);
+        assert!(be::u64(&mut root.slice(..7)).is_err());
+    }
+
+    #[test]
+    fn signed_integers() {
+        let mut b = Bytes::from_static(&[0x80, 0xfe, 0xff, 0x7f, 0xff, 0xff, 0xff, 0xff, 0xfe]);
+        let vals = [
+            le::i8(&mut b).unwrap().val,
+            le::i16(&mut b).unwrap().val,
+            le::i32(&mut b).unwrap().val,
+            be::i16(&mut b).unwrap().val,
+        ];
+        let vals: Vec<_> = vals.iter().map(|v| format!("{:?}", v.eval())).collect();
+        assert_eq!(vals, ["-128", "-2", "-129", "-2"]);
+        let d = le::i64(&mut Bytes::from_static(&[0xff; 8])).unwrap();
+        assert_eq!(d.out, -1);
+        assert!(matches!(d.val.eval(), Val::I64(-1)));
+        assert_eq!(d.val.to_string(), "-1 (0xffffffffffffffff)");
+    }
+
+    #[test]
+    fn floats() {
+        let d = le::f32(&mut Bytes::from_static(&[0x00, 0x00, 0x80, 0x3f])).unwrap();
+        assert_eq!(d.out, 1.0);
+        let d = be::f64(&mut Bytes::from_static(&[0xc0, 0, 0, 0, 0, 0, 0, 0])).unwrap();
+        assert_eq!(d.out, -2.0);
+        let special = [
+            [0x7f, 0xf8, 0, 0, 0, 0, 0, 0],
+            [0x7f, 0xf0, 0, 0, 0, 0, 0, 0],
+            [0xff, 0xf0, 0, 0, 0, 0, 0, 0],
+        ];
+        let mut o = Obj::default();
+        for (k, b) in ["nan", "infinity", "minus_infinity"]
+            .into_iter()
+            .zip(special)
+        {
+            o.add(k, be::f64(&mut Bytes::copy_from_slice(&b))).unwrap();
+        }
+        let v = Val::Obj(Box::new(o)).eval();
+        assert!(matches!(v.query("nan"), Ok(Val::F64(x)) if x.is_nan()));
+        assert!(matches!(v.query("minus_infinity"), Ok(Val::F64(x)) if *x == f64::NEG_INFINITY));
+        let debug = |k| format!("{:?}", v.query(k).unwrap());
+        assert_eq!([debug("nan"), debug("infinity")], ["NaN", "inf"]);
+        let _ = v.to_string();
+        #[cfg(feature = "serde")]
+        crate::ser::to_cbor(&v, &Bytes::new());
+    }
+
+    #[test]
+    fn nul_terminated_strings() {
+        let root = Bytes::from_static(b"abc\0\0rest");
+        let _input = input(&root);
+        let mut b = root.clone();
+        let d = cstr(&mut b).unwrap();
+        assert_eq!(d.out, &b"abc"[..]);
+        assert!(matches!(&d.val, Val::Str(s) if **s == b"abc"[..]));
+        assert_eq!(d.meta.offset_in(&root), Some(0..4));
+        let d = cstr(&mut b).unwrap();
+        assert!(d.out.is_empty());
+        assert_eq!(d.meta.offset_in(&root), Some(4..5));
+        assert_eq!(b, &b"rest"[..]);
+        let e = err(cstr(&mut b));
+        assert_eq!(e.msg(), "expected NUL in the remaining 4 bytes");
+        assert_eq!(e.context(), ["NUL-terminated string"]);
+        assert_eq!(b, &b"rest"[..]);
+    }
+
+    #[test]
+    fn utf16le_strings() {
+        // "Ä€", an unpaired surrogate, and "x" terminated by NUL
+        let root = Bytes::from_static(&[0xc4, 0, 0xac, 0x20, 0x00, 0xd8, b'x', 0, 0, 0, 1]);
+        let _input = input(&root);
+        let mut b = root.clone();
+        let d = utf16le(&mut b, 2).unwrap();
+        assert_eq!(d.out, "Ä€");
+        assert!(matches!(&d.val, Val::Str(s) if **s == "Ä€".as_bytes()[..]));
+        assert_eq!(d.meta.offset_in(&root), Some(0..4));
+        let d = utf16le_cstr(&mut b).unwrap();
+        assert_eq!(d.out, "\u{fffd}x");
+        assert_eq!(d.meta.offset_in(&root), Some(4..10));
+        assert_eq!(b, &[1][..]);
+        assert!(utf16le(&mut b, 1).is_err());
+        assert!(utf16le_cstr(&mut b).is_err());
+        assert!(utf16le(&mut b, usize::MAX).is_err());
+    }
+
+    #[test]
+    fn peeking_does_not_consume() {
+        let b = Bytes::from_static(&[0x50, 0x4b, 0x03, 0x04]);
+        assert_eq!(peek::u8(&b), Some(0x50));
+        assert_eq!(peek::u16(&b), Some(0x4b50));
+        assert_eq!(peek::u32(&b), Some(0x04034b50));
+        assert_eq!(peek::bytes(&b, 2), Some(&b"PK"[..]));
+        assert_eq!(b.len(), 4);
+        assert_eq!(peek::bytes(&b, 5), None);
+        assert_eq!(peek::u32(&b[1..]), None);
+        assert_eq!(peek::u16(&b[3..]), None);
+        assert_eq!(peek::u8(&b[4..]), None);
+    }
+
+    #[test]
+    fn padding() {
+        let origin = Bytes::from_static(&[0; 10]);
+        let _input = input(&origin);
+        let mut b = origin.slice(3..);
+        let d = pad_to(&mut b, 4, &origin).unwrap();
+        assert_eq!(d.out, 1);
+        assert!(matches!(d.val, Val::Raw { gap: true }));
+        assert_eq!(d.meta.offset_in(&origin), Some(3..4));
+        // already aligned
+        let d = pad_to(&mut b, 4, &origin).unwrap();
+        assert_eq!((d.out, b.len()), (0, 6));
+        // relative to the origin, not to the start of `b`
+        let mut b = origin.slice(5..);
+        assert_eq!(pad_to(&mut b, 4, &origin.slice(1..)).unwrap().out, 0);
+        // too few bytes remain
+        let mut b = origin.slice(9..);
+        let e = err(pad_to(&mut b, 8, &origin));
+        assert_eq!(e.msg(), "expected 7 padding bytes, but only 1 remain");
+        assert_eq!(b.len(), 1);
+        // not inside the origin
+        let e = err(pad_to(&mut origin.clone(), 4, &origin.slice(1..)));
+        assert_eq!(e.msg(), "padding does not start inside its origin");
+    }
+
+    #[test]
+    fn symbolic_values() {
+        #[derive(Debug, num_derive::FromPrimitive)]
+        enum Method {
+            Deflated = 8,
+        }
+        let b = Bytes::from_static(&[8, 0, 99, 0]);
+        let d = sym::<Obj, Method, _>(le::u16(&mut b.slice(..2))).unwrap();
+        assert_eq!(d.out, 8);
+        assert!(d.val.forced().is_none());
+        let Val::Obj(o) = d.val.force() else {
+            panic!("expected an object");
+        };
+        assert!(matches!(o.get("value"), Some((_, Val::U16(8)))));
+        assert!(matches!(o.get("name"), Some((_, Val::Str(s))) if **s == b"Deflated"[..]));
+        let d = sym::<Obj, Method, _>(le::u16(&mut b.slice(2..))).unwrap();
+        let Val::Obj(o) = d.val.force() else {
+            panic!("expected an object");
+        };
+        assert_eq!(o.iter().map(|(k, ..)| k).collect::<Vec<_>>(), ["value"]);
+        // nothing is built for values that are discarded
+        let d = sym::<Discard, Method, _>(le::u16(&mut b.slice(..2))).unwrap();
+        assert!(matches!(d.val, Val::Raw { gap: false }));
+    }
+
+    #[test]
+    fn precise_signatures() {
+        let d = precise(&mut Bytes::from_static(b"PK\x03\x04"), b"PK\x03\x04", false).unwrap();
+        assert!(matches!(&d.val, Val::Str(s) if **s == b"PK\x03\x04"[..]));
+        assert!(d.meta.warnings().is_empty());
+        let mut b = Bytes::from_static(b"PK\x05\x06");
+        let e = err(precise(&mut b.clone(), b"PK\x03\x04", false));
+        assert_eq!(
+            e.msg(),
+            r#"expected byte sequence "PK\u{3}\u{4}", found "PK\u{5}\u{6}""#
+        );
+        let d = precise(&mut b, b"PK\x03\x04", true).unwrap();
+        assert_eq!(d.meta.warnings(), [e.msg()]);
+        assert!(b.is_empty());
+        let e = err(precise(&mut Bytes::from_static(b"PK"), b"PK\x03\x04", true));
+        assert_eq!(e.msg(), r#"expected byte sequence "PK\u{3}\u{4}""#);
+    }
+
+    #[test]
+    fn integers_of_any_width() {
+        let root = Bytes::from_static(&[0x01, 0x02, 0x03, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e, 0x0f]);
+        let _input = input(&root);
+        let mut b = root.clone();
+        let d = le::uint(&mut b, 3).unwrap();
+        assert_eq!(d.out, 0x030201);
+        assert!(matches!(d.val, Val::U64(0x030201)));
+        let d = be::uint(&mut b, 6).unwrap();
+        assert_eq!(d.out, 0x0a0b0c0d0e0f);
+        assert_eq!(d.meta.offset_in(&root), Some(3..9));
+        for width in [0, 9] {
+            let e = err(le::uint(&mut root.clone(), width));
+            let msg = format!("expected integer width between 1 and 8 bytes, found {width}");
+            assert_eq!(e.msg(), msg);
+        }
+        assert!(be::uint(&mut root.slice(..2), 3).is_err());
+    }
+
+    #[test]
+    fn u128_values() {
+        // the UUID 00112233-4455-6677-8899-aabbccddeeff
+        let root: Bytes = (0..16).map(|i| i * 0x11).collect();
+        let d = be::u128(&mut root.clone()).unwrap();
+        assert_eq!(d.out, 0x00112233_44556677_8899aabb_ccddeeff);
+        assert_eq!(d.meta.offset_in(&root), Some(0..16));
+        assert!(matches!(d.val.eval(), Val::U128(u) if *u == d.out));
+        let d = le::u128(&mut root.clone()).unwrap();
+        assert_eq!(d.out, 0xffeeddcc_bbaa9988_77665544_33221100);
+        assert!(le::u128(&mut root.slice(1..)).is_err());
+    }
+
+    #[test]
+    fn repeated_objects() {
+        let root = Bytes::from_static(&[1, 0, 2, 0, 3]);
+        let mut o = Obj::default();
+        let r = o.add_arr_consumed("records", &mut root.clone(), |b, a| {
+            a.add_repeat(b, 3, |b, o| o.add("x", le::u16(b)))
+        });
+        let e = err(r);
+        let path = [
+            Index::Str("x".into()),
+            Index::Int(2),
+            Index::Str("records".into()),
+        ];
+        assert_eq!(e.path(), path);
+        // the elements before the failing one are kept
+        let v = Val::Obj(Box::new(o));
+        assert!(matches!(v.query("records[1].x"), Ok(Val::U16(2))));
+
+        let mut a = Arr::default();
+        let xs = a.add_repeat_all(root.slice(..4), |b, o| o.add("x", le::u16(b)));
+        assert_eq!(xs.unwrap(), [1, 2]);
+        assert_eq!(a.len(), 2);
+        let e = err(Arr::default().add_repeat_all(root, |_, _| Ok(())));
+        assert_eq!((e.msg(), e.path()), ("element consumed no bytes", &[][..]));
+    }
+
+    #[test]
+    fn objects_until_sentinel() {
+        let byte = |b: &mut Bytes, a: &mut Arr| {
+            a.add_obj_consumed(b, |b, o| o.add("x", le::u8(b)))?;
+            Ok(())
+        };
+        let mut b = Bytes::from_static(&[1, 2, 0xff, 0xff, 3]);
+        let mut a = Arr::default();
+        a.add_until(&mut b, &[0xff, 0xff], byte).unwrap();
+        assert_eq!((a.len(), &b[..]), (2, &[0xff, 0xff, 3][..]));
+        // a sentinel longer than the rest does not match
+        let mut b = Bytes::from_static(&[1, 0xff]);
+        let mut a = Arr::default();
+        a.add_until(&mut b, &[0xff, 0xff], byte).unwrap();
+        assert_eq!((a.len(), b.len()), (2, 0));
+        let e = err(Arr::default().add_until(&mut Bytes::from_static(&[1]), &[0], |_, _| Ok(())));
+        assert_eq!(e.msg(), "element consumed no bytes");
+    }
+
+    #[test]
+    fn length_prefixed() {
+        let root = Bytes::from_static(&[3, 0, b'a', b'b', b'c', b'd']);
+        let mut b = root.clone();
+        let d = with_input(&root, || len_prefixed(&mut b, |b| le::uint(b, 2))).unwrap();
+        assert_eq!(d.out, &b"abc"[..]);
+        assert_eq!(d.meta.offset_in(&root), Some(0..5));
+        let v = d.val;
+        let span = |k| v.get(&Index::Str(k)).unwrap().0.offset_in(&root);
+        assert_eq!(
+            (span("length".into()), span("data".into())),
+            (Some(0..2), Some(2..5))
+        );
+        assert_eq!(b, &b"d"[..]);
+        let e = with_input(&root, || {
+            len_prefixed(&mut root.slice(..4), |b| le::uint(b, 2))
+        });
+        let e = err(e);
+        assert_eq!(e.path(), [Index::Str("data".into())]);
+        assert_eq!(e.offset(), Some(2));
+    }
+
+    #[test]
+    fn truncation_offsets() {
+        let root = Bytes::from_static(&[0; 10]);
+        let _input = input(&root);
+        let mut b = root.slice(6..);
+        let e = err(try_split_off(&mut b, 5));
+        assert_eq!(e.remaining(), 4);
+        assert_eq!(e.offset(), Some(root.len() - e.remaining()));
+        assert_eq!(
+            e.to_string(),
+            "expected 5 bytes, but only 4 remain (at offset 0x6)"
+        );
+        // a buffer that starts later in the input, such as one read from a stream
+        let chunk = Bytes::from_static(&[0; 4]);
+        let mut b = chunk.slice(1..);
+        let e = Cursor::new(chunk, 100).run(|| err(try_split_off(&mut b, 5)));
+        assert_eq!(e.offset(), Some(101));
+    }
+
+    #[test]
+    fn keys_from_decoded_bytes() {
+        let root = Bytes::from_static(b"size\0\x2a\x00");
+        let _input = input(&root);
+        let mut b = root.clone();
+        let name = cstr(&mut b).unwrap().out;
+        let key = String::from_utf8_lossy(&name).into_owned();
+        let mut o = Obj::default();
+        assert_eq!(
+            Sink::add_owned(&mut o, key.into(), le::u16(&mut b)).unwrap(),
+            42
+        );
+        let (m, v) = o.get("size").unwrap();
+        assert_eq!(m.offset_in(&root), Some(5..7));
+        assert_eq!(v.to_string(), "42 (0x2a)");
+        assert_eq!(o.iter().map(|(k, ..)| k).collect::<Vec<_>>(), ["size"]);
+    }
+
+    #[test]
+    fn lookups_take_the_first_duplicate() {
+        let root = Bytes::from_static(&[1, 2, 3]);
+        let _input = input(&root);
+        let byte = |i: usize| {
+            Ok(Decoded::new(
+                Meta::from(root.slice(i..i + 1)),
+                Val::U64(i as u64),
+                (),
+            ))
+        };
+        let mut o = Obj::with_duplicate_keys(DuplicateKeys::Keep);
+        o.add("x", byte(0)).unwrap();
+        o.add("x", byte(1)).unwrap();
+        let mut inner = Obj::default();
+        inner.add("y", byte(2)).unwrap();
+        let lazy = Val::lazy(move || Val::Obj(Box::new(inner)));
+        o.add("z", Ok(Decoded::new(Meta::from(root.clone()), lazy, ())))
+            .unwrap();
+        assert_eq!(o.get("x").unwrap().0.offset_in(&root), Some(0..1));
+        *o.get_mut("x").unwrap().1 = Val::Null;
+        assert!(matches!(
+            o.iter().map(|(_, _, v)| v).collect::<Vec<_>>()[..],
+            [Val::Null, Val::U64(1), _]
+        ));
+        let path = [Index::Str("z".into()), Index::Str("y".into())];
+        assert_eq!(o.get_path(&path).unwrap().0.offset_in(&root), Some(2..3));
+        assert!(o
+            .get_path(&[Index::Str("z".into()), Index::Int(0)])
+            .is_none());
+        assert!(o.get("w").is_none() && o.get_mut("w").is_none());
+    }
+
+    #[test]
+    fn duplicate_key_policies() {
+        let add = |o: &mut Obj, k: &'static str| {
+            o.add(k, Ok(Decoded::new(Meta::from(Bytes::new()), Val::Null, ())))
+                .unwrap()
+        };
+        let keys = |o: &Obj| o.iter().map(|(k, ..)| k.to_string()).collect::<Vec<_>>();
+        let mut suffixed = Obj::default();
+        let mut kept = Obj::with_duplicate_keys(DuplicateKeys::Keep);
+        for o in [&mut suffixed, &mut kept] {
+            for k in ["tag", "tag", "tag_3", "tag"] {
+                add(o, k);
+            }
+        }
+        // a suffix is skipped if a key with it was added already
+        assert_eq!(keys(&suffixed), ["tag", "tag_2", "tag_3", "tag_4"]);
+        assert!(suffixed.keys_are_unique());
+        assert!(suffixed.contains_key("tag_2") && !suffixed.contains_key("tag_5"));
+        assert_eq!(keys(&kept), ["tag", "tag", "tag_3", "tag"]);
+        assert!(!kept.keys_are_unique());
+        assert!(kept.contains_key("tag") && !kept.contains_key("tag_2"));
+        // appended fields follow the policy of the object they are appended to
+        let mut o = Obj::default();
+        add(&mut o, "tag");
+        o.append(kept);
+        assert_eq!(keys(&o), ["tag", "tag_2", "tag_3", "tag_3_2", "tag_4"]);
+        let mut o = Obj::with_duplicate_keys(DuplicateKeys::Keep);
+        add(&mut o, "tag");
+        o.append(suffixed);
+        assert_eq!(keys(&o), ["tag", "tag", "tag_2", "tag_3", "tag_4"]);
+    }
+
+    #[test]
+    fn large_objects_index_their_keys() {
+        let field = |k: String| (Key::from(k), Meta::from(Bytes::new()), Val::Null);
+        let n = 4 * INDEXED_LEN;
+        let mut o: Obj = (0..n).map(|i| field(format!("k{}", i % 2))).collect();
+        assert!(o.2.is_some() && o.keys_are_unique());
+        assert_eq!(o.last().unwrap().0, format!("k1_{}", n / 2));
+        o.insert_at(0, "k0", Meta::from(Bytes::new()), Val::Null);
+        assert_eq!(o.iter().next().unwrap().0, format!("k0_{}", n / 2 + 1));
+        assert!(o.contains_key("k0_2") && !o.contains_key(&format!("k0_{}", n)));
+        // objects that keep duplicates need no index
+        let mut kept = Obj::with_duplicate_keys(DuplicateKeys::Keep);
+        (0..n).for_each(|_| {
+            let (k, m, v) = field("k".into());
+            kept.push(k, m, v)
+        });
+        assert!(kept.2.is_none() && kept.len() == n && !kept.contains_key("k_2"));
+    }
+
+    #[test]
+    fn absent_optional_fields_consume_nothing() {
+        let mut b = Bytes::from_static(&[1, 2]);
+        let mut o = Obj::default();
+        assert_eq!(o.add_opt("a", true, || le::u8(&mut b)).unwrap(), Some(1));
+        assert_eq!(o.add_opt("b", false, || le::u8(&mut b)).unwrap(), None);
+        assert_eq!(b, &[2][..]);
+        assert!(o.get("a").is_some() && o.get("b").is_none());
+        let e = err(o.add_opt("c", true, || le::u16(&mut b)));
+        assert_eq!(e.path(), [Index::Str("c".into())]);
+    }
+
+    #[test]
+    fn iteration_follows_insertion_order() {
+        let mut o = Obj::default();
+        for k in ["c", "a", "b", "a"] {
+            o.add(k, Ok(Decoded::new(Meta::from(Bytes::new()), Val::Null, ())))
+                .unwrap();
+        }
+        assert_eq!((o.len(), o.last().unwrap().0), (4, "a_2"));
+        let keys: Vec<_> = (&o).into_iter().map(|(k, ..)| k).collect();
+        assert_eq!(keys, ["c", "a", "b", "a_2"]);
+        let keys: Vec<_> = o.into_iter().map(|(k, ..)| k).collect();
+        assert_eq!(keys, ["c", "a", "b", "a_2"]);
+
+        assert!(Arr::default().is_empty() && Arr::default().last().is_none());
+        let a: Arr = [3, 1, 2]
+            .map(|i| (Meta::from(Bytes::new()), Val::U64(i)))
+            .into_iter()
+            .collect();
+        let vals = |a: &Arr| a.iter().map(|(_, v)| v.to_string()).collect::<Vec<_>>();
+        assert_eq!(vals(&a), ["3 (0x3)", "1 (0x1)", "2 (0x2)"]);
+        assert!(matches!(a.last(), Some((_, Val::U64(2)))));
+        let n = a
+            .into_iter()
+            .filter(|(_, v)| matches!(v, Val::U64(_)))
+            .count();
+        assert_eq!(n, 3);
+    }
+
+    bitflags::bitflags! {
+        #[derive(Clone, Copy, Debug, PartialEq)]
+        struct Mode: u8 {
+            const read = 1 << 0;
+            const write = 1 << 1;
+            const _ = !0;
+        }
+    }
+
+    #[test]
+    fn forced_flags_lie_in_the_input() {
+        let root = Bytes::from_static(&[0, 0, 0b101, 0]);
+        let _input = input(&root);
+        let mut b = root.slice(2..);
+        let d = flags::<Obj, Mode>(le::u8(&mut b)).unwrap();
+        assert!(d.val.forced().is_none());
+        assert_eq!(d.meta.offset_in(&root), Some(2..3));
+        let Val::Obj(o) = d.val.force() else {
+            panic!("expected an object");
+        };
+        for (k, m, _) in o.iter() {
+            assert_eq!(m.offset_in(&root), Some(2..3), "{k}");
+        }
+        assert_eq!(o.len(), 3);
+    }
+
+    #[test]
+    fn raw_bytes_are_captured() {
+        let mut b = Bytes::from_static(&[0xde, 0xad, 0xbe, 0xef]);
+        let d = raw(&mut b, 3).unwrap();
+        assert!(matches!(&d.val, Val::Bytes(v) if **v == d.out));
+        assert_eq!((&d.out[..], &b[..]), (&[0xde, 0xad, 0xbe][..], &[0xef][..]));
+    }
+
+    #[test]
+    fn diff_kinds() {
+        let obj = |root: &Bytes, keys: &[&'static str]| {
+            let mut o = Obj::default();
+            let _input = input(root);
+            for (i, k) in keys.iter().enumerate() {
+                o.add(*k, le::u8(&mut root.slice(i..))).unwrap();
+            }
+            Val::Obj(Box::new(o))
+        };
+        let (ra, rb) = (
+            Bytes::from_static(&[1, 2, 3]),
+            Bytes::from_static(&[1, 9, 2]),
+        );
+        let (a, b) = (obj(&ra, &["x", "y"]), obj(&rb, &["x", "z", "y"]));
+        let kinds = |diffs: Vec<Diff>| {
+            let d = diffs
+                .into_iter()
+                .map(|d| (Path(&d.path).to_string(), d.kind, d.old, d.new));
+            d.collect::<Vec<_>>()
+        };
+        let s = |s: &str| Some(s.to_string());
+        assert_eq!(
+            kinds(diff(&a, &b)),
+            [("z".into(), DiffKind::Added, None, s("9"))]
+        );
+        assert_eq!(
+            kinds(diff(&b, &a)),
+            [("z".into(), DiffKind::Removed, s("9"), None)]
+        );
+        assert_eq!(
+            kinds(diff_moved((&ra, &a), (&rb, &b))),
+            [
+                ("y".into(), DiffKind::Moved, s("0x1"), s("0x2")),
+                ("z".into(), DiffKind::Added, None, s("9"))
+            ]
+        );
+        assert!(diff(&a, &a).is_empty());
+    }
+
+    #[test]
+    fn path_parsing() {
+        let path = Index::parse_path(".files[12].name").unwrap();
+        assert_eq!(Path(&path).to_string(), "files[12].name");
+        assert!(matches!(&path[..], [Index::Str(_), Index::Int(12), Index::Str(k)] if k == "name"));
+        assert_eq!(Index::parse_path("").unwrap(), []);
+        let errors = ["a[1", "a[x]", "a..b"].map(|p| Index::parse_path(p).unwrap_err());
+        let expected = [
+            "expected `]`",
+            "invalid array index `x`",
+            "empty key in path `a..b`",
+        ];
+        assert_eq!(errors, expected);
+    }
+
+    #[test]
+    fn coverage_of_gaps_and_overlaps() {
+        let root = Bytes::from_static(&[0; 10]);
+        let _input = input(&root);
+        let mut o = Obj::default();
+        for (k, r) in [("a", 1..4), ("b", 3..5), ("c", 7..8)] {
+            let d = Decoded::new(Meta::from(root.slice(r)), Val::Null, ());
+            o.add(k, Ok(d)).unwrap();
+        }
+        let c = coverage(&Val::Obj(Box::new(o)), &root);
+        assert_eq!((c.covered, c.uncovered), (5, vec![0..1, 5..7, 8..10]));
+        let [overlap] = &c.overlaps[..] else {
+            panic!("expected one overlap");
+        };
+        assert_eq!(overlap.range, 3..4);
+        let paths = overlap.paths.each_ref().map(|p| Path(p).to_string());
+        assert_eq!(paths, ["a", "b"]);
+    }
+
+    #[test]
+    fn fields_at_offsets() {
+        let root = Bytes::from(vec![0; 6]);
+        let _input = input(&root);
+        let leaf = |r: Range<usize>| (Meta::from(root.slice(r)), Val::Null);
+        let (x, b) = (leaf(0..1), leaf(2..3));
+        let a = Val::Obj(Box::new([("x".into(), x.0, x.1)].into_iter().collect()));
+        // bytes that are equal to those of the root, but not part of it
+        let derived = (Meta::from(Bytes::from(vec![0; 6])), Val::Null);
+        let entries = [
+            ("derived".into(), derived.0, derived.1),
+            ("a".into(), Meta::from(root.slice(0..4)), a),
+            ("b".into(), b.0, b.1),
+        ];
+        let v = Val::lazy(move || Val::Obj(Box::new(entries.into_iter().collect())));
+        let at = |offset| {
+            let (path, range) = v.field_at(&root, offset)?;
+            Some((Path(&path).to_string(), range))
+        };
+        assert_eq!(at(0), Some(("a.x".into(), 0..1)));
+        // leaves are preferred over objects that contain the offset in none of their fields
+        assert_eq!(at(2), Some(("b".into(), 2..3)));
+        assert_eq!(at(3), Some(("a".into(), 0..4)));
+        assert_eq!(at(5), None);
+    }
+
+    /// Arrays nested `depth` times around a lazy null.
+    fn nested(depth: usize) -> Val {
+        let m = || Meta::from(Bytes::new());
+        let mut v = Val::lazy(|| Val::Null);
+        for _ in 0..depth {
+            v = Val::Arr(Box::new(
+                [(m(), v), (m(), Val::Bool(true))].into_iter().collect(),
+            ));
+        }
+        v
+    }
+
+    #[test]
+    fn evaluation_limits() {
+        let v = nested(50);
+        // number of nested arrays, and the first value that is not an array
+        let depth = |v: &Val| {
+            let (mut n, mut mv) = (0, (Meta::from(Bytes::new()), v.clone()));
+            while let (_, Val::Arr(a)) = &mv {
+                let (m, v) = a.iter().next().unwrap();
+                (n, mv) = (n + 1, (m.clone(), v.clone()));
+            }
+            (n, mv.0.description().map(String::from), mv.1)
+        };
+        let opts = EvalOpts {
+            max_depth: Some(3),
+            ..EvalOpts::default()
+        };
+        let (n, description, last) = depth(&v.eval_with(&opts));
+        assert_eq!(
+            (n, description.as_deref()),
+            (4, Some("truncated: depth limit"))
+        );
+        assert!(matches!(last, Val::Raw { gap: false }));
+
+        // nodes are counted depth first, so the first elements are evaluated
+        let opts = EvalOpts {
+            max_nodes: Some(5),
+            ..EvalOpts::default()
+        };
+        let e = v.eval_with(&opts);
+        let (n, description, _) = depth(&e);
+        assert_eq!(
+            (n, description.as_deref()),
+            (6, Some("truncated: node limit"))
+        );
+        let Val::Arr(a) = e else { unreachable!() };
+        let (m, v_) = a.last().unwrap();
+        assert!(matches!(v_, Val::Raw { gap: false }));
+        assert_eq!(m.description(), Some("truncated: node limit"));
+
+        // lazy values are kept unless forced
+        let opts = EvalOpts {
+            force_lazy: false,
+            ..EvalOpts::default()
+        };
+        let (n, _, last) = depth(&nested(50).eval_with(&opts));
+        assert!(n == 50 && matches!(last, Val::Lazy(_)));
+        let (n, _, last) = depth(&nested(2).eval());
+        assert!(n == 2 && matches!(last, Val::Null));
+    }
+
+    #[test]
+    fn rendered_errors() {
+        let root = Bytes::from_static(&[0; 4]);
+        let mut o = Obj::default();
+        let e = with_input(&root, || {
+            o.add_obj("header", Meta::from(&root), |_, o| {
+                let e = Error::new(&root.slice(3..), "bad").with_context("version 2");
+                o.add::<()>("magic", Err(e))
+            })
+        });
+        let e = err(e).with_context("archive");
+        assert_eq!(e.offset(), Some(3));
+        let expected = "header.magic: in archive, version 2: bad (at offset 0x3)";
+        assert_eq!(e.to_string(), expected);
+        let e: Box<dyn std::error::Error> = Box::new(e);
+        assert_eq!(e.to_string(), expected);
+        // errors outside of the input do not know their offset
+        let e = Error::new(&root.slice(3..), "bad");
+        assert_eq!((e.offset(), e.remaining()), (None, 1));
+        assert_eq!(e.to_string(), "bad");
+    }
+
+    #[test]
+    fn unknown_flag_bits() {
+        let fields = |bits: u8| {
+            let d = flags::<Obj, Mode>(le::u8(&mut Bytes::from(vec![bits]))).unwrap();
+            assert_eq!(d.out.bits(), bits);
+            let Val::Obj(o) = d.val.eval() else {
+                panic!("expected an object");
+            };
+            let field = |v: &Val| match v {
+                Val::Arr(a) => format!("{:?}", a.iter().map(|(_, v)| v).collect::<Vec<_>>()),
+                v => format!("{v:?}"),
+            };
+            o.iter()
+                .map(|(k, _, v)| format!("{k}: {}", field(v)))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(fields(0b10), ["read: false", "write: true"]);
+        let expected = ["read: true", "write: false", "unknown_bits: [2, 7]"];
+        assert_eq!(fields(0b1000_0101), expected);
+        // flags are decoded only into objects
+        let d = flags::<Discard, Mode>(le::u8(&mut Bytes::from_static(&[0x80]))).unwrap();
+        assert!(matches!(d.val, Val::Raw { gap: false }));
+    }
+
+    #[test]
+    fn dos_date_and_time() {
+        let d = dos::datetime::<Obj>(&mut Bytes::from_static(&[0xa5, 0x52, 0x67, 0x5a])).unwrap();
+        let dt = d.out;
+        assert_eq!(dt.to_string(), "2025-03-07T10:21:10");
+        assert_eq!(dt.unix(), 1741342870);
+        assert!(d.meta.warnings().is_empty());
+        let v = d.val;
+        assert!(matches!(v.query("fat_date.year"), Ok(Val::U16(2025))));
+        assert!(matches!(v.query("fat_time.second"), Ok(Val::U8(10))));
+        assert!(matches!(v.query("iso8601"), Ok(Val::Str(s)) if **s == b"2025-03-07T10:21:10"[..]));
+
+        // second 62, day 0, and month 13 are decoded, but warned about
+        let mut b = Bytes::from_static(&[0x1f, 0x00, 0xa0, 0x01]);
+        let d = dos::datetime::<Obj>(&mut b).unwrap();
+        let expected = [
+            "second 62 is out of range",
+            "day 0 is out of range",
+            "month 13 is out of range",
+        ];
+        assert_eq!(d.meta.warnings(), expected);
+        assert_eq!(d.out.to_string(), "1980-13-00T00:00:62");
+        assert!(b.is_empty());
+        assert!(err(dos::datetime::<Obj>(&mut Bytes::from_static(&[0; 3])))
+            .msg()
+            .starts_with("expected 4 bytes"));
+    }
+
+    #[test]
+    fn unix_times() {
+        let known = [
+            (0, "1970-01-01T00:00:00Z"),
+            (-1, "1969-12-31T23:59:59Z"),
+            (1 << 31, "2038-01-19T03:14:08Z"),
+            (-(1 << 31), "1901-12-13T20:45:52Z"),
+            (951782400, "2000-02-29T00:00:00Z"),
+        ];
+        for (secs, iso) in known {
+            assert_eq!(iso8601(secs), iso);
+        }
+        // signed 32-bit times reach back before 1970
+        let d = unix_time::<Obj, _>(le::i32(&mut Bytes::from_static(&[0xff; 4]))).unwrap();
+        assert_eq!(d.out, -1);
+        assert!(matches!(d.val.query("unix"), Ok(Val::I32(-1))));
+        assert!(
+            matches!(d.val.query("iso8601"), Ok(Val::Str(s)) if **s == b"1969-12-31T23:59:59Z"[..])
+        );
+        // times that do not fit into an `i64` have no date
+        let d = unix_time::<Obj, _>(le::u64(&mut Bytes::from_static(&[0xff; 8]))).unwrap();
+        assert!(matches!(d.val.query("unix"), Ok(Val::U64(u64::MAX))));
+        assert!(d.val.query("iso8601").is_err());
+    }
+
+    #[test]
+    fn checksums() {
+        assert_eq!(checksum::crc32(b"123456789"), 0xcbf43926);
+        assert_eq!(checksum::adler32(b"Wikipedia"), 0x11e60398);
+        assert_eq!(checksum::adler32(b""), 1);
+        // sums are reduced before they overflow
+        assert_eq!(checksum::adler32(&[0xff; 100_000]), 0x149a_302c);
+        let stored = || le::u32(&mut Bytes::from_static(&[0x26, 0x39, 0xf4, 0xcb])).unwrap();
+        let keys = |v: &Val| match v {
+            Val::Obj(o) => o
+                .iter()
+                .map(|(k, _, v)| format!("{k}: {v:?}"))
+                .collect::<Vec<_>>(),
+            v => panic!("expected an object, found {v:?}"),
+        };
+        let valid = ["value: 3421780262", "computed: 3421780262", "valid: true"];
+        assert_eq!(keys(&checksum::verify(stored(), Some(0xcbf43926))), valid);
+        assert_eq!(
+            keys(&checksum::verify(stored(), Some(0)))[2],
+            "valid: false"
+        );
+        assert_eq!(
+            keys(&checksum::verify(stored(), None)),
+            ["value: 3421780262"]
+        );
+    }
+
+    #[test]
+    fn expected_values() {
+        let d = expect(le::u8(&mut Bytes::from_static(&[3])), 0).unwrap();
+        assert_eq!(d.out, 3);
+        assert!(matches!(d.val, Val::U8(3)));
+        assert_eq!(d.meta.warnings(), ["expected 0, found 3"]);
+        let d = expect(le::u8(&mut Bytes::from_static(&[0])), 0).unwrap();
+        assert!(d.meta.warnings().is_empty());
+        assert!(expect(le::u8(&mut Bytes::new()), 0).is_err());
     }
 }
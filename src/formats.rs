@@ -0,0 +1,79 @@
+//! Registry of formats that are added at run time.
+//!
+//! Applications that embed this crate can register decoders for their own formats,
+//! which are then detected by [`crate::sniff`] together with the built-in formats.
+
+use crate::decode::{with_input, Obj, Result};
+use crate::Format;
+use alloc::sync::Arc;
+use bytes::Bytes;
+use std::sync::RwLock;
+
+/// Object-safe version of [`crate::Format`].
+///
+/// In contrast to [`crate::Format`], options are stored in the format itself.
+pub trait DynFormat: Send + Sync {
+    /// Short name of the format, such as `"zip"`.
+    fn name(&self) -> &'static str;
+
+    /// Return true if `b` (the whole input) looks like it is in this format.
+    fn sniff(&self, b: &[u8]) -> bool;
+
+    /// Decode `b` into `o`.
+    ///
+    /// On error, `o` contains everything that was decoded up to the error.
+    fn decode(&self, o: &mut Obj, b: Bytes) -> Result;
+}
+
+/// Every [`Format`] is a [`DynFormat`] that decodes with the default options.
+impl<F: Format + Send + Sync> DynFormat for F {
+    fn name(&self) -> &'static str {
+        F::NAME
+    }
+
+    fn sniff(&self, b: &[u8]) -> bool {
+        F::sniff(b)
+    }
+
+    fn decode(&self, o: &mut Obj, b: Bytes) -> Result {
+        with_input(&b.clone(), || F::decode(o, b, &F::Opts::default()))
+    }
+}
+
+static REGISTRY: RwLock<Vec<Arc<dyn DynFormat>>> = RwLock::new(Vec::new());
+
+/// Register a format, replacing any registered format with the same name.
+///
+/// Formats are sniffed in the order in which they were first registered.
+pub fn register(format: Box<dyn DynFormat>) {
+    let mut formats = REGISTRY.write().unwrap();
+    let format: Arc<dyn DynFormat> = format.into();
+    match formats.iter_mut().find(|f| f.name() == format.name()) {
+        Some(f) => *f = format,
+        None => formats.push(format),
+    }
+}
+
+/// Remove the registered format with the given name, returning it if it was registered.
+pub fn unregister(name: &str) -> Option<Arc<dyn DynFormat>> {
+    let mut formats = REGISTRY.write().unwrap();
+    let i = formats.iter().position(|f| f.name() == name)?;
+    Some(formats.remove(i))
+}
+
+/// Return the registered format with the given name.
+pub fn get(name: &str) -> Option<Arc<dyn DynFormat>> {
+    let formats = REGISTRY.read().unwrap();
+    formats.iter().find(|f| f.name() == name).cloned()
+}
+
+/// Names of all registered formats, in sniffing order.
+pub fn names() -> Vec<&'static str> {
+    REGISTRY.read().unwrap().iter().map(|f| f.name()).collect()
+}
+
+/// Return the first registered format that `b` looks like.
+pub fn sniff(b: &[u8]) -> Option<Arc<dyn DynFormat>> {
+    let formats = REGISTRY.read().unwrap();
+    formats.iter().find(|f| f.sniff(b)).cloned()
+}
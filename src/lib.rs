@@ -0,0 +1,307 @@
+//! A framework for decoding binary data.
+//!
+//! Decoders produce a tree of values ([`decode::Val`]),
+//! where every node stores the bytes it was derived from ([`decode::Meta`]).
+//!
+//! Decoding the same input with the same options always yields the same tree:
+//! objects and arrays keep the order in which their values were decoded,
+//! and decoders that work in parallel merge their results in input order.
+//! Warnings are reported sorted by offset.
+
+extern crate alloc;
+
+pub mod decode;
+pub mod formats;
+#[cfg(feature = "serde")]
+pub mod ser;
+pub mod tar;
+pub mod trace;
+pub mod zip;
+
+use alloc::rc::Rc;
+use bytes::Bytes;
+use core::fmt::Display;
+use core::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+use core::time::Duration;
+use decode::{Error, Obj, Result};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// Predicate on the names of archive entries, selecting which entries to decode.
+pub type Filter = Rc<dyn Fn(&[u8]) -> bool>;
+
+/// Callback that is called during decoding with the amount of work done and the total work.
+///
+/// For ZIP archives, work is measured in entries, for tar archives in bytes.
+pub type Progress = Rc<dyn Fn(u64, u64)>;
+
+/// Phase of decoding.
+#[derive(Clone, Copy, Debug)]
+pub enum Phase {
+    /// Finding the structures that point to the rest of the input,
+    /// such as the ZIP end of central directory record.
+    Locate,
+    /// Decoding headers.
+    Headers,
+    /// Decompressing data, which happens when lazy values are forced.
+    Decompress,
+}
+
+/// Measurements taken during decoding.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// Time spent in each phase, indexed by [`Phase`].
+    time: [Mutex<Duration>; 3],
+}
+
+impl Metrics {
+    /// Run `f`, adding the time it takes to `phase` if `metrics` is given.
+    pub fn time<T>(metrics: Option<&Self>, phase: Phase, f: impl FnOnce() -> T) -> T {
+        let Some(m) = metrics else { return f() };
+        let start = Instant::now();
+        let y = f();
+        *m.time[phase as usize].lock().unwrap() += start.elapsed();
+        y
+    }
+
+    /// Time spent in `phase`.
+    pub fn elapsed(&self, phase: Phase) -> Duration {
+        *self.time[phase as usize].lock().unwrap()
+    }
+}
+
+/// Counters of the features found in the input, such as compression methods.
+///
+/// Counters are keyed by the name of the format and the name of the feature,
+/// such as `("zip", "compression_method: deflated")`.
+/// A single collector can be shared between several inputs to count over all of them.
+#[derive(Debug, Default)]
+pub struct Stats {
+    counts: Mutex<BTreeMap<&'static str, BTreeMap<String, u64>>>,
+}
+
+impl Stats {
+    /// Increment the counter of `feature` in `format` if `stats` is given.
+    pub fn count(stats: Option<&Self>, format: &'static str, feature: impl Display) {
+        if let Some(s) = stats {
+            s.add(format, feature, 1)
+        }
+    }
+
+    /// Add `n` to the counter of `feature` in `format`.
+    pub fn add(&self, format: &'static str, feature: impl Display, n: u64) {
+        let mut counts = self.counts.lock().unwrap();
+        let features = counts.entry(format).or_default();
+        *features.entry(feature.to_string()).or_default() += n;
+    }
+
+    /// Value of the counter of `feature` in `format`.
+    pub fn get(&self, format: &str, feature: &str) -> u64 {
+        let counts = self.counts.lock().unwrap();
+        let count = counts.get(format).and_then(|f| f.get(feature));
+        count.copied().unwrap_or(0)
+    }
+
+    /// All counters, ordered by format and feature.
+    pub fn counts(&self) -> BTreeMap<&'static str, BTreeMap<String, u64>> {
+        self.counts.lock().unwrap().clone()
+    }
+}
+
+/// Limits on the lengths and counts that decoders accept from the input.
+///
+/// Crafted inputs can declare huge lengths and counts;
+/// these limits make decoding them fail early with an error naming the exceeded limit,
+/// instead of failing late or allocating excessive memory.
+#[derive(Clone, Debug)]
+pub struct Limits {
+    /// Maximal number of bytes of a single field whose length is given by the input.
+    pub max_field_bytes: Option<u64>,
+    /// Maximal number of bytes that a single entry may decompress to.
+    pub max_decompressed_bytes: Option<usize>,
+    /// Maximal number of entries in an archive.
+    pub max_entries: Option<usize>,
+}
+
+/// The default limits are generous, but finite.
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_field_bytes: Some(1 << 40),
+            max_decompressed_bytes: Some(1 << 32),
+            max_entries: Some(1 << 24),
+        }
+    }
+}
+
+impl Limits {
+    /// No limits at all.
+    pub fn unlimited() -> Self {
+        Self {
+            max_field_bytes: None,
+            max_decompressed_bytes: None,
+            max_entries: None,
+        }
+    }
+
+    /// Fail if a field of `n` bytes at `b` exceeds [`Self::max_field_bytes`].
+    pub fn field(&self, b: &Bytes, n: u64) -> Result {
+        match self.max_field_bytes {
+            Some(max) if n > max => {
+                let msg = format!("field of {n} bytes exceeds the limit max_field_bytes = {max}");
+                Err(Error::new(b, msg))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Fail if `n` entries, the next of which starts at `b`, exceed [`Self::max_entries`].
+    pub fn entries(&self, b: &Bytes, n: usize) -> Result {
+        match self.max_entries {
+            Some(max) if n > max => {
+                let msg = format!("{n} entries exceed the limit max_entries = {max}");
+                Err(Error::new(b, msg))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Value of [`Self::max_decompressed_bytes`], or the maximal `usize` if it is not set.
+    pub fn decompressed(&self) -> usize {
+        self.max_decompressed_bytes.unwrap_or(usize::MAX)
+    }
+}
+
+/// Cache of uncompressed data, keyed by the span of the compressed data.
+///
+/// When the total size of the cached data exceeds the maximum,
+/// the least recently used data is evicted.
+#[derive(Debug, Default)]
+pub struct Cache {
+    max: usize,
+    entries: Mutex<CacheEntries>,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+#[derive(Debug, Default)]
+struct CacheEntries {
+    /// Compressed data, the data uncompressed from it, and the time of its last use,
+    /// keyed by the address and length of the compressed data.
+    ///
+    /// Holding the compressed data ensures that its address is not reused.
+    map: HashMap<(usize, usize), (Bytes, Bytes, u64)>,
+    /// Total number of uncompressed bytes.
+    size: usize,
+    time: u64,
+}
+
+impl Cache {
+    /// Create a cache that holds at most `max` uncompressed bytes.
+    pub fn new(max: usize) -> Self {
+        Self {
+            max,
+            ..Self::default()
+        }
+    }
+
+    fn key(compressed: &Bytes) -> (usize, usize) {
+        (compressed.as_ptr() as usize, compressed.len())
+    }
+
+    /// Return the data uncompressed from `compressed`, if it is cached.
+    pub fn get(&self, compressed: &Bytes) -> Option<Bytes> {
+        let mut e = self.entries.lock().unwrap();
+        e.time += 1;
+        let time = e.time;
+        let hit = e.map.get_mut(&Self::key(compressed)).map(|(_, u, t)| {
+            *t = time;
+            u.clone()
+        });
+        let counter = if hit.is_some() {
+            &self.hits
+        } else {
+            &self.misses
+        };
+        counter.fetch_add(1, Relaxed);
+        hit
+    }
+
+    /// Cache the data uncompressed from `compressed`, evicting other data if necessary.
+    ///
+    /// Data larger than the maximal size of the cache is not cached.
+    pub fn insert(&self, compressed: Bytes, uncompressed: Bytes) {
+        if uncompressed.len() > self.max {
+            return;
+        }
+        let mut e = self.entries.lock().unwrap();
+        e.time += 1;
+        e.size += uncompressed.len();
+        let value = (compressed.clone(), uncompressed, e.time);
+        if let Some((_, old, _)) = e.map.insert(Self::key(&compressed), value) {
+            e.size -= old.len();
+        }
+        while e.size > self.max {
+            let lru = e.map.iter().min_by_key(|(_, (.., t))| *t).map(|(k, _)| *k);
+            if let Some((_, u, _)) = lru.and_then(|k| e.map.remove(&k)) {
+                e.size -= u.len();
+            }
+        }
+    }
+
+    /// Number of times that cached data was found.
+    pub fn hits(&self) -> usize {
+        self.hits.load(Relaxed)
+    }
+
+    /// Number of times that data was not found in the cache.
+    pub fn misses(&self) -> usize {
+        self.misses.load(Relaxed)
+    }
+}
+
+/// A binary format that can be decoded into a value tree.
+pub trait Format {
+    /// Short name of the format, such as `"zip"`.
+    const NAME: &'static str;
+    /// Options that influence decoding.
+    type Opts: Default;
+
+    /// Return true if `b` (the whole input) looks like it is in this format.
+    fn sniff(b: &[u8]) -> bool;
+
+    /// Decode `b` into `o`.
+    ///
+    /// On error, `o` contains everything that was decoded up to the error.
+    fn decode(o: &mut Obj, b: Bytes, opts: &Self::Opts) -> Result;
+}
+
+/// Function that returns true if the input looks like it is in a certain format.
+pub type Sniff = fn(&[u8]) -> bool;
+
+/// Names and sniffing functions of all formats.
+///
+/// Formats are sniffed in this order, so formats with a cheaper or
+/// more reliable detection come first.
+/// For example, ZIP comes last because it has to search for its
+/// end of central directory record at the end of the input.
+pub const FORMATS: &[(&str, Sniff)] = &[
+    (tar::Tar::NAME, tar::Tar::sniff),
+    (zip::Zip::NAME, zip::Zip::sniff),
+];
+
+/// Return the name of the first format that `b` looks like.
+///
+/// Formats registered with [`formats::register`] are sniffed before the built-in ones,
+/// so that they can claim inputs that also look like a built-in format,
+/// such as firmware images that end with a ZIP archive.
+pub fn sniff(b: &[u8]) -> Option<&'static str> {
+    if let Some(f) = formats::sniff(b) {
+        return Some(f.name());
+    }
+    FORMATS
+        .iter()
+        .find(|(_, sniff)| sniff(b))
+        .map(|(name, _)| *name)
+}
@@ -1,40 +1,35 @@
-#[macro_use]
-mod tar;
-mod decode;
-mod zip;
+mod cli;
 
-fn main() -> std::io::Result<()> {
-    /*
-    use std::rc::Rc;
-    let file: &[u8] = include_bytes!("../test.tar");
-    let mut d = file;
-    let tar = tar2::decode_tar(&mut d);
-    for file in tar {
-        use tar::AsVal;
-        let file = file.unwrap();
-        //dbg!(&file);
-        //dbg!(file.as_val());
-        dbg!(file.as_src_val());
-        let src_val = tar::SrcVal::Dyn(&file);
-        let json = tar::JsonVal::Src(Rc::new(src_val));
-    }
-    */
+use cli::error::Error;
+use cli::{Cli, Command};
+use std::process::ExitCode;
+
+#[cfg(feature = "alloc-stats")]
+#[global_allocator]
+static ALLOC: cli::stats::Counting = cli::stats::Counting;
 
-    let mut args = std::env::args();
-    args.next();
-    let filename = args.next().expect("pass ZIP filename as argument");
-    let file = std::fs::File::open(filename.clone())?;
-    let mmap = unsafe { memmap2::Mmap::map(&file) }?;
-    let b = bytes::Bytes::from_owner(mmap);
-    let mut o = decode::Obj::default();
+fn run(cli: Cli) -> Result<ExitCode, Error> {
+    if cli.dump_config {
+        return cli::config::dump();
+    }
+    match cli.command() {
+        Command::Decode(args) => cli::decode::run(args),
+        Command::Extract(args) => cli::extract::run(args),
+        Command::List(args) => cli::list::run(args),
+        Command::Cat(args) => cli::cat::run(args),
+        Command::Hex(args) => cli::hex::run(args),
+        Command::Diff(args) => cli::diff::run(args),
+        Command::Coverage(args) => cli::coverage::run(args),
+        Command::Scan(args) => cli::scan::run(args),
+        #[cfg(feature = "tui")]
+        Command::Tui(args) => cli::tui::run(args),
+        Command::Verify(args) => cli::verify::run(args),
+    }
+}
 
-    let r = if filename.ends_with(".tar") {
-        tar::decode_tar(&mut o, b)
-    } else {
-        zip::decode_zip(&mut o, b, &zip::Opts::default())
-    };
-    let o = decode::Val::Obj(o).eval();
-    dbg!(o);
-    dbg!(r.unwrap());
-    Ok(())
+fn main() -> ExitCode {
+    Cli::load().and_then(run).unwrap_or_else(|e| {
+        eprintln!("error: {e}");
+        e.exit_code()
+    })
 }
@@ -0,0 +1,303 @@
+//! Serialization of decoded values with serde.
+//!
+//! Objects become maps, arrays become sequences, and lazy values are forced.
+
+use crate::decode::{Arr, Meta, Obj, Path, Val};
+use bytes::Bytes;
+use serde::ser::{Serialize, SerializeMap, Serializer};
+use std::collections::BTreeSet;
+
+/// How to serialize values that consist of bytes, namely [`Val::Raw`] and [`Val::Bytes`].
+#[derive(Clone, Copy, Debug, Default)]
+pub enum BytesAs {
+    /// Serialize the bytes as base64 string.
+    #[default]
+    Base64,
+    /// Serialize the location of the bytes as `{"len": n, "offset": o}`.
+    ///
+    /// The offset is relative to [`Opts::root`], or null if it cannot be determined.
+    Span,
+    /// Serialize the bytes as byte string, which is efficient in binary formats such as CBOR.
+    Bytes,
+}
+
+/// Serialization options.
+#[derive(Clone, Debug, Default)]
+pub struct Opts {
+    pub bytes: BytesAs,
+    /// Bytes relative to which offsets are given.
+    pub root: Option<Bytes>,
+    /// Serialize only the first value of every key in an object.
+    ///
+    /// Otherwise, keys that occur several times in an object are serialized several times,
+    /// which is syntactically valid JSON, but which many consumers do not expect.
+    pub dedup: bool,
+    /// Serialize every value below the root as map with the keys `start`, `length`, `value`,
+    /// and optionally `description`, `error`, `warnings`, and `points_to`.
+    ///
+    /// The start is relative to [`Opts::root`], or null if it cannot be determined.
+    pub spans: bool,
+}
+
+/// Decoded value that is serialized with the given options.
+pub struct Ser<'a> {
+    m: Option<&'a Meta>,
+    v: &'a Val,
+    opts: &'a Opts,
+}
+
+impl<'a> Ser<'a> {
+    pub fn new(v: &'a Val, opts: &'a Opts) -> Self {
+        let m = None;
+        Self { m, v, opts }
+    }
+
+    fn bytes<S: Serializer>(&self, b: &Bytes, s: S) -> Result<S::Ok, S::Error> {
+        match self.opts.bytes {
+            BytesAs::Base64 => s.serialize_str(&base64(b)),
+            BytesAs::Span => {
+                // only bytes that the value was decoded from have a span
+                let m = self.m.filter(|m| {
+                    let span = m.bytes();
+                    span.as_ptr() == b.as_ptr() && span.len() == b.len()
+                });
+                let root = self.opts.root.as_ref();
+                let offset = m
+                    .zip(root)
+                    .and_then(|(m, r)| m.offset_in(r))
+                    .map(|r| r.start);
+                let mut map = s.serialize_map(Some(2))?;
+                map.serialize_entry("len", &b.len())?;
+                map.serialize_entry("offset", &offset)?;
+                map.end()
+            }
+            BytesAs::Bytes => s.serialize_bytes(b),
+        }
+    }
+
+    /// Serialize the value without its span.
+    fn value<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        match self.v.force() {
+            Val::Null => s.serialize_unit(),
+            Val::Bool(b) => s.serialize_bool(*b),
+            Val::U8(u) => s.serialize_u8(*u),
+            Val::U16(u) => s.serialize_u16(*u),
+            Val::U32(u) => s.serialize_u32(*u),
+            Val::U64(u) => s.serialize_u64(*u),
+            Val::U128(u) => s.serialize_u128(**u),
+            Val::I8(i) => s.serialize_i8(*i),
+            Val::I16(i) => s.serialize_i16(*i),
+            Val::I32(i) => s.serialize_i32(*i),
+            Val::I64(i) => s.serialize_i64(*i),
+            Val::F32(x) => s.serialize_f32(*x),
+            Val::F64(x) => s.serialize_f64(*x),
+            Val::Str(st) => s.serialize_str(&String::from_utf8_lossy(st)),
+            Val::Bytes(b) => self.bytes(b, s),
+            Val::Raw { .. } => match self.m {
+                Some(m) => self.bytes(&m.bytes(), s),
+                None => s.serialize_unit(),
+            },
+            Val::Arr(a) => arr(a, self.opts, s),
+            Val::Obj(o) => obj(o, self.opts, s),
+            Val::Lazy(_) => unreachable!(),
+        }
+    }
+}
+
+/// Value of a [`Ser`] that is serialized without its span.
+struct Value<'a>(&'a Ser<'a>);
+
+impl Serialize for Value<'_> {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        self.0.value(s)
+    }
+}
+
+fn child<'a>(m: &'a Meta, v: &'a Val, opts: &'a Opts) -> Ser<'a> {
+    let m = Some(m);
+    Ser { m, v, opts }
+}
+
+fn obj<S: Serializer>(o: &Obj, opts: &Opts, s: S) -> Result<S::Ok, S::Error> {
+    let entry = |(k, m, v)| (k, child(m, v, opts));
+    if opts.dedup {
+        let mut seen = BTreeSet::new();
+        s.collect_map(o.iter().filter(|(k, ..)| seen.insert(*k)).map(entry))
+    } else {
+        s.collect_map(o.iter().map(entry))
+    }
+}
+
+fn arr<S: Serializer>(a: &Arr, opts: &Opts, s: S) -> Result<S::Ok, S::Error> {
+    s.collect_seq(a.iter().map(|(m, v)| child(m, v, opts)))
+}
+
+impl Serialize for Ser<'_> {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        let m = match self.m {
+            Some(m) if self.opts.spans => m,
+            _ => return self.value(s),
+        };
+        let root = self.opts.root.as_ref();
+        let start = root.and_then(|r| m.offset_in(r)).map(|r| r.start);
+        let mut map = s.serialize_map(None)?;
+        map.serialize_entry("start", &start)?;
+        map.serialize_entry("length", &m.bytes().len())?;
+        map.serialize_entry("value", &Value(self))?;
+        if let Some(d) = m.description() {
+            map.serialize_entry("description", d)?;
+        }
+        if let Some(e) = m.error() {
+            map.serialize_entry("error", &e.to_string())?;
+        }
+        if !m.warnings().is_empty() {
+            map.serialize_entry("warnings", m.warnings())?;
+        }
+        if let Some(path) = m.points_to() {
+            map.serialize_entry("points_to", &Path(path).to_string())?;
+        }
+        map.end()
+    }
+}
+
+impl Serialize for Val {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        Ser::new(self, &Opts::default()).serialize(s)
+    }
+}
+
+impl Serialize for Obj {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        obj(self, &Opts::default(), s)
+    }
+}
+
+impl Serialize for Arr {
+    fn serialize<S: Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        arr(self, &Opts::default(), s)
+    }
+}
+
+/// Serialize a value as CBOR, with the spans of all values below it relative to `root`.
+///
+/// Bytes are serialized as byte strings.
+pub fn to_cbor(v: &Val, root: &Bytes) -> Vec<u8> {
+    let opts = Opts {
+        bytes: BytesAs::Bytes,
+        root: Some(root.clone()),
+        spans: true,
+        ..Opts::default()
+    };
+    let mut out = Vec::new();
+    // writing to a vector does not fail
+    ciborium::into_writer(&Ser::new(v, &opts), &mut out).unwrap();
+    out
+}
+
+/// Encode bytes as base64 with padding.
+fn base64(b: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(b.len().div_ceil(3) * 4);
+    for chunk in b.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, c)| n | u32::from(*c) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize].into());
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode::{le, raw, with_input, Decoded};
+
+    /// Object `{"n": 1, "data": b"abc", "n": 2}` with lazy `n`s, decoded from a root.
+    fn sample(root: &Bytes) -> Val {
+        let mut b = root.clone();
+        let mut o = Obj::with_duplicate_keys(crate::decode::DuplicateKeys::Keep);
+        let lazy = |d: Decoded<u8>| {
+            let v = d.val.clone();
+            d.with_val(Val::lazy(move || v))
+        };
+        with_input(root, || {
+            o.add("n", le::u8(&mut b).map(lazy)).unwrap();
+            o.add("data", raw(&mut b, 3)).unwrap();
+            o.add("n", le::u8(&mut b).map(lazy)).unwrap();
+        });
+        Val::Obj(Box::new(o))
+    }
+
+    #[test]
+    fn json() {
+        let root = Bytes::from_static(&[1, b'a', b'b', b'c', 2]);
+        let v = sample(&root);
+        let json = serde_json::to_string(&v).unwrap();
+        assert_eq!(json, r#"{"n":1,"data":"YWJj","n":2}"#);
+        let opts = Opts {
+            bytes: BytesAs::Span,
+            root: Some(root),
+            dedup: true,
+            ..Opts::default()
+        };
+        let json = serde_json::to_string(&Ser::new(&v, &opts)).unwrap();
+        assert_eq!(json, r#"{"n":1,"data":{"len":3,"offset":1}}"#);
+        let a: Arr = [(Meta::from(Bytes::new()), Val::I8(-1))]
+            .into_iter()
+            .collect();
+        assert_eq!(serde_json::to_string(&a).unwrap(), "[-1]");
+    }
+
+    #[test]
+    fn base64_padding() {
+        let encoded = ["", "YQ==", "YWI=", "YWJj", "YWJjZA=="];
+        for (n, e) in encoded.iter().enumerate() {
+            assert_eq!(base64(&b"abcd"[..n]), *e);
+        }
+    }
+
+    #[test]
+    fn cbor_round_trip() {
+        use ciborium::Value as C;
+        let root = Bytes::from_static(&[1, b'a', b'b', b'c', 2]);
+        let v = sample(&root);
+        let c: C = ciborium::from_reader(&to_cbor(&v, &root)[..]).unwrap();
+        let field = |c: &C, k: &str| {
+            let C::Map(m) = c else {
+                panic!("expected a map, found {c:?}");
+            };
+            let v = m.iter().find(|(k_, _)| k_.as_text() == Some(k));
+            v.map(|(_, v)| v.clone()).unwrap()
+        };
+        let span = |c: &C| {
+            let int = |k| u64::try_from(field(c, k).as_integer().unwrap()).unwrap();
+            int("start")..int("start") + int("length")
+        };
+        let C::Map(fields) = &c else {
+            panic!("expected a map, found {c:?}");
+        };
+        let spans: Vec<_> = fields.iter().map(|(_, f)| span(f)).collect();
+        assert_eq!(spans, [0..1, 1..4, 4..5]);
+        let data = field(&c, "data");
+        assert_eq!(field(&data, "value"), C::Bytes(b"abc".to_vec()));
+        assert_eq!(field(&fields[2].1, "value"), C::Integer(2.into()));
+
+        let root = Bytes::from_static(include_bytes!("../test.zip"));
+        let mut o = Obj::default();
+        crate::zip::decode_zip(&mut o, root.clone(), &Default::default()).unwrap();
+        let c: C = ciborium::from_reader(&to_cbor(&Val::Obj(Box::new(o)), &root)[..]).unwrap();
+        let eocd = field(&c, "end_of_central_directory_record");
+        assert_eq!(span(&eocd), 0x1ed..0x203);
+        let size = field(&field(&eocd, "value"), "size_of_central_dir");
+        assert_eq!(span(&size), 0x1f9..0x1fd);
+        assert_eq!(field(&size, "value"), C::Integer(235.into()));
+        assert!(field(&size, "description").is_text());
+    }
+}
@@ -1,5 +1,9 @@
 use crate::decode::*;
-use bytes::Bytes;
+use crate::{Filter, Format, Limits, Metrics, Phase, Progress, Stats};
+use alloc::sync::Arc;
+use bytes::{Bytes, BytesMut};
+use num_derive::FromPrimitive;
+use std::io::{self, Read};
 
 /// Take longest prefix of bytes until NUL.
 fn decode_str(b: Bytes) -> Bytes {
@@ -10,35 +14,76 @@ fn decode_str(b: Bytes) -> Bytes {
     }
 }
 
-fn decode_ustar(o: &mut Obj, b: &mut Bytes) -> Result {
-    o.add("magic", raw(b, 6))?;
-    o.add("version", take_oct8(b))?;
-    o.add("uname", take_str(b, 32))?;
-    o.add("gname", take_str(b, 32))?;
-    o.add("devmajor", take_oct32(b))?;
-    o.add("devminor", take_oct32(b))?;
-    o.add("prefix", take_str(b, 155))?;
-    Ok(())
+/// Fields of the ustar header extension.
+#[derive(Default)]
+struct Ustar {
+    uname: Bytes,
+    gname: Bytes,
+    prefix: Bytes,
+}
+
+fn decode_ustar<S: Sink>(o: &mut S, b: &mut Bytes) -> Result<Ustar> {
+    o.add_described("magic", "ustar indicator", raw(b, 6))?;
+    o.add_described("version", "ustar version", take_oct8(b))?;
+    let uname = o.add_described("uname", "name of the owner", take_str(b, 32))?;
+    let gname = o.add_described("gname", "name of the group", take_str(b, 32))?;
+    o.add_described("devmajor", "major number of a device", take_oct32(b))?;
+    o.add_described("devminor", "minor number of a device", take_oct32(b))?;
+    let prefix = o.add_described("prefix", "prefix of the name", take_str(b, 155))?;
+    Ok(Ustar {
+        uname,
+        gname,
+        prefix,
+    })
 }
 
-const BLOCK_BYTES: usize = 512;
-const END_MARKER: [u8; BLOCK_BYTES * 2] = [0; BLOCK_BYTES * 2];
+/// Size of a block; headers and data are padded to multiples of it.
+pub const BLOCK_BYTES: usize = 512;
+/// Two zero blocks that mark the end of an archive.
+pub const END_MARKER: [u8; BLOCK_BYTES * 2] = [0; BLOCK_BYTES * 2];
 
+/// Take a NUL-padded string of `n` bytes.
+///
+/// Names in tar archives have no declared encoding,
+/// so strings that are not valid UTF-8 are returned as bytes.
 fn take_str(b: &mut Bytes, n: usize) -> Result<Decoded<Bytes>> {
     let b = take(b, n)?;
     let m = Meta::from(&b);
     let s = decode_str(b);
-    Ok(Decoded::new(m, Val::Str(s.clone()), s))
+    let v = if core::str::from_utf8(&s).is_ok() {
+        Val::Str(Box::new(s.clone()))
+    } else {
+        Val::Bytes(Box::new(s.clone()))
+    };
+    Ok(Decoded::new(m, v, s))
 }
 
 macro_rules! take_oct_str {
     ($name: ident, $ty: ident, $f: expr, $width: expr) => {
         fn $name(b: &mut Bytes) -> Result<Decoded<$ty>> {
             let b = take(b, $width)?;
-            // TODO: fail if not string or string contains non-digits
+            // GNU tar stores numbers too large for octal in base-256, marked by the high bit
+            if let [first, rest @ ..] = &b[..] {
+                if first & 0x80 != 0 {
+                    let digits = core::iter::once(first & 0x7f).chain(rest.iter().copied());
+                    // fields have at most 12 bytes, so this does not overflow
+                    let u = digits.fold(0u128, |acc, d| acc << 8 | u128::from(d));
+                    let msg =
+                        || format!("base-256 number {u} does not fit into {}", stringify!($ty));
+                    let u = $ty::try_from(u).map_err(|_| Error::new(&b, msg()))?;
+                    return Ok(Decoded::new(Meta::from(b), $f(u), u));
+                }
+            }
             let s = decode_str(b.clone());
-            let s = core::str::from_utf8(&s).unwrap();
-            let u = $ty::from_str_radix(s.trim_matches(' '), 8).unwrap();
+            let err = || Error::new(&b, format!("expected octal number, found {s:?}"));
+            let s = core::str::from_utf8(&s).map_err(|_| err())?;
+            let s = s.trim_matches(' ');
+            // GNU tar leaves unused numeric fields empty
+            let u = if s.is_empty() {
+                0
+            } else {
+                $ty::from_str_radix(s, 8).map_err(|_| err())?
+            };
             Ok(Decoded::new(Meta::from(b), $f(u), u))
         }
     };
@@ -47,38 +92,857 @@ take_oct_str!(take_oct8, u8, Val::U8, 2);
 take_oct_str!(take_oct32, u32, Val::U32, 8);
 take_oct_str!(take_oct64, u64, Val::U64, 12);
 
-fn decode_file<'a>(o: &mut Obj, b: &mut Bytes) -> Result {
-    let init = b.clone();
-    let offset = |b: &[u8]| b.as_ptr() as usize - init.as_ptr() as usize;
-    let padding = |b: &[u8]| BLOCK_BYTES - (offset(b) % BLOCK_BYTES);
-
-    o.add("name", take_str(b, 100))?;
-    o.add("mode", take_oct32(b))?;
-    o.add("uid", take_oct32(b))?;
-    o.add("gid", take_oct32(b))?;
-    let size = o.add("size", take_oct64(b))?;
-    o.add("mtime", take_oct64(b))?;
-    o.add("chksum", take_oct32(b))?;
-    o.add("typeflag", take_str(b, 1))?;
-    o.add("linkname", take_str(b, 100))?;
-    if b.starts_with(b"ustar\0") {
-        o.add_consumed("ustar", b, |b, v| decode_ustar(v.make_obj(), b))?;
-    }
-    o.add("header_block_padding", raw(b, padding(b)))?;
-    let size: usize = size.try_into().unwrap();
-    o.add("data", raw(b, size))?;
-    o.add("data_block_padding", raw(b, padding(b)))?;
+/// Decode Unix permissions that are rendered in octal.
+fn take_mode(b: &mut Bytes) -> Result<Decoded<u32>> {
+    fn format(v: &Val, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        match v {
+            Val::U32(u) => write!(f, "{u:#o}"),
+            v => write!(f, "{v:?}"),
+        }
+    }
+    take_oct32(b).map(|d| d.map_meta(|m| m.with_format(format)))
+}
+
+/// Return true if the checksum stored in the header matches the header.
+///
+/// This can detect tar files without magic bytes, such as v7 tar files.
+pub fn valid_checksum(header: &[u8]) -> bool {
+    const CHKSUM: core::ops::Range<usize> = 148..156;
+    let Some(stored) = header.get(CHKSUM) else {
+        return false;
+    };
+    let stored = stored.split(|c| *c == b'\0').next().unwrap_or_default();
+    let stored = core::str::from_utf8(stored).ok();
+    let stored = stored.and_then(|s| u32::from_str_radix(s.trim_matches(' '), 8).ok());
+    let sum = |b: &[u8]| b.iter().map(|c| u32::from(*c)).sum::<u32>();
+    let computed = sum(&header[..CHKSUM.start]) + 8 * u32::from(b' ') + sum(&header[CHKSUM.end..]);
+    header[0] != 0 && stored == Some(computed)
+}
+
+/// Type of an entry.
+#[allow(non_camel_case_types)]
+#[derive(Debug, FromPrimitive)]
+#[repr(u8)]
+enum TypeFlag {
+    /// Regular file in old tar archives.
+    regular_old = b'\0',
+    regular = b'0',
+    hard_link = b'1',
+    symbolic_link = b'2',
+    character_device = b'3',
+    block_device = b'4',
+    directory = b'5',
+    fifo = b'6',
+    contiguous = b'7',
+    gnu_dump_dir = b'D',
+    gnu_long_link = b'K',
+    gnu_long_name = b'L',
+    gnu_multi_volume = b'M',
+    gnu_sparse = b'S',
+    gnu_volume_header = b'V',
+    pax_global = b'g',
+    pax_extended = b'x',
+}
+
+/// Return the offset of the first block after the first one
+/// that looks like a header or an end marker, or the length of `b` if there is none.
+fn next_header(b: &[u8]) -> usize {
+    let mut blocks = (BLOCK_BYTES..b.len()).step_by(BLOCK_BYTES);
+    let header = |i: &usize| {
+        let block = b.get(*i..*i + BLOCK_BYTES);
+        block.is_some_and(valid_checksum) || b[*i..].starts_with(&END_MARKER)
+    };
+    blocks.find(header).unwrap_or(b.len())
+}
+
+/// Fields of the header that precede the ustar header extension.
+struct Header {
+    name: Bytes,
+    mode: u32,
+    uid: u32,
+    gid: u32,
+    size: u64,
+    mtime: u64,
+    typeflag: u8,
+    linkname: Bytes,
+}
+
+/// File, directory, or link stored in a tar archive.
+pub struct Entry {
+    /// Name of the entry, including the ustar prefix.
+    pub name: Bytes,
+    /// Unix permissions.
+    pub mode: u32,
+    pub uid: u32,
+    pub gid: u32,
+    /// Modification time in seconds since the Unix epoch.
+    pub mtime: u64,
+    /// Type of the entry, such as `b'0'` for regular files or `b'5'` for directories.
+    pub typeflag: u8,
+    /// Target of a symbolic or hard link.
+    pub linkname: Bytes,
+    pub uname: Bytes,
+    pub gname: Bytes,
+    pub data: Bytes,
+}
+
+/// Decode the records of a PAX extended header, such as `path` or `SCHILY.xattr.user.tag`.
+///
+/// Each record has the form `<length> <keyword>=<value>\n`,
+/// where the length counts the bytes of the whole record.
+/// Archives tend to repeat the same keywords, so they are interned with `keys`.
+fn decode_pax<S: Sink>(o: &mut S, b: &mut Bytes, keys: &mut Interner) -> Result {
+    while !b.is_empty() {
+        let err = |msg: &str| Error::new(b, format!("invalid PAX record: {msg}"));
+        let space = b.iter().position(|c| *c == b' ');
+        let len = space.and_then(|i| core::str::from_utf8(&b[..i]).ok()?.parse::<usize>().ok());
+        let (Some(space), Some(len)) = (space, len) else {
+            return Err(err("expected length"));
+        };
+        if len <= space || len > b.len() || b[len - 1] != b'\n' {
+            return Err(err(&format!("length {len} does not end at a newline")));
+        }
+        let record = b.split_to(len);
+        let body = &record[space + 1..len - 1];
+        let eq = body.iter().position(|c| *c == b'=');
+        let key = eq.and_then(|eq| core::str::from_utf8(&body[..eq]).ok());
+        let (Some(eq), Some(key)) = (eq, key) else {
+            return Err(Error::new(&record, "invalid PAX record: expected keyword"));
+        };
+        let value = record.slice(space + 1 + eq + 1..len - 1);
+        let v = if core::str::from_utf8(&value).is_ok() {
+            Val::Str(Box::new(value))
+        } else {
+            Val::Bytes(Box::new(value))
+        };
+        o.add_owned(
+            keys.intern(key),
+            Ok(Decoded::new(Meta::from(record), v, ())),
+        )?;
+    }
     Ok(())
 }
 
-pub fn decode_tar(o: &mut Obj, mut b: Bytes) -> Result {
-    o.add_consumed("files", &mut b, |b, a| {
-        let a = a.make_arr();
-        while !b.starts_with(&END_MARKER) && !b.is_empty() {
-            a.add_consumed(b, |b, o| decode_file(o.make_obj(), b))?;
+fn decode_file<S: Sink>(
+    o: &mut S,
+    b: &mut Bytes,
+    opts: &Opts,
+    keys: &mut Interner,
+) -> Result<Entry> {
+    // the start of the header is aligned to a block
+    let header = b.clone();
+    let typeflag = |b: &mut Bytes| sym::<S, TypeFlag, _>(le::u8(b));
+    let mtime = |b: &mut Bytes| unix_time::<S, _>(take_oct64(b));
+
+    let Header {
+        name,
+        mode,
+        uid,
+        gid,
+        size,
+        mtime,
+        typeflag,
+        linkname,
+    } = decode_struct!(o, b, Header {
+        /// name of the entry, without the ustar prefix
+        name: take_str(100),
+        /// Unix permissions
+        mode: take_mode,
+        /// user ID of the owner
+        uid: take_oct32,
+        /// group ID of the owner
+        gid: take_oct32,
+        /// size of the data, in bytes
+        size: take_oct64,
+        /// modification time in seconds since the Unix epoch
+        mtime: mtime,
+        /// sum of the header bytes, with the checksum taken as spaces
+        skip chksum: take_oct32,
+        /// type of the entry
+        typeflag: typeflag,
+        /// target of a link
+        linkname: take_str(100),
+    });
+    let variant = match peek::bytes(b, 6) {
+        Some(b"ustar\0") => "ustar",
+        Some(b"ustar ") => "gnu",
+        _ => "v7",
+    };
+    count(opts, format_args!("variant: {variant}"));
+    let mut ustar = Ustar::default();
+    if variant == "ustar" {
+        let r = o.add_obj_consumed("ustar", b, |b, o| decode_ustar(o, b));
+        ustar = r.map_err(|e| e.with_context("ustar header"))?;
+    }
+    o.add("header_block_padding", pad_to(b, BLOCK_BYTES, &header))?;
+    opts.limits.field(b, size)?;
+    let size: usize = size
+        .try_into()
+        .map_err(|_| Error::new(b, format!("file size {size} does not fit into memory")))?;
+    let data = if matches!(typeflag, b'x' | b'g') {
+        o.add_obj_consumed("data", b, |b, o| {
+            let data = take(b, size)?;
+            decode_pax(o, &mut data.clone(), keys)?;
+            Ok(data)
+        })?
+    } else {
+        o.add("data", raw(b, size))?
+    };
+    o.add("data_block_padding", pad_to(b, BLOCK_BYTES, &header))?;
+    // old tar archives mark regular files with NUL
+    let typeflag = if typeflag == b'\0' { b'0' } else { typeflag };
+    if let Some(stats) = &opts.stats {
+        count(opts, format_args!("typeflag: {:?}", char::from(typeflag)));
+        let features = [
+            ("name_prefix", !ustar.prefix.is_empty()),
+            ("links", !linkname.is_empty()),
+            ("empty_data", data.is_empty()),
+        ];
+        for (feature, _) in features.into_iter().filter(|(_, present)| *present) {
+            count(opts, feature);
         }
-        Ok(())
+        stats.add(Tar::NAME, "data_bytes", data.len() as u64);
+    }
+    let name = if ustar.prefix.is_empty() {
+        name
+    } else {
+        Bytes::from([&ustar.prefix[..], b"/", &name].concat())
+    };
+    Ok(Entry {
+        name,
+        mode,
+        uid,
+        gid,
+        mtime,
+        typeflag,
+        linkname,
+        uname: ustar.uname,
+        gname: ustar.gname,
+        data,
+    })
+}
+
+/// Options for tar decoding.
+#[derive(Default)]
+pub struct Opts {
+    /// Only decode entries whose names match this filter.
+    pub filter: Option<Filter>,
+    /// Report the number of decoded bytes.
+    pub progress: Option<Progress>,
+    /// Measure the time spent in each phase.
+    pub metrics: Option<Arc<Metrics>>,
+    /// Count the features found in the input.
+    pub stats: Option<Arc<Stats>>,
+    /// Continue after entries that fail to decode.
+    ///
+    /// The error of such an entry is stored in its metadata,
+    /// and its value becomes a gap up to the next block that looks like a header.
+    pub recover: bool,
+    /// Limits on field sizes and the number of entries.
+    pub limits: Limits,
+}
+
+/// Increment the counter of `feature` if statistics are collected.
+fn count(opts: &Opts, feature: impl core::fmt::Display) {
+    Stats::count(opts.stats.as_deref(), Tar::NAME, feature)
+}
+
+/// The tar archive format.
+pub struct Tar;
+
+impl Format for Tar {
+    const NAME: &'static str = "tar";
+    type Opts = Opts;
+
+    fn sniff(b: &[u8]) -> bool {
+        let magic = b
+            .get(257..263)
+            .is_some_and(|m| m == b"ustar\0" || m == b"ustar ");
+        magic || b.get(..BLOCK_BYTES).is_some_and(valid_checksum)
+    }
+
+    fn decode(o: &mut Obj, b: Bytes, opts: &Opts) -> Result {
+        decode_tar(o, b, opts)
+    }
+}
+
+/// Decode a tar archive into `o`.
+pub fn decode_tar(o: &mut Obj, b: Bytes, opts: &Opts) -> Result {
+    let metrics = opts.metrics.as_deref();
+    let r = Metrics::time(metrics, Phase::Headers, || {
+        with_input(&b, || decode_entries(o, b.clone(), opts))
+    });
+    r.map(|_| ())
+}
+
+/// Decode the entries of a tar archive.
+pub fn entries(b: Bytes, opts: &Opts) -> Result<Vec<Entry>> {
+    with_input(&b, || decode_entries(&mut Discard, b.clone(), opts))
+}
+
+fn decode_entries<S: Sink>(o: &mut S, mut b: Bytes, opts: &Opts) -> Result<Vec<Entry>> {
+    let total = b.len() as u64;
+    let entries = o.add_arr_consumed("files", &mut b, |b, a| {
+        // estimate that files take a header and a data block, but do not trust large inputs
+        a.reserve((b.len() / (2 * BLOCK_BYTES)).min(1 << 16));
+        let mut entries = Vec::new();
+        // number of files decoded so far, including those not kept
+        let mut i = 0;
+        let mut keys = Interner::default();
+        a.add_until(b, &END_MARKER, |b, a| {
+            opts.limits.entries(b, i + 1)?;
+            let start = b.clone();
+            let entry = a.add_obj_consumed(b, |b, o| decode_file(o, b, opts, &mut keys));
+            match entry {
+                Ok(entry) => {
+                    count(opts, "entries");
+                    if opts.filter.as_ref().is_none_or(|f| f(&entry.name)) {
+                        entries.push(entry);
+                    } else {
+                        count(opts, "filtered_entries");
+                        a.pop();
+                    }
+                }
+                Err(_) if opts.recover => {
+                    count(opts, "recovered_errors");
+                    *b = start;
+                    a.last_to_gap(b.split_to(next_header(b)));
+                }
+                Err(e) => return Err(e.with_context(format!("file {i}"))),
+            }
+            i += 1;
+            if let Some(progress) = &opts.progress {
+                progress(total - b.len() as u64, total);
+            }
+            Ok(())
+        })?;
+        Ok(entries)
     })?;
-    // TODO: if !b.is_empty(), check presence of end marker
-    Ok(())
+    match b.strip_prefix(&END_MARKER) {
+        None => count(opts, "missing_end_marker"),
+        Some(rest) if rest.iter().any(|c| *c != 0) => count(opts, "data_after_end_marker"),
+        Some(_) => count(opts, "end_marker"),
+    }
+    if b.starts_with(&END_MARKER) {
+        o.add("end_marker", raw(&mut b, END_MARKER.len()))?;
+    }
+    if !b.is_empty() {
+        o.add_gap("unparsed", b);
+    }
+    Ok(entries)
+}
+
+/// Entry of a tar archive read from a stream, see [`decode_tar_read`].
+pub struct Streamed {
+    /// Offset of the entry in the stream.
+    pub offset: usize,
+    /// Header and padded data of the entry, to which the spans in `file` refer.
+    ///
+    /// The spans also know their offsets in the stream, see [`Meta::offset`].
+    pub bytes: Bytes,
+    /// Decoded entry, as it appears in the `files` of [`decode_tar`].
+    pub file: Obj,
+    pub entry: Entry,
+}
+
+/// Iterator over the entries of a tar archive read from a stream, see [`decode_tar_read`].
+pub struct Stream<'a, R> {
+    r: R,
+    opts: &'a Opts,
+    /// Number of bytes read so far.
+    offset: usize,
+    /// Number of entries read so far, including those not kept.
+    i: usize,
+    done: bool,
+    /// Keywords of PAX records, shared by all entries.
+    keys: Interner,
+}
+
+/// Decode a tar archive read from `r`, one entry at a time.
+///
+/// In contrast to [`decode_tar`], this does not need the whole archive in memory:
+/// only the entry being decoded is read, so memory is bounded by the largest entry.
+/// Reading stops at the first zero block, which starts the end marker.
+/// Errors carry their offset in the stream, and end the iteration.
+pub fn decode_tar_read<R: Read>(r: R, opts: &Opts) -> Stream<'_, R> {
+    Stream {
+        r,
+        opts,
+        offset: 0,
+        i: 0,
+        done: false,
+        keys: Interner::default(),
+    }
+}
+
+impl<R: Read> Stream<'_, R> {
+    /// Append up to `n` bytes to `buf`, fewer only if the stream ends before.
+    ///
+    /// The buffer grows only as bytes arrive, so that a huge length claimed by the input
+    /// does not allocate memory that the stream never fills.
+    fn read(&mut self, buf: &mut BytesMut, n: usize) -> Result<usize> {
+        const CHUNK_BYTES: usize = 64 * 1024;
+        let mut filled = 0;
+        while filled < n {
+            let start = buf.len();
+            buf.resize(start + (n - filled).min(CHUNK_BYTES), 0);
+            let r = self.r.read(&mut buf[start..]);
+            buf.truncate(start + r.as_ref().map_or(0, |k| *k));
+            match r {
+                Ok(0) => break,
+                Ok(k) => filled += k,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => {
+                    let offset = self.offset + start;
+                    return Err(Error::new(&Bytes::new(), e).with_offset(offset));
+                }
+            }
+        }
+        Ok(filled)
+    }
+
+    fn next_entry(&mut self) -> Result<Option<Streamed>> {
+        let mut buf = BytesMut::new();
+        if self.read(&mut buf, BLOCK_BYTES)? == 0 {
+            count(self.opts, "missing_end_marker");
+            return Ok(None);
+        }
+        if buf.iter().all(|c| *c == 0) {
+            count(self.opts, "end_marker");
+            return Ok(None);
+        }
+        let limits = &self.opts.limits;
+        let offset = self.offset;
+        limits
+            .entries(&Bytes::new(), self.i + 1)
+            .map_err(|e| e.with_offset(offset))?;
+        // if the size cannot be read, decoding the header yields an error
+        let size = buf
+            .get(124..136)
+            .map(|s| take_oct64(&mut Bytes::copy_from_slice(s)));
+        if let Some(Ok(size)) = size {
+            let header = Bytes::copy_from_slice(&buf);
+            let err = |e: Error| e.with_offset(offset + BLOCK_BYTES);
+            limits.field(&header, size.out).map_err(err)?;
+            // reading stops at the end of the stream, so lengths that overflow need no error
+            let padded = size.out.checked_next_multiple_of(BLOCK_BYTES as u64);
+            let padded = padded.and_then(|p| usize::try_from(p).ok());
+            self.read(&mut buf, padded.unwrap_or(usize::MAX))?;
+        }
+        let bytes = buf.freeze();
+        self.offset += bytes.len();
+        let mut file = Obj::default();
+        // spans and errors know their offsets in the stream from the cursor
+        let (opts, keys) = (self.opts, &mut self.keys);
+        let entry = Cursor::new(bytes.clone(), offset)
+            .run(|| decode_file(&mut file, &mut bytes.clone(), opts, keys))
+            .map_err(|e| e.with_context(format!("file {}", self.i)))?;
+        count(self.opts, "entries");
+        self.i += 1;
+        Ok(Some(Streamed {
+            offset,
+            bytes,
+            file,
+            entry,
+        }))
+    }
+}
+
+impl<R: Read> Iterator for Stream<'_, R> {
+    type Item = Result<Streamed>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.done {
+            match self.next_entry() {
+                Ok(Some(s)) if self.opts.filter.as_ref().is_none_or(|f| f(&s.entry.name)) => {
+                    return Some(Ok(s))
+                }
+                Ok(Some(_)) => count(self.opts, "filtered_entries"),
+                Ok(None) => self.done = true,
+                Err(e) => {
+                    self.done = true;
+                    return Some(Err(e));
+                }
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Archive of entries with the given type flags and data.
+    fn tar(entries: &[(u8, &[u8])]) -> Bytes {
+        let mut out = Vec::new();
+        for (i, (typeflag, data)) in entries.iter().enumerate() {
+            let mut h = [0u8; BLOCK_BYTES];
+            let name = format!("file{i}");
+            h[..name.len()].copy_from_slice(name.as_bytes());
+            h[124..136].copy_from_slice(format!("{:011o}\0", data.len()).as_bytes());
+            h[148..156].fill(b' ');
+            h[156] = *typeflag;
+            let sum: u32 = h.iter().map(|c| u32::from(*c)).sum();
+            h[148..156].copy_from_slice(format!("{sum:06o}\0 ").as_bytes());
+            out.extend(h);
+            out.extend(*data);
+            out.resize(out.len().next_multiple_of(BLOCK_BYTES), 0);
+        }
+        out.extend(END_MARKER);
+        out.into()
+    }
+
+    /// Overwrite the bytes of the header at `offset`, starting at `at`, and fix its checksum.
+    fn patch(b: &Bytes, offset: usize, at: usize, bytes: &[u8]) -> Bytes {
+        let mut b = b.to_vec();
+        let h = &mut b[offset..offset + BLOCK_BYTES];
+        h[at..at + bytes.len()].copy_from_slice(bytes);
+        h[148..156].fill(b' ');
+        let sum: u32 = h.iter().map(|c| u32::from(*c)).sum();
+        h[148..156].copy_from_slice(format!("{sum:06o}\0 ").as_bytes());
+        b.into()
+    }
+
+    #[test]
+    fn pax_keywords_are_interned() {
+        let records: &[u8] = b"30 SCHILY.xattr.user.tag=blue\n12 path=a/b\n";
+        let b = tar(&[(b'x', records), (b'0', b"a"), (b'x', records), (b'0', b"b")]);
+        let mut o = Obj::default();
+        decode_tar(&mut o, b.clone(), &Opts::default()).unwrap();
+        let v = Val::Obj(Box::new(o));
+        let data = |i: usize| match v.query(&format!("files[{i}].data")) {
+            Ok(Val::Obj(o)) => o.clone(),
+            v => panic!("expected PAX records, found {v:?}"),
+        };
+        let (first, second) = (data(0), data(2));
+        let keys: Vec<_> = first.iter().map(|(k, ..)| k).collect();
+        assert_eq!(keys, ["SCHILY.xattr.user.tag", "path"]);
+        let (_, m, v) = first.iter().next().unwrap();
+        assert_eq!(m.offset_in(&b), Some(512..542));
+        assert!(matches!(v, Val::Str(s) if **s == b"blue"[..]));
+        // both headers refer to the same text of each keyword
+        for ((k1, ..), (k2, ..)) in first.iter().zip(second.iter()) {
+            assert_eq!(k1.as_ptr(), k2.as_ptr());
+        }
+    }
+
+    #[test]
+    fn pax_record_length_must_end_at_newline() {
+        let b = tar(&[(b'x', b"11 path=a/b\n")]);
+        let Err(e) = entries(b, &Opts::default()) else {
+            panic!("expected an error");
+        };
+        assert!(e.to_string().contains("does not end at a newline"), "{e}");
+    }
+
+    #[test]
+    fn truncation_errors_name_the_file_and_header() {
+        // the second entry of test.tar, cut off in the group name of its ustar header
+        let b = Bytes::from_static(&include_bytes!("../test.tar")[..1024 + 300]);
+        let Err(e) = decode_tar(&mut Obj::default(), b, &Opts::default()) else {
+            panic!("expected an error");
+        };
+        let expected = "files[1].ustar.gname: in file 1, ustar header: \
+            expected 32 bytes, but only 3 remain (at offset 0x529)";
+        assert_eq!(e.to_string(), expected);
+    }
+
+    #[test]
+    fn prefixes_of_seeds_do_not_panic() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz/seeds/tar");
+        for f in std::fs::read_dir(dir).unwrap() {
+            let b = Bytes::from(std::fs::read(f.unwrap().path()).unwrap());
+            for n in 0..=b.len() {
+                for recover in [false, true] {
+                    let opts = Opts {
+                        recover,
+                        ..Opts::default()
+                    };
+                    let mut o = Obj::default();
+                    let _ = decode_tar(&mut o, b.slice(..n), &opts);
+                    let _ = Val::Obj(Box::new(o)).eval().to_string();
+                    let _ = entries(b.slice(..n), &opts);
+                    decode_tar_read(&b[..n], &opts).for_each(drop);
+                }
+            }
+        }
+    }
+
+    fn stats(b: &'static [u8]) -> Vec<(String, u64)> {
+        let stats = Arc::new(Stats::default());
+        let opts = Opts {
+            stats: Some(stats.clone()),
+            ..Opts::default()
+        };
+        let _ = decode_tar(&mut Obj::default(), Bytes::from_static(b), &opts);
+        stats.counts().remove("tar").unwrap().into_iter().collect()
+    }
+
+    #[test]
+    fn stats_of_fixtures() {
+        let counts = stats(include_bytes!("../test.tar"));
+        let expected = [
+            ("data_bytes", 11),
+            ("end_marker", 1),
+            ("entries", 2),
+            ("typeflag: '0'", 2),
+            ("variant: ustar", 2),
+        ];
+        assert_eq!(counts, expected.map(|(k, n)| (k.to_string(), n)));
+
+        let counts = stats(include_bytes!("../fuzz/seeds/tar/pax.tar"));
+        let expected = [
+            ("data_bytes", 199),
+            ("empty_data", 1),
+            ("end_marker", 1),
+            ("entries", 3),
+            ("links", 1),
+            ("typeflag: '0'", 1),
+            ("typeflag: '2'", 1),
+            ("typeflag: 'x'", 1),
+            ("variant: ustar", 3),
+        ];
+        assert_eq!(counts, expected.map(|(k, n)| (k.to_string(), n)));
+
+        // the first entry of test.tar, without the end marker
+        let counts = stats(&include_bytes!("../test.tar")[..1024]);
+        let expected = [
+            ("data_bytes", 5),
+            ("entries", 1),
+            ("missing_end_marker", 1),
+            ("typeflag: '0'", 1),
+            ("variant: ustar", 1),
+        ];
+        assert_eq!(counts, expected.map(|(k, n)| (k.to_string(), n)));
+    }
+
+    #[test]
+    fn trailing_bytes_are_gaps() {
+        let b = Bytes::from([&include_bytes!("../test.tar")[..], b"JUNK"].concat());
+        let mut o = Obj::default();
+        decode_tar(&mut o, b.clone(), &Opts::default()).unwrap();
+        let (m, v) = o.get("unparsed").unwrap();
+        assert!(matches!(v, Val::Raw { gap: true }));
+        assert_eq!(m.offset_in(&b), Some(b.len() - 4..b.len()));
+        let coverage = coverage(&Val::Obj(Box::new(o)), &b);
+        assert_eq!(coverage.uncovered, []);
+        assert_eq!(coverage.covered, b.len());
+    }
+
+    #[test]
+    fn end_marker_is_decoded() {
+        let b = Bytes::from_static(include_bytes!("../test.tar"));
+        let mut o = Obj::default();
+        decode_tar(&mut o, b.clone(), &Opts::default()).unwrap();
+        let (m, _) = o.get("end_marker").unwrap();
+        let start = b.len() - END_MARKER.len();
+        assert_eq!(m.offset_in(&b), Some(start..start + END_MARKER.len()));
+    }
+
+    #[test]
+    fn mode_is_rendered_in_octal() {
+        let mut o = Obj::default();
+        decode_tar(
+            &mut o,
+            Bytes::from_static(include_bytes!("../test.tar")),
+            &Opts::default(),
+        )
+        .unwrap();
+        let (m, mode) = o
+            .get_path(&Index::parse_path("files[0].mode").unwrap())
+            .unwrap();
+        assert_eq!(m.formatted(mode).as_deref(), Some("0o644"));
+        assert_eq!(Meta::from(Bytes::new()).formatted(mode), None);
+    }
+
+    #[test]
+    fn names_that_are_not_utf8_are_bytes() {
+        let b = patch(&tar(&[(b'0', b"")]), 0, 0, b"caf\xe9\0");
+        let name = |b: Bytes| {
+            let mut o = Obj::default();
+            decode_tar(&mut o, b, &Opts::default()).unwrap();
+            o.get_path(&Index::parse_path("files[0].name").unwrap())
+                .unwrap()
+                .1
+                .clone()
+        };
+        assert!(matches!(name(b), Val::Bytes(b) if *b == b"caf\xe9"[..]));
+        let b = Bytes::from_static(include_bytes!("../test.tar"));
+        assert!(matches!(name(b), Val::Str(s) if *s == b"test1"[..]));
+    }
+
+    #[test]
+    fn display_tree() {
+        let b = tar(&[(b'0', b"hi")]);
+        let mut o = Obj::default();
+        decode_tar(&mut o, b.clone(), &Opts::default()).unwrap();
+        let v = Val::Obj(Box::new(o));
+        assert!(v.to_string().contains("\n    mtime: <lazy>\n"));
+        let opts = DisplayOpts {
+            force: true,
+            root: Some(b),
+            ..DisplayOpts::default()
+        };
+        let expected = r#"files @0x0..0x400:
+  [0] @0x0..0x400:
+    name @0x0..0x64: "file0"
+    mode @0x64..0x6c: 0o0
+    uid @0x6c..0x74: 0 (0x0)
+    gid @0x74..0x7c: 0 (0x0)
+    size @0x7c..0x88: 2 (0x2)
+    mtime @0x88..0x94:
+      unix @0x88..0x94: 0 (0x0)
+      iso8601 @0x88..0x94: "1970-01-01T00:00:00Z"
+    chksum @0x94..0x9c: 1298 (0x512)
+    typeflag @0x9c..0x9d:
+      value @0x9c..0x9d: 48 (0x30)
+      name @0x9c..0x9d: "regular"
+    linkname @0x9d..0x101: ""
+    header_block_padding @0x101..0x200: <255 bytes>
+    data @0x200..0x202: <2 bytes>
+    data_block_padding @0x202..0x400: <510 bytes>
+end_marker @0x400..0x800: <1024 bytes>
+"#;
+        assert_eq!(v.display(&opts).to_string(), expected);
+    }
+
+    #[test]
+    fn queries() {
+        let mut o = Obj::default();
+        decode_tar(&mut o, tar(&[(b'0', b"a"), (b'0', b"b")]), &Opts::default()).unwrap();
+        let v = Val::Obj(Box::new(o));
+        assert!(matches!(v.query("files[1].name"), Ok(Val::Str(s)) if **s == b"file1"[..]));
+        assert!(
+            matches!(v.query(".files[0].typeflag.name"), Ok(Val::Str(s)) if **s == b"regular"[..])
+        );
+        assert_eq!(
+            v.query("files[2].name").unwrap_err(),
+            "no value at `files[2]`"
+        );
+    }
+
+    #[test]
+    fn recover_from_a_broken_entry() {
+        let mut b = tar(&[(b'0', b"a"), (b'0', b"b")]).to_vec();
+        b[124] = b'x';
+        let b = Bytes::from(b);
+        assert!(decode_tar(&mut Obj::default(), b.clone(), &Opts::default()).is_err());
+        let opts = Opts {
+            recover: true,
+            ..Opts::default()
+        };
+        let mut o = Obj::default();
+        decode_tar(&mut o, b.clone(), &opts).unwrap();
+        let v = Val::Obj(Box::new(o));
+        let Ok(Val::Arr(files)) = v.query("files") else {
+            panic!("expected an array of files");
+        };
+        let files: Vec<_> = files.iter().collect();
+        let [(m0, v0), (m1, v1)] = files[..] else {
+            panic!("expected two files, found {}", files.len());
+        };
+        assert!(matches!(v0, Val::Raw { gap: true }));
+        assert_eq!(m0.offset_in(&b), Some(0..1024));
+        assert_eq!(m0.error().unwrap().path(), [Index::Str("size".into())]);
+        assert!(m1.error().is_none());
+        assert!(matches!(v1.query("name"), Ok(Val::Str(s)) if **s == b"file1"[..]));
+    }
+
+    #[test]
+    fn huge_sizes_exceed_the_limit() {
+        // a size of 8 GiB
+        let b = patch(&tar(&[(b'0', b"a")]), 0, 124, b"77777777777\0");
+        let opts = Opts {
+            limits: Limits {
+                max_field_bytes: Some(1 << 20),
+                ..Limits::default()
+            },
+            ..Opts::default()
+        };
+        let msg = "field of 8589934591 bytes exceeds the limit max_field_bytes = 1048576";
+        let e = decode_tar(&mut Obj::default(), b.clone(), &opts).unwrap_err();
+        assert_eq!(e.msg(), msg);
+        let Some(Err(e)) = decode_tar_read(&b[..], &opts).next() else {
+            panic!("expected an error");
+        };
+        assert_eq!((e.msg(), e.offset()), (msg, Some(BLOCK_BYTES)));
+        // without limits, the size fails only once the data turns out to be missing
+        let opts = Opts {
+            limits: Limits::unlimited(),
+            ..Opts::default()
+        };
+        let e = decode_tar(&mut Obj::default(), b.clone(), &opts).unwrap_err();
+        assert!(e.msg().starts_with("expected 8589934591 bytes"), "{e}");
+    }
+
+    #[test]
+    fn entries_exceed_the_limit() {
+        let b = tar(&[(b'0', b"a"), (b'0', b"b")]);
+        let opts = Opts {
+            limits: Limits {
+                max_entries: Some(1),
+                ..Limits::default()
+            },
+            ..Opts::default()
+        };
+        let e = decode_tar(&mut Obj::default(), b.clone(), &opts).unwrap_err();
+        assert_eq!(e.msg(), "2 entries exceed the limit max_entries = 1");
+        assert_eq!(e.offset(), Some(1024));
+        let streamed: Vec<_> = decode_tar_read(&b[..], &opts).collect();
+        assert!(matches!(&streamed[..], [Ok(_), Err(e)] if e.offset() == Some(1024)));
+    }
+
+    /// Reader that returns at most 100 bytes per call.
+    struct Trickle<R>(R);
+
+    impl<R: Read> Read for Trickle<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = buf.len().min(100);
+            self.0.read(&mut buf[..n])
+        }
+    }
+
+    #[test]
+    fn stream_in_small_reads() {
+        let b = tar(&[(b'0', &[7; 1000]), (b'0', b""), (b'0', b"b")]);
+        let r = Trickle(io::Cursor::new(b.clone()));
+        let streamed: Vec<_> = decode_tar_read(r, &Opts::default())
+            .map(Result::unwrap)
+            .collect();
+        let offsets: Vec<_> = streamed.iter().map(|s| (s.offset, s.bytes.len())).collect();
+        assert_eq!(offsets, [(0, 1536), (1536, 512), (2048, 1024)]);
+        let mut o = Obj::default();
+        decode_tar(&mut o, b.clone(), &Opts::default()).unwrap();
+        for (i, s) in streamed.iter().enumerate() {
+            assert_eq!(s.bytes, b[s.offset..s.offset + s.bytes.len()]);
+            let (m, _) = s.file.get("data").unwrap();
+            let data = m.offset_in(&s.bytes).unwrap();
+            assert_eq!(data.start, BLOCK_BYTES);
+            assert_eq!(s.bytes.slice(data), s.entry.data);
+            // the offsets in the stream are those in the whole archive
+            let path = Index::parse_path(&format!("files[{i}].data")).unwrap();
+            let (whole, _) = o.get_path(&path).unwrap();
+            assert_eq!(m.offset(), whole.offset_in(&b));
+            assert_eq!(m.offset().map(|r| r.start), Some(s.offset + BLOCK_BYTES));
+        }
+    }
+
+    #[test]
+    fn streamed_sizes_do_not_allocate_up_front() {
+        // a size of 8 GiB, of which only the end marker is there
+        let b = patch(&tar(&[]), 0, 124, b"77777777777\0");
+        let opts = Opts {
+            limits: Limits::unlimited(),
+            ..Opts::default()
+        };
+        let r = Trickle(io::Cursor::new(b));
+        let streamed: Vec<_> = decode_tar_read(r, &opts).collect();
+        let [Err(e)] = &streamed[..] else {
+            panic!("expected one error");
+        };
+        assert!(e.msg().starts_with("expected 8589934591 bytes"), "{e}");
+        // the data starts after the header
+        assert_eq!(e.offset(), Some(BLOCK_BYTES));
+    }
 }
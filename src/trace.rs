@@ -0,0 +1,204 @@
+//! Observation of decoding as it happens.
+//!
+//! An [`Observer`] installed with [`observe`] is told about every field
+//! that is added to an [`Obj`] or [`Arr`] on the current thread,
+//! in the order in which the fields are decoded.
+//! This helps to find out how far a failing decoder got.
+//!
+//! Objects and arrays are reported after their contents, once their span is known.
+//! Not reported are values produced by lazy values, values decoded on other threads
+//! (such as ZIP central directories decoded in parallel), and values decoded into
+//! [`Discard`](crate::decode::Discard).
+//!
+//! [`Obj`]: crate::decode::Obj
+//! [`Arr`]: crate::decode::Arr
+
+use crate::decode::{Error, Index, Meta, Path};
+use alloc::rc::Rc;
+use bytes::Bytes;
+use core::cell::{Cell, RefCell};
+
+/// Receiver of the fields that are decoded.
+///
+/// Paths start from the object that decoding started with.
+pub trait Observer {
+    /// A value was added at `path`.
+    fn on_field(&mut self, path: &[Index], meta: &Meta);
+
+    /// Decoding the value at `path` failed.
+    ///
+    /// This is called only where the error occurred, not for every structure enclosing it.
+    fn on_error(&mut self, path: &[Index], err: &Error);
+}
+
+/// Observer that prints every field as `path @ start..end (len)` to standard error.
+pub struct StderrTracer {
+    /// Input that offsets are relative to.
+    pub root: Bytes,
+}
+
+impl Observer for StderrTracer {
+    fn on_field(&mut self, path: &[Index], meta: &Meta) {
+        let len = meta.bytes().len();
+        match meta.offset_in(&self.root) {
+            Some(r) => eprintln!("{} @ {:#x}..{:#x} ({len})", Path(path), r.start, r.end),
+            None => eprintln!("{} @ derived ({len})", Path(path)),
+        }
+    }
+
+    fn on_error(&mut self, path: &[Index], err: &Error) {
+        eprintln!("{}: error: {}", Path(path), err)
+    }
+}
+
+struct State {
+    observer: Rc<RefCell<dyn Observer>>,
+    path: Vec<Index>,
+}
+
+std::thread_local! {
+    static STATE: RefCell<Option<State>> = const { RefCell::new(None) };
+    /// True if `STATE` holds an observer, so that hooks need not borrow it otherwise.
+    static OBSERVED: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Install `state` on the current thread, returning the state it replaces.
+fn install(state: Option<State>) -> Option<State> {
+    OBSERVED.set(state.is_some());
+    STATE.with(|s| s.replace(state))
+}
+
+/// Reinstalls the outer state when dropped, also if the observed function panics.
+struct Restore(Option<State>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        install(self.0.take());
+    }
+}
+
+/// Run `f`, reporting the fields that it decodes to `observer`.
+///
+/// Calls can be nested, in which case the innermost observer is used.
+pub fn observe<T>(observer: Rc<RefCell<dyn Observer>>, f: impl FnOnce() -> T) -> T {
+    let path = Vec::new();
+    let _restore = Restore(install(Some(State { observer, path })));
+    f()
+}
+
+/// Run `f` with `i` appended to the current path, if an observer is installed.
+pub(crate) fn enter<T>(i: impl FnOnce() -> Index, f: impl FnOnce() -> T) -> T {
+    if !OBSERVED.get() {
+        return f();
+    }
+    let entered = STATE.with(|s| match &mut *s.borrow_mut() {
+        Some(s) => {
+            s.path.push(i());
+            true
+        }
+        None => false,
+    });
+    let y = f();
+    if entered {
+        STATE.with(|s| s.borrow_mut().as_mut().map(|s| s.path.pop()));
+    }
+    y
+}
+
+/// Report the value at `i` in the current path, if an observer is installed.
+pub(crate) fn field(i: impl FnOnce() -> Index, m: &Meta) {
+    with_path(i, |o, path| o.on_field(path, m))
+}
+
+/// Report an error at `i` in the current path, unless it was already reported deeper down.
+pub(crate) fn error(i: impl FnOnce() -> Index, e: &Error) {
+    if e.path().is_empty() {
+        with_path(i, |o, path| o.on_error(path, e))
+    }
+}
+
+fn with_path(i: impl FnOnce() -> Index, f: impl FnOnce(&mut dyn Observer, &[Index])) {
+    if !OBSERVED.get() {
+        return;
+    }
+    STATE.with(|s| {
+        if let Some(s) = &mut *s.borrow_mut() {
+            s.path.push(i());
+            f(&mut *s.observer.borrow_mut(), &s.path);
+            s.path.pop();
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode::{le, Obj};
+    use crate::tar::{decode_tar, Opts};
+
+    /// Observer that records the paths of fields and errors as text.
+    #[derive(Default)]
+    struct Recorder(Vec<String>);
+
+    impl Observer for Recorder {
+        fn on_field(&mut self, path: &[Index], _: &Meta) {
+            self.0.push(Path(path).to_string())
+        }
+
+        fn on_error(&mut self, path: &[Index], _: &Error) {
+            self.0.push(format!("{}: error", Path(path)))
+        }
+    }
+
+    fn record<T>(f: impl FnOnce() -> T) -> (T, Vec<String>) {
+        let recorder = Rc::new(RefCell::new(Recorder::default()));
+        let y = observe(recorder.clone(), f);
+        (y, recorder.take().0)
+    }
+
+    #[test]
+    fn fields_of_a_tar_in_order() {
+        let b = Bytes::from_static(include_bytes!("../test.tar"));
+        let (r, paths) = record(|| decode_tar(&mut Obj::default(), b, &Opts::default()));
+        assert!(r.is_ok());
+        let header = [
+            "name", "mode", "uid", "gid", "size", "mtime", "chksum", "typeflag", "linkname",
+        ];
+        let ustar = [
+            "magic", "version", "uname", "gname", "devmajor", "devminor", "prefix",
+        ];
+        let mut expected = Vec::new();
+        for i in 0..2 {
+            let file = format!("files[{i}]");
+            expected.extend(header.iter().map(|k| format!("{file}.{k}")));
+            // structures are reported after their contents
+            expected.extend(ustar.iter().map(|k| format!("{file}.ustar.{k}")));
+            expected.push(format!("{file}.ustar"));
+            let rest = ["header_block_padding", "data", "data_block_padding"];
+            expected.extend(rest.iter().map(|k| format!("{file}.{k}")));
+            expected.push(file);
+        }
+        expected.extend(["files".into(), "end_marker".into()]);
+        assert_eq!(paths, expected);
+    }
+
+    #[test]
+    fn nested_observers_and_panics_restore_the_outer_observer() {
+        let add = |k| {
+            let mut b = Bytes::from_static(&[1]);
+            let _ = Obj::default().add(k, le::u8(&mut b));
+        };
+        let (_, paths) = record(|| {
+            add("outer");
+            let (_, inner) = record(|| add("inner"));
+            assert_eq!(inner, ["inner"]);
+            let panicked = std::panic::catch_unwind(|| record(|| panic!("in the observer")));
+            assert!(panicked.is_err());
+            add("after");
+            let mut b = Bytes::new();
+            let _ = Obj::default().add("short", le::u8(&mut b));
+        });
+        assert_eq!(paths, ["outer", "after", "short: error"]);
+        assert!(!OBSERVED.get() && STATE.with(|s| s.borrow().is_none()));
+    }
+}
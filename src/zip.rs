@@ -1,7 +1,9 @@
 use crate::decode::*;
+use crate::{Cache, Filter, Format, Limits, Metrics, Phase, Progress, Stats};
+use alloc::sync::Arc;
 use bitflags::bitflags;
 use bytes::Bytes;
-use core::fmt::Display;
+use core::fmt::{self, Display, Formatter};
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
 
@@ -12,50 +14,126 @@ const EOCD_64_SIG: &[u8; 4] = b"PK\x06\x06";
 const EOCD_LOCATOR_SIG: &[u8; 4] = b"PK\x06\x07";
 const DATA_INDICATOR_SIG: &[u8; 4] = b"PK\x07\x08";
 
+const VERSION_MADE_BY: &str = "specification version and host system of the creator";
+const VERSION_NEEDED: &str = "minimum specification version needed to extract";
+
+/// Signatures of records, keyed by the name of the records in the decoded value.
+pub const SIGNATURES: &[(&str, &[u8; 4])] = &[
+    ("central_directories", CENTRAL_DIR_SIG),
+    ("local_files", LOCAL_FILE_SIG),
+    ("data_indicator", DATA_INDICATOR_SIG),
+    ("end_of_central_directory_record", EOCD_SIG),
+    ("end_of_central_directory_locator", EOCD_LOCATOR_SIG),
+    ("end_of_central_directory_record_zip64", EOCD_64_SIG),
+];
+
+/// Options for ZIP decoding.
 #[derive(Default)]
 pub struct Opts {
-    force: bool,
+    /// Accept structures even if their signature does not match.
+    pub force: bool,
+    /// Limits on field sizes, uncompressed sizes, and the number of entries.
+    pub limits: Limits,
+    /// Only decode entries whose names match this filter.
+    pub filter: Option<Filter>,
+    /// Report the number of decoded entries.
+    pub progress: Option<Progress>,
+    /// Measure the time spent in each phase.
+    pub metrics: Option<Arc<Metrics>>,
+    /// Reuse data that was uncompressed before from the same span of the input.
+    pub cache: Option<Arc<Cache>>,
+    /// Decode central directory records in parallel.
+    ///
+    /// This has an effect only if the `rayon` feature is enabled.
+    pub parallel: bool,
+    /// Count the features found in the input.
+    pub stats: Option<Arc<Stats>>,
+    /// Continue after central directory records that fail to decode.
+    ///
+    /// The error of such a record is stored in its metadata,
+    /// and its value becomes a gap up to the next record signature.
+    pub recover: bool,
+}
+
+/// Increment the counter of `feature` if statistics are collected.
+fn count(opts: &Opts, feature: impl Display) {
+    Stats::count(opts.stats.as_deref(), Zip::NAME, feature)
+}
+
+/// The ZIP archive format.
+pub struct Zip;
+
+impl Format for Zip {
+    const NAME: &'static str = "zip";
+    type Opts = Opts;
+
+    fn sniff(b: &[u8]) -> bool {
+        b.starts_with(LOCAL_FILE_SIG) || find(b, EOCD_SIG, EOCD_MAX_LEN).is_some()
+    }
+
+    fn decode(o: &mut Obj, b: Bytes, opts: &Opts) -> Result {
+        decode_zip(o, b, opts)
+    }
 }
 
 #[derive(Debug)]
 struct EndOfCentralDirRecord {
     disk_nr: u32,
-    size_cd: u64,
-    offset_cd: u64,
+    nr_of_central_dir_records: u64,
+    size_of_central_dir: u64,
+    offset_of_start_of_central_dir: u64,
 }
 
 // Maximal size for ZIP-32: 4*16+2*32 bits = 128 bits
-fn decode_eocd_common(o: &mut Obj, b: &mut Bytes, zip64: bool) -> Result<EndOfCentralDirRecord> {
+fn decode_eocd_common<S: Sink>(
+    o: &mut S,
+    b: &mut Bytes,
+    zip64: bool,
+) -> Result<EndOfCentralDirRecord> {
     let u16_as_u32 = |b: &mut Bytes| le::u16(b).map(|d| d.map_out(Into::into));
     let u16_as_u64 = |b: &mut Bytes| le::u16(b).map(|d| d.map_out(Into::into));
     let u32_as_u64 = |b: &mut Bytes| le::u32(b).map(|d| d.map_out(Into::into));
     let small = if zip64 { le::u32 } else { u16_as_u32 };
     let count = if zip64 { le::u64 } else { u16_as_u64 };
     let large = if zip64 { le::u64 } else { u32_as_u64 };
+    let same_disk = |b: &mut Bytes, disk_nr| expect(small(b), disk_nr);
 
-    let disk_nr = o.add("disk_nr", small(b))?;
-    o.add("start_disk_nr", small(b))?;
-    o.add("nr_of_central_dir_records_on_disk", count(b))?;
-    o.add("nr_of_central_dir_records", count(b))?;
-    let size_cd = o.add("size_of_central_dir", large(b))?;
-    let offset_cd = o.add("offset_of_start_of_central_dir", large(b))?;
-    Ok(EndOfCentralDirRecord {
-        disk_nr,
-        size_cd,
-        offset_cd,
-    })
+    Ok(decode_struct!(o, b, EndOfCentralDirRecord {
+        /// number of this disk
+        disk_nr: small,
+        /// number of the disk where the central directory starts
+        skip start_disk_nr: same_disk(disk_nr),
+        /// number of central directory records on this disk
+        skip nr_of_central_dir_records_on_disk: count,
+        /// total number of central directory records
+        nr_of_central_dir_records: count,
+        /// size of the central directory, in bytes
+        size_of_central_dir: large,
+        /// offset of start of central directory, in bytes from start of archive
+        offset_of_start_of_central_dir: large,
+    }))
 }
 
-// Maximal size: 32+128+16+(2^16 * 8) bits = 524464 bits = 65558 bytes
-fn decode_eocd(o: &mut Obj, b: &mut Bytes, opts: &Opts) -> Result<EndOfCentralDirRecord> {
+/// Maximal size of an end of central directory record, including its comment.
+///
+/// 32+128+16+(2^16 * 8) bits = 524464 bits = 65558 bytes
+const EOCD_MAX_LEN: usize = 65558;
+
+fn decode_eocd<S: Sink>(o: &mut S, b: &mut Bytes, opts: &Opts) -> Result<EndOfCentralDirRecord> {
     o.add("signature", precise(b, EOCD_SIG, opts.force))?;
     let eocdr = decode_eocd_common(o, b, false)?;
-    let comment_length = o.add("comment_length", le::u16(b))?;
-    o.add("comment", raw(b, comment_length.into()))?;
+    let len = |b: &mut Bytes| le::u16(b).map(|d| d.map_out(u64::from));
+    let comment = o.add_described("comment", "archive comment", len_prefixed(b, len))?;
+    if !comment.is_empty() {
+        count(opts, "archive_comment");
+    }
+    if !b.is_empty() {
+        o.add_gap("unparsed", core::mem::take(b));
+    }
     Ok(eocdr)
 }
 
-fn decode_extensible_data(o: &mut Obj, b: &mut Bytes) -> Result {
+fn decode_extensible_data<S: Sink>(o: &mut S, b: &mut Bytes) -> Result {
     o.add("tag", le::u16(b))?;
     let data_size = o.add("size", le::u16(b))?;
     o.add("data", raw(b, data_size.into()))?;
@@ -67,11 +145,19 @@ fn into_usize(i: impl TryInto<usize> + Copy + Display, b: &Bytes) -> Result<usiz
     i.try_into().map_err(|_| Error::new(b, msg()))
 }
 
-fn decode_eocd64(o: &mut Obj, b: &mut Bytes, opts: &Opts) -> Result<EndOfCentralDirRecord> {
+fn decode_eocd64<S: Sink>(o: &mut S, b: &mut Bytes, opts: &Opts) -> Result<EndOfCentralDirRecord> {
     o.add("signature", precise(b, EOCD_64_SIG, opts.force))?;
-    let size_eocd = o.add("size_of_end_of_central_directory", le::u64(b))?;
-    o.add("version_made_by", le::u16(b))?;
-    o.add("version_needed", le::u16(b))?;
+    let size_eocd = o.add_described(
+        "size_of_end_of_central_directory",
+        "size of the remaining record, in bytes",
+        le::u64(b),
+    )?;
+    o.add_described("version_made_by", VERSION_MADE_BY, le::u16(b))?;
+    o.add_described(
+        "version_needed",
+        VERSION_NEEDED,
+        sym::<S, Version, _>(le::u16(b)),
+    )?;
     let eocdr = decode_eocd_common(o, b, true)?;
 
     // number of bytes read by this function so far
@@ -79,24 +165,29 @@ fn decode_eocd64(o: &mut Obj, b: &mut Bytes, opts: &Opts) -> Result<EndOfCentral
     let msg = || format!("expected at least {READ}, found {size_eocd}");
     let err = || Error::new(b, msg());
     let rest: u64 = size_eocd.checked_sub(READ).ok_or_else(err)?;
-    let mut b = take(b, into_usize(rest, b)?)?;
-    o.add_mut("extensible_data", Meta::from(&b), |_, ed| {
-        let ed = ed.make_arr();
-        while !b.is_empty() {
-            ed.add_consumed(&mut b, |b, v| decode_extensible_data(v.make_obj(), b))?;
-        }
-        Ok(())
+    opts.limits.field(b, rest)?;
+    let b = take(b, into_usize(rest, b)?)?;
+    o.add_arr("extensible_data", Meta::from(&b), |_, ed| {
+        ed.add_repeat_all(b.clone(), |b, o| decode_extensible_data(o, b))
     })?;
 
     Ok(eocdr)
 }
 
 // Total size: 32+32+64+32 bits = 160 bits = 20 bytes
-fn decode_eocdl(o: &mut Obj, b: &mut Bytes, opts: &Opts) -> Result<u64> {
+fn decode_eocdl<S: Sink>(o: &mut S, b: &mut Bytes, opts: &Opts) -> Result<u64> {
     o.add("signature", precise(b, EOCD_LOCATOR_SIG, opts.force))?;
-    o.add("disk_nr", le::u32(b))?;
-    let offset_cdr = o.add("offset_of_end_of_central_dir_record", le::u64(b))?;
-    o.add("total_disk_nr", le::u32(b))?;
+    o.add_described(
+        "disk_nr",
+        "number of the disk with the ZIP64 end of central directory record",
+        le::u32(b),
+    )?;
+    let offset_cdr = o.add_described(
+        "offset_of_end_of_central_dir_record",
+        "offset of the ZIP64 end of central directory record, in bytes from start of archive",
+        le::u64(b),
+    )?;
+    o.add_described("total_disk_nr", "total number of disks", le::u32(b))?;
     Ok(offset_cdr)
 }
 
@@ -104,9 +195,12 @@ fn decode_eocdl(o: &mut Obj, b: &mut Bytes, opts: &Opts) -> Result<u64> {
 struct Common {
     flags: Flags,
     compression_method: u16,
+    last_modification: dos::DosDateTime,
+    crc_32: StoredCrc,
     compressed_size: u32,
-    filename_len: u16,
-    extra_field_len: u16,
+    uncompressed_size: u32,
+    file_name_length: u16,
+    extra_field_length: u16,
 }
 
 bitflags! {
@@ -132,8 +226,22 @@ bitflags! {
     }
 }
 
+/// Bits of [`Flags`] that are reserved and must not be set.
+#[derive(PartialEq)]
+struct ReservedFlags(u16);
+
+impl ReservedFlags {
+    const MASK: u16 = 1 << 12 | 1 << 14 | 1 << 15;
+}
+
+impl Display for ReservedFlags {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "reserved flags {:#06x}", self.0)
+    }
+}
+
 #[allow(non_camel_case_types)]
-#[derive(Debug, FromPrimitive)]
+#[derive(Clone, Copy, Debug, FromPrimitive)]
 enum CompressionMethod {
     none = 0,
     shrunk = 1,
@@ -152,63 +260,24 @@ enum CompressionMethod {
     pp_md = 98,
 }
 
-macro_rules! flags_obj {
-    ($meta:ident, $flags:ident, $ty: ident) => {{
-        let has = |name| $flags.contains($ty::from_name(name).unwrap());
-        let f = |(name, _)| (name, $meta.clone(), Val::Bool(has(name)));
-        Val::Obj(Obj($ty::all().iter_names().map(f).collect()))
-    }};
-}
-
-macro_rules! lazy_flags {
-    ($d: expr, $ty: ident) => {{
-        let d = $d;
-        let flags = $ty::from_bits_retain(d.out);
-        let (m_, flags_) = (d.meta.clone(), flags.clone());
-        let lazy = move || flags_obj!(m_, flags_, $ty);
-        Decoded::new(d.meta, Val::lazy(lazy), flags)
-    }};
-}
-
-// https://stackoverflow.com/a/8012148
-fn mask(u: u16, offset: u8, width: u8) -> u8 {
-    let mask = ((1 << width as u16) - 1) << offset;
-    ((u & mask) >> offset) as u8
-}
-
-// https://learn.microsoft.com/en-gb/windows/win32/api/winbase/nf-winbase-dosdatetimetofiletime
-fn sec_min_hr(time: u16) -> (u8, u8, u8) {
-    (mask(time, 0, 5), mask(time, 5, 6), mask(time, 11, 5))
-}
-
-fn day_month_year(date: u16) -> (u8, u8, u8) {
-    (mask(date, 0, 5), mask(date, 5, 4), mask(date, 9, 7))
-}
-
-fn decode_td<F>(b: &mut Bytes, f: F) -> Result<Decoded<u16>>
-where
-    F: FnOnce(u16) -> [(&'static str, Val); 3],
-{
-    let d = le::u16(b)?;
-    let span = d.meta.bytes.clone();
-    let entries = f(d.out);
-    let entry = move |(k, v)| (k, Meta::from(span.clone()), v);
-    let val = move || Val::Obj(Obj(entries.into_iter().map(entry).collect()));
-    Ok(d.with_val(Val::lazy(val)))
-}
-
-fn decode_time_date(o: &mut Obj, b: &mut Bytes) -> Result<(u16, u16)> {
-    let time = decode_td(b, |time| {
-        let (sec, min, hr) = sec_min_hr(time);
-        let (sec, min, hr) = (Val::U8(sec * 2), Val::U8(min), Val::U8(hr));
-        [("second", sec), ("minute", min), ("hour", hr)]
-    });
-    let date = decode_td(b, |date| {
-        let (day, mon, yr) = day_month_year(date);
-        let (day, mon, yr) = (Val::U8(day), Val::U8(mon), Val::U16(yr as u16 + 1980));
-        [("day", day), ("month", mon), ("year", yr)]
-    });
-    Ok((o.add("fat_time", time)?, o.add("fat_date", date)?))
+/// Version of the specification that introduced the features needed to extract a file.
+#[allow(non_camel_case_types)]
+#[derive(Debug, FromPrimitive)]
+enum Version {
+    default = 10,
+    volume_label = 11,
+    deflated_or_directory_or_encrypted = 20,
+    enhanced_deflated = 21,
+    pk_ware_dcl_imploded = 25,
+    patch_data = 27,
+    zip64 = 45,
+    bzip2 = 46,
+    des_or_strong_encryption = 50,
+    aes = 51,
+    rc2_corrected = 52,
+    rc4 = 61,
+    central_directory_encryption = 62,
+    lzma_or_pp_md_or_blowfish_or_twofish = 63,
 }
 
 bitflags! {
@@ -224,17 +293,17 @@ bitflags! {
     }
 }
 
-fn decode_extended_timestamp(o: &mut Obj, b: &mut Bytes) -> Result<()> {
-    let flags = o.add("flags", Ok(lazy_flags!(le::u8(b)?, Timestamp)))?;
+fn decode_extended_timestamp<S: Sink>(o: &mut S, b: &mut Bytes) -> Result<()> {
+    let flags = o.add("flags", flags::<S, Timestamp>(le::u8(b)))?;
     let times = [
         ("modification_time", Timestamp::modification_time_present),
         ("access_time", Timestamp::access_time_present),
         ("creation_time", Timestamp::creation_time_present),
     ];
     for (key, flag) in times {
-        if flags.contains(flag) && !b.is_empty() {
-            o.add(key, le::u32(b))?;
-        }
+        let present = flags.contains(flag) && !b.is_empty();
+        // Info-ZIP stores signed times, which reach back before 1970
+        o.add_opt(key, present, || unix_time::<S, _>(le::i32(b)))?;
     }
     Ok(())
 }
@@ -247,181 +316,561 @@ struct Zip64 {
     disk_nr_start: Option<u32>,
 }
 
-fn decode_zip64(o: &mut Obj, b: &mut Bytes) -> Result<Zip64> {
-    let mut zip64 = Zip64::default();
-    if !b.is_empty() {
-        zip64.uncompressed_size = Some(o.add("uncompressed_size", le::u64(b))?);
-    }
-    if !b.is_empty() {
-        zip64.compressed_size = Some(o.add("compressed_size", le::u64(b))?);
-    }
-    if !b.is_empty() {
-        zip64.local_file_offset = Some(o.add("local_file_offset", le::u64(b))?);
+/// Header fields that hold a placeholder, so that the ZIP64 extra field holds their values.
+#[derive(Clone, Copy, Default)]
+struct Placeholders {
+    uncompressed_size: bool,
+    compressed_size: bool,
+    local_file_offset: bool,
+    disk_nr_start: bool,
+}
+
+impl Placeholders {
+    /// Placeholders among the sizes, which local file headers and records have in common.
+    fn of(common: &Common) -> Self {
+        Self {
+            uncompressed_size: common.uncompressed_size == u32::MAX,
+            compressed_size: common.compressed_size == u32::MAX,
+            ..Self::default()
+        }
     }
-    if !b.is_empty() {
-        zip64.disk_nr_start = Some(o.add("disk_nr_start", le::u32(b))?);
+}
+
+fn decode_zip64<S: Sink>(o: &mut S, b: &mut Bytes, p: Placeholders) -> Result<Zip64> {
+    // fields are present only for placeholders, in this order, as long as bytes remain
+    let present = |placeholder: bool, b: &Bytes| placeholder && !b.is_empty();
+    Ok(Zip64 {
+        uncompressed_size: o.add_opt(
+            "uncompressed_size",
+            present(p.uncompressed_size, b),
+            || le::u64(b),
+        )?,
+        compressed_size: o.add_opt("compressed_size", present(p.compressed_size, b), || {
+            le::u64(b)
+        })?,
+        local_file_offset: o.add_opt(
+            "local_file_offset",
+            present(p.local_file_offset, b),
+            || le::u64(b),
+        )?,
+        disk_nr_start: o.add_opt("disk_nr_start", present(p.disk_nr_start, b), || le::u32(b))?,
+    })
+}
+
+/// Render a CRC-32 as zero-padded hexadecimal.
+fn format_crc(v: &Val, f: &mut Formatter) -> fmt::Result {
+    match v {
+        Val::U32(u) => write!(f, "{u:#010x}"),
+        v => write!(f, "{v:?}"),
     }
-    Ok(zip64)
 }
 
-fn decode_common(o: &mut Obj, b: &mut Bytes) -> Result<Common> {
-    let flags = o.add("flags", Ok(lazy_flags!(le::u16(b)?, Flags)))?;
-    let method = |d: Decoded<_>| {
-        let method = CompressionMethod::from_u16(d.out).map(|m| format!("{m:?}"));
-        d.map_meta(|m| m.describe(method))
+/// Decode a CRC-32 that is rendered as zero-padded hexadecimal.
+fn crc_32(b: &mut Bytes) -> Result<Decoded<u32>> {
+    le::u32(b).map(|d| d.map_meta(|m| m.with_format(format_crc)))
+}
+
+/// CRC-32 stored in a header, together with the bytes of its field.
+#[derive(Clone, Debug)]
+struct StoredCrc {
+    value: u32,
+    bytes: Bytes,
+}
+
+fn decode_common<S: Sink>(o: &mut S, b: &mut Bytes) -> Result<Common> {
+    let flags = |b: &mut Bytes| {
+        let d = le::u16(b)?;
+        let bits = d.out;
+        let d = expect(
+            Ok(d.map_out(|u| ReservedFlags(u & ReservedFlags::MASK))),
+            ReservedFlags(0),
+        );
+        flags::<S, Flags>(d.map(|d| d.map_out(|_| bits)))
     };
-    let compression_method = o.add("compression_method", le::u16(b).map(method))?;
-    o.add_consumed("last_modification", b, |b, v| {
-        decode_time_date(v.make_obj(), b)
-    })?;
-    o.add("crc_32", le::u32(b))?;
-    let compressed_size = o.add("compressed_size", le::u32(b))?;
-    o.add("uncompressed_size", le::u32(b))?;
-    Ok(Common {
-        flags,
-        compression_method,
-        compressed_size,
-        filename_len: o.add("file_name_length", le::u16(b))?,
-        extra_field_len: o.add("extra_field_length", le::u16(b))?,
-    })
+    let method = |b: &mut Bytes| sym::<S, CompressionMethod, _>(le::u16(b));
+    let datetime = |b: &mut Bytes| dos::datetime::<S>(b);
+    let crc = |b: &mut Bytes| {
+        let d = crc_32(b)?;
+        let bytes = d.meta.bytes().clone();
+        Ok(d.map_out(|value| StoredCrc { value, bytes }))
+    };
+    Ok(decode_struct!(
+        o,
+        b,
+        Common {
+            /// general purpose bit flags
+            flags: flags,
+            /// method used to compress the file data
+            compression_method: method,
+            /// time of the last modification, in local time
+            last_modification: datetime,
+            /// CRC-32 of the uncompressed data
+            crc_32: crc,
+            /// size of the compressed data, in bytes
+            compressed_size: le::u32,
+            /// size of the uncompressed data, in bytes
+            uncompressed_size: le::u32,
+            /// length of the file name, in bytes
+            file_name_length: le::u16,
+            /// length of the extra fields, in bytes
+            extra_field_length: le::u16,
+        }
+    ))
 }
 
 #[derive(Debug)]
 struct CentralDirRecord {
     common: Common,
+    version_made_by: u16,
+    external_attributes: u32,
+    file_name: Bytes,
+    extra_fields: Bytes,
+    file_comment: Bytes,
+    compressed_size: u64,
+    uncompressed_size: u64,
     disk_nr_start: u32,
     local_file_offset: u64,
 }
 
-fn decode_name_and_fields(o: &mut Obj, b: &mut Bytes, common: &Common) -> Result<Zip64> {
-    o.add("file_name", raw(b, common.filename_len.into()))?;
-    let efs_slice = take(b, common.extra_field_len.into())?;
-    o.add_mut("extra_fields", Meta::from(&efs_slice), |_, efs| {
-        decode_extra_fields(efs.make_arr(), efs_slice)
-    })
+/// Decode the file name and the extra fields, returning them and the ZIP64 extra field.
+fn decode_name_and_fields<S: Sink>(
+    o: &mut S,
+    b: &mut Bytes,
+    common: &Common,
+    placeholders: Placeholders,
+) -> Result<(Bytes, Bytes, Zip64)> {
+    let file_name = o.add_described(
+        "file_name",
+        "name of the file, including its path",
+        raw(b, common.file_name_length.into()),
+    )?;
+    let efs_slice = take(b, common.extra_field_length.into())?;
+    let zip64 = o.add_arr("extra_fields", Meta::from(&efs_slice), |_, efs| {
+        decode_extra_fields(efs, efs_slice.clone(), placeholders)
+    })?;
+    replace_zip64(o, &zip64);
+    Ok((file_name, efs_slice, zip64))
 }
 
-fn decode_cdr(o: &mut Obj, b: &mut Bytes, opts: &Opts) -> Result<CentralDirRecord> {
-    o.add("signature", precise(b, CENTRAL_DIR_SIG, opts.force))?;
-    o.add("version_made_by", le::u16(b))?;
-    o.add("version_needed", le::u16(b))?;
+/// Replace header fields by the values of the ZIP64 extra field that supersede them.
+///
+/// Only fields that hold a placeholder like 0xFFFFFFFF are superseded,
+/// and they keep spanning the header bytes.
+fn replace_zip64<S: Sink>(o: &mut S, zip64: &Zip64) {
+    let with = |v| {
+        move |m: &Meta| {
+            let d = m
+                .description()
+                .map(|d| format!("{d}, from the ZIP64 extra field"));
+            (m.clone().describe(d), v)
+        }
+    };
+    let fields = [
+        ("uncompressed_size", zip64.uncompressed_size),
+        ("compressed_size", zip64.compressed_size),
+        (
+            "relative_offset_of_local_file_header",
+            zip64.local_file_offset,
+        ),
+    ];
+    for (k, v) in fields {
+        if let Some(v) = v {
+            o.replace(k, with(Val::U64(v)))
+        }
+    }
+    if let Some(d) = zip64.disk_nr_start {
+        o.replace("disk_number_where_file_starts", with(Val::U32(d)))
+    }
+}
+
+fn decode_cdr<S: Sink>(o: &mut S, b: &mut Bytes, force: bool) -> Result<CentralDirRecord> {
+    o.add("signature", precise(b, CENTRAL_DIR_SIG, force))?;
+    let version_made_by = o.add_described("version_made_by", VERSION_MADE_BY, le::u16(b))?;
+    o.add_described(
+        "version_needed",
+        VERSION_NEEDED,
+        sym::<S, Version, _>(le::u16(b)),
+    )?;
     let common = decode_common(o, b)?;
 
-    let file_comment_len = o.add("file_comment_length", le::u16(b))?;
-    let disk_nr_start = o.add("disk_number_where_file_starts", le::u16(b))?;
-    o.add("internal_file_attributes", le::u16(b))?;
-    o.add("external_file_attributes", le::u32(b))?;
-    let local_file_offset = o.add("relative_offset_of_local_file_header", le::u32(b))?;
+    let file_comment_len = o.add_described(
+        "file_comment_length",
+        "length of the file comment, in bytes",
+        le::u16(b),
+    )?;
+    let disk_nr_start = o.add_described(
+        "disk_number_where_file_starts",
+        "number of the disk where the local file starts",
+        le::u16(b),
+    )?;
+    o.add_described(
+        "internal_file_attributes",
+        "bit 0 is set if the file is apparently text",
+        le::u16(b),
+    )?;
+    let external_attributes = o.add_described(
+        "external_file_attributes",
+        "attributes of the file, depending on the host system",
+        le::u32(b),
+    )?;
+    let local_file_offset = o.add_described(
+        "relative_offset_of_local_file_header",
+        "offset of the local file header, in bytes from start of its disk",
+        le::u32(b),
+    )?;
 
-    let zip64 = decode_name_and_fields(o, b, &common)?;
-    o.add("file_comment", raw(b, file_comment_len.into()))?;
+    let placeholders = Placeholders {
+        local_file_offset: local_file_offset == u32::MAX,
+        disk_nr_start: disk_nr_start == u16::MAX,
+        ..Placeholders::of(&common)
+    };
+    let (file_name, extra_fields, zip64) = decode_name_and_fields(o, b, &common, placeholders)?;
+    let file_comment = o.add_described(
+        "file_comment",
+        "comment of the file",
+        raw(b, file_comment_len.into()),
+    )?;
 
     Ok(CentralDirRecord {
+        version_made_by,
+        external_attributes,
+        file_name,
+        extra_fields,
+        file_comment,
+        compressed_size: zip64
+            .compressed_size
+            .unwrap_or(common.compressed_size.into()),
+        uncompressed_size: zip64
+            .uncompressed_size
+            .unwrap_or(common.uncompressed_size.into()),
         common,
         disk_nr_start: zip64.disk_nr_start.unwrap_or(disk_nr_start.into()),
         local_file_offset: zip64.local_file_offset.unwrap_or(local_file_offset.into()),
     })
 }
 
-fn uncompress(b: Bytes, method: CompressionMethod) -> Val {
-    use miniz_oxide::inflate::decompress_to_vec;
-    Val::Obj(Obj(match method {
-        CompressionMethod::deflated => decompress_to_vec(&b).ok().map(Bytes::from),
-        CompressionMethod::none => Some(b.clone()),
-        _ => None,
+#[cfg(test)]
+std::thread_local! {
+    /// Number of times that deflated data was uncompressed on this thread.
+    static INFLATIONS: core::cell::Cell<usize> = const { core::cell::Cell::new(0) };
+}
+
+/// Count that deflated data is uncompressed.
+fn count_inflation() {
+    #[cfg(test)]
+    INFLATIONS.with(|n| n.set(n.get() + 1));
+}
+
+/// Uncompress `b`.
+///
+/// Return `Ok(None)` if the compression method is not supported or if the data is corrupt,
+/// and an error if the data would uncompress to more than `limit` bytes.
+fn inflate(b: Bytes, method: CompressionMethod, limit: usize) -> Result<Option<Bytes>> {
+    use miniz_oxide::inflate::{decompress_to_vec_with_limit, TINFLStatus};
+    let exceeded = |b| {
+        let msg = format!("uncompressed data exceeds the limit max_decompressed_bytes = {limit}");
+        Err(Error::new(b, msg))
+    };
+    match method {
+        CompressionMethod::deflated => {
+            count_inflation();
+            match decompress_to_vec_with_limit(&b, limit) {
+                Ok(u) => Ok(Some(u.into())),
+                Err(e) if e.status == TINFLStatus::HasMoreOutput => exceeded(&b),
+                Err(_) => Ok(None),
+            }
+        }
+        CompressionMethod::none if b.len() > limit => exceeded(&b),
+        CompressionMethod::none => Ok(Some(b)),
+        _ => Ok(None),
+    }
+}
+
+/// Feed the uncompressed data to `f` in chunks, without holding all of it in memory.
+///
+/// Return the number of uncompressed bytes, or `None` if the data is corrupt.
+/// If the data would uncompress to more than `limit` bytes, uncompressing stops there,
+/// and the returned number exceeds `limit`.
+fn inflate_with(
+    b: &[u8],
+    method: CompressionMethod,
+    limit: usize,
+    mut f: impl FnMut(&[u8]),
+) -> Option<usize> {
+    use miniz_oxide::inflate::core::{decompress, DecompressorOxide, TINFL_LZ_DICT_SIZE};
+    use miniz_oxide::inflate::TINFLStatus;
+    match method {
+        CompressionMethod::deflated => (),
+        CompressionMethod::none => {
+            if b.len() <= limit {
+                f(b);
+            }
+            return Some(b.len());
+        }
+        _ => return None,
+    }
+    count_inflation();
+    let mut state = Box::<DecompressorOxide>::default();
+    // the decompressor uses the output buffer as window for back-references
+    let mut window = alloc::vec![0; TINFL_LZ_DICT_SIZE];
+    let (mut input, mut pos, mut total) = (b, 0, 0usize);
+    loop {
+        let (status, read, written) = decompress(&mut state, input, &mut window, pos, 0);
+        input = &input[read..];
+        total = total.checked_add(written)?;
+        if total > limit {
+            return Some(total);
+        }
+        f(&window[pos..pos + written]);
+        pos = (pos + written) % window.len();
+        match status {
+            TINFLStatus::Done => return Some(total),
+            TINFLStatus::HasMoreOutput => (),
+            _ => return None,
+        }
     }
-    .into_iter()
-    .map(|uc| ("uncompressed", Meta::from(uc), Val::default()))
-    .collect()))
 }
 
-fn decode_extra_field(o: &mut Obj, b: &mut Bytes) -> Result<Option<Zip64>> {
+/// CRC-32 of the data that `compressed` uncompresses to.
+///
+/// In contrast to uncompressing the data and hashing it,
+/// this takes constant memory regardless of the size of the data.
+/// Return `None` if the compression method is not supported,
+/// if the data is corrupt, or if it would uncompress to more than `limit` bytes.
+pub fn crc32(compressed: &[u8], compression_method: u16, limit: usize) -> Option<u32> {
+    let method = CompressionMethod::from_u16(compression_method)?;
+    let mut hasher = crc32fast::Hasher::new();
+    let n = inflate_with(compressed, method, limit, |chunk| hasher.update(chunk))?;
+    (n <= limit).then(|| hasher.finalize())
+}
+
+/// Uncompress `b` if its compression method is known, and verify the result against `crc`.
+///
+/// Both the uncompressed data and the verification are lazy.
+/// The verification streams the data through the hasher,
+/// so only forcing the uncompressed data holds all of it in memory.
+fn uncompress(
+    b: Bytes,
+    method: Option<CompressionMethod>,
+    limit: usize,
+    cache: Option<Arc<Cache>>,
+    metrics: Option<Arc<Metrics>>,
+    crc: StoredCrc,
+) -> Val {
+    // stored data is not copied when uncompressing it, so there is no point in caching it
+    let deflated = matches!(method, Some(CompressionMethod::deflated));
+    let cache = cache.filter(|_| deflated);
+    let cached = {
+        let (b, cache) = (b.clone(), cache.clone());
+        move || cache.as_deref()?.get(&b).filter(|u| u.len() <= limit)
+    };
+    let uncompressed = {
+        let (b, metrics, cached) = (b.clone(), metrics.clone(), cached.clone());
+        Val::lazy(move || {
+            let inflate = || {
+                let u = inflate(b.clone(), method?, limit).ok()??;
+                if let Some(c) = &cache {
+                    c.insert(b, u.clone())
+                }
+                Some(u)
+            };
+            let u =
+                cached().or_else(|| Metrics::time(metrics.as_deref(), Phase::Decompress, inflate));
+            u.map_or(Val::Null, |u| Val::Bytes(Box::new(u)))
+        })
+    };
+    let crc_meta = Meta::from(&crc.bytes).with_format(format_crc);
+    let crc = crc.value;
+    let verified = {
+        let (b, crc_meta, uncompressed) = (b.clone(), crc_meta.clone(), uncompressed.clone());
+        Val::lazy(move || {
+            // hash the uncompressed data if it is available, else stream it through the hasher
+            let available = match uncompressed.forced() {
+                Some(Val::Bytes(u)) => Some((**u).clone()),
+                _ => cached(),
+            };
+            let mut exceeded = false;
+            let computed = match (available, method) {
+                (Some(u), _) => Some(checksum::crc32(&u)),
+                (None, Some(method)) => {
+                    Metrics::time(metrics.as_deref(), Phase::Decompress, || {
+                        let mut hasher = crc32fast::Hasher::new();
+                        let n = inflate_with(&b, method, limit, |chunk| hasher.update(chunk))?;
+                        exceeded = n > limit;
+                        (!exceeded).then(|| hasher.finalize())
+                    })
+                }
+                (None, None) => None,
+            };
+            let stored = Decoded::new(crc_meta, Val::U32(crc), crc);
+            let mut v = checksum::verify(stored, computed);
+            if let Val::Obj(o) = &mut v {
+                if let (Some((m, _)), Some(c)) = (o.get_mut("valid"), computed) {
+                    if c != crc {
+                        m.warn(format!("expected CRC-32 {crc:#010x}, found {c:#010x}"));
+                    }
+                }
+                if let Some((m, _)) = o.get_mut("value").filter(|_| exceeded) {
+                    let msg = format!(
+                        "uncompressed data exceeds the limit max_decompressed_bytes = {limit}"
+                    );
+                    *m = m.clone().with_error(Error::new(&b, msg));
+                }
+            }
+            v
+        })
+    };
+    let uncompressed_meta = Meta::from(&b).describe(Some("uncompressed from these bytes".into()));
+    let crc_meta = crc_meta.describe(Some("CRC-32 given by the central directory".into()));
+    let entries = [
+        ("uncompressed".into(), uncompressed_meta, uncompressed),
+        ("crc_32".into(), crc_meta, verified),
+    ];
+    Val::Obj(Box::new(entries.into_iter().collect()))
+}
+
+fn decode_extra_field<S: Sink>(
+    o: &mut S,
+    b: &mut Bytes,
+    placeholders: Placeholders,
+) -> Result<Option<Zip64>> {
     let tag = o.add("tag", le::u16(b))?;
     let size = o.add("size", le::u16(b))?;
     let mut d = raw(b, size.into())?;
-    o.add_mut("data", d.meta, |_, o| match tag {
-        0x001 => decode_zip64(o.make_obj(), &mut d.out).map(Some),
-        0x5455 => decode_extended_timestamp(o.make_obj(), &mut d.out).map(|_| None),
-        _ => {
-            *o = d.val;
-            Ok(None)
-        }
-    })
+    let r = match tag {
+        0x001 => o.add_obj("data", d.meta, |_, o| {
+            decode_zip64(o, &mut d.out, placeholders).map(Some)
+        }),
+        0x5455 => o.add_obj("data", d.meta, |_, o| {
+            decode_extended_timestamp(o, &mut d.out).map(|_| None)
+        }),
+        _ => o.add("data", Ok(d)).map(|_| None),
+    };
+    r.map_err(|e| e.with_context(format!("extra field {tag:#06x}")))
 }
 
-fn decode_extra_fields(a: &mut Arr, mut b: Bytes) -> Result<Zip64> {
-    let mut zip64 = Zip64::default();
-    while !b.is_empty() {
-        let y = a.add_consumed(&mut b, |b, v| decode_extra_field(v.make_obj(), b))?;
-        zip64 = y.unwrap_or(zip64);
-    }
-    Ok(zip64)
+fn decode_extra_fields<A: ArrSink>(
+    a: &mut A,
+    b: Bytes,
+    placeholders: Placeholders,
+) -> Result<Zip64> {
+    let ys = a.add_repeat_all(b, |b, o| decode_extra_field(o, b, placeholders))?;
+    // the last ZIP64 extra field wins
+    Ok(ys.into_iter().flatten().last().unwrap_or_default())
+}
+
+/// Return true if `b` starts with the optional signature of a data indicator.
+fn has_data_indicator_sig(b: &[u8]) -> bool {
+    peek::bytes(b, DATA_INDICATOR_SIG.len()).is_some_and(|sig| sig == DATA_INDICATOR_SIG)
 }
 
-fn decode_data_indicator(o: &mut Obj, b: &mut Bytes) -> Result<()> {
-    if b.starts_with(DATA_INDICATOR_SIG) {
+fn decode_data_indicator<S: Sink>(o: &mut S, b: &mut Bytes) -> Result<()> {
+    if has_data_indicator_sig(b) {
         o.add("signature", precise(b, DATA_INDICATOR_SIG, true))?;
     }
-    o.add("crc32_uncompressed", le::u32(b))?;
-    o.add("compressed_size", le::u32(b))?;
-    o.add("uncompressed_size", le::u32(b))?;
+    o.add_described(
+        "crc32_uncompressed",
+        "CRC-32 of the uncompressed data",
+        crc_32(b),
+    )?;
+    o.add_described(
+        "compressed_size",
+        "size of the compressed data, in bytes",
+        le::u32(b),
+    )?;
+    o.add_described(
+        "uncompressed_size",
+        "size of the uncompressed data, in bytes",
+        le::u32(b),
+    )?;
     Ok(())
 }
 
-fn decode_local_file(o: &mut Obj, b: &mut Bytes, opts: &Opts, cdr_common: &Common) -> Result<()> {
+/// Decode a local file and return its compressed data.
+fn decode_local_file<S: Sink>(
+    o: &mut S,
+    b: &mut Bytes,
+    opts: &Opts,
+    cdr_common: &Common,
+) -> Result<Bytes> {
     o.add("signature", precise(b, LOCAL_FILE_SIG, opts.force))?;
-    o.add("version_needed", le::u16(b))?;
+    o.add_described(
+        "version_needed",
+        VERSION_NEEDED,
+        sym::<S, Version, _>(le::u16(b)),
+    )?;
     let lf_common = decode_common(o, b)?;
-    let zip64 = decode_name_and_fields(o, b, &lf_common)?;
+    let placeholders = Placeholders::of(&lf_common);
+    let (_, _, zip64) = decode_name_and_fields(o, b, &lf_common, placeholders)?;
     // no file_comment here (unlike in central directory)
 
     let compressed_size = match zip64
         .compressed_size
         .unwrap_or(lf_common.compressed_size.into())
     {
-        0 => cdr_common.compressed_size.into(),
+        0 => {
+            count(opts, "size_from_central_directory");
+            cdr_common.compressed_size.into()
+        }
         s => s,
     };
+    opts.limits.field(b, compressed_size)?;
     let compressed_size = into_usize(compressed_size, b)?;
 
+    let mut data = b.slice(..0);
     if compressed_size > 0 {
         let compressed = raw(b, compressed_size)?;
+        data = compressed.out.clone();
         let method = CompressionMethod::from_u16(lf_common.compression_method);
-        let f = |method| Val::lazy(move || uncompress(compressed.out, method));
-        let entry = Decoded::new(compressed.meta, method.map_or(Val::default(), f), ());
+        if method.is_none() {
+            count(opts, "unknown_compression_method");
+        }
+        let limit = opts.limits.decompressed();
+        let (metrics, cache) = (opts.metrics.clone(), opts.cache.clone());
+        let crc = cdr_common.crc_32.clone();
+        let lazy = S::lazy(move || uncompress(compressed.out, method, limit, cache, metrics, crc));
+        let entry = Decoded::new(compressed.meta, lazy, ());
         o.add("compressed", Ok(entry))?;
     }
 
     if lf_common.flags.contains(Flags::data_descriptor) {
-        o.add_consumed("data_indicator", b, |b, v| {
-            decode_data_indicator(v.make_obj(), b)
-        })?;
+        count(opts, "data_descriptor");
+        if has_data_indicator_sig(b) {
+            count(opts, "data_descriptor_signature");
+        }
+        o.add_obj_consumed("data_indicator", b, |b, o| decode_data_indicator(o, b))?;
     }
-    Ok(())
+    Ok(data)
 }
 
+/// Return the position of the last occurrence of `sig` in the last `len` bytes of `b`.
+///
+/// This touches only the last `len` bytes, so that searching a memory-mapped file
+/// does not read the pages before them.
 fn find(b: &[u8], sig: &[u8; 4], len: usize) -> Option<usize> {
-    Some(b.len() - (b.windows(4).rev().take(len - 3).position(|w| w == sig)? + 4))
+    let start = b.len().saturating_sub(len);
+    Some(start + memchr::memmem::rfind(&b[start..], sig)?)
 }
 
-fn add_with<T, F>(o: &mut Obj, k: &'static str, mut b: Bytes, opts: &Opts, f: F) -> Result<T>
+fn add_with<S: Sink, T, F>(o: &mut S, k: &'static str, mut b: Bytes, opts: &Opts, f: F) -> Result<T>
 where
-    F: FnOnce(&mut Obj, &mut Bytes, &Opts) -> Result<T>,
+    F: FnOnce(&mut S, &mut Bytes, &Opts) -> Result<T>,
 {
-    o.add_mut(k, Meta::from(&b), |_, v| f(v.make_obj(), &mut b, &opts))
+    o.add_obj(k, Meta::from(&b), |_, o| f(o, &mut b, opts))
 }
 
-fn decode_eocds(o: &mut Obj, b: &mut Bytes, opts: &Opts) -> Result<EndOfCentralDirRecord> {
-    let eocds_abs = find(&b, EOCD_SIG, 65558)
+fn decode_eocds<S: Sink>(o: &mut S, b: &mut Bytes, opts: &Opts) -> Result<EndOfCentralDirRecord> {
+    let eocds_abs = find(b, EOCD_SIG, EOCD_MAX_LEN)
         .ok_or_else(|| Error::new(b, "could not find end of central directory"))?;
 
     let k = "end_of_central_directory_record";
     let eocd = add_with(o, k, b.split_off(eocds_abs), opts, decode_eocd)?;
 
-    if let Some(eocdl_abs) = find(&b, EOCD_LOCATOR_SIG, 20) {
+    if let Some(eocdl_abs) = find(b, EOCD_LOCATOR_SIG, 20) {
         let k = "end_of_central_directory_locator";
         let offset_eocd = add_with(o, k, b.split_off(eocdl_abs), opts, decode_eocdl)?;
         let offset_eocd: usize = into_usize(offset_eocd, b)?;
+        count(opts, "zip64");
 
         let k = "end_of_central_directory_record_zip64";
         add_with(o, k, try_split_off(b, offset_eocd)?, opts, decode_eocd64)
@@ -430,34 +879,1118 @@ fn decode_eocds(o: &mut Obj, b: &mut Bytes, opts: &Opts) -> Result<EndOfCentralD
     }
 }
 
-fn decode_cds(a: &mut Arr, mut b: Bytes, opts: &Opts) -> Result<Vec<CentralDirRecord>> {
+/// Decode central directory records, returning those that are kept and the number of all records.
+///
+/// Records that are kept and whose local files lie on disk `disk_nr` are linked to their local files.
+fn decode_cds<A: ArrSink>(
+    a: &mut A,
+    mut b: Bytes,
+    disk_nr: u32,
+    opts: &Opts,
+) -> Result<(Vec<CentralDirRecord>, usize)> {
     let mut cds = Vec::new();
+    // number of local files that will be decoded for the records kept so far
+    let mut local_files = 0;
+    let mut keep = |a: &mut A, cdr: CentralDirRecord| {
+        if opts.stats.is_some() {
+            count_cdr(opts, &cdr);
+        }
+        if opts.filter.as_ref().is_none_or(|f| f(&cdr.file_name)) {
+            if cdr.disk_nr_start == disk_nr {
+                let path = [Index::Str("local_files".into()), Index::Int(local_files)];
+                a.link_last("relative_offset_of_local_file_header", path.into());
+                local_files += 1;
+            }
+            cds.push(cdr);
+        } else {
+            count(opts, "filtered_entries");
+            a.pop();
+        }
+    };
+    // number of records decoded so far, including those not kept
+    let mut i = 0;
+    #[cfg(feature = "rayon")]
+    if opts.parallel {
+        for (m, o, cdr) in decode_cds_parallel::<A::Obj>(&mut b, opts.force) {
+            opts.limits.entries(&m.bytes(), i + 1)?;
+            a.push(m, o);
+            keep(a, cdr);
+            i += 1;
+        }
+    }
     while !b.is_empty() {
-        cds.push(a.add_consumed(&mut b, |b, v| decode_cdr(v.make_obj(), b, &opts))?);
+        opts.limits.entries(&b, i + 1)?;
+        let start = b.clone();
+        let cdr = a.add_obj_consumed(&mut b, |b, o| decode_cdr(o, b, opts.force));
+        match cdr {
+            Ok(cdr) => keep(a, cdr),
+            Err(_) if opts.recover => {
+                count(opts, "recovered_errors");
+                // skip to the next record, which is not necessarily at the start of this one
+                let next = memchr::memmem::find(&start[1..], CENTRAL_DIR_SIG);
+                b = start;
+                a.last_to_gap(b.split_to(next.map_or(b.len(), |n| n + 1)));
+            }
+            Err(e) => return Err(e.with_context(format!("central directory record {i}"))),
+        }
+        i += 1;
     }
-    Ok(cds)
+    Ok((cds, i))
 }
 
-pub fn decode_zip(root: &mut Obj, mut b: Bytes, opts: &Opts) -> Result {
-    let eocd = decode_eocds(root, &mut b, opts)?;
+/// Count the features of a central directory record.
+fn count_cdr(opts: &Opts, cdr: &CentralDirRecord) {
+    use bitflags::Flags as _;
+    count(opts, "entries");
+    if cdr.file_name.ends_with(b"/") {
+        count(opts, "directories");
+    }
+    let method = cdr.common.compression_method;
+    match CompressionMethod::from_u16(method) {
+        Some(m) => count(opts, format_args!("compression_method: {m:?}")),
+        None => count(opts, format_args!("compression_method: {method}")),
+    }
+    let flags = Flags::FLAGS.iter().filter(|f| !f.name().is_empty());
+    for f in flags.filter(|f| cdr.common.flags.contains(f.value().clone())) {
+        count(opts, format_args!("flag: {}", f.name()));
+    }
+    count(
+        opts,
+        format_args!("host_system: {}", cdr.version_made_by >> 8),
+    );
+    // count the tags of the extra fields, ignoring a truncated last field
+    let mut efs = &cdr.extra_fields[..];
+    while let [t0, t1, s0, s1, rest @ ..] = efs {
+        let tag = u16::from_le_bytes([*t0, *t1]);
+        count(opts, format_args!("extra_field: {tag:#06x}"));
+        efs = rest
+            .get(usize::from(u16::from_le_bytes([*s0, *s1]))..)
+            .unwrap_or_default();
+    }
+    if !cdr.file_comment.is_empty() {
+        count(opts, "file_comment");
+    }
+}
 
-    let offset_cd = into_usize(eocd.offset_cd, &b)?;
-    let mut cd_slice = try_split_off(&mut b, offset_cd)?;
-    let size_cd = into_usize(eocd.size_cd, &cd_slice)?;
-    try_split_off(&mut cd_slice, size_cd)?;
-    let cd = root.add_mut("central_directories", Meta::from(&cd_slice), |_, cd| {
-        decode_cds(cd.make_arr(), cd_slice, opts)
-    })?;
+/// Decode central directory records in parallel, consuming them from `b`.
+///
+/// Records are split by their lengths before decoding them,
+/// and returned in input order, so that the result does not depend on scheduling.
+/// Decoding stops before the first record that cannot be split off or decoded,
+/// so that decoding it serially yields the same error.
+#[cfg(feature = "rayon")]
+fn decode_cds_parallel<S: Sink>(b: &mut Bytes, force: bool) -> Vec<(Meta, S, CentralDirRecord)> {
+    use rayon::prelude::*;
+    // length of the fixed part of a record and offset of its variable lengths
+    const FIXED: usize = 46;
+    const LENGTHS: usize = 28;
+    let mut records = Vec::new();
+    let mut rest = b.clone();
+    while rest.len() >= FIXED {
+        let len = |i| usize::from(u16::from_le_bytes([rest[i], rest[i + 1]]));
+        let n = FIXED + len(LENGTHS) + len(LENGTHS + 2) + len(LENGTHS + 4);
+        if n > rest.len() {
+            break;
+        }
+        records.push(rest.split_to(n));
+    }
+    // decode on the worker threads from the cursor of this thread
+    let input = current_input();
+    let decode = |record: &Bytes| {
+        within(input.clone(), || {
+            let mut o = S::default();
+            let cdr = decode_cdr(&mut o, &mut record.clone(), force).ok()?;
+            Some((Meta::from(record), o, cdr))
+        })
+    };
+    let decoded: Vec<_> = records.par_iter().map(decode).collect();
+    let decoded: Vec<_> = decoded.into_iter().map_while(|d| d).collect();
+    let len = decoded.iter().map(|(m, ..)| m.bytes().len()).sum();
+    let _ = b.split_to(len);
+    decoded
+}
+
+/// File or directory stored in a ZIP archive.
+pub struct Entry {
+    /// Name of the entry, using `/` as path separator.
+    pub name: Bytes,
+    pub compression_method: u16,
+    /// MS-DOS time of last modification.
+    pub fat_time: u16,
+    /// MS-DOS date of last modification.
+    pub fat_date: u16,
+    /// CRC-32 of the uncompressed data.
+    pub crc_32: u32,
+    pub compressed_size: u64,
+    pub uncompressed_size: u64,
+    /// Version and host system (upper byte) of the software that created the entry.
+    pub version_made_by: u16,
+    pub external_attributes: u32,
+    /// Compressed data.
+    pub compressed: Bytes,
+}
+
+impl Entry {
+    /// Return true if the entry is a directory.
+    pub fn is_dir(&self) -> bool {
+        self.name.ends_with(b"/")
+    }
 
-    root.add_mut("local_files", Meta::from(&b), |_, lf| {
-        let a = lf.make_arr();
-        for cdr in cd.iter().filter(|cdr| cdr.disk_nr_start == eocd.disk_nr) {
+    /// Unix file mode, if the entry was created on Unix.
+    pub fn unix_mode(&self) -> Option<u32> {
+        (self.version_made_by >> 8 == 3).then_some(self.external_attributes >> 16)
+    }
+
+    /// Modification time in seconds since the Unix epoch.
+    ///
+    /// MS-DOS times do not store a time zone; we interpret them as UTC.
+    pub fn mtime(&self) -> i64 {
+        let (time, date) = (self.fat_time, self.fat_date);
+        dos::DosDateTime { time, date }.unix()
+    }
+
+    /// Uncompress the data.
+    ///
+    /// Return `None` if the compression method is not supported,
+    /// if the data is corrupt, or if it would uncompress to more than `limit` bytes.
+    pub fn uncompress(&self, limit: usize) -> Option<Bytes> {
+        let method = CompressionMethod::from_u16(self.compression_method)?;
+        inflate(self.compressed.clone(), method, limit).ok()?
+    }
+
+    /// CRC-32 of the uncompressed data, computed without uncompressing all data at once.
+    ///
+    /// See [`crc32`].
+    pub fn crc32(&self, limit: usize) -> Option<u32> {
+        crc32(&self.compressed, self.compression_method, limit)
+    }
+}
+
+/// Decode the entries of a ZIP archive.
+pub fn entries(b: Bytes, opts: &Opts) -> Result<Vec<Entry>> {
+    with_input(&b, || decode_entries(&mut Discard, b.clone(), opts))
+}
+
+/// Decode a ZIP archive into `root`.
+pub fn decode_zip(root: &mut Obj, b: Bytes, opts: &Opts) -> Result {
+    let r = with_input(&b, || decode_entries(root, b.clone(), opts));
+    r.map(|_| ())
+}
+
+/// Decode a ZIP archive read from `r` into `root`.
+///
+/// Because the central directory is at the end of an archive,
+/// this reads the whole stream into memory before decoding it.
+/// Return the bytes that were read, to which the spans in `root` refer,
+/// together with the result of decoding.
+pub fn decode_zip_read(root: &mut Obj, mut r: impl std::io::Read, opts: &Opts) -> (Bytes, Result) {
+    let mut v = Vec::new();
+    let read = r.read_to_end(&mut v);
+    let b = Bytes::from(v);
+    match read {
+        Ok(_) => (b.clone(), decode_zip(root, b, opts)),
+        Err(e) => {
+            let e = Error::new(&b.slice(b.len()..), e).with_offset(b.len());
+            (b, Err(e))
+        }
+    }
+}
+
+fn decode_entries<S: Sink>(root: &mut S, mut b: Bytes, opts: &Opts) -> Result<Vec<Entry>> {
+    let metrics = opts.metrics.as_deref();
+    let eocd = Metrics::time(metrics, Phase::Locate, || decode_eocds(root, &mut b, opts))?;
+    Metrics::time(metrics, Phase::Headers, || {
+        decode_records(root, b, &eocd, opts)
+    })
+}
+
+/// Decode the central directory and the local files.
+fn decode_records<S: Sink>(
+    root: &mut S,
+    mut b: Bytes,
+    eocd: &EndOfCentralDirRecord,
+    opts: &Opts,
+) -> Result<Vec<Entry>> {
+    let cd =
+        decode_cd(root, &mut b, eocd, opts).map_err(|e| e.with_context("central directory"))?;
+    // end of the last local file, relative to the start of the input
+    let mut end = 0;
+    let entries = root.add_arr("local_files", Meta::from(&b), |_, a| {
+        let mut entries = Vec::new();
+        let cd: Vec<_> = cd
+            .into_iter()
+            .filter(|cdr| cdr.disk_nr_start == eocd.disk_nr)
+            .collect();
+        a.reserve(cd.len());
+        let total = cd.len() as u64;
+        for cdr in cd {
             let offset = into_usize(cdr.local_file_offset, &b)?;
             let mut lfr_slice = try_slice(&b, offset..)?;
-            a.add_consumed(&mut lfr_slice, |b, v| {
-                decode_local_file(v.make_obj(), b, &opts, &cdr.common)
-            })?;
+            let entry =
+                a.add_obj_consumed(&mut lfr_slice, |b, o| decode_entry(o, b, &cdr, opts))?;
+            end = end.max(b.len() - lfr_slice.len());
+            entries.push(entry);
+            if let Some(progress) = &opts.progress {
+                progress(entries.len() as u64, total);
+            }
+        }
+        Ok(entries)
+    })?;
+    // filtered local files are not decoded, so their bytes are not known to be unparsed
+    if opts.filter.is_none() && end < b.len() {
+        root.add_gap("unparsed_before_central_directory", b.slice(end..));
+    }
+    Ok(entries)
+}
+
+/// Decode the central directory, leaving in `b` only the input before it.
+fn decode_cd<S: Sink>(
+    root: &mut S,
+    b: &mut Bytes,
+    eocd: &EndOfCentralDirRecord,
+    opts: &Opts,
+) -> Result<Vec<CentralDirRecord>> {
+    let offset_cd = into_usize(eocd.offset_of_start_of_central_dir, b)?;
+    let mut cd_slice = try_split_off(b, offset_cd)?;
+    opts.limits.field(&cd_slice, eocd.size_of_central_dir)?;
+    let size_cd = into_usize(eocd.size_of_central_dir, &cd_slice)?;
+    let after_cd = try_split_off(&mut cd_slice, size_cd)?;
+    if !after_cd.is_empty() {
+        root.add_gap("unparsed_after_central_directory", after_cd);
+    }
+    // every record takes at least 46 bytes, which bounds a bogus record count
+    let nr_records = usize::try_from(eocd.nr_of_central_dir_records).unwrap_or(usize::MAX);
+    let nr_records = nr_records.min(size_cd / 46);
+    root.add_arr("central_directories", Meta::from(&cd_slice), |m, a| {
+        a.reserve(nr_records);
+        let (cds, decoded) = decode_cds(a, cd_slice, eocd.disk_nr, opts)?;
+        let expected = eocd.nr_of_central_dir_records;
+        if decoded as u64 != expected {
+            count(opts, "record_count_mismatch");
+            let msg = format!("expected {expected} records as given by the end of central directory record, found {decoded}");
+            m.warn(msg);
         }
-        Ok(())
+        Ok(cds)
+    })
+}
+
+/// Decode the local file described by `cdr` into `o`.
+fn decode_entry<S: Sink>(
+    o: &mut S,
+    b: &mut Bytes,
+    cdr: &CentralDirRecord,
+    opts: &Opts,
+) -> Result<Entry> {
+    let context = |e: Error| {
+        let name = String::from_utf8_lossy(&cdr.file_name);
+        e.with_context(format!("local file {name:?}"))
+    };
+    let compressed = decode_local_file(o, b, opts, &cdr.common).map_err(context)?;
+    Ok(Entry {
+        name: cdr.file_name.clone(),
+        compression_method: cdr.common.compression_method,
+        fat_time: cdr.common.last_modification.time,
+        fat_date: cdr.common.last_modification.date,
+        crc_32: cdr.common.crc_32.value,
+        compressed_size: cdr.compressed_size,
+        uncompressed_size: cdr.uncompressed_size,
+        version_made_by: cdr.version_made_by,
+        external_attributes: cdr.external_attributes,
+        compressed,
     })
 }
+
+/// ZIP archive whose local files are decoded on demand.
+///
+/// Creating a directory decodes only the end of central directory records
+/// and the central directory, which is fast even for large archives.
+/// Local files can then be decoded individually, and decoded again
+/// with different options after invalidating them.
+pub struct Directory {
+    root: Obj,
+    /// Input before the central directory, which contains the local files.
+    b: Bytes,
+    disk_nr: u32,
+    cd: Vec<CentralDirRecord>,
+    /// Decoded local files, indexed like the central directory records.
+    local_files: Vec<Option<(Meta, Val)>>,
+}
+
+impl Directory {
+    /// Decode the end of central directory records and the central directory.
+    pub fn new(mut b: Bytes, opts: &Opts) -> Result<Self> {
+        let metrics = opts.metrics.as_deref();
+        let mut root = Obj::default();
+        let eocd = Metrics::time(metrics, Phase::Locate, || {
+            decode_eocds(&mut root, &mut b, opts)
+        })?;
+        let cd = Metrics::time(metrics, Phase::Headers, || {
+            decode_cd(&mut root, &mut b, &eocd, opts)
+        });
+        let cd = cd.map_err(|e| e.with_context("central directory"))?;
+        Ok(Self {
+            root,
+            b,
+            disk_nr: eocd.disk_nr,
+            local_files: cd.iter().map(|_| None).collect(),
+            cd,
+        })
+    }
+
+    /// Decoded end of central directory records and central directory.
+    pub fn root(&self) -> &Obj {
+        &self.root
+    }
+
+    /// Number of entries in the central directory.
+    pub fn len(&self) -> usize {
+        self.cd.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.cd.is_empty()
+    }
+
+    /// Name of the `i`-th entry.
+    pub fn name(&self, i: usize) -> Option<&Bytes> {
+        self.cd.get(i).map(|cdr| &cdr.file_name)
+    }
+
+    /// Decode the local file of the `i`-th entry, replacing any previously decoded one.
+    ///
+    /// Errors refer to the path `local_files[i]`.
+    pub fn decode_entry(&mut self, i: usize, opts: &Opts) -> Result<Entry> {
+        let cdr = (self.cd.get(i)).ok_or_else(|| Error::new(&self.b, "no such entry"))?;
+        let path = |e: Error| {
+            let e = e.with_index(Index::Int(i));
+            e.with_index(Index::Str("local_files".into()))
+        };
+        if cdr.disk_nr_start != self.disk_nr {
+            return Err(path(Error::new(&self.b, "entry is on another disk")));
+        }
+        let offset = into_usize(cdr.local_file_offset, &self.b).map_err(path)?;
+        let mut b = try_slice(&self.b, offset..).map_err(path)?;
+        let (mut m, mut o) = (Meta::from(&b), Obj::default());
+        let metrics = opts.metrics.as_deref();
+        let entry = Metrics::time(metrics, Phase::Headers, || {
+            consume(&mut b, &mut m, |b| decode_entry(&mut o, b, cdr, opts))
+        });
+        let entry = entry.map_err(path)?;
+        self.local_files[i] = Some((m, Val::Obj(Box::new(o))));
+        Ok(entry)
+    }
+
+    /// Local file of the `i`-th entry, if it was decoded.
+    pub fn local_file(&self, i: usize) -> Option<(&Meta, &Val)> {
+        let (m, v) = self.local_files.get(i)?.as_ref()?;
+        Some((m, v))
+    }
+
+    /// Forget the decoded local file of the `i`-th entry, so that it can be decoded again.
+    pub fn invalidate(&mut self, i: usize) {
+        if let Some(lf) = self.local_files.get_mut(i) {
+            *lf = None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(b: &'static [u8]) -> Val {
+        let mut o = Obj::default();
+        decode_zip(&mut o, Bytes::from_static(b), &Opts::default()).unwrap();
+        Val::Obj(Box::new(o))
+    }
+
+    /// Build an archive with one entry named `a`, whose CRC-32 is wrong unless `data` is empty.
+    fn single_entry(method: u16, extra_fields: &[u8], data: &[u8]) -> Vec<u8> {
+        // version needed, flags, method, time, date, CRC-32, and sizes
+        let mut common = [20, 0, 0, 0].to_vec();
+        common.extend(method.to_le_bytes());
+        common.extend([0, 0, 0x21, 0, 0, 0, 0, 0]);
+        common.extend([(data.len() as u32).to_le_bytes(); 2].concat());
+        common.extend([1, 0]);
+        common.extend((extra_fields.len() as u16).to_le_bytes());
+        let mut b = b"PK\x03\x04".to_vec();
+        b.extend(&common);
+        b.extend(b"a");
+        b.extend(extra_fields);
+        b.extend(data);
+        let cd_offset = b.len() as u32;
+        b.extend(b"PK\x01\x02\x14\x00");
+        b.extend(&common);
+        // comment length, disk, attributes, and offset of the local file
+        b.extend([0; 14]);
+        b.extend(b"a");
+        b.extend(extra_fields);
+        let cd_size = b.len() as u32 - cd_offset;
+        b.extend(b"PK\x05\x06\x00\x00\x00\x00\x01\x00\x01\x00");
+        b.extend(cd_size.to_le_bytes());
+        b.extend(cd_offset.to_le_bytes());
+        b.extend([0, 0]);
+        b
+    }
+
+    #[test]
+    fn verify_without_uncompressing() {
+        let v = decode(include_bytes!("../test.zip"));
+        let compressed = v.query("local_files[0].compressed").unwrap();
+        let valid = compressed.query("crc_32.valid").unwrap();
+        assert!(matches!(valid, Val::Bool(true)));
+        let uncompressed = compressed.query("uncompressed").unwrap();
+        assert!(uncompressed.forced().is_none());
+        assert!(matches!(uncompressed.force(), Val::Bytes(b) if **b == b"Hello"[..]));
+    }
+
+    #[test]
+    fn inflate_at_most_once() {
+        let inflations = || INFLATIONS.with(|n| n.get());
+        let v = decode(include_bytes!("../test.zip"));
+        let before = inflations();
+        // printing does not force lazy values unless asked to
+        let _ = v.to_string();
+        let _ = v.warnings(&Bytes::new());
+        assert_eq!(inflations(), before);
+
+        // uncompressing the data and verifying it against its CRC-32 inflates it once
+        let _ = v.eval();
+        let _ = v.eval();
+        assert_eq!(inflations(), before + 2);
+    }
+
+    #[test]
+    fn entry_errors_have_paths() {
+        let mut dir = Directory::new(
+            Bytes::from_static(include_bytes!("../test.zip")),
+            &Opts::default(),
+        )
+        .unwrap();
+        dir.cd[1].local_file_offset = 1 << 40;
+        let Err(e) = dir.decode_entry(1, &Opts::default()) else {
+            panic!("offset beyond the input")
+        };
+        let path = [Index::Int(1), Index::Str("local_files".into())];
+        assert_eq!(e.path(), path);
+        assert!(dir.decode_entry(0, &Opts::default()).is_ok());
+    }
+
+    #[test]
+    fn prefixes_of_seeds_do_not_panic() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz/seeds/zip");
+        for f in std::fs::read_dir(dir).unwrap() {
+            let b = Bytes::from(std::fs::read(f.unwrap().path()).unwrap());
+            for n in 0..=b.len() {
+                for (force, recover) in [(false, false), (true, true)] {
+                    let opts = || Opts {
+                        force,
+                        recover,
+                        ..Opts::default()
+                    };
+                    let mut o = Obj::default();
+                    let _ = decode_zip(&mut o, b.slice(..n), &opts());
+                    let _ = Val::Obj(Box::new(o)).eval().to_string();
+                    let _ = entries(b.slice(..n), &opts());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn parallel_decoding_is_deterministic() {
+        let b = Bytes::from_static(include_bytes!("../test.zip"));
+        let decode = |parallel| {
+            let mut o = Obj::default();
+            let opts = Opts {
+                parallel,
+                ..Opts::default()
+            };
+            decode_zip(&mut o, b.clone(), &opts).unwrap();
+            Val::Obj(Box::new(o)).to_canonical_string(&b)
+        };
+        let serial = decode(false);
+        for _ in 0..3 {
+            assert_eq!(decode(true), serial);
+        }
+        assert_eq!(decode(false), serial);
+    }
+
+    #[test]
+    fn stats_of_test_zip() {
+        let stats = Arc::new(Stats::default());
+        let opts = Opts {
+            stats: Some(stats.clone()),
+            ..Opts::default()
+        };
+        let mut o = Obj::default();
+        decode_zip(
+            &mut o,
+            Bytes::from_static(include_bytes!("../test.zip")),
+            &opts,
+        )
+        .unwrap();
+        let counts = stats.counts();
+        let counts: Vec<_> = counts["zip"].iter().map(|(k, n)| (&**k, *n)).collect();
+        assert_eq!(
+            counts,
+            [
+                ("compression_method: deflated", 2),
+                ("compression_method: none", 1),
+                ("data_descriptor", 2),
+                ("data_descriptor_signature", 2),
+                ("directories", 1),
+                ("entries", 3),
+                ("extra_field: 0x5455", 3),
+                ("extra_field: 0x7875", 3),
+                ("flag: data_descriptor", 2),
+                ("host_system: 3", 3),
+                ("size_from_central_directory", 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn trailing_bytes_are_gaps() {
+        let b = Bytes::from([&include_bytes!("../test.zip")[..], b"JUNK"].concat());
+        let mut o = Obj::default();
+        decode_zip(&mut o, b.clone(), &Opts::default()).unwrap();
+        // bytes after the end of central directory record are part of it
+        let (_, eocd) = o.get("end_of_central_directory_record").unwrap();
+        let (m, v) = eocd.get(&Index::Str("unparsed".into())).unwrap();
+        assert!(matches!(v, Val::Raw { gap: true }));
+        assert_eq!(m.offset_in(&b), Some(b.len() - 4..b.len()));
+        let coverage = coverage(&Val::Obj(Box::new(o)), &b);
+        assert_eq!(coverage.uncovered, []);
+        assert_eq!(coverage.covered, b.len());
+    }
+
+    #[test]
+    fn forced_signature_mismatch_is_a_warning() {
+        let mut b = include_bytes!("../test.zip").to_vec();
+        b[3] = 0xff;
+        let b = Bytes::from(b);
+        let mut o = Obj::default();
+        assert!(decode_zip(&mut o, b.clone(), &Opts::default()).is_err());
+        let opts = Opts {
+            force: true,
+            ..Opts::default()
+        };
+        let mut o = Obj::default();
+        decode_zip(&mut o, b.clone(), &opts).unwrap();
+        let warnings = Val::Obj(Box::new(o)).warnings(&b);
+        let [w] = &warnings[..] else {
+            panic!("expected one warning, found {warnings:?}");
+        };
+        let msg = r#"expected byte sequence "PK\u{3}\u{4}", found "PK\u{3}ÿ""#;
+        assert_eq!(w.msg, msg);
+        assert_eq!(Path(&w.path).to_string(), "local_files[0].signature");
+        assert_eq!(w.offset, Some(0));
+    }
+
+    #[test]
+    fn some_timestamps_are_absent() {
+        let keys = |o: &Obj| o.iter().map(|(k, ..)| k.to_owned()).collect::<Vec<_>>();
+        // central directory headers flag all times but store only the modification time
+        let mut b = Bytes::from_static(&[0x07, 0, 0, 0, 0]);
+        let mut o = Obj::default();
+        decode_extended_timestamp(&mut o, &mut b).unwrap();
+        assert_eq!(keys(&o), ["flags", "modification_time"]);
+        let mut b = Bytes::from_static(&[0x05, 0, 0, 0, 0, 0x80, 0x51, 0x01, 0, 0xff]);
+        let mut o = Obj::default();
+        decode_extended_timestamp(&mut o, &mut b).unwrap();
+        assert_eq!(keys(&o), ["flags", "modification_time", "creation_time"]);
+        let creation = o.get("creation_time").unwrap().1;
+        assert!(
+            matches!(creation.query("iso8601"), Ok(Val::Str(s)) if **s == b"1970-01-02T00:00:00Z"[..])
+        );
+        assert_eq!(b, &[0xff][..]);
+    }
+
+    #[test]
+    fn crc_is_rendered_as_hex() {
+        let v = decode(include_bytes!("../test.zip"));
+        let path = Index::parse_path("central_directories[0].crc_32").unwrap();
+        let root = Meta::from(Bytes::new());
+        let (m, crc) = v.get_path(&root, &path).unwrap();
+        assert_eq!(m.formatted(crc).as_deref(), Some("0xf7d18982"));
+        let mut b = include_bytes!("../test.zip").to_vec();
+        b[0x112..0x116].fill(0);
+        let mut o = Obj::default();
+        decode_zip(&mut o, Bytes::from(b), &Opts::default()).unwrap();
+        let diffs = diff(&v, &Val::Obj(Box::new(o)));
+        let crc = diffs
+            .iter()
+            .find(|d| Path(&d.path).to_string() == "central_directories[0].crc_32")
+            .unwrap();
+        assert_eq!(
+            (crc.old.as_deref(), crc.new.as_deref()),
+            (Some("0xf7d18982"), Some("0x00000000"))
+        );
+    }
+
+    #[test]
+    fn diff_of_modification_times() {
+        let a = decode(include_bytes!("../test.zip"));
+        let mut b = include_bytes!("../test.zip").to_vec();
+        // one minute and one day earlier
+        b[0xa] ^= 0x20;
+        b[0xc] ^= 0x01;
+        let b = decode(b.leak());
+        let diffs: Vec<_> = diff(&a, &b)
+            .into_iter()
+            .map(|d| {
+                (
+                    Path(&d.path).to_string(),
+                    d.kind,
+                    d.old.unwrap(),
+                    d.new.unwrap(),
+                )
+            })
+            .collect();
+        let changed = |path: &str, old: &str, new: &str| {
+            let path = format!("local_files[0].last_modification.{path}");
+            (path, DiffKind::Changed, old.to_string(), new.to_string())
+        };
+        let expected = [
+            changed("fat_time.minute", "21", "20"),
+            changed("fat_date.day", "7", "6"),
+            changed(
+                "iso8601",
+                r#""2025-03-07T10:21:10""#,
+                r#""2025-03-06T10:20:10""#,
+            ),
+        ];
+        assert_eq!(diffs, expected);
+    }
+
+    #[test]
+    fn queries() {
+        let v = decode(include_bytes!("../test.zip"));
+        let length = v.query("end_of_central_directory_record.comment.length");
+        assert!(matches!(length, Ok(Val::U16(0))));
+        // lazy values are forced along the way
+        let flag = v.query("local_files[0].flags.encrypted");
+        assert!(matches!(flag, Ok(Val::Bool(false))), "{flag:?}");
+        let missing = |p| v.query(p).unwrap_err();
+        assert_eq!(
+            missing("local_files[3].file_name"),
+            "no value at `local_files[3]`"
+        );
+        assert_eq!(missing("local_files.x"), "no value at `local_files.x`");
+        assert_eq!(missing("local_files[0"), "expected `]`");
+    }
+
+    #[test]
+    fn spans_of_sibling_leaves_do_not_overlap() {
+        let root = Bytes::from_static(include_bytes!("../test.zip"));
+        let v = decode(include_bytes!("../test.zip"));
+        let spans = v.spans(&root, false);
+        let mut leaves: Vec<_> = spans
+            .iter()
+            .filter(|s| s.leaf)
+            .map(|s| {
+                (
+                    Path(&s.path[..s.path.len() - 1]).to_string(),
+                    s.range.clone(),
+                )
+            })
+            .collect();
+        leaves.sort_by_key(|(parent, r)| (parent.clone(), r.start, r.end));
+        for w in leaves.windows(2) {
+            if w[0].0 == w[1].0 {
+                assert!(w[0].1.end <= w[1].1.start, "{:?} overlaps {:?}", w[0], w[1]);
+            }
+        }
+        let flags = |spans: &[Span]| {
+            let s = spans
+                .iter()
+                .find(|s| Path(&s.path).to_string() == "local_files[0].flags");
+            (
+                s.unwrap().range.clone(),
+                s.unwrap().leaf,
+                s.unwrap().value.is_some(),
+            )
+        };
+        assert_eq!(flags(&spans), (6..8, true, false));
+        // forcing lazy values reveals the flags
+        let forced = v.spans(&root, true);
+        assert_eq!(flags(&forced), (6..8, false, false));
+        let paths: Vec<_> = forced.iter().map(|s| Path(&s.path).to_string()).collect();
+        assert!(paths.iter().any(|p| p == "local_files[0].flags.encrypted"));
+        assert!(forced.len() > spans.len());
+        // values that lie outside the root are skipped
+        assert!(v.spans(&Bytes::from_static(b"other"), true).is_empty());
+    }
+
+    #[test]
+    fn prepended_junk_is_uncovered() {
+        // test.zip after 4 bytes of junk, with its offsets shifted accordingly
+        let mut b = [&b"JUNK"[..], include_bytes!("../test.zip")].concat();
+        let mut shift = |at: usize| {
+            let offset = u32::from_le_bytes(b[at..at + 4].try_into().unwrap());
+            b[at..at + 4].copy_from_slice(&(offset + 4).to_le_bytes());
+        };
+        for record in [0x102, 0x152, 0x1a2] {
+            shift(4 + record + 42);
+        }
+        shift(4 + 0x1fd);
+        let b = Bytes::from(b);
+        let mut o = Obj::default();
+        decode_zip(&mut o, b.clone(), &Opts::default()).unwrap();
+        let c = coverage(&Val::Obj(Box::new(o)), &b);
+        assert_eq!(c.uncovered, vec![0..4]);
+        assert_eq!(c.covered, b.len() - 4);
+        assert!(c.overlaps.is_empty());
+    }
+
+    #[test]
+    fn evaluation_in_place() {
+        fn lazy(v: &Val) -> usize {
+            match v {
+                Val::Lazy(_) => 1,
+                Val::Arr(a) => a.iter().map(|(_, v)| lazy(v)).sum(),
+                Val::Obj(o) => o.iter().map(|(_, _, v)| lazy(v)).sum(),
+                _ => 0,
+            }
+        }
+        let root = Bytes::from_static(include_bytes!("../test.zip"));
+        let expected = decode(include_bytes!("../test.zip")).eval();
+        assert_eq!(lazy(&expected), 0);
+        let v = decode(include_bytes!("../test.zip"));
+        assert!(lazy(&v) > 0);
+        // the clone shares its lazy values with `v`, which stay in `v`
+        let mut shared = v.clone();
+        shared.eval_in_place();
+        let mut unshared = v;
+        unshared.eval_in_place();
+        for v in [shared, unshared] {
+            assert_eq!(lazy(&v), 0);
+            assert_eq!(
+                v.to_canonical_string(&root),
+                expected.to_canonical_string(&root)
+            );
+        }
+    }
+
+    #[test]
+    fn recover_from_a_broken_record() {
+        let mut b = include_bytes!("../test.zip").to_vec();
+        b[0x152] = b'X';
+        let b = Bytes::from(b);
+        assert!(decode_zip(&mut Obj::default(), b.clone(), &Opts::default()).is_err());
+        let opts = Opts {
+            recover: true,
+            ..Opts::default()
+        };
+        let mut o = Obj::default();
+        decode_zip(&mut o, b.clone(), &opts).unwrap();
+        let (_, cds) = o.get("central_directories").unwrap();
+        let Val::Arr(cds) = cds else {
+            panic!("expected an array, found {cds:?}");
+        };
+        assert_eq!(cds.len(), 3);
+        let records: Vec<_> = cds.iter().collect();
+        let (m, v) = records[1];
+        assert!(matches!(v, Val::Raw { gap: true }));
+        assert_eq!(m.offset_in(&b), Some(0x152..0x1a2));
+        let e = m.error().unwrap();
+        assert!(e.msg().starts_with("expected byte sequence"), "{e}");
+        for (m, v) in [records[0], records[2]] {
+            assert!(m.error().is_none());
+            assert!(matches!(v.query("file_name"), Ok(Val::Bytes(_))));
+        }
+    }
+
+    #[test]
+    fn rendered_errors() {
+        let mut b = include_bytes!("../test.zip").to_vec();
+        // file name length of the second central directory record
+        b[0x152 + 28] = 0xff;
+        let b = Bytes::from(b);
+        let e = decode_zip(&mut Obj::default(), b.clone(), &Opts::default()).unwrap_err();
+        let expected = "central_directories[1].file_name: \
+            in central directory, central directory record 1: \
+            expected 255 bytes, but only 109 remain (at offset 0x180)";
+        assert_eq!(e.to_string(), expected);
+    }
+
+    #[test]
+    fn record_count_mismatch_is_a_warning() {
+        let mut b = include_bytes!("../test.zip").to_vec();
+        // nr_of_central_dir_records
+        b[0x1f7] = 2;
+        let b = Bytes::from(b);
+        let mut o = Obj::default();
+        decode_zip(&mut o, b.clone(), &Opts::default()).unwrap();
+        let v = Val::Obj(Box::new(o));
+        let warnings = v.warnings(&b);
+        let [w] = &warnings[..] else {
+            panic!("expected one warning, found {warnings:?}");
+        };
+        let msg = "expected 2 records as given by the end of central directory record, found 3";
+        assert_eq!(w.msg, msg);
+        assert_eq!(Path(&w.path).to_string(), "central_directories");
+        assert_eq!(w.offset, Some(0x102));
+        assert!(decode(include_bytes!("../test.zip"))
+            .warnings(&b)
+            .is_empty());
+    }
+
+    #[test]
+    fn uncompressed_data_exceeds_the_limit() {
+        let opts = Opts {
+            limits: Limits {
+                max_decompressed_bytes: Some(4),
+                ..Limits::default()
+            },
+            ..Opts::default()
+        };
+        let mut o = Obj::default();
+        decode_zip(
+            &mut o,
+            Bytes::from_static(include_bytes!("../test.zip")),
+            &opts,
+        )
+        .unwrap();
+        let path = Index::parse_path("local_files[0].compressed.crc_32.value").unwrap();
+        let (m, _) = o.get_path(&path).unwrap();
+        let msg = "uncompressed data exceeds the limit max_decompressed_bytes = 4";
+        assert_eq!(m.error().map(Error::msg), Some(msg));
+    }
+
+    #[test]
+    fn trees_move_across_threads() {
+        let root = Bytes::from_static(include_bytes!("../test.zip"));
+        let expected = decode(include_bytes!("../test.zip"))
+            .eval()
+            .to_canonical_string(&root);
+        let unevaluated = decode(include_bytes!("../test.zip"));
+        let evaluated = unevaluated.eval();
+        // the unevaluated tree is forced on the other thread, and shared with this one
+        let shared = unevaluated.clone();
+        let strings = std::thread::spawn({
+            let root = root.clone();
+            move || [unevaluated, evaluated].map(|v| v.to_canonical_string(&root))
+        });
+        assert_eq!(
+            strings.join().unwrap(),
+            [expected.clone(), expected.clone()]
+        );
+        let flags = shared.query("local_files[0].flags").unwrap();
+        assert!(matches!(flags, Val::Lazy(_)) && flags.forced().is_some());
+        assert_eq!(shared.to_canonical_string(&root), expected);
+    }
+
+    #[test]
+    fn crc_spans_the_central_directory_field() {
+        let root = Bytes::from_static(include_bytes!("../test.zip"));
+        let v = decode(include_bytes!("../test.zip"));
+        let m = Meta::from(root.clone());
+        let span = |p: &str| {
+            let (m, _) = v.get_path(&m, &Index::parse_path(p).unwrap()).unwrap();
+            m.offset_in(&root)
+        };
+        let field = span("central_directories[0].crc_32");
+        assert_eq!(field, Some(0x112..0x116));
+        assert_eq!(span("local_files[0].compressed.crc_32"), field);
+        assert_eq!(span("local_files[0].compressed.crc_32.value"), field);
+        let data = span("local_files[0].compressed");
+        assert_eq!(span("local_files[0].compressed.uncompressed"), data);
+    }
+
+    #[test]
+    fn unexpected_values_are_warnings() {
+        let mut b = include_bytes!("../test.zip").to_vec();
+        // a reserved flag of the first local file, and the disk of the central directory
+        b[0x7] |= 0x80;
+        b[0x1f3] = 1;
+        let b = Bytes::from(b);
+        let mut o = Obj::default();
+        decode_zip(&mut o, b.clone(), &Opts::default()).unwrap();
+        let warnings = Val::Obj(Box::new(o)).warnings(&b);
+        let warnings: Vec<_> = warnings
+            .iter()
+            .map(|w| (Path(&w.path).to_string(), w.msg.as_str()))
+            .collect();
+        let expected = [
+            (
+                "local_files[0].flags",
+                "expected reserved flags 0x0000, found reserved flags 0x8000",
+            ),
+            (
+                "end_of_central_directory_record.start_disk_nr",
+                "expected 0, found 1",
+            ),
+        ];
+        assert_eq!(warnings, expected.map(|(p, m)| (p.to_string(), m)));
+    }
+
+    #[test]
+    fn records_link_to_their_local_files() {
+        let root = Bytes::from_static(include_bytes!("../test.zip"));
+        let links = |opts: &Opts| {
+            let mut o = Obj::default();
+            decode_zip(&mut o, root.clone(), opts).unwrap();
+            let v = Val::Obj(Box::new(o));
+            let Ok(Val::Arr(cds)) = v.query("central_directories") else {
+                panic!("expected central directory records");
+            };
+            let (rm, mut links) = (Meta::from(&root), Vec::new());
+            for (_, cdr) in cds.iter() {
+                let Val::Obj(cdr) = cdr else { unreachable!() };
+                let (m, offset) = cdr.get("relative_offset_of_local_file_header").unwrap();
+                let path = m.points_to().unwrap();
+                // the link resolves to the local file at the offset
+                let (lf, _) = v.get_path(&rm, path).unwrap();
+                let Val::U32(offset) = offset.eval() else {
+                    unreachable!()
+                };
+                assert_eq!(lf.offset_in(&root).map(|r| r.start), Some(offset as usize));
+                links.push(Path(path).to_string());
+            }
+            links
+        };
+        let expected = ["local_files[0]", "local_files[1]", "local_files[2]"];
+        assert_eq!(links(&Opts::default()), expected);
+        // links count only the local files that are kept
+        let opts = Opts {
+            filter: Some(alloc::rc::Rc::new(|name: &[u8]| name != b"test/test1")),
+            ..Opts::default()
+        };
+        assert_eq!(links(&opts), expected[..2]);
+    }
+
+    #[test]
+    fn repeated_extra_fields_keep_their_keys() {
+        // two extended timestamps with different modification times
+        let ts = |t: u8| [0x55, 0x54, 5, 0, 1, t, 0, 0, 0];
+        let v = decode(single_entry(0, &[ts(1), ts(2)].concat(), &[]).leak());
+        for p in ["local_files[0]", "central_directories[0]"] {
+            let efs = v.query(&format!("{p}.extra_fields")).unwrap();
+            let Val::Arr(efs) = efs else { unreachable!() };
+            assert_eq!(efs.len(), 2);
+            for (i, (_, ef)) in efs.iter().enumerate() {
+                let Val::Obj(ef) = ef else { unreachable!() };
+                assert!(ef.keys_are_unique());
+                assert!(matches!(ef.get("tag"), Some((_, Val::U16(0x5455)))));
+                assert!(!ef.contains_key("tag_2"));
+                let t = v.query(&format!("{p}.extra_fields[{i}].data.modification_time"));
+                assert!(t.is_ok());
+            }
+        }
+    }
+
+    #[test]
+    fn unknown_compression_methods_uncompress_to_null() {
+        let v = decode(single_entry(99, &[], b"abc").leak());
+        let compressed = v.query("local_files[0].compressed").unwrap();
+        let uncompressed = compressed.query("uncompressed").unwrap();
+        assert!(matches!(uncompressed.force(), Val::Null));
+        // the bytes that could not be uncompressed are still known
+        let Val::Obj(o) = compressed.force() else {
+            unreachable!()
+        };
+        assert_eq!(&o.get("uncompressed").unwrap().0.bytes()[..], b"abc");
+        let opts = DisplayOpts {
+            force: true,
+            root: None,
+            canonical: false,
+        };
+        let shown = compressed.display(&opts).to_string();
+        assert!(shown.lines().any(|l| l == "uncompressed: null"), "{shown}");
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_value(&v).unwrap();
+            let u = json.pointer("/local_files/0/compressed/uncompressed");
+            assert_eq!(u, Some(&serde_json::Value::Null));
+        }
+    }
+
+    #[test]
+    fn zip64_values_replace_placeholders() {
+        let u64_at = |v: &Val, path: &str| match v.query(path) {
+            Ok(Val::U64(n)) => *n,
+            r => panic!("{path}: {:?}", r.map(|v| v.to_string())),
+        };
+        // sizes of the local file header only
+        let v = decode(include_bytes!("../fuzz/seeds/zip/z64.zip"));
+        assert_eq!(u64_at(&v, "local_files[0].compressed_size"), 12);
+        assert_eq!(u64_at(&v, "local_files[0].uncompressed_size"), 12);
+        let cdr_size = v.query("central_directories[0].compressed_size");
+        assert!(matches!(cdr_size, Ok(Val::U32(12))));
+        let data = v.query("local_files[0].compressed.uncompressed").unwrap();
+        assert!(matches!(data.force(), Val::Bytes(b) if **b == b"hello zip64\n"[..]));
+
+        // all fields of the record
+        let v = decode(include_bytes!("../fuzz/seeds/zip/z64cd.zip"));
+        for k in ["compressed_size", "uncompressed_size"] {
+            assert_eq!(u64_at(&v, &format!("central_directories[0].{k}")), 6);
+        }
+        let offset = "central_directories[0].relative_offset_of_local_file_header";
+        assert_eq!(u64_at(&v, offset), 0);
+        let disk = v.query("central_directories[0].disk_number_where_file_starts");
+        assert!(matches!(disk, Ok(Val::U32(0))));
+        let Val::Obj(o) = v.query("central_directories[0]").unwrap() else {
+            unreachable!()
+        };
+        let (m, _) = o.get("compressed_size").unwrap();
+        // replaced fields still span the placeholders in the header
+        assert_eq!(&m.bytes()[..], [0xff; 4]);
+        let d = m.description().unwrap();
+        assert!(d.ends_with(", from the ZIP64 extra field"), "{d}");
+    }
+
+    #[test]
+    fn zip64_fields_are_only_read_for_placeholders() {
+        // a ZIP64 extra field with only the offset of the local file
+        let mut b = single_entry(0, &[1, 0, 8, 0, 0, 0, 0, 0, 0, 0, 0, 0], b"abc");
+        let cd_offset = u32::from_le_bytes(b[b.len() - 6..][..4].try_into().unwrap());
+        let at = cd_offset as usize + 42;
+        b[at..at + 4].copy_from_slice(&[0xff; 4]);
+        let v = decode(b.leak());
+        let cdr = "central_directories[0]";
+        let offset = v.query(&format!("{cdr}.relative_offset_of_local_file_header"));
+        assert!(matches!(offset, Ok(Val::U64(0))));
+        assert!(matches!(
+            v.query(&format!("{cdr}.compressed_size")),
+            Ok(Val::U32(3))
+        ));
+        let keys = |path: &str| match v.query(path) {
+            Ok(Val::Obj(o)) => o.iter().map(|(k, ..)| k.to_string()).collect::<Vec<_>>(),
+            _ => panic!("{path}"),
+        };
+        let zip64 = keys(&format!("{cdr}.extra_fields[0].data"));
+        assert_eq!(zip64, ["local_file_offset"]);
+        // the sizes in the local file header are no placeholders, so nothing is read
+        assert!(keys("local_files[0].extra_fields[0].data").is_empty());
+        assert!(matches!(
+            v.query("local_files[0].compressed_size"),
+            Ok(Val::U32(3))
+        ));
+    }
+
+    #[test]
+    fn fields_at_offsets() {
+        let root = Bytes::from_static(include_bytes!("../test.zip"));
+        let v = decode(include_bytes!("../test.zip"));
+        let at = |offset| {
+            let (path, range) = v.field_at(&root, offset)?;
+            Some((Path(&path).to_string(), range))
+        };
+        // a byte inside the name of the first file, which follows its 30 bytes of header
+        assert_eq!(at(32), Some(("local_files[0].file_name".into(), 30..40)));
+        // of the flags that share their bytes, the first one is returned
+        let flag = Some(("local_files[0].flags.encrypted".into(), 6..8));
+        assert_eq!(at(6), flag);
+        // uncompressed data spans the compressed bytes that it is uncompressed from
+        let (m, _) = match v.query("local_files[0]") {
+            Ok(Val::Obj(o)) => o.get("compressed").unwrap(),
+            _ => unreachable!(),
+        };
+        let r = m.offset_in(&root).unwrap();
+        let uncompressed = "local_files[0].compressed.uncompressed".into();
+        assert_eq!(at(r.start), Some((uncompressed, r)));
+        assert_eq!(at(root.len()), None);
+    }
+}
@@ -0,0 +1,49 @@
+//! Comparing the canonical output for the sample archives with the stored output.
+//!
+//! Run with `UPDATE_GOLDEN=1` to store the current output instead,
+//! after checking that the changes to it are intended.
+
+use binspan::decode::{Obj, Result, Val};
+use binspan::{tar, zip};
+use bytes::Bytes;
+use std::path::Path;
+
+fn check(name: &str, decode: impl FnOnce(&mut Obj, Bytes) -> Result) {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR"));
+    let input = Bytes::from(std::fs::read(dir.join(name)).unwrap());
+    let mut o = Obj::default();
+    decode(&mut o, input.clone()).unwrap();
+    let out = Val::Obj(Box::new(o)).to_canonical_string(&input);
+
+    let golden = dir.join("tests/golden").join(format!("{name}.txt"));
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::write(&golden, out).unwrap();
+        return;
+    }
+    let expected = std::fs::read_to_string(&golden).unwrap();
+    let diff = out.lines().zip(expected.lines()).position(|(a, e)| a != e);
+    let line = diff.unwrap_or(out.lines().count().min(expected.lines().count()));
+    assert!(
+        out == expected,
+        "output for {name} differs from {} at line {}, rerun with UPDATE_GOLDEN=1 if intended\n\
+         output:   {:?}\nexpected: {:?}",
+        golden.display(),
+        line + 1,
+        out.lines().nth(line),
+        expected.lines().nth(line),
+    );
+}
+
+#[test]
+fn zip() {
+    check("test.zip", |o, b| {
+        zip::decode_zip(o, b, &zip::Opts::default())
+    })
+}
+
+#[test]
+fn tar() {
+    check("test.tar", |o, b| {
+        tar::decode_tar(o, b, &tar::Opts::default())
+    })
+}